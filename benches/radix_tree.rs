@@ -0,0 +1,73 @@
+//! Compares the radix tree behind MODE TRIE with the per-char trie it replaced, at 1M keys.
+//!
+//! Run with `cargo bench --bench radix_tree`.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+use smirk::core::radix_tree::RadixTree;
+use trie::Trie;
+
+/// Counts the bytes currently allocated, so each structure's footprint can be read off.
+struct Counting;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+const KEYS: usize = 1_000_000;
+/// The old trie's remove walks the key once per char and prints a line each time, so only this many are removed.
+const REMOVED: usize = 10_000;
+const PREFIX: &str = "user:12";
+
+/// Runs `f`, printing how long it took and how much more memory is allocated afterwards.
+fn measure<T>(name: &str, f: impl FnOnce() -> T) -> T {
+    let before = ALLOCATED.load(Ordering::Relaxed);
+    let started = Instant::now();
+    let result = f();
+    let grown = ALLOCATED.load(Ordering::Relaxed) as isize - before as isize;
+    println!("{:<32} {:>10.1?} {:>10} MB", name, started.elapsed(), grown / (1024 * 1024));
+    result
+}
+
+fn main() {
+    let keys: Vec<String> = (0..KEYS).map(|i| format!("user:{}", i)).collect();
+
+    let mut radix = measure("radix tree: insert", || {
+        let mut tree = RadixTree::new();
+        keys.iter().for_each(|key| {
+            tree.insert(key);
+        });
+        tree
+    });
+    let listed = measure("radix tree: keys under prefix", || radix.keys_with_prefix(PREFIX).count());
+    let counted = measure("radix tree: count under prefix", || radix.count_prefix(PREFIX));
+    assert_eq!(listed, counted);
+    measure("radix tree: remove", || keys[..REMOVED].iter().for_each(|key| {
+        radix.remove(key);
+    }));
+    drop(radix);
+
+    let mut old = measure("per-char trie: insert", || {
+        let mut trie = Trie::default();
+        keys.iter().for_each(|key| trie.add(key, Some(String::new())));
+        trie
+    });
+    let old_listed = measure("per-char trie: keys under prefix", || old.get_keys_under_prefix(PREFIX).len());
+    assert_eq!(listed, old_listed);
+    measure("per-char trie: remove", || keys[..REMOVED].iter().for_each(|key| old.remove(key)));
+}
@@ -0,0 +1,17 @@
+use std::env;
+use std::process::Command;
+
+/// Records the compiler and target smirk is built with, which module libraries have to match.
+fn main() {
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| String::from("rustc"));
+    let version = Command::new(rustc)
+        .arg("--version")
+        .output()
+        .ok()
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|version| version.trim().to_string())
+        .unwrap_or_else(|| String::from("an unknown rustc"));
+    println!("cargo:rustc-env=SMIRK_RUSTC_VERSION={}", version);
+    println!("cargo:rustc-env=SMIRK_TARGET={}", env::var("TARGET").unwrap());
+    println!("cargo:rerun-if-changed=build.rs");
+}
@@ -0,0 +1,30 @@
+//! A module adding WORDCOUNT, which counts the words in a String value.
+//!
+//! Build it with `cargo build --example word_count_module` and start the server with
+//! `--load-module target/debug/examples/libword_count_module.so`.
+
+use smirk::core::command_spec::CommandSpec;
+use smirk::core::module::{ModuleCommand, Registrar, first_key};
+use smirk::core::smirk_map::SmirkMap;
+
+fn word_count(smirk_map: &mut SmirkMap, arguments: &[Vec<u8>]) -> Result<Vec<u8>, String> {
+    let key = String::from_utf8_lossy(&arguments[0]).to_string();
+    let text = smirk_map.get::<String>(&key).map_err(|e| e.to_string().trim_end().trim_end_matches('.').to_string())?;
+    Ok(format!("{}\n", text.split_whitespace().count()).into_bytes())
+}
+
+fn init(registrar: &mut Registrar) {
+    registrar.command(ModuleCommand {
+        spec: CommandSpec {
+            name: "WORDCOUNT",
+            min_args: 1,
+            max_args: Some(1),
+            syntax: "WORDCOUNT <key>",
+            summary: "Counts the words in the String at the key."
+        },
+        keys: first_key,
+        handler: word_count
+    });
+}
+
+smirk::smirk_module!(init);
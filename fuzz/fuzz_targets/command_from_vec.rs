@@ -0,0 +1,16 @@
+//! Feeds arbitrary lines to the command parser, which must reject them rather than panic.
+//!
+//! Run with `cargo +nightly fuzz run command_from_vec` from the crate root.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use smirk::core::command::Command;
+
+fuzz_target!(|line: &[u8]| {
+    if let Ok(mut command) = Command::from_vec(line.to_vec()) {
+        // What the server does with every command it parses, before running it.
+        command.namespace("fuzz:");
+        command.keys();
+    }
+});
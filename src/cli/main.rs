@@ -0,0 +1,395 @@
+use std::{
+    env,
+    io::{self, ErrorKind, Read, Write},
+    net::TcpStream,
+    path::PathBuf,
+    process::exit,
+    thread,
+    time::{Duration, Instant}
+};
+
+use rustyline::completion::{Completer, Pair};
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+
+use smirk::core::command_spec::COMMANDS;
+use smirk::core::snapshot::{self, SnapshotFormat};
+use smirk::core::snapshot_keys::SnapshotKeys;
+
+/// How long to wait for the first byte of a reply.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(10);
+/// Once a reply has started, it's taken to be complete after this long without more data.
+const REPLY_IDLE: Duration = Duration::from_millis(50);
+/// How often `--latency` prints its numbers.
+const LATENCY_INTERVAL: Duration = Duration::from_secs(1);
+/// Pause between the pings `--latency` sends.
+const LATENCY_PAUSE: Duration = Duration::from_millis(10);
+/// Lines of history kept in the history file.
+const HISTORY_SIZE: usize = 1000;
+
+struct CliConfig {
+    host: String,
+    port: u16,
+    /// Connect to this unix socket instead of host and port.
+    socket: Option<String>,
+    /// Measure PING round trips instead of starting the prompt.
+    latency: bool,
+    /// Snapshots to check offline instead of connecting, and to compare if there are two.
+    verify: Vec<String>,
+    /// Keys to decrypt `verify`'s snapshots with, as the server's `--snapshot-key-file`.
+    snapshot_key_file: Option<String>
+}
+
+impl CliConfig {
+    fn from_args() -> CliConfig {
+        let args: Vec<String> = env::args().skip(1).collect();
+        let mut config = CliConfig {
+            host: String::from("127.0.0.1"),
+            port: 53173,
+            socket: None,
+            latency: false,
+            verify: Vec::new(),
+            snapshot_key_file: None
+        };
+
+        let mut i = 0;
+        while i < args.len() {
+            if args[i] == "--latency" {
+                config.latency = true;
+                i += 1;
+                continue;
+            }
+            if args[i] == "--verify" && i + 1 < args.len() {
+                // --verify <snapshot> [other snapshot]
+                let count = if args.get(i + 2).is_some_and(|other| !other.starts_with('-')) { 2 } else { 1 };
+                config.verify = args[i + 1..=i + count].to_vec();
+                i += count + 1;
+                continue;
+            }
+            if args[i] == "-h" && i + 1 < args.len() {
+                config.host = args[i+1].clone();
+            } else if args[i] == "-p" && i + 1 < args.len() {
+                config.port = args[i+1].parse().unwrap_or(config.port);
+            } else if args[i] == "-s" && i + 1 < args.len() {
+                config.socket = Some(args[i+1].clone());
+            } else if args[i] == "--snapshot-key-file" && i + 1 < args.len() {
+                config.snapshot_key_file = Some(args[i+1].clone());
+            } else {
+                eprintln!("Usage: smirk-cli [-h host] [-p port | -s socket] [--latency] | --verify <snapshot> [other snapshot] [--snapshot-key-file <file>]");
+                exit(2);
+            }
+            i += 2;
+        }
+        config
+    }
+
+    fn describe(&self) -> String {
+        match &self.socket {
+            Some(socket) => socket.clone(),
+            None => format!("{}:{}", self.host, self.port)
+        }
+    }
+}
+
+/// The connection to the server, over TCP or a unix socket.
+enum Connection {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(std::os::unix::net::UnixStream)
+}
+
+impl Connection {
+    fn open(config: &CliConfig) -> io::Result<Connection> {
+        #[cfg(unix)]
+        if let Some(socket) = &config.socket {
+            return std::os::unix::net::UnixStream::connect(socket).map(Connection::Unix);
+        }
+        #[cfg(not(unix))]
+        if config.socket.is_some() {
+            return Err(io::Error::new(ErrorKind::Unsupported, "unix sockets aren't supported on this platform"));
+        }
+        TcpStream::connect((config.host.as_str(), config.port)).map(Connection::Tcp)
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        match self {
+            Connection::Tcp(stream) => stream.set_read_timeout(timeout),
+            #[cfg(unix)]
+            Connection::Unix(stream) => stream.set_read_timeout(timeout)
+        }
+    }
+
+    fn stream(&mut self) -> &mut dyn ReadWrite {
+        match self {
+            Connection::Tcp(stream) => stream,
+            #[cfg(unix)]
+            Connection::Unix(stream) => stream
+        }
+    }
+
+    /// Sends one command and reads its reply.
+    ///
+    /// The protocol doesn't say how long a reply is, so reading stops once the server goes quiet.
+    /// Blocking reads wait as long as the command might block.
+    fn send(&mut self, command: &str) -> io::Result<Vec<u8>> {
+        self.stream().write_all(format!("{}\n", command).as_bytes())?;
+        self.set_read_timeout(if blocks(command) { None } else { Some(REPLY_TIMEOUT) })?;
+
+        let mut reply = Vec::new();
+        let mut buffer = [0; 4096];
+        loop {
+            match self.stream().read(&mut buffer) {
+                Ok(0) if reply.is_empty() => return Err(io::Error::new(ErrorKind::ConnectionAborted, "connection closed")),
+                Ok(0) => return Ok(reply),
+                Ok(n) => {
+                    reply.extend_from_slice(&buffer[..n]);
+                    if reply.ends_with(b"\n") {
+                        self.set_read_timeout(Some(REPLY_IDLE))?;
+                    }
+                }
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => return Ok(reply),
+                Err(e) => return Err(e)
+            }
+        }
+    }
+
+    /// Sends PING and returns how long the PONG took to arrive.
+    ///
+    /// Unlike `send`, this stops at the end of the line instead of waiting for the server to go
+    /// quiet, which would swamp the measurement.
+    fn ping(&mut self) -> io::Result<Duration> {
+        self.set_read_timeout(Some(REPLY_TIMEOUT))?;
+        let started = Instant::now();
+        self.stream().write_all(b"PING\n")?;
+        let mut reply = Vec::new();
+        let mut byte = [0];
+        while !reply.ends_with(b"\n") {
+            match self.stream().read(&mut byte)? {
+                0 => return Err(io::Error::new(ErrorKind::ConnectionAborted, "connection closed")),
+                _ => reply.push(byte[0])
+            }
+        }
+        if reply != b"PONG\n" {
+            return Err(io::Error::new(ErrorKind::InvalidData, String::from_utf8_lossy(&reply).trim_end().to_string()));
+        }
+        Ok(started.elapsed())
+    }
+}
+
+trait ReadWrite: Read + Write {}
+impl<T: Read + Write> ReadWrite for T {}
+
+/// Whether a command may legitimately take longer than `REPLY_TIMEOUT` to answer.
+fn blocks(command: &str) -> bool {
+    let words: Vec<String> = command.split_whitespace().map(|w| w.to_uppercase()).collect();
+    words.first().is_some_and(|w| w == "XREAD") && words.iter().any(|w| w == "BLOCK")
+}
+
+/// Pings the server over and over, printing the minimum, maximum and average round trip once a
+/// second until interrupted, like `redis-cli --latency`.
+fn measure_latency(connection: &mut Connection) -> io::Result<()> {
+    let (mut min, mut max, mut total, mut samples) = (Duration::MAX, Duration::ZERO, Duration::ZERO, 0u32);
+    let mut last_print = Instant::now();
+    loop {
+        let round_trip = connection.ping()?;
+        min = min.min(round_trip);
+        max = max.max(round_trip);
+        total += round_trip;
+        samples += 1;
+        if last_print.elapsed() >= LATENCY_INTERVAL {
+            print!(
+                "\rmin: {:.3} ms, max: {:.3} ms, avg: {:.3} ms ({} samples)",
+                min.as_secs_f64() * 1000.0,
+                max.as_secs_f64() * 1000.0,
+                (total / samples).as_secs_f64() * 1000.0,
+                samples
+            );
+            io::stdout().flush()?;
+            last_print = Instant::now();
+        }
+        thread::sleep(LATENCY_PAUSE);
+    }
+}
+
+/// Numbers the lines of multi-line replies, like a list. Single lines are printed as they are.
+fn pretty_print(reply: &[u8]) {
+    let reply = String::from_utf8_lossy(reply);
+    let lines: Vec<&str> = reply.lines().collect();
+    match lines.len() {
+        0 => println!("(no reply)"),
+        1 => println!("{}", lines[0]),
+        n => {
+            let width = n.to_string().len();
+            for (i, line) in lines.iter().enumerate() {
+                println!("{:>width$}) {}", i + 1, line, width = width);
+            }
+        }
+    }
+}
+
+/// Completes command names at the start of the line, in the case the user started typing in.
+struct CommandCompleter;
+
+impl Completer for CommandCompleter {
+    type Candidate = Pair;
+
+    fn complete(&self, line: &str, pos: usize, _: &Context<'_>) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let typed = &line[..pos];
+        let start = typed.len() - typed.trim_start().len();
+        let word = &typed[start..];
+        if word.contains(' ') {
+            return Ok((pos, Vec::new()));
+        }
+        let lowercase = word.chars().next().is_some_and(|c| c.is_lowercase());
+        let candidates = COMMANDS
+            .iter()
+            .map(|spec| spec.name)
+            .filter(|name| name.starts_with(&word.to_uppercase()))
+            .map(|name| {
+                let name = if lowercase { name.to_lowercase() } else { name.to_string() };
+                Pair { display: name.clone(), replacement: format!("{} ", name) }
+            })
+            .collect();
+        Ok((start, candidates))
+    }
+}
+
+impl Hinter for CommandCompleter {
+    type Hint = String;
+}
+impl Highlighter for CommandCompleter {}
+impl Validator for CommandCompleter {}
+impl Helper for CommandCompleter {}
+
+fn history_path() -> Option<PathBuf> {
+    env::var_os("HOME").map(|home| PathBuf::from(home).join(".smirk_cli_history"))
+}
+
+/// `--verify`: reads each snapshot without a server, which checks it against its checksum, and
+/// with two lists the keys that differ going from the first to the second.
+///
+/// # Returns
+///
+/// * The exit code: 0 if the snapshots read and don't differ, 1 otherwise.
+fn verify_snapshots(paths: &[String], key_file: Option<&str>) -> i32 {
+    let keys = match key_file.map(SnapshotKeys::from_file) {
+        None => SnapshotKeys::default(),
+        Some(Ok(keys)) => keys,
+        Some(Err(e)) => {
+            eprintln!("{}.", e);
+            return 1;
+        }
+    };
+    let mut snapshots = Vec::new();
+    for path in paths {
+        match snapshot::read_snapshot(path, SnapshotFormat::from_path(path), false, &keys) {
+            Ok(read) => {
+                println!("Read {} keys from \"{}\".", read["keys"].as_object().map_or(0, |keys| keys.len()), path);
+                snapshots.push(read);
+            }
+            Err(e) => {
+                eprintln!("Couldn't verify \"{}\": {}.", path, e);
+                return 1;
+            }
+        }
+    }
+    match snapshots.as_slice() {
+        [before, after] => {
+            let diff = snapshot::diff(before, after);
+            print!("{}", diff);
+            println!("{} from \"{}\" to \"{}\".", diff.summary(), paths[0], paths[1]);
+            i32::from(!diff.is_empty())
+        }
+        _ => 0
+    }
+}
+
+fn main() {
+    let config = CliConfig::from_args();
+    if !config.verify.is_empty() {
+        exit(verify_snapshots(&config.verify, config.snapshot_key_file.as_deref()));
+    }
+    let mut connection = match Connection::open(&config) {
+        Ok(connection) => Some(connection),
+        Err(e) => {
+            eprintln!("Could not connect to {}: {}", config.describe(), e);
+            exit(1);
+        }
+    };
+
+    if config.latency {
+        if let Some(Err(e)) = connection.as_mut().map(measure_latency) {
+            eprintln!("\nLost the connection to {}: {}", config.describe(), e);
+            exit(1);
+        }
+        return;
+    }
+
+    let editor_config = rustyline::Config::builder()
+        .max_history_size(HISTORY_SIZE)
+        .map(|builder| builder.auto_add_history(true).build());
+    let mut editor: Editor<CommandCompleter, DefaultHistory> = match editor_config.and_then(Editor::with_config) {
+        Ok(editor) => editor,
+        Err(e) => {
+            eprintln!("Could not start the line editor: {}", e);
+            exit(1);
+        }
+    };
+    editor.set_helper(Some(CommandCompleter));
+    let history = history_path();
+    if let Some(history) = &history {
+        // There's no history file the first time the CLI runs.
+        editor.load_history(history).ok();
+    }
+
+    let prompt = format!("{}> ", config.describe());
+    loop {
+        let line = match editor.readline(&prompt) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) | Err(ReadlineError::Eof) => break,
+            Err(e) => {
+                eprintln!("{}", e);
+                break;
+            }
+        };
+        let command = line.trim();
+        if command.is_empty() {
+            continue;
+        }
+        if command.eq_ignore_ascii_case("exit") {
+            break;
+        }
+
+        // A dropped connection is reopened for the next command, like redis-cli does.
+        if connection.is_none() {
+            match Connection::open(&config) {
+                Ok(reopened) => connection = Some(reopened),
+                Err(e) => {
+                    eprintln!("Could not connect to {}: {}", config.describe(), e);
+                    continue;
+                }
+            }
+        }
+        match connection.as_mut().map(|c| c.send(command)) {
+            Some(Ok(reply)) => pretty_print(&reply),
+            Some(Err(e)) => {
+                eprintln!("Lost the connection to {}: {}", config.describe(), e);
+                connection = None;
+            }
+            None => {}
+        }
+        if command.eq_ignore_ascii_case("quit") {
+            break;
+        }
+    }
+
+    if let Some(history) = &history {
+        if let Err(e) = editor.save_history(history) {
+            eprintln!("Could not save history to {}: {}", history.display(), e);
+        }
+    }
+}
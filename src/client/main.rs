@@ -1,3 +1,101 @@
+use std::{
+    env,
+    io::{Read, Write},
+    net::TcpStream,
+    process::exit,
+    time::Duration
+};
+
+use smirk::core::smirk_messages::is_failure;
+use smirk::core::tokenizer::quote;
+
+struct ClientConfig {
+    host: String,
+    port: u16,
+    /// Connect to this unix socket instead of host and port.
+    socket: Option<String>,
+    command: Vec<String>
+}
+
+impl ClientConfig {
+    fn from_args() -> ClientConfig {
+        let args: Vec<String> = env::args().skip(1).collect();
+        let mut config = ClientConfig {
+            host: String::from("127.0.0.1"),
+            port: 53173,
+            socket: None,
+            command: Vec::new()
+        };
+
+        let mut i = 0;
+        while i < args.len() {
+            if args[i] == "-h" && i + 1 < args.len() {
+                config.host = args[i+1].clone();
+                i += 2;
+            } else if args[i] == "-p" && i + 1 < args.len() {
+                config.port = args[i+1].parse().unwrap_or(config.port);
+                i += 2;
+            } else if args[i] == "-s" && i + 1 < args.len() {
+                config.socket = Some(args[i+1].clone());
+                i += 2;
+            } else {
+                config.command = args[i..].to_vec();
+                break;
+            }
+        }
+        config
+    }
+}
+
+/// Sends a single command followed by QUIT and returns everything the server replied before "Bye.".
+fn run_command(config: &ClientConfig) -> Result<String, String> {
+    let command: Vec<String> = config.command.iter().map(|argument| quote(argument)).collect();
+    let request = format!("{}\nQUIT\n", command.join(" "));
+    #[cfg(unix)]
+    if let Some(socket) = &config.socket {
+        let stream = std::os::unix::net::UnixStream::connect(socket)
+            .map_err(|e| format!("Could not connect to {}: {}", socket, e))?;
+        stream.set_read_timeout(Some(Duration::from_secs(30))).ok();
+        return exchange(stream, &request);
+    }
+    #[cfg(not(unix))]
+    if config.socket.is_some() {
+        return Err(String::from("Unix sockets aren't supported on this platform"));
+    }
+
+    let stream = TcpStream::connect((config.host.as_str(), config.port))
+        .map_err(|e| format!("Could not connect to {}:{}: {}", config.host, config.port, e))?;
+    stream.set_read_timeout(Some(Duration::from_secs(30))).ok();
+    exchange(stream, &request)
+}
+
+fn exchange(mut stream: impl Read + Write, request: &str) -> Result<String, String> {
+    stream.write_all(request.as_bytes()).map_err(|e| format!("Could not send command: {}", e))?;
+
+    let mut reply = Vec::new();
+    stream.read_to_end(&mut reply).map_err(|e| format!("Could not read reply: {}", e))?;
+    let reply = String::from_utf8_lossy(&reply).to_string();
+    Ok(reply.strip_suffix("Bye.\n").unwrap_or(&reply).to_string())
+}
+
 fn main() {
-    println!("Hello, world.");
+    let config = ClientConfig::from_args();
+    if config.command.is_empty() {
+        eprintln!("Usage: smirk-client [-h host] [-p port | -s socket] <command> [args...]");
+        exit(2);
+    }
+
+    match run_command(&config) {
+        Ok(reply) if reply.starts_with("-ERR ") || is_failure(reply.trim_end()) => {
+            eprint!("{}", reply);
+            exit(1);
+        }
+        Ok(reply) => {
+            print!("{}", reply);
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            exit(1);
+        }
+    }
 }
@@ -0,0 +1,67 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+use serde_json::Value;
+
+/// Somewhere records live besides the map, so smirk can run as a cache in front of a database.
+///
+/// Records cross over as `snapshot::record_to_json` descriptions. The server loads a key from the
+/// store when a command names one the map doesn't have, and hands the store every key a command
+/// writes or deletes. Keys that only expire are left alone in the store, and aren't loaded from
+/// it once they have.
+pub trait BackingStore: Send + Sync {
+    /// The record at key, or `None` if the store doesn't have it either.
+    fn load(&self, key: &str) -> Result<Option<Value>, String>;
+
+    /// Saves a record that was just written.
+    fn store(&self, key: &str, record: &Value) -> Result<(), String>;
+
+    /// Forgets a key that was just deleted. Keys the store never had aren't an error.
+    fn remove(&self, key: &str) -> Result<(), String>;
+}
+
+/// A BackingStore keeping every record as a JSON file in one directory.
+///
+/// Files are named after the SHA-1 of their key, so keys of any length or content make valid
+/// file names.
+pub struct DirectoryStore {
+    dir: PathBuf
+}
+
+impl DirectoryStore {
+    /// Keeps records in `dir`, creating it if it doesn't exist.
+    pub fn new(dir: &str) -> Result<DirectoryStore, String> {
+        fs::create_dir_all(dir).map_err(|e| format!("Can't use \"{}\" as a backing store: {}", dir, e))?;
+        Ok(DirectoryStore { dir: PathBuf::from(dir) })
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", sha1_smol::Sha1::from(key).digest()))
+    }
+}
+
+impl BackingStore for DirectoryStore {
+    fn load(&self, key: &str) -> Result<Option<Value>, String> {
+        match fs::read(self.path(key)) {
+            Ok(bytes) => serde_json::from_slice(&bytes).map(Some).map_err(|e| e.to_string()),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.to_string())
+        }
+    }
+
+    fn store(&self, key: &str, record: &Value) -> Result<(), String> {
+        // Written aside and renamed into place, so a crash never leaves half a record behind.
+        let path = self.path(key);
+        let partial = path.with_extension("json.tmp");
+        fs::write(&partial, record.to_string()).map_err(|e| e.to_string())?;
+        fs::rename(&partial, &path).map_err(|e| e.to_string())
+    }
+
+    fn remove(&self, key: &str) -> Result<(), String> {
+        match fs::remove_file(self.path(key)) {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e.to_string()),
+            _ => Ok(())
+        }
+    }
+}
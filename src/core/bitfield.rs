@@ -0,0 +1,183 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// Offsets past this many bits are refused, capping a BITFIELD record at 512MB.
+pub const MAX_BITS: u64 = 1 << 32;
+
+/// The width and signedness of a BITFIELD integer, written `i<bits>` (1 to 64) or `u<bits>` (1 to 63).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BitFieldType {
+    pub signed: bool,
+    pub bits: u32
+}
+
+impl BitFieldType {
+    fn min(&self) -> i128 {
+        if self.signed { -(1i128 << (self.bits - 1)) } else { 0 }
+    }
+
+    fn max(&self) -> i128 {
+        if self.signed { (1i128 << (self.bits - 1)) - 1 } else { (1i128 << self.bits) - 1 }
+    }
+
+    /// Parses an offset, either a bit number or `#<n>` for the n-th field of this type.
+    pub fn offset(&self, s: &str) -> Result<u64, String> {
+        let offset = match s.strip_prefix('#') {
+            Some(n) => n.parse::<u64>().ok().and_then(|n| n.checked_mul(self.bits as u64)),
+            None => s.parse::<u64>().ok()
+        };
+        offset
+            .filter(|offset| offset + (self.bits as u64) <= MAX_BITS)
+            .ok_or(format!("Invalid offset \"{}\", expected a bit number or #<n> below {}", s, MAX_BITS))
+    }
+}
+
+impl FromStr for BitFieldType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid type \"{}\", expected i1 to i64 or u1 to u63", s);
+        let signed = match s.as_bytes().first() {
+            Some(b'i' | b'I') => true,
+            Some(b'u' | b'U') => false,
+            _ => return Err(invalid())
+        };
+        let bits = s[1..].parse::<u32>().map_err(|_| invalid())?;
+        match (signed, bits) {
+            (true, 1..=64) | (false, 1..=63) => Ok(BitFieldType { signed, bits }),
+            _ => Err(invalid())
+        }
+    }
+}
+
+impl fmt::Display for BitFieldType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", if self.signed { 'i' } else { 'u' }, self.bits)
+    }
+}
+
+/// What SET and INCRBY do with a value that doesn't fit the field.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Overflow {
+    /// Keep the low bits, so the value wraps around.
+    Wrap,
+    /// Clamp to the smallest or largest value the field holds.
+    Sat,
+    /// Leave the field alone and reply with a null.
+    Fail
+}
+
+impl FromStr for Overflow {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_uppercase().as_str() {
+            "WRAP" => Ok(Overflow::Wrap),
+            "SAT" => Ok(Overflow::Sat),
+            "FAIL" => Ok(Overflow::Fail),
+            _ => Err(format!("Invalid overflow \"{}\", expected WRAP, SAT or FAIL", s))
+        }
+    }
+}
+
+impl fmt::Display for Overflow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Overflow::Wrap => write!(f, "WRAP"),
+            Overflow::Sat => write!(f, "SAT"),
+            Overflow::Fail => write!(f, "FAIL")
+        }
+    }
+}
+
+/// One step of a BITFIELD command. Offsets are in bits from the start of the record.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BitFieldOp {
+    Get(BitFieldType, u64),
+    /// Type, offset and the new value.
+    Set(BitFieldType, u64, i64),
+    /// Type, offset and the increment.
+    IncrBy(BitFieldType, u64, i64),
+    /// Applies to the SETs and INCRBYs after it, until the next OVERFLOW.
+    Overflow(Overflow)
+}
+
+impl BitFieldOp {
+    pub fn writes(&self) -> bool {
+        matches!(self, BitFieldOp::Set(..) | BitFieldOp::IncrBy(..))
+    }
+
+    /// Whether the step has a reply, which all but OVERFLOW do.
+    pub fn replies(&self) -> bool {
+        !matches!(self, BitFieldOp::Overflow(_))
+    }
+}
+
+/// Reads the field at `offset`, most significant bit first. Bits past the end of `bytes` are 0.
+pub fn get(bytes: &[u8], ty: BitFieldType, offset: u64) -> i64 {
+    let mut value: u64 = 0;
+    for bit in offset..offset + ty.bits as u64 {
+        let byte = bytes.get((bit / 8) as usize).copied().unwrap_or(0);
+        value = (value << 1) | ((byte >> (7 - bit % 8)) & 1) as u64;
+    }
+    if ty.signed && ty.bits < 64 && (value >> (ty.bits - 1)) & 1 == 1 {
+        // Sign extend from the field's top bit.
+        value |= u64::MAX << ty.bits;
+    }
+    value as i64
+}
+
+/// Writes the low `ty.bits` bits of `value` at `offset`, growing `bytes` with zeros if needed.
+fn put(bytes: &mut Vec<u8>, ty: BitFieldType, offset: u64, value: i64) {
+    let end = (offset + ty.bits as u64).div_ceil(8) as usize;
+    if bytes.len() < end {
+        bytes.resize(end, 0);
+    }
+    for (i, bit) in (offset..offset + ty.bits as u64).enumerate() {
+        let set = ((value as u64) >> (ty.bits as usize - 1 - i)) & 1 == 1;
+        let mask = 1 << (7 - bit % 8);
+        let byte = &mut bytes[(bit / 8) as usize];
+        if set { *byte |= mask } else { *byte &= !mask }
+    }
+}
+
+/// Fits `value` into the field following `overflow`, `None` if it doesn't fit and that's a failure.
+pub fn fit(ty: BitFieldType, value: i128, overflow: Overflow) -> Option<i64> {
+    if (ty.min()..=ty.max()).contains(&value) {
+        return Some(value as i64);
+    }
+    match overflow {
+        Overflow::Wrap => Some(((value - ty.min()).rem_euclid(1i128 << ty.bits) + ty.min()) as i64),
+        Overflow::Sat => Some(value.clamp(ty.min(), ty.max()) as i64),
+        Overflow::Fail => None
+    }
+}
+
+/// Runs the steps against `bytes` in order. Each GET replies with the value, SET with the old
+/// value and INCRBY with the new one, or `None` where OVERFLOW FAIL stopped a write.
+pub fn apply(bytes: &mut Vec<u8>, ops: &[BitFieldOp]) -> Vec<Option<i64>> {
+    let mut overflow = Overflow::Wrap;
+    let mut replies = Vec::new();
+    for op in ops {
+        match *op {
+            BitFieldOp::Get(ty, offset) => replies.push(Some(get(bytes, ty, offset))),
+            BitFieldOp::Set(ty, offset, value) => {
+                let old = get(bytes, ty, offset);
+                let new = fit(ty, value as i128, overflow);
+                if let Some(new) = new {
+                    put(bytes, ty, offset, new);
+                }
+                replies.push(new.map(|_| old));
+            }
+            BitFieldOp::IncrBy(ty, offset, increment) => {
+                let new = fit(ty, get(bytes, ty, offset) as i128 + increment as i128, overflow);
+                if let Some(new) = new {
+                    put(bytes, ty, offset, new);
+                }
+                replies.push(new);
+            }
+            BitFieldOp::Overflow(o) => overflow = o
+        }
+    }
+    replies
+}
@@ -0,0 +1,110 @@
+use super::hyper_log_log::hash;
+
+/// The error rate BF.ADD reserves new filters with.
+pub const DEFAULT_ERROR_RATE: f64 = 0.01;
+/// The capacity BF.ADD reserves new filters with.
+pub const DEFAULT_CAPACITY: u64 = 100;
+/// The most bits a filter may have, 512 MiB of them like the longest string.
+pub const MAX_BITS: u64 = 1 << 32;
+
+/// A Bloom filter, answering whether an element might have been added, with no false negatives
+/// and false positives at about the error rate it was sized for.
+///
+/// Its size is fixed when it's reserved, so adding more elements than its capacity still works
+/// but makes false positives more likely than the error rate.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BloomFilter {
+    bits: Vec<u64>,
+    bit_count: u64,
+    hashes: u32,
+    capacity: u64,
+    error_rate: f64,
+    items: u64
+}
+
+impl Default for BloomFilter {
+    fn default() -> Self {
+        BloomFilter::new(DEFAULT_CAPACITY, DEFAULT_ERROR_RATE).unwrap()
+    }
+}
+
+impl BloomFilter {
+    /// A filter sized to hold `capacity` elements with false positives at about `error_rate`.
+    pub fn new(capacity: u64, error_rate: f64) -> Result<BloomFilter, String> {
+        let bit_count = BloomFilter::bits_for(capacity, error_rate)?;
+        let ln2 = std::f64::consts::LN_2;
+        let hashes = ((bit_count as f64 / capacity as f64) * ln2).round().max(1.0) as u32;
+        Ok(BloomFilter { bits: vec![0; bit_count.div_ceil(64) as usize], bit_count, hashes, capacity, error_rate, items: 0 })
+    }
+
+    /// How many bits a filter for `capacity` elements at `error_rate` has, without making one.
+    pub fn bits_for(capacity: u64, error_rate: f64) -> Result<u64, String> {
+        if capacity == 0 {
+            return Err(String::from("capacity must be at least 1"));
+        }
+        if !(error_rate > 0.0 && error_rate < 1.0) {
+            return Err(String::from("error rate must be between 0 and 1"));
+        }
+        let ln2 = std::f64::consts::LN_2;
+        let bits = (-(capacity as f64) * error_rate.ln() / (ln2 * ln2)).ceil().max(64.0);
+        if bits > MAX_BITS as f64 {
+            return Err(format!("it would need more than the {} bits a filter may have", MAX_BITS));
+        }
+        Ok(bits as u64)
+    }
+
+    /// Puts back a filter as it was saved.
+    pub fn from_parts(capacity: u64, error_rate: f64, items: u64, bits: Vec<u64>) -> Result<BloomFilter, String> {
+        let mut filter = BloomFilter::new(capacity, error_rate)?;
+        if bits.len() != filter.bits.len() {
+            return Err(format!("expected {} words of bits, found {}", filter.bits.len(), bits.len()));
+        }
+        filter.bits = bits;
+        filter.items = items;
+        Ok(filter)
+    }
+
+    pub fn capacity(&self) -> u64 {
+        self.capacity
+    }
+
+    pub fn error_rate(&self) -> f64 {
+        self.error_rate
+    }
+
+    /// How many elements were added that the filter didn't already seem to have.
+    pub fn items(&self) -> u64 {
+        self.items
+    }
+
+    pub fn bits(&self) -> &[u64] {
+        &self.bits
+    }
+
+    /// The bit positions for an element, by double hashing.
+    fn positions(&self, element: &[u8]) -> impl Iterator<Item = u64> + '_ {
+        let first = hash(element);
+        let second = hash(&first.to_le_bytes()) | 1;
+        (0..self.hashes as u64).map(move |i| first.wrapping_add(i.wrapping_mul(second)) % self.bit_count)
+    }
+
+    /// Adds an element. Returns true if it wasn't already in the filter, as far as it can tell.
+    pub fn add(&mut self, element: &[u8]) -> bool {
+        let positions: Vec<u64> = self.positions(element).collect();
+        let mut added = false;
+        for position in positions {
+            let (word, bit) = ((position / 64) as usize, position % 64);
+            added |= self.bits[word] & (1 << bit) == 0;
+            self.bits[word] |= 1 << bit;
+        }
+        if added {
+            self.items += 1;
+        }
+        added
+    }
+
+    /// Whether the element may have been added. False means it definitely wasn't.
+    pub fn contains(&self, element: &[u8]) -> bool {
+        self.positions(element).all(|position| self.bits[(position / 64) as usize] & (1 << (position % 64)) != 0)
+    }
+}
@@ -0,0 +1,393 @@
+use std::any::type_name;
+use std::fmt::Display;
+use std::io::Write;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+
+use bigdecimal::BigDecimal;
+use num::{BigInt, CheckedAdd, CheckedDiv, CheckedMul, CheckedSub, Float, ToPrimitive, Zero};
+use serde_json::Value;
+
+use super::float_format::{FloatFormat, FloatFormattable};
+use super::record::{Null, SharedValue};
+use super::smirk_map::{SmirkMap, normalize_float_literal};
+use super::smirk_messages::SmirkMessages;
+use super::timeseries::Aggregation;
+use super::vector::Vector;
+
+/// Which of ADD, SUB, MUL and DIV a codec is asked to run. ADD carries whether missing keys count
+/// as zero.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Arithmetic {
+    Add(bool),
+    Sub,
+    Mul,
+    Div
+}
+
+/// A type SET and GET can be given by name. The built-in types are `CODECS`, and embedders
+/// can add types of their own with `register`.
+pub trait ValueType: Send + Sync {
+    /// The Rust type values are stored as, which records keep. `std::any::type_name` gives it.
+    fn rust_type(&self) -> &'static str;
+
+    /// Parses SET's value, `None` if it isn't one of these.
+    fn parse(&self, value: &[u8]) -> Option<SharedValue>;
+
+    /// The bytes GET writes for a stored value, `None` if it's another type.
+    fn serialize(&self, value: &SharedValue, format: &FloatFormat) -> Option<Vec<u8>>;
+
+    /// Runs ADD, SUB, MUL or DIV over the values at keys. `None` means the type has no arithmetic.
+    fn arithmetic(&self, _smirk_map: &SmirkMap, _arithmetic: Arithmetic, _keys: Vec<String>) -> Option<Result<SharedValue, SmirkMessages>> {
+        None
+    }
+
+    /// Runs AGG over the values at keys, leaving out those of another type with `skip_mismatched`.
+    /// `None` means the type can't be aggregated.
+    fn aggregate(
+        &self,
+        _smirk_map: &SmirkMap,
+        _aggregation: Aggregation,
+        _keys: &[String],
+        _skip_mismatched: bool,
+        _format: &FloatFormat
+    ) -> Option<Result<String, SmirkMessages>> {
+        None
+    }
+
+    /// Appends what `serialize` gives to `out`, returning false if the value is another type.
+    fn write(&self, value: &SharedValue, format: &FloatFormat, out: &mut Vec<u8>) -> bool {
+        match self.serialize(value, format) {
+            Some(serialized) => {
+                out.extend_from_slice(&serialized);
+                true
+            }
+            None => false
+        }
+    }
+
+    /// Serializes the value at key, failing the way `downcast` does if it isn't this type.
+    fn render(&self, key: &str, value: &SharedValue, format: &FloatFormat) -> Result<Vec<u8>, SmirkMessages> {
+        match self.serialize(value, format) {
+            Some(serialized) => Ok(serialized),
+            None if value.is::<Null>() => Err(SmirkMessages::NullValue(String::from(key))),
+            None => Err(SmirkMessages::TypeMismatch(String::from(key), self.rust_type().to_string()))
+        }
+    }
+}
+
+/// Runs one of ADD, SUB, MUL and DIV over the values at keys.
+pub type ArithmeticFn = fn(&SmirkMap, Arithmetic, Vec<String>) -> Result<SharedValue, SmirkMessages>;
+
+/// Runs AGG over the values at keys.
+pub type AggregateFn = fn(&SmirkMap, Aggregation, &[String], bool, &FloatFormat) -> Result<String, SmirkMessages>;
+
+/// How SET, GET and the arithmetic commands handle one of the built-in type names.
+///
+/// Supporting a new built-in type is one entry in `CODECS`. Type names without a codec or a
+/// registered type are stored as binary values.
+pub struct Codec {
+    pub name: &'static str,
+    pub rust_type: fn() -> &'static str,
+    pub parser: fn(&[u8]) -> Option<SharedValue>,
+    pub renderer: fn(&SharedValue, &FloatFormat) -> Option<String>,
+    /// Writes the same text as `renderer` into a reply, without building a `String` first.
+    pub writer: fn(&SharedValue, &FloatFormat, &mut Vec<u8>) -> bool,
+    /// `None` for types there's no arithmetic on.
+    pub arithmetic: Option<ArithmeticFn>,
+    /// `None` for types AGG can't total.
+    pub aggregate: Option<AggregateFn>
+}
+
+impl ValueType for Codec {
+    fn rust_type(&self) -> &'static str {
+        (self.rust_type)()
+    }
+
+    fn parse(&self, value: &[u8]) -> Option<SharedValue> {
+        (self.parser)(value)
+    }
+
+    fn serialize(&self, value: &SharedValue, format: &FloatFormat) -> Option<Vec<u8>> {
+        (self.renderer)(value, format).map(String::into_bytes)
+    }
+
+    fn arithmetic(&self, smirk_map: &SmirkMap, arithmetic: Arithmetic, keys: Vec<String>) -> Option<Result<SharedValue, SmirkMessages>> {
+        self.arithmetic.map(|run| run(smirk_map, arithmetic, keys))
+    }
+
+    fn aggregate(
+        &self,
+        smirk_map: &SmirkMap,
+        aggregation: Aggregation,
+        keys: &[String],
+        skip_mismatched: bool,
+        format: &FloatFormat
+    ) -> Option<Result<String, SmirkMessages>> {
+        self.aggregate.map(|run| run(smirk_map, aggregation, keys, skip_mismatched, format))
+    }
+
+    fn write(&self, value: &SharedValue, format: &FloatFormat, out: &mut Vec<u8>) -> bool {
+        (self.writer)(value, format, out)
+    }
+}
+
+fn parse<T: FromStr + Send + Sync + 'static>(value: &[u8]) -> Option<SharedValue> {
+    String::from_utf8_lossy(value).parse::<T>().ok().map(|value| Arc::new(value) as SharedValue)
+}
+
+/// Floats can also be written as hexadecimal literals, like `0x1.8p+1`.
+fn parse_float<T: FromStr + Send + Sync + 'static>(value: &[u8]) -> Option<SharedValue> {
+    parse::<T>(&normalize_float_literal(value.to_vec()))
+}
+
+fn display<T: Display + 'static>(value: &SharedValue, _: &FloatFormat) -> Option<String> {
+    value.downcast_ref::<T>().map(|value| value.to_string())
+}
+
+fn display_float<T: FloatFormattable + 'static>(value: &SharedValue, format: &FloatFormat) -> Option<String> {
+    value.downcast_ref::<T>().map(|value| value.format_with(format))
+}
+
+fn write_display<T: Display + 'static>(value: &SharedValue, _: &FloatFormat, out: &mut Vec<u8>) -> bool {
+    match value.downcast_ref::<T>() {
+        Some(value) => {
+            write!(out, "{}", value).unwrap();
+            true
+        }
+        None => false
+    }
+}
+
+/// Integers are formatted with itoa on the stack rather than through `Display`.
+fn write_integer<T: itoa::Integer + 'static>(value: &SharedValue, _: &FloatFormat, out: &mut Vec<u8>) -> bool {
+    match value.downcast_ref::<T>() {
+        Some(value) => {
+            out.extend_from_slice(itoa::Buffer::new().format(*value).as_bytes());
+            true
+        }
+        None => false
+    }
+}
+
+/// Floats are formatted with ryu, which writes the same shortest round-trip digits as `Display`
+/// but much quicker. Ryu switches to scientific notation for very large and very small values and
+/// always writes a fractional part, so those go through `Display` and a trailing `.0` is dropped.
+/// Formats other than the default are left to `FloatFormattable`.
+fn write_float<T: ryu::Float + Float + FloatFormattable + Display + 'static>(
+    value: &SharedValue,
+    format: &FloatFormat,
+    out: &mut Vec<u8>
+) -> bool {
+    let Some(value) = value.downcast_ref::<T>() else {
+        return false;
+    };
+    let mut buffer = ryu::Buffer::new();
+    match (*format == FloatFormat::Default && value.is_finite()).then(|| buffer.format_finite(*value)) {
+        Some(formatted) if !formatted.contains('e') => out.extend_from_slice(formatted.strip_suffix(".0").unwrap_or(formatted).as_bytes()),
+        _ if *format == FloatFormat::Default => write!(out, "{}", value).unwrap(),
+        _ => out.extend_from_slice(value.format_with(format).as_bytes())
+    }
+    true
+}
+
+/// Arithmetic for the integer types, failing rather than overflowing.
+fn checked<
+    T: CheckedAdd<Output = T> + CheckedSub<Output = T> + CheckedMul<Output = T> + CheckedDiv<Output = T>
+        + Zero + Default + Clone + Display + Send + Sync + 'static
+>(
+    smirk_map: &SmirkMap,
+    arithmetic: Arithmetic,
+    keys: Vec<String>
+) -> Result<SharedValue, SmirkMessages> {
+    let total = match arithmetic {
+        Arithmetic::Add(skip_missing) => smirk_map.add::<T>(keys, skip_missing),
+        Arithmetic::Sub => smirk_map.sub::<T>(keys),
+        Arithmetic::Mul => smirk_map.mul::<T>(keys),
+        Arithmetic::Div => smirk_map.div::<T>(keys)
+    }?;
+    Ok(Arc::new(total))
+}
+
+/// Arithmetic for f32 and f64, which follows IEEE 754 rather than failing.
+fn float<T: Float + Default + Send + Sync + 'static>(
+    smirk_map: &SmirkMap,
+    arithmetic: Arithmetic,
+    keys: Vec<String>
+) -> Result<SharedValue, SmirkMessages> {
+    let total = match arithmetic {
+        Arithmetic::Add(skip_missing) => smirk_map.sum::<T>(keys, skip_missing),
+        Arithmetic::Sub => smirk_map.fold_float::<T>(keys, |a, b| a - b),
+        Arithmetic::Mul => smirk_map.fold_float::<T>(keys, |a, b| a * b),
+        Arithmetic::Div => smirk_map.fold_float::<T>(keys, |a, b| a / b)
+    }?;
+    Ok(Arc::new(total))
+}
+
+fn decimal(
+    smirk_map: &SmirkMap,
+    arithmetic: Arithmetic,
+    keys: Vec<String>
+) -> Result<SharedValue, SmirkMessages> {
+    let total = match arithmetic {
+        Arithmetic::Add(skip_missing) => smirk_map.sum::<BigDecimal>(keys, skip_missing),
+        Arithmetic::Sub => smirk_map.fold::<BigDecimal, _>(keys, |a, b, _| Ok(a - b)),
+        Arithmetic::Mul => smirk_map.fold::<BigDecimal, _>(keys, |a, b, _| Ok(a * b)),
+        Arithmetic::Div => smirk_map.fold::<BigDecimal, _>(keys, |a, b, key| {
+            if b.is_zero() {
+                return Err(SmirkMessages::DivideByZeroError(key.clone()));
+            }
+            Ok(a / b)
+        })
+    }?;
+    Ok(Arc::new(total))
+}
+
+/// AGG's result over `values`, shown with `show`. `sum` adds them up as the type and `mean`
+/// turns their total into the average.
+fn aggregate<T: PartialOrd>(
+    aggregation: Aggregation,
+    values: &[&T],
+    sum: impl Fn(&[&T]) -> Result<T, SmirkMessages>,
+    mean: impl Fn(&T) -> String,
+    show: impl Fn(&T) -> String
+) -> Result<String, SmirkMessages> {
+    match aggregation {
+        Aggregation::Count => Ok(values.len().to_string()),
+        Aggregation::Sum => sum(values).map(|total| show(&total)),
+        _ if values.is_empty() => Ok(String::from("No values to aggregate.")),
+        Aggregation::Avg => sum(values).map(|total| mean(&total)),
+        Aggregation::Min => Ok(values.iter().copied().reduce(|a, b| if b < a { b } else { a }).map(&show).unwrap_or_default()),
+        Aggregation::Max => Ok(values.iter().copied().reduce(|a, b| if b > a { b } else { a }).map(&show).unwrap_or_default())
+    }
+}
+
+/// AGG for the integer types. Totals fail rather than overflow, and averages are floats.
+fn checked_aggregate<T: CheckedAdd + Zero + PartialOrd + Display + ToPrimitive + 'static>(
+    smirk_map: &SmirkMap,
+    aggregation: Aggregation,
+    keys: &[String],
+    skip_mismatched: bool,
+    format: &FloatFormat
+) -> Result<String, SmirkMessages> {
+    let values = smirk_map.operands::<T>(keys, skip_mismatched)?;
+    aggregate(
+        aggregation,
+        &values,
+        |values| values.iter().try_fold(T::zero(), |total, value| total.checked_add(value).ok_or(SmirkMessages::AddOverflowError())),
+        |total| (total.to_f64().unwrap_or(f64::NAN) / values.len() as f64).format_with(format),
+        |value| value.to_string()
+    )
+}
+
+fn float_aggregate<T: Float + FloatFormattable + 'static>(
+    smirk_map: &SmirkMap,
+    aggregation: Aggregation,
+    keys: &[String],
+    skip_mismatched: bool,
+    format: &FloatFormat
+) -> Result<String, SmirkMessages> {
+    let values = smirk_map.operands::<T>(keys, skip_mismatched)?;
+    aggregate(
+        aggregation,
+        &values,
+        |values| Ok(values.iter().fold(T::zero(), |total, value| total + **value)),
+        |total| (*total / T::from(values.len()).unwrap_or(T::nan())).format_with(format),
+        |value| value.format_with(format)
+    )
+}
+
+fn decimal_aggregate(
+    smirk_map: &SmirkMap,
+    aggregation: Aggregation,
+    keys: &[String],
+    skip_mismatched: bool,
+    _: &FloatFormat
+) -> Result<String, SmirkMessages> {
+    let values = smirk_map.operands::<BigDecimal>(keys, skip_mismatched)?;
+    aggregate(
+        aggregation,
+        &values,
+        |values| Ok(values.iter().fold(BigDecimal::zero(), |total, value| total + *value)),
+        |total| (total / BigDecimal::from(values.len() as u64)).to_string(),
+        |value| value.to_string()
+    )
+}
+
+macro_rules! codec {
+    ($name:literal, $ty:ty, $parser:ident, $renderer:ident, $writer:ident) => {
+        codec!(@ $name, $ty, $parser, $renderer, $writer, None, None)
+    };
+    ($name:literal, $ty:ty, $parser:ident, $renderer:ident, $writer:ident, $arithmetic:expr, $aggregate:expr) => {
+        codec!(@ $name, $ty, $parser, $renderer, $writer, Some($arithmetic), Some($aggregate))
+    };
+    (@ $name:literal, $ty:ty, $parser:ident, $renderer:ident, $writer:ident, $arithmetic:expr, $aggregate:expr) => {
+        Codec {
+            name: $name,
+            rust_type: type_name::<$ty>,
+            parser: $parser::<$ty>,
+            renderer: $renderer::<$ty>,
+            writer: $writer::<$ty>,
+            arithmetic: $arithmetic,
+            aggregate: $aggregate
+        }
+    };
+}
+
+pub static CODECS: [Codec; 21] = [
+    codec!("i8", i8, parse, display, write_integer, checked::<i8>, checked_aggregate::<i8>),
+    codec!("i16", i16, parse, display, write_integer, checked::<i16>, checked_aggregate::<i16>),
+    codec!("i32", i32, parse, display, write_integer, checked::<i32>, checked_aggregate::<i32>),
+    codec!("i64", i64, parse, display, write_integer, checked::<i64>, checked_aggregate::<i64>),
+    codec!("i128", i128, parse, display, write_integer, checked::<i128>, checked_aggregate::<i128>),
+    codec!("u8", u8, parse, display, write_integer, checked::<u8>, checked_aggregate::<u8>),
+    codec!("u16", u16, parse, display, write_integer, checked::<u16>, checked_aggregate::<u16>),
+    codec!("u32", u32, parse, display, write_integer, checked::<u32>, checked_aggregate::<u32>),
+    codec!("u64", u64, parse, display, write_integer, checked::<u64>, checked_aggregate::<u64>),
+    codec!("u128", u128, parse, display, write_integer, checked::<u128>, checked_aggregate::<u128>),
+    codec!("isize", isize, parse, display, write_integer, checked::<isize>, checked_aggregate::<isize>),
+    codec!("usize", usize, parse, display, write_integer, checked::<usize>, checked_aggregate::<usize>),
+    codec!("BigInt", BigInt, parse, display, write_display, checked::<BigInt>, checked_aggregate::<BigInt>),
+    codec!("BigDecimal", BigDecimal, parse, display, write_display, decimal, decimal_aggregate),
+    codec!("Json", Value, parse, display, write_display),
+    codec!("Vector", Vector, parse, display, write_display),
+    codec!("f32", f32, parse_float, display_float, write_float, float::<f32>, float_aggregate::<f32>),
+    codec!("f64", f64, parse_float, display_float, write_float, float::<f64>, float_aggregate::<f64>),
+    codec!("bool", bool, parse, display, write_display),
+    codec!("char", char, parse, display, write_display),
+    codec!("String", String, parse, display, write_display)
+];
+
+/// Types embedders have added with `register`, by name.
+static REGISTERED: RwLock<Vec<(String, &'static dyn ValueType)>> = RwLock::new(Vec::new());
+
+/// Adds a type of the embedder's own, so SET, GET and DRYRUN take `name` like a built-in type
+/// name, and ADD, SUB, MUL and DIV do too if it has arithmetic. Snapshots save its values as
+/// `serialize` writes them and read them back with `parse`.
+///
+/// Types are registered for the life of the process, so this is meant for startup.
+pub fn register(name: &str, value_type: impl ValueType + 'static) -> Result<(), String> {
+    register_boxed(name, Box::new(value_type))
+}
+
+pub(crate) fn register_boxed(name: &str, value_type: Box<dyn ValueType>) -> Result<(), String> {
+    let mut registered = REGISTERED.write().unwrap();
+    if CODECS.iter().any(|codec| codec.name == name) || registered.iter().any(|(registered, _)| registered == name) {
+        return Err(format!("There's already a type called \"{}\"", name));
+    }
+    registered.push((name.to_string(), Box::leak(value_type)));
+    Ok(())
+}
+
+/// Every type added with `register`.
+pub fn registered() -> Vec<&'static dyn ValueType> {
+    REGISTERED.read().unwrap().iter().map(|(_, value_type)| *value_type).collect()
+}
+
+/// Looks up the type for a type name as clients write it, e.g. `i32` or `BigDecimal`.
+pub fn find(name: &str) -> Option<&'static dyn ValueType> {
+    match CODECS.iter().find(|codec| codec.name == name) {
+        Some(codec) => Some(codec),
+        None => REGISTERED.read().unwrap().iter().find(|(registered, _)| registered == name).map(|(_, value_type)| *value_type)
+    }
+}
@@ -1,71 +1,1160 @@
-use super::smirk_search_mode::SmirkSearchMode;
+use std::fmt;
+use std::time::{Duration, UNIX_EPOCH};
+
+use bigdecimal::BigDecimal;
+
+use super::bitfield::{BitFieldOp, BitFieldType, Overflow};
+use super::float_format::FloatFormat;
+use super::metadata_index::MetadataField;
+use super::geo::{GeoOrigin, GeoSearch, GeoUnit, valid_lon_lat};
+use super::list::End;
+use super::smirk_map::{CasExpected, is_numeric};
+use super::smirk_search_mode::{KeyOrder, KeyPattern, SmirkSearchMode};
+use super::snapshot::SnapshotFormat;
+use super::stream::{StreamFields, StreamId};
+use super::timeseries::Aggregation;
+use super::vector::{Vector, VectorIndex, VectorMetric};
 
 use super::command_error::CommandError;
+use super::command_spec;
+use super::module;
+use super::tokenizer::tokenize;
+
+/// A password given to AUTH. Its Debug leaves the password out, so commands can be logged.
+pub struct Password(pub String);
+
+impl fmt::Debug for Password {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("***")
+    }
+}
 
 #[derive(Debug)]
 pub enum Command {
-    Set(String, String, Vec<u8>),
-    Get(String, String),
+    /// Type, key, value and the EX TTL in seconds.
+    Set(String, String, Vec<u8>, Option<u64>),
+    Get(String, String, Option<Vec<u8>>),
+    GetAny(String, Option<Vec<u8>>),
+    /// Optional type and key. Replies like GET, then deletes the key.
+    GetDel(Option<String>, String),
+    /// Optional type, key, and a new TTL in seconds, `Some(None)` to remove it or `None` to leave it.
+    GetEx(Option<String>, String, Option<Option<u64>>),
+    /// Type, key, what the record must still be and the new value.
+    SetCas(String, String, CasExpected, Vec<u8>),
+    /// Key, the most requests allowed, and the window they're counted over in seconds.
+    RateLimit(String, u64, u64),
+    /// Key, token and TTL in seconds.
+    SetLock(String, String, u64),
+    /// Key and token.
+    ReleaseLock(String, String),
+    /// Key, token and the new TTL in seconds.
+    ExtendLock(String, String, u64),
+    SetNull(String, String),
+    Cast(String, String),
+    JsonGet(String, String),
+    /// Key and the first and last byte positions, negative ones counting from the end.
+    GetRange(String, i64, i64),
+    GetChunked(String),
+    /// Key, byte offset and the bytes to write there.
+    SetRange(String, usize, Vec<u8>),
+    /// Key, width and signedness, overflow policy and starting value.
+    Counter(String, BitFieldType, Overflow, i64),
+    /// INCR, DECR, INCRBY and DECRBY: the key and how much to add.
+    IncrBy(String, i64),
+    /// Key, error rate and capacity.
+    BfReserve(String, f64, u64),
+    BfAdd(String, String),
+    BfExists(String, String),
+    /// Key and retention period in milliseconds.
+    TsCreate(String, Option<u64>),
+    /// Key, timestamp in milliseconds (`None` for `*`) and value.
+    TsAdd(String, Option<u64>, f64),
+    /// Key, first and last timestamps, and how to bucket the samples, if at all.
+    TsRange(String, u64, u64, Option<(Aggregation, u64)>),
+    PfAdd(String, Vec<String>),
+    BitField(String, Vec<BitFieldOp>),
+    PfCount(Vec<String>),
+    PfMerge(String, Vec<String>),
+    IndexCreate(String, MetadataField),
+    /// Index name and the field value to look up.
+    IndexQuery(String, String),
+    IndexDrop(String),
+    IndexList,
+    VIndexCreate(String, VectorIndex),
+    VIndexDrop(String),
+    VIndexList,
+    /// Index name, K and the query vector.
+    VSearch(String, usize, Vector),
+    /// Key and `(longitude, latitude, member)` triples.
+    GeoAdd(String, Vec<(f64, f64, String)>),
+    GeoDist(String, String, String, GeoUnit),
+    GeoSearch(String, GeoSearch),
+    /// Key, explicit ID (`None` for `*`) and the entry's fields.
+    XAdd(String, Option<StreamId>, StreamFields),
+    /// Key, start ID, end ID and COUNT.
+    XRange(String, StreamId, StreamId, Option<usize>),
+    /// COUNT, BLOCK milliseconds and the streams to read, each with the ID to read after (`None` for `$`).
+    XRead(Option<usize>, Option<u64>, Vec<(String, Option<StreamId>)>),
+    /// Group, consumer, COUNT, BLOCK milliseconds and the streams to read, each with the ID to
+    /// read the consumer's pending entries after (`None` for `>`, entries new to the group).
+    XReadGroup(String, String, Option<usize>, Option<u64>, Vec<(String, Option<StreamId>)>),
+    /// Key, group, the ID to deliver entries after (`None` for `$`) and MKSTREAM.
+    XGroupCreate(String, String, Option<StreamId>, bool),
+    XGroupDestroy(String, String),
+    /// Key, group and the IDs to acknowledge.
+    XAck(String, String, Vec<StreamId>),
+    /// Key, group, consumer, the minimum idle milliseconds and the IDs to claim.
+    XClaim(String, String, String, u64, Vec<StreamId>),
+    /// Key and group.
+    XPending(String, String),
+    ZAdd(String, Vec<(f64, String)>),
+    ZScore(String, String),
+    /// Key, start rank, stop rank and whether to include scores.
+    ZRange(String, i64, i64, bool),
+    /// Key, min score, max score and whether to include scores.
+    ZRangeByScore(String, f64, f64, bool),
+    ZRem(String, Vec<String>),
+    ZIncrBy(String, f64, String),
+    /// LPUSH or RPUSH: the end, key and elements.
+    Push(End, String, Vec<String>),
+    /// LPOP or RPOP.
+    Pop(End, String),
+    /// BLPOP or BRPOP: the end, the keys, and how many milliseconds to wait, 0 for forever.
+    BlockingPop(End, Vec<String>, u64),
+    LLen(String),
+    /// Key, start index and stop index.
+    LRange(String, i64, i64),
+    JsonSet(String, String, Vec<u8>),
     Del(Vec<String>),
-    Keys(String),
+    /// DEL BYTAG: deletes every key carrying the tag.
+    DelByTag(String),
+    /// Deletes every key matching the pattern in one go.
+    DelPattern(KeyPattern),
+    /// Sets the TTL of every key matching the pattern, or removes it with `None`.
+    ExpirePattern(KeyPattern, Option<u64>),
+    TagAdd(String, Vec<String>),
+    TagDel(String, Vec<String>),
+    /// Every key carrying the tag.
+    TagKeys(String),
+    /// Every tag on the key.
+    TagList(String),
+    /// Pattern, order, how many matches to skip (OFFSET) and how many to list after that (LIMIT).
+    Keys(KeyPattern, KeyOrder, usize, Option<usize>),
     Mode(SmirkSearchMode),
     TtlGet(String),
     TtlSet(String, Option<u64>),
     Exists(String),
+    /// Keys and the script source.
+    Eval(Vec<String>, String),
+    /// Script SHA1, keys and arguments.
+    EvalSha(String, Vec<String>, Vec<String>),
+    ScriptLoad(String),
+    ScriptExists(Vec<String>),
+    ScriptFlush,
+    Touch(Vec<String>),
+    /// Key and how many old values to list, all of them if `None`.
+    History(String, Option<usize>),
+    /// Key and how many old values to keep for it.
+    KeepHistory(String, usize),
+    RestoreVersion(String, u64),
+    Multi,
+    Exec,
+    Discard,
+    Watch(Vec<String>),
+    Unwatch,
     Type(String),
+    Object(String),
+    /// When the key was created, last written and last read.
+    Stat(String),
     Quit,
     Save,
-    Add(String,Vec<String>)
+    /// File path and format, guessed from the extension if `None`.
+    Export(String, Option<SnapshotFormat>),
+    Import(String, Option<SnapshotFormat>),
+    /// Snapshot to compare the keyspace with, the save file if `None`, and its format.
+    Verify(Option<String>, Option<SnapshotFormat>),
+    Dump(String),
+    /// Key, TTL (`None` for 0), the DUMP blob and whether an existing key may be replaced.
+    Restore(String, Option<u64>, String, bool),
+    /// Host, port, key and whether to delete the local copy afterwards.
+    Migrate(String, u16, String, bool),
+    /// Type, keys and whether missing keys count as zero (SKIPMISSING).
+    Add(String, Vec<String>, bool),
+    Sub(String,Vec<String>),
+    Mul(String,Vec<String>),
+    Div(String,Vec<String>),
+    /// ADDSTORE, SUBSTORE, MULSTORE and DIVSTORE: the arithmetic command, storing its result at the key.
+    Store(String, Box<Command>),
+    ClusterKeySlot(String),
+    ClusterSlots,
+    FormatFloat(FloatFormat),
+    /// Turns checksummed lines and replies on or off for the client.
+    Checksum(bool),
+    Wait(u64, u64),
+    /// Blocks until the key expires or is deleted, for up to the timeout in milliseconds. 0 waits forever.
+    WaitExpire(String, u64),
+    DryRun(Box<Command>),
+    KeysCursor(KeyPattern, KeyOrder, String, u64),
+    /// How to combine the values, their type and the keys holding them.
+    Agg(Aggregation, String, Vec<String>),
+    /// Like `Agg`, over the keys matching a pattern that hold the type.
+    AggMatch(Aggregation, String, KeyPattern),
+    /// Numeric type, lowest and highest value (`None` for unbounded) and LIMIT.
+    ScanValues(String, Option<BigDecimal>, Option<BigDecimal>, Option<usize>),
+    /// Like `ScanValues`, saving the keys to a cursor with a name and TTL instead.
+    ScanValuesCursor(String, Option<BigDecimal>, Option<BigDecimal>, String, u64),
+    CursorPage(String, usize, usize),
+    CursorDel(String),
+    CursorDrop(String),
+    ConfigGet(String),
+    ConfigSet(String, String),
+    SlowLogGet(usize),
+    /// How many of the most accessed keys to list.
+    HotKeys(usize),
+    SlowLogLen,
+    SlowLogReset,
+    /// Turns tracing of the client's own commands on or off.
+    Trace(bool),
+    /// Looks up one trace by its ID.
+    TraceGet(u64),
+    /// How many of the most recent traces to list.
+    TraceList(usize),
+    /// Seconds to hold the server up for, as if the command were slow.
+    DebugSleep(f64),
+    DebugObject(String),
+    /// Expires the key now, whatever its TTL.
+    DebugSetExpireNow(String),
+    ClientId,
+    ClientList,
+    ClientKill(String),
+    Status,
+    Info,
+    /// User name and password.
+    Auth(String, Password),
+    /// PONG, or the message to send back instead.
+    Ping(Option<String>),
+    Echo(String),
+    /// The command to describe, or `None` to list them all.
+    Help(Option<String>),
+    ModuleList,
+    ModuleLoad(String),
+    /// A command a module added: its name, its arguments, and which of them are keys. The keys
+    /// are put back into the arguments when it runs, so namespaces apply to them.
+    Module(String, Vec<Vec<u8>>, Vec<(usize, String)>)
 }
 
+/// COUNT, BLOCK and the streams with their IDs, as XREAD and XREADGROUP take them.
+type StreamReads = (Option<usize>, Option<u64>, Vec<(String, Option<StreamId>)>);
+
 impl Command {
+    /// The keys a command reads or writes, used to route it to the node owning them.
+    pub fn keys(&self) -> Vec<&String> {
+        match self {
+            Command::Set(_, key, _, _)
+            | Command::SetCas(_, key, _, _)
+            | Command::SetLock(key, _, _)
+            | Command::RateLimit(key, _, _)
+            | Command::ReleaseLock(key, _)
+            | Command::ExtendLock(key, _, _)
+            | Command::Get(_, key, _)
+            | Command::GetAny(key, _)
+            | Command::SetNull(_, key)
+            | Command::Cast(key, _)
+            | Command::JsonGet(key, _)
+            | Command::GetRange(key, _, _)
+            | Command::GetChunked(key)
+            | Command::SetRange(key, _, _)
+            | Command::ZAdd(key, _)
+            | Command::XAdd(key, _, _)
+            | Command::GeoAdd(key, _)
+            | Command::GeoDist(key, _, _, _)
+            | Command::GeoSearch(key, _)
+            | Command::XRange(key, _, _, _)
+            | Command::XGroupCreate(key, _, _, _)
+            | Command::XGroupDestroy(key, _)
+            | Command::XAck(key, _, _)
+            | Command::XClaim(key, _, _, _, _)
+            | Command::XPending(key, _)
+            | Command::PfAdd(key, _)
+            | Command::BfReserve(key, _, _)
+            | Command::Counter(key, _, _, _)
+            | Command::IncrBy(key, _)
+            | Command::BfAdd(key, _)
+            | Command::TsCreate(key, _)
+            | Command::TsAdd(key, _, _)
+            | Command::TsRange(key, _, _, _)
+            | Command::BfExists(key, _)
+            | Command::BitField(key, _)
+            | Command::ZScore(key, _)
+            | Command::ZRange(key, _, _, _)
+            | Command::ZRangeByScore(key, _, _, _)
+            | Command::ZRem(key, _)
+            | Command::ZIncrBy(key, _, _)
+            | Command::Push(_, key, _)
+            | Command::Pop(_, key)
+            | Command::LLen(key)
+            | Command::LRange(key, _, _)
+            | Command::JsonSet(key, _, _)
+            | Command::TagAdd(key, _)
+            | Command::TagDel(key, _)
+            | Command::TagList(key)
+            | Command::TtlGet(key)
+            | Command::GetDel(_, key)
+            | Command::GetEx(_, key, _)
+            | Command::TtlSet(key, _)
+            | Command::Exists(key)
+            | Command::Object(key)
+            | Command::Stat(key)
+            | Command::DebugObject(key)
+            | Command::DebugSetExpireNow(key)
+            | Command::WaitExpire(key, _)
+            | Command::History(key, _)
+            | Command::KeepHistory(key, _)
+            | Command::RestoreVersion(key, _)
+            | Command::Dump(key)
+            | Command::Restore(key, _, _, _)
+            | Command::Migrate(_, _, key, _)
+            | Command::Type(key) => vec![key],
+            Command::Del(keys)
+            | Command::Eval(keys, _)
+            | Command::EvalSha(_, keys, _)
+            | Command::Watch(keys)
+            | Command::Touch(keys)
+            | Command::Add(_, keys, _)
+            | Command::Sub(_, keys)
+            | Command::Mul(_, keys)
+            | Command::Div(_, keys)
+            | Command::Agg(_, _, keys)
+            | Command::BlockingPop(_, keys, _) => keys.iter().collect(),
+            Command::PfCount(keys) => keys.iter().collect(),
+            Command::XRead(_, _, streams) | Command::XReadGroup(_, _, _, _, streams) => streams.iter().map(|(key, _)| key).collect(),
+            Command::PfMerge(destination, keys) => {
+                let mut all = vec![destination];
+                all.extend(keys);
+                all
+            }
+            Command::Store(destination, command) => {
+                let mut keys = vec![destination];
+                keys.extend(command.keys());
+                keys
+            }
+            Command::Module(_, _, keys) => keys.iter().map(|(_, key)| key).collect(),
+            Command::DryRun(command) => command.keys(),
+            _ => Vec::new()
+        }
+    }
+
+    /// Moves the command into `namespace` by prefixing every key, tag and cursor name it mentions,
+    /// so clients with different namespaces can share a server without seeing each other's data.
+    pub fn namespace(&mut self, namespace: &str) {
+        if namespace.is_empty() {
+            return;
+        }
+        for name in self.names_mut() {
+            name.insert_str(0, namespace);
+        }
+    }
+
+    /// Every key, tag and cursor name in the command, as `namespace` left them.
+    pub fn names(&mut self) -> Vec<String> {
+        self.names_mut().into_iter().map(|name| name.clone()).collect()
+    }
+
+    /// Every key, tag and cursor name in the command, for `namespace`.
+    fn names_mut(&mut self) -> Vec<&mut String> {
+        match self {
+            Command::Set(_, key, _, _)
+            | Command::SetCas(_, key, _, _)
+            | Command::SetLock(key, _, _)
+            | Command::RateLimit(key, _, _)
+            | Command::ReleaseLock(key, _)
+            | Command::ExtendLock(key, _, _)
+            | Command::Get(_, key, _)
+            | Command::GetAny(key, _)
+            | Command::SetNull(_, key)
+            | Command::Cast(key, _)
+            | Command::JsonGet(key, _)
+            | Command::GetRange(key, _, _)
+            | Command::GetChunked(key)
+            | Command::SetRange(key, _, _)
+            | Command::ZAdd(key, _)
+            | Command::XAdd(key, _, _)
+            | Command::GeoAdd(key, _)
+            | Command::GeoDist(key, _, _, _)
+            | Command::GeoSearch(key, _)
+            | Command::XRange(key, _, _, _)
+            | Command::XGroupCreate(key, _, _, _)
+            | Command::XGroupDestroy(key, _)
+            | Command::XAck(key, _, _)
+            | Command::XClaim(key, _, _, _, _)
+            | Command::XPending(key, _)
+            | Command::PfAdd(key, _)
+            | Command::BfReserve(key, _, _)
+            | Command::Counter(key, _, _, _)
+            | Command::IncrBy(key, _)
+            | Command::BfAdd(key, _)
+            | Command::TsCreate(key, _)
+            | Command::TsAdd(key, _, _)
+            | Command::TsRange(key, _, _, _)
+            | Command::BfExists(key, _)
+            | Command::BitField(key, _)
+            | Command::ZScore(key, _)
+            | Command::ZRange(key, _, _, _)
+            | Command::ZRangeByScore(key, _, _, _)
+            | Command::ZRem(key, _)
+            | Command::ZIncrBy(key, _, _)
+            | Command::Push(_, key, _)
+            | Command::Pop(_, key)
+            | Command::LLen(key)
+            | Command::LRange(key, _, _)
+            | Command::JsonSet(key, _, _)
+            | Command::TagList(key)
+            | Command::TtlGet(key)
+            | Command::GetDel(_, key)
+            | Command::GetEx(_, key, _)
+            | Command::TtlSet(key, _)
+            | Command::Exists(key)
+            | Command::Object(key)
+            | Command::Stat(key)
+            | Command::DebugObject(key)
+            | Command::DebugSetExpireNow(key)
+            | Command::WaitExpire(key, _)
+            | Command::History(key, _)
+            | Command::KeepHistory(key, _)
+            | Command::RestoreVersion(key, _)
+            | Command::Dump(key)
+            | Command::Restore(key, _, _, _)
+            | Command::Migrate(_, _, key, _)
+            | Command::Type(key)
+            | Command::TagKeys(key)
+            | Command::DelByTag(key)
+            | Command::KeysCursor(_, _, key, _)
+            | Command::ScanValuesCursor(_, _, _, key, _)
+            | Command::CursorPage(key, _, _)
+            | Command::CursorDel(key)
+            | Command::CursorDrop(key) => vec![key],
+            Command::Del(keys)
+            | Command::Eval(keys, _)
+            | Command::EvalSha(_, keys, _)
+            | Command::Watch(keys)
+            | Command::Touch(keys)
+            | Command::Add(_, keys, _)
+            | Command::Sub(_, keys)
+            | Command::Mul(_, keys)
+            | Command::Div(_, keys)
+            | Command::Agg(_, _, keys)
+            | Command::BlockingPop(_, keys, _)
+            | Command::PfCount(keys) => keys.iter_mut().collect(),
+            Command::TagAdd(key, tags) | Command::TagDel(key, tags) => {
+                let mut names = vec![key];
+                names.extend(tags.iter_mut());
+                names
+            }
+            Command::XRead(_, _, streams) | Command::XReadGroup(_, _, _, _, streams) => streams.iter_mut().map(|(key, _)| key).collect(),
+            Command::PfMerge(destination, keys) => {
+                let mut names = vec![destination];
+                names.extend(keys.iter_mut());
+                names
+            }
+            Command::Store(destination, command) => {
+                let mut names = vec![destination];
+                names.extend(command.names_mut());
+                names
+            }
+            Command::Module(_, _, keys) => keys.iter_mut().map(|(_, key)| key).collect(),
+            Command::DryRun(command) => command.names_mut(),
+            _ => Vec::new()
+        }
+    }
+
+    /// Whether the command runs straight away inside MULTI instead of being queued.
+    pub fn controls_transaction(&self) -> bool {
+        matches!(
+            self,
+            Command::Multi | Command::Exec | Command::Discard | Command::Watch(_) | Command::Unwatch | Command::Quit
+        )
+    }
+
+    /// Parses the `[COUNT <n>] [BLOCK <ms>] STREAMS <key> [key ...] <id> [id ...]` that ends XREAD
+    /// and XREADGROUP. An ID of `latest` is read as `None`, e.g. `$` for XREAD.
+    fn stream_reads(command_name: &str, tokens: &[&[u8]], latest: &[u8]) -> Result<StreamReads, CommandError> {
+        let mismatch = || CommandError::ArgumentMismatch(command_name.to_string());
+        let invalid = |token: &[u8]| CommandError::InvalidArgument(command_name.to_string(), String::from_utf8_lossy(token).to_string());
+        let tok_len = tokens.len();
+        let mut count = None;
+        let mut block = None;
+        let mut i = 0;
+        while i + 1 < tok_len && !tokens[i].eq_ignore_ascii_case(b"STREAMS") {
+            let value = String::from_utf8_lossy(tokens[i + 1]);
+            match tokens[i].to_ascii_uppercase().as_slice() {
+                b"COUNT" => count = Some(value.parse::<usize>().map_err(|_| invalid(value.as_bytes()))?),
+                b"BLOCK" => block = Some(value.parse::<u64>().map_err(|_| invalid(value.as_bytes()))?),
+                _ => return Err(mismatch())
+            }
+            i += 2;
+        }
+        let rest = &tokens[(i + 1).min(tok_len)..];
+        if i >= tok_len || !tokens[i].eq_ignore_ascii_case(b"STREAMS") || rest.is_empty() || !rest.len().is_multiple_of(2) {
+            return Err(mismatch());
+        }
+        let (keys, ids) = rest.split_at(rest.len() / 2);
+        let mut streams = Vec::new();
+        for (key, id) in keys.iter().zip(ids) {
+            let id = match *id {
+                id if id == latest => None,
+                id => Some(String::from_utf8_lossy(id).parse::<StreamId>().map_err(|_| invalid(id))?)
+            };
+            streams.push((String::from_utf8_lossy(key).to_string(), id));
+        }
+        Ok((count, block, streams))
+    }
+
     pub fn from_vec(v: Vec<u8>) -> Result<Self, CommandError> {
+        Command::from_arguments(Command::split_line(v)?)
+    }
+
+    /// Splits a line into its arguments, unquoted, the command name first.
+    pub fn split_line(v: Vec<u8>) -> Result<Vec<Vec<u8>>, CommandError> {
         let mut trimmed_v = v;
+        // Telnet and friends end lines with \r\n, netcat and most clients with just \n.
         if trimmed_v.last() == Some(&b'\n') {
             trimmed_v.pop();
         }
+        if trimmed_v.last() == Some(&b'\r') {
+            trimmed_v.pop();
+        }
+        tokenize(&trimmed_v)
+    }
 
-        let mut tokens: Vec<&[u8]> = trimmed_v.split(|&x| x == b' ').collect();
-
-        let mut cmd = tokens[0].to_ascii_uppercase();
-        if cmd.last() == Some(&b'\n') {
-            cmd.pop();
+    /// Parses a line `split_line` has split up.
+    pub fn from_arguments(arguments: Vec<Vec<u8>>) -> Result<Self, CommandError> {
+        let command = Command::from_tokens(&arguments)?;
+        // Keys are read with from_utf8_lossy, so bytes that aren't UTF-8 come out as U+FFFD and
+        // different keys would share a record. A U+FFFD sent as UTF-8 is a real part of the key.
+        let mangled = command
+            .keys()
+            .into_iter()
+            .find(|key| key.contains(char::REPLACEMENT_CHARACTER) && !arguments.iter().any(|a| a == key.as_bytes()));
+        if let Some(key) = mangled {
+            return Err(CommandError::InvalidKey(key.to_string()));
         }
-        tokens = tokens.split_off(1);
+        Ok(command)
+    }
+
+    /// Parses a command that has already been split into arguments, the name first.
+    fn from_tokens(arguments: &[Vec<u8>]) -> Result<Self, CommandError> {
+        let Some((cmd, tokens)) = arguments.split_first() else {
+            return Err(CommandError::NoInput);
+        };
+        let cmd = cmd.to_ascii_uppercase();
+        let tokens: Vec<&[u8]> = tokens.iter().map(Vec::as_slice).collect();
         let tok_len = tokens.len();
+        let command_name = String::from_utf8_lossy(&cmd).to_string();
+        let mismatch = || CommandError::ArgumentMismatch(command_name.clone());
+        let invalid = |token: &[u8]| CommandError::InvalidArgument(command_name.clone(), String::from_utf8_lossy(token).to_string());
+        match command_spec::find(&command_name) {
+            Some(spec) if !spec.accepts(tok_len) => return Err(mismatch()),
+            Some(_) => {}
+            None => {
+                return match module::find_command(&command_name) {
+                    Some(command) if !command.spec.accepts(tok_len) => Err(mismatch()),
+                    Some(command) => {
+                        let arguments = arguments[1..].to_vec();
+                        let keys = (command.keys)(&arguments)
+                            .into_iter()
+                            .filter(|&i| i < arguments.len())
+                            .map(|i| (i, String::from_utf8_lossy(&arguments[i]).to_string()))
+                            .collect();
+                        Ok(Command::Module(command.spec.name.to_string(), arguments, keys))
+                    }
+                    None => Err(CommandError::UnknownCommand(command_name))
+                };
+            }
+        }
         match cmd.as_slice() {
             b"SET" => {
-                if tok_len < 3 {
-                    return Err(CommandError::ArgumentMismatch)
-                }
-
-                let data_tokens = tokens[2..].to_vec();
+                // A trailing `EX <secs>` sets a TTL along with the value.
+                let ttl = match tok_len {
+                    n if n >= 5 && tokens[n - 2].eq_ignore_ascii_case(b"EX") => {
+                        String::from_utf8_lossy(tokens[n - 1]).parse::<u64>().ok()
+                    }
+                    _ => None
+                };
+                let data_end = if ttl.is_some() { tok_len - 2 } else { tok_len };
+                let data_tokens = tokens[2..data_end].to_vec();
                 let data_with_spaces = data_tokens.join(&b' ');
 
                 Ok(
                     Command::Set(
                         String::from_utf8_lossy(tokens[0]).to_string(),
                         String::from_utf8_lossy(tokens[1]).to_string(),
-                        data_with_spaces
+                        data_with_spaces,
+                        ttl
                     )
                 )
             },
+            b"SETCAS" => {
+                // SETCAS <type> <key> VERSION <n> <value...> or SETCAS <type> <key> VALUE <old> <value...>
+                let expected = String::from_utf8_lossy(tokens[3]).to_string();
+                let expected = match tokens[2].to_ascii_uppercase().as_slice() {
+                    b"VERSION" => CasExpected::Version(expected.parse().map_err(|_| invalid(expected.as_bytes()))?),
+                    b"VALUE" => CasExpected::Value(expected),
+                    _ => return Err(mismatch())
+                };
+                Ok(Command::SetCas(
+                    String::from_utf8_lossy(tokens[0]).to_string(),
+                    String::from_utf8_lossy(tokens[1]).to_string(),
+                    expected,
+                    tokens[4..].join(&b' ')
+                ))
+            }
             b"GET" => {
-                if tok_len != 2 {
-                    return Err(CommandError::ArgumentMismatch);
+                let typeless_default = tok_len >= 3
+                    && tokens[1].eq_ignore_ascii_case(b"DEFAULT")
+                    && !(tok_len >= 4 && tokens[2].eq_ignore_ascii_case(b"DEFAULT"));
+                if tok_len == 1 || typeless_default {
+                    let default = if typeless_default { Some(tokens[2..].to_vec().join(&b' ')) } else { None };
+                    return Ok(Command::GetAny(String::from_utf8_lossy(tokens[0]).to_string(), default));
                 }
+                let default = match tok_len {
+                    2 => None,
+                    n if n >= 4 && tokens[2].eq_ignore_ascii_case(b"DEFAULT") => {
+                        Some(tokens[3..].to_vec().join(&b' '))
+                    }
+                    _ => return Err(mismatch())
+                };
                 Ok(
                     Command::Get(
                         String::from_utf8_lossy(tokens[0])
                             .to_string(),
                         String::from_utf8_lossy(tokens[1])
-                            .to_string()
+                            .to_string(),
+                        default
+                    )
+                )
+            },
+            b"GETDEL" => match tokens.as_slice() {
+                [key] => Ok(Command::GetDel(None, String::from_utf8_lossy(key).to_string())),
+                [t, key] => Ok(Command::GetDel(Some(String::from_utf8_lossy(t).to_string()), String::from_utf8_lossy(key).to_string())),
+                _ => Err(mismatch())
+            },
+            b"GETEX" => {
+                let (ttl, rest) = match tokens.as_slice() {
+                    [rest @ .., last] if !rest.is_empty() && last.eq_ignore_ascii_case(b"PERSIST") => (Some(None), rest),
+                    [rest @ .., ex, secs] if !rest.is_empty() && ex.eq_ignore_ascii_case(b"EX") => {
+                        let secs = String::from_utf8_lossy(secs).parse::<u64>().map_err(|_| invalid(secs))?;
+                        (Some(Some(secs)), rest)
+                    }
+                    rest => (None, rest)
+                };
+                match rest {
+                    [key] => Ok(Command::GetEx(None, String::from_utf8_lossy(key).to_string(), ttl)),
+                    [t, key] => Ok(Command::GetEx(Some(String::from_utf8_lossy(t).to_string()), String::from_utf8_lossy(key).to_string(), ttl)),
+                    _ => Err(mismatch())
+                }
+            }
+            b"INDEX" => {
+                let args: Vec<String> = tokens.iter().map(|t| String::from_utf8_lossy(t).to_string()).collect();
+                match (args.first().map(|a| a.to_uppercase()).as_deref(), &args[1.min(tok_len)..]) {
+                    (Some("CREATE"), [name, on, field]) if on.eq_ignore_ascii_case("ON") => {
+                        let field = field.parse::<MetadataField>().map_err(|_| invalid(field.as_bytes()))?;
+                        Ok(Command::IndexCreate(name.clone(), field))
+                    }
+                    (Some("QUERY"), [name, value]) => Ok(Command::IndexQuery(name.clone(), value.clone())),
+                    (Some("DROP"), [name]) => Ok(Command::IndexDrop(name.clone())),
+                    (Some("LIST"), []) => Ok(Command::IndexList),
+                    _ => Err(mismatch())
+                }
+            }
+            b"TAG" => {
+                let args: Vec<String> = tokens.iter().map(|t| String::from_utf8_lossy(t).to_string()).collect();
+                match (args.first().map(|a| a.to_uppercase()).as_deref(), &args[1.min(tok_len)..]) {
+                    (Some("ADD"), [key, tags @ ..]) if !tags.is_empty() => Ok(Command::TagAdd(key.clone(), tags.to_vec())),
+                    (Some("DEL"), [key, tags @ ..]) if !tags.is_empty() => Ok(Command::TagDel(key.clone(), tags.to_vec())),
+                    (Some("KEYS"), [tag]) => Ok(Command::TagKeys(tag.clone())),
+                    (Some("LIST"), [key]) => Ok(Command::TagList(key.clone())),
+                    _ => Err(mismatch())
+                }
+            }
+            b"VINDEX" => {
+                let args: Vec<String> = tokens.iter().map(|t| String::from_utf8_lossy(t).to_string()).collect();
+                match (args.first().map(|a| a.to_uppercase()).as_deref(), &args[1.min(tok_len)..]) {
+                    (Some("CREATE"), [name, rest @ ..]) => {
+                        let mut prefix = None;
+                        let mut dim = None;
+                        let mut metric = VectorMetric::Cosine;
+                        for option in rest.chunks(2) {
+                            match (option[0].to_uppercase().as_str(), option.get(1)) {
+                                ("PREFIX", Some(p)) => prefix = Some(p.clone()),
+                                ("DIM", Some(d)) => dim = d.parse::<usize>().ok().filter(|d| *d > 0),
+                                ("METRIC", Some(m)) => metric = m.parse().map_err(|_| invalid(m.as_bytes()))?,
+                                _ => return Err(mismatch())
+                            }
+                        }
+                        match (prefix, dim) {
+                            (Some(prefix), Some(dim)) => Ok(Command::VIndexCreate(name.clone(), VectorIndex { prefix, dim, metric })),
+                            _ => Err(mismatch())
+                        }
+                    }
+                    (Some("DROP"), [name]) => Ok(Command::VIndexDrop(name.clone())),
+                    (Some("LIST"), []) => Ok(Command::VIndexList),
+                    _ => Err(mismatch())
+                }
+            }
+            b"VSEARCH" => {
+                let k = String::from_utf8_lossy(tokens[1]).parse::<usize>().map_err(|_| invalid(tokens[1]))?;
+                let query = String::from_utf8_lossy(&tokens[2..].join(&b' '))
+                    .parse::<Vector>()
+                    .map_err(|_| invalid(&tokens[2..].join(&b' ')))?;
+                Ok(Command::VSearch(String::from_utf8_lossy(tokens[0]).to_string(), k, query))
+            }
+            b"GEOADD" => {
+                if !(tok_len - 1).is_multiple_of(3) {
+                    return Err(mismatch());
+                }
+                let mut members = Vec::new();
+                for triple in tokens[1..].chunks(3) {
+                    let lon = String::from_utf8_lossy(triple[0]).parse::<f64>();
+                    let lat = String::from_utf8_lossy(triple[1]).parse::<f64>();
+                    match (lon, lat) {
+                        (Ok(lon), Ok(lat)) if valid_lon_lat(lon, lat) => {
+                            members.push((lon, lat, String::from_utf8_lossy(triple[2]).to_string()));
+                        }
+                        _ => return Err(invalid(&triple[..2].join(&b' ')))
+                    }
+                }
+                Ok(Command::GeoAdd(String::from_utf8_lossy(tokens[0]).to_string(), members))
+            }
+            b"GEODIST" => {
+                let unit = match tokens.get(3) {
+                    None => GeoUnit::Meters,
+                    Some(unit) => String::from_utf8_lossy(unit).parse::<GeoUnit>().map_err(|_| invalid(unit))?
+                };
+                Ok(
+                    Command::GeoDist(
+                        String::from_utf8_lossy(tokens[0]).to_string(),
+                        String::from_utf8_lossy(tokens[1]).to_string(),
+                        String::from_utf8_lossy(tokens[2]).to_string(),
+                        unit
+                    )
+                )
+            }
+            b"GEOSEARCH" => {
+                let args: Vec<String> = tokens[1..].iter().map(|t| String::from_utf8_lossy(t).to_string()).collect();
+                let number = |s: &String| s.parse::<f64>().map_err(|_| invalid(s.as_bytes()));
+                let mut origin = None;
+                let mut radius = None;
+                let mut search = GeoSearch {
+                    origin: GeoOrigin::LonLat(0.0, 0.0),
+                    radius: 0.0,
+                    unit: GeoUnit::Meters,
+                    ascending: None,
+                    count: None,
+                    with_dist: false
+                };
+                let mut i = 0;
+                while i < args.len() {
+                    match (args[i].to_uppercase().as_str(), &args[i + 1..]) {
+                        ("FROMMEMBER", [member, ..]) => {
+                            origin = Some(GeoOrigin::Member(member.clone()));
+                            i += 2;
+                        }
+                        ("FROMLONLAT", [lon, lat, ..]) => {
+                            let (lon, lat) = (number(lon)?, number(lat)?);
+                            if !valid_lon_lat(lon, lat) {
+                                return Err(mismatch());
+                            }
+                            origin = Some(GeoOrigin::LonLat(lon, lat));
+                            i += 3;
+                        }
+                        ("BYRADIUS", [r, unit, ..]) => {
+                            radius = Some(number(r)?);
+                            search.unit = unit.parse::<GeoUnit>().map_err(|_| invalid(unit.as_bytes()))?;
+                            i += 3;
+                        }
+                        ("COUNT", [n, ..]) => {
+                            search.count = Some(n.parse::<usize>().map_err(|_| invalid(n.as_bytes()))?);
+                            i += 2;
+                        }
+                        ("ASC", _) => {
+                            search.ascending = Some(true);
+                            i += 1;
+                        }
+                        ("DESC", _) => {
+                            search.ascending = Some(false);
+                            i += 1;
+                        }
+                        ("WITHDIST", _) => {
+                            search.with_dist = true;
+                            i += 1;
+                        }
+                        _ => return Err(mismatch())
+                    }
+                }
+                match (origin, radius) {
+                    (Some(origin), Some(radius)) if radius >= 0.0 => {
+                        search.origin = origin;
+                        search.radius = radius;
+                        Ok(Command::GeoSearch(String::from_utf8_lossy(tokens[0]).to_string(), search))
+                    }
+                    _ => Err(mismatch())
+                }
+            }
+            b"XADD" => {
+                if !tok_len.is_multiple_of(2) {
+                    return Err(mismatch());
+                }
+                let id = match tokens[1] {
+                    b"*" => None,
+                    id => Some(String::from_utf8_lossy(id).parse::<StreamId>().map_err(|_| invalid(id))?)
+                };
+                let fields = tokens[2..]
+                            .chunks(2)
+                            .map(|pair| (String::from_utf8_lossy(pair[0]).to_string(), String::from_utf8_lossy(pair[1]).to_string()))
+                            .collect();
+                Ok(Command::XAdd(String::from_utf8_lossy(tokens[0]).to_string(), id, fields))
+            }
+            b"XRANGE" => {
+                let count = match tok_len {
+                    3 => None,
+                    5 if tokens[3].eq_ignore_ascii_case(b"COUNT") => {
+                        Some(String::from_utf8_lossy(tokens[4]).parse::<usize>().map_err(|_| invalid(tokens[4]))?)
+                    }
+                    _ => return Err(mismatch())
+                };
+                let start = StreamId::parse_bound(&String::from_utf8_lossy(tokens[1]), false);
+                let end = StreamId::parse_bound(&String::from_utf8_lossy(tokens[2]), true);
+                match (start, end) {
+                    (Ok(start), Ok(end)) => Ok(Command::XRange(String::from_utf8_lossy(tokens[0]).to_string(), start, end, count)),
+                    (Err(_), _) => Err(invalid(tokens[1])),
+                    (_, Err(_)) => Err(invalid(tokens[2]))
+                }
+            }
+            b"XREAD" => {
+                let (count, block, streams) = Command::stream_reads(&command_name, &tokens, b"$")?;
+                Ok(Command::XRead(count, block, streams))
+            }
+            b"XREADGROUP" => {
+                if !tokens[0].eq_ignore_ascii_case(b"GROUP") {
+                    return Err(mismatch());
+                }
+                let group = String::from_utf8_lossy(tokens[1]).to_string();
+                let consumer = String::from_utf8_lossy(tokens[2]).to_string();
+                let (count, block, streams) = Command::stream_reads(&command_name, &tokens[3..], b">")?;
+                Ok(Command::XReadGroup(group, consumer, count, block, streams))
+            }
+            b"XGROUP" => {
+                let key = String::from_utf8_lossy(tokens[1]).to_string();
+                let group = String::from_utf8_lossy(tokens[2]).to_string();
+                match (tokens[0].to_ascii_uppercase().as_slice(), &tokens[3..]) {
+                    (b"CREATE", [id, options @ ..]) => {
+                        let mkstream = match options {
+                            [] => false,
+                            [option] if option.eq_ignore_ascii_case(b"MKSTREAM") => true,
+                            _ => return Err(mismatch())
+                        };
+                        let start = match *id {
+                            b"$" => None,
+                            id => Some(String::from_utf8_lossy(id).parse::<StreamId>().map_err(|_| invalid(id))?)
+                        };
+                        Ok(Command::XGroupCreate(key, group, start, mkstream))
+                    }
+                    (b"DESTROY", []) => Ok(Command::XGroupDestroy(key, group)),
+                    _ => Err(CommandError::UnknownCommand(format!("XGROUP {}", String::from_utf8_lossy(tokens[0]).to_uppercase())))
+                }
+            }
+            b"XACK" | b"XCLAIM" => {
+                let key = String::from_utf8_lossy(tokens[0]).to_string();
+                let group = String::from_utf8_lossy(tokens[1]).to_string();
+                let first_id = if cmd.as_slice() == b"XACK" { 2 } else { 4 };
+                let ids = tokens[first_id..]
+                    .iter()
+                    .map(|id| String::from_utf8_lossy(id).parse::<StreamId>().map_err(|_| invalid(id)))
+                    .collect::<Result<Vec<StreamId>, CommandError>>()?;
+                if cmd.as_slice() == b"XACK" {
+                    return Ok(Command::XAck(key, group, ids));
+                }
+                let consumer = String::from_utf8_lossy(tokens[2]).to_string();
+                let min_idle = String::from_utf8_lossy(tokens[3]).parse::<u64>().map_err(|_| invalid(tokens[3]))?;
+                Ok(Command::XClaim(key, group, consumer, min_idle, ids))
+            }
+            b"XPENDING" => Ok(Command::XPending(
+                String::from_utf8_lossy(tokens[0]).to_string(),
+                String::from_utf8_lossy(tokens[1]).to_string()
+            )),
+            b"COUNTER" => {
+                let ty = String::from_utf8_lossy(tokens[1]).parse::<BitFieldType>().map_err(|_| invalid(tokens[1]))?;
+                let overflow = String::from_utf8_lossy(tokens[2]).parse::<Overflow>().map_err(|_| invalid(tokens[2]))?;
+                let value = match tokens.get(3) {
+                    Some(value) => String::from_utf8_lossy(value).parse::<i64>().map_err(|_| invalid(value))?,
+                    None => 0
+                };
+                Ok(Command::Counter(String::from_utf8_lossy(tokens[0]).to_string(), ty, overflow, value))
+            }
+            b"INCR" | b"DECR" | b"INCRBY" | b"DECRBY" => {
+                let by = match tokens.get(1) {
+                    Some(by) => String::from_utf8_lossy(by).parse::<i64>().map_err(|_| invalid(by))?,
+                    None => 1
+                };
+                let by = match cmd.as_slice() {
+                    b"DECR" | b"DECRBY" => by.checked_neg().ok_or_else(|| invalid(tokens[1]))?,
+                    _ => by
+                };
+                Ok(Command::IncrBy(String::from_utf8_lossy(tokens[0]).to_string(), by))
+            }
+            b"BF.RESERVE" => {
+                let error_rate = match String::from_utf8_lossy(tokens[1]).parse::<f64>() {
+                    Ok(rate) if rate > 0.0 && rate < 1.0 => rate,
+                    _ => return Err(invalid(tokens[1]))
+                };
+                let capacity = match String::from_utf8_lossy(tokens[2]).parse::<u64>() {
+                    Ok(capacity) if capacity > 0 => capacity,
+                    _ => return Err(invalid(tokens[2]))
+                };
+                Ok(Command::BfReserve(String::from_utf8_lossy(tokens[0]).to_string(), error_rate, capacity))
+            }
+            b"BF.ADD" | b"BF.EXISTS" => {
+                let key = String::from_utf8_lossy(tokens[0]).to_string();
+                let element = String::from_utf8_lossy(tokens[1]).to_string();
+                if cmd.as_slice() == b"BF.ADD" {
+                    Ok(Command::BfAdd(key, element))
+                } else {
+                    Ok(Command::BfExists(key, element))
+                }
+            }
+            b"TS.CREATE" => {
+                let retention = match tok_len {
+                    1 => None,
+                    3 if tokens[1].eq_ignore_ascii_case(b"RETENTION") => {
+                        Some(String::from_utf8_lossy(tokens[2]).parse::<u64>().map_err(|_| invalid(tokens[2]))?)
+                    }
+                    _ => return Err(mismatch())
+                };
+                Ok(Command::TsCreate(String::from_utf8_lossy(tokens[0]).to_string(), retention))
+            }
+            b"TS.ADD" => {
+                let timestamp = match tokens[1] {
+                    b"*" => None,
+                    timestamp => Some(String::from_utf8_lossy(timestamp).parse::<u64>().map_err(|_| invalid(timestamp))?)
+                };
+                let value = match String::from_utf8_lossy(tokens[2]).parse::<f64>() {
+                    Ok(value) if value.is_finite() => value,
+                    _ => return Err(invalid(tokens[2]))
+                };
+                Ok(Command::TsAdd(String::from_utf8_lossy(tokens[0]).to_string(), timestamp, value))
+            }
+            b"TS.RANGE" => {
+                let bound = |token: &[u8], open: u64| match token {
+                    b"-" | b"+" => Ok(open),
+                    token => String::from_utf8_lossy(token).parse::<u64>().map_err(|_| invalid(token))
+                };
+                let from = bound(tokens[1], 0)?;
+                let to = bound(tokens[2], u64::MAX)?;
+                let aggregation = match tok_len {
+                    3 => None,
+                    6 if tokens[3].eq_ignore_ascii_case(b"AGG") => {
+                        let aggregation = String::from_utf8_lossy(tokens[4]).parse::<Aggregation>().map_err(|_| invalid(tokens[4]))?;
+                        let bucket = match String::from_utf8_lossy(tokens[5]).parse::<u64>() {
+                            Ok(bucket) if bucket > 0 => bucket,
+                            _ => return Err(invalid(tokens[5]))
+                        };
+                        Some((aggregation, bucket))
+                    }
+                    _ => return Err(mismatch())
+                };
+                Ok(Command::TsRange(String::from_utf8_lossy(tokens[0]).to_string(), from, to, aggregation))
+            }
+            b"PFADD" | b"PFMERGE" => {
+                let key = String::from_utf8_lossy(tokens[0]).to_string();
+                let rest = tokens[1..]
+                            .iter()
+                            .map(|x| String::from_utf8_lossy(x).to_string())
+                            .collect();
+                if cmd.as_slice() == b"PFADD" {
+                    Ok(Command::PfAdd(key, rest))
+                } else {
+                    Ok(Command::PfMerge(key, rest))
+                }
+            }
+            b"BITFIELD" => {
+                let args: Vec<String> = tokens[1..].iter().map(|t| String::from_utf8_lossy(t).to_string()).collect();
+                let field = |ty: &String, offset: &String| -> Result<(BitFieldType, u64), CommandError> {
+                    let ty = ty.parse::<BitFieldType>().map_err(|_| invalid(ty.as_bytes()))?;
+                    Ok((ty, ty.offset(offset).map_err(|_| invalid(offset.as_bytes()))?))
+                };
+                let number = |s: &String| s.parse::<i64>().map_err(|_| invalid(s.as_bytes()));
+                let mut ops = Vec::new();
+                let mut i = 0;
+                while i < args.len() {
+                    match (args[i].to_uppercase().as_str(), &args[i + 1..]) {
+                        ("GET", [ty, offset, ..]) => {
+                            let (ty, offset) = field(ty, offset)?;
+                            ops.push(BitFieldOp::Get(ty, offset));
+                            i += 3;
+                        }
+                        ("SET", [ty, offset, value, ..]) => {
+                            let (ty, offset) = field(ty, offset)?;
+                            ops.push(BitFieldOp::Set(ty, offset, number(value)?));
+                            i += 4;
+                        }
+                        ("INCRBY", [ty, offset, increment, ..]) => {
+                            let (ty, offset) = field(ty, offset)?;
+                            ops.push(BitFieldOp::IncrBy(ty, offset, number(increment)?));
+                            i += 4;
+                        }
+                        ("OVERFLOW", [overflow, ..]) => {
+                            ops.push(BitFieldOp::Overflow(overflow.parse::<Overflow>().map_err(|_| invalid(overflow.as_bytes()))?));
+                            i += 2;
+                        }
+                        _ => return Err(mismatch())
+                    }
+                }
+                if !ops.iter().any(BitFieldOp::replies) {
+                    return Err(mismatch());
+                }
+                Ok(Command::BitField(String::from_utf8_lossy(tokens[0]).to_string(), ops))
+            }
+            b"PFCOUNT" => {
+                let keys = tokens
+                            .into_iter()
+                            .map(|x| String::from_utf8_lossy(x).to_string())
+                            .collect();
+                Ok(Command::PfCount(keys))
+            }
+            b"ZADD" => {
+                if tok_len.is_multiple_of(2) {
+                    return Err(mismatch());
+                }
+                let mut members = Vec::new();
+                for pair in tokens[1..].chunks(2) {
+                    let score = String::from_utf8_lossy(pair[0]).parse::<f64>().ok().filter(|score| score.is_finite()).ok_or_else(|| invalid(pair[0]))?;
+                    members.push((score, String::from_utf8_lossy(pair[1]).to_string()));
+                }
+                Ok(Command::ZAdd(String::from_utf8_lossy(tokens[0]).to_string(), members))
+            }
+            b"ZSCORE" => {
+                Ok(
+                    Command::ZScore(
+                        String::from_utf8_lossy(tokens[0]).to_string(),
+                        String::from_utf8_lossy(tokens[1]).to_string()
+                    )
+                )
+            }
+            b"ZRANGE" | b"ZRANGEBYSCORE" => {
+                let with_scores = tok_len == 4 && tokens[3].eq_ignore_ascii_case(b"WITHSCORES");
+                if tok_len == 4 && !with_scores {
+                    return Err(mismatch());
+                }
+                let key = String::from_utf8_lossy(tokens[0]).to_string();
+                let start = String::from_utf8_lossy(tokens[1]).to_string();
+                let stop = String::from_utf8_lossy(tokens[2]).to_string();
+                if cmd.as_slice() == b"ZRANGE" {
+                    match (start.parse::<i64>(), stop.parse::<i64>()) {
+                        (Ok(start), Ok(stop)) => Ok(Command::ZRange(key, start, stop, with_scores)),
+                        (Err(_), _) => Err(invalid(start.as_bytes())),
+                        (_, Err(_)) => Err(invalid(stop.as_bytes()))
+                    }
+                } else {
+                    match (start.parse::<f64>(), stop.parse::<f64>()) {
+                        (Ok(min), Ok(max)) => Ok(Command::ZRangeByScore(key, min, max, with_scores)),
+                        (Err(_), _) => Err(invalid(start.as_bytes())),
+                        (_, Err(_)) => Err(invalid(stop.as_bytes()))
+                    }
+                }
+            }
+            b"ZREM" => {
+                let members = tokens[1..]
+                            .iter()
+                            .map(|x| String::from_utf8_lossy(x).to_string())
+                            .collect();
+                Ok(Command::ZRem(String::from_utf8_lossy(tokens[0]).to_string(), members))
+            }
+            b"LPUSH" | b"RPUSH" => {
+                let end = if cmd.as_slice() == b"LPUSH" { End::Left } else { End::Right };
+                let elements = tokens[1..]
+                            .iter()
+                            .map(|x| String::from_utf8_lossy(x).to_string())
+                            .collect();
+                Ok(Command::Push(end, String::from_utf8_lossy(tokens[0]).to_string(), elements))
+            }
+            b"LPOP" => Ok(Command::Pop(End::Left, String::from_utf8_lossy(tokens[0]).to_string())),
+            b"RPOP" => Ok(Command::Pop(End::Right, String::from_utf8_lossy(tokens[0]).to_string())),
+            b"BLPOP" | b"BRPOP" => {
+                let end = if cmd.as_slice() == b"BLPOP" { End::Left } else { End::Right };
+                let timeout = String::from_utf8_lossy(tokens[tok_len - 1]).parse::<u64>().map_err(|_| invalid(tokens[tok_len - 1]))?;
+                let keys = tokens[..tok_len - 1]
+                            .iter()
+                            .map(|x| String::from_utf8_lossy(x).to_string())
+                            .collect();
+                Ok(Command::BlockingPop(end, keys, timeout))
+            }
+            b"LLEN" => Ok(Command::LLen(String::from_utf8_lossy(tokens[0]).to_string())),
+            b"LRANGE" => {
+                let key = String::from_utf8_lossy(tokens[0]).to_string();
+                let start = String::from_utf8_lossy(tokens[1]).parse::<i64>().map_err(|_| invalid(tokens[1]))?;
+                let stop = String::from_utf8_lossy(tokens[2]).parse::<i64>().map_err(|_| invalid(tokens[2]))?;
+                Ok(Command::LRange(key, start, stop))
+            }
+            b"ZINCRBY" => {
+                let increment = String::from_utf8_lossy(tokens[1]).parse::<f64>().ok().filter(|increment| increment.is_finite()).ok_or_else(|| invalid(tokens[1]))?;
+                Ok(
+                    Command::ZIncrBy(
+                        String::from_utf8_lossy(tokens[0]).to_string(),
+                        increment,
+                        String::from_utf8_lossy(tokens[2]).to_string()
+                    )
+                )
+            }
+            b"JSON.GET" => {
+                let path = tokens.get(1).map(|p| String::from_utf8_lossy(p).to_string()).unwrap_or_default();
+                Ok(Command::JsonGet(String::from_utf8_lossy(tokens[0]).to_string(), path))
+            }
+            b"GETCHUNKED" => {
+                Ok(Command::GetChunked(String::from_utf8_lossy(tokens[0]).to_string()))
+            }
+            b"GETRANGE" => {
+                let position = |token: &[u8]| String::from_utf8_lossy(token).parse::<i64>().map_err(|_| invalid(token));
+                Ok(Command::GetRange(String::from_utf8_lossy(tokens[0]).to_string(), position(tokens[1])?, position(tokens[2])?))
+            }
+            b"SETRANGE" => {
+                let offset = String::from_utf8_lossy(tokens[1]).parse::<usize>().map_err(|_| invalid(tokens[1]))?;
+                Ok(Command::SetRange(String::from_utf8_lossy(tokens[0]).to_string(), offset, tokens[2..].join(&b' ')))
+            }
+            b"JSON.SET" => {
+                Ok(
+                    Command::JsonSet(
+                        String::from_utf8_lossy(tokens[0]).to_string(),
+                        String::from_utf8_lossy(tokens[1]).to_string(),
+                        tokens[2..].join(&b' ')
+                    )
+                )
+            }
+            b"CHECKSUM" => {
+                match tokens[0].to_ascii_uppercase().as_slice() {
+                    b"ON" => Ok(Command::Checksum(true)),
+                    b"OFF" => Ok(Command::Checksum(false)),
+                    _ => Err(invalid(tokens[0]))
+                }
+            }
+            b"CAST" => {
+                Ok(
+                    Command::Cast(
+                        String::from_utf8_lossy(tokens[0]).to_string(),
+                        String::from_utf8_lossy(tokens[1]).to_string()
+                    )
+                )
+            },
+            b"SETNULL" => {
+                Ok(
+                    Command::SetNull(
+                        String::from_utf8_lossy(tokens[0]).to_string(),
+                        String::from_utf8_lossy(tokens[1]).to_string()
                     )
                 )
             },
             b"DEL" => {
-                if tok_len < 1 {
-                    return Err(CommandError::ArgumentMismatch);
+                if tok_len == 2 && tokens[0].eq_ignore_ascii_case(b"BYTAG") {
+                    return Ok(Command::DelByTag(String::from_utf8_lossy(tokens[1]).to_string()));
                 }
                 let keys = tokens
                             .into_iter()
@@ -78,22 +1167,116 @@ impl Command {
                 )
             }
             b"KEYS" => {
-                if tok_len != 1 {
-                    return Err(CommandError::ArgumentMismatch);
+                let mut pattern = KeyPattern::from(String::from_utf8_lossy(tokens[0]).as_ref());
+                // SORT comes first, on its own meaning ALPHA.
+                let mut options = &tokens[1..];
+                let mut order = KeyOrder::Unordered;
+                if options.first().is_some_and(|option| option.eq_ignore_ascii_case(b"SORT")) {
+                    order = KeyOrder::Alpha;
+                    options = &options[1..];
+                    if let Some(by) = options.first().and_then(|by| String::from_utf8_lossy(by).parse().ok()) {
+                        order = by;
+                        options = &options[1..];
+                    }
+                }
+                // CURSOR comes last, and takes the place of OFFSET and LIMIT.
+                let cursor = match options {
+                    [rest @ .., cursor, name, ttl] if cursor.eq_ignore_ascii_case(b"CURSOR") => {
+                        let ttl = String::from_utf8_lossy(ttl)
+                            .parse::<u64>()
+                            .map_err(|_| CommandError::InvalidTtlSpecified(String::from_utf8_lossy(ttl).to_string()))?;
+                        options = rest;
+                        Some((String::from_utf8_lossy(name).to_string(), ttl))
+                    }
+                    _ => None
+                };
+                // KEYS <pattern> [SORT [ALPHA|LENGTH]] [MODIFIEDSINCE <unix time>] [OFFSET <n>] [LIMIT <n>]
+                let (mut offset, mut limit) = (0, None);
+                for option in options.chunks(2) {
+                    let [name, value] = option else {
+                        return Err(mismatch());
+                    };
+                    let count = || String::from_utf8_lossy(value).parse::<usize>().map_err(|_| invalid(value));
+                    match name.to_ascii_uppercase().as_slice() {
+                        b"MODIFIEDSINCE" => {
+                            let since = String::from_utf8_lossy(value).parse::<u64>().ok().and_then(|secs| UNIX_EPOCH.checked_add(Duration::from_secs(secs)));
+                            pattern.modified_since = Some(since.ok_or(invalid(value))?);
+                        }
+                        b"OFFSET" if cursor.is_none() => offset = count()?,
+                        b"LIMIT" if cursor.is_none() => limit = Some(count()?),
+                        _ => return Err(mismatch())
+                    }
+                }
+                match cursor {
+                    Some((name, ttl)) => Ok(Command::KeysCursor(pattern, order, name, ttl)),
+                    None => Ok(Command::Keys(pattern, order, offset, limit))
                 }
-                Ok(Command::Keys(String::from_utf8_lossy(tokens[0]).to_string()))
             }
-            b"MODE" => {
-                if tok_len != 1 {
-                    return Err(CommandError::ArgumentMismatch);
+            b"DELPATTERN" => {
+                Ok(Command::DelPattern(KeyPattern::from(String::from_utf8_lossy(tokens[0]).as_ref())))
+            }
+            b"EXPIREPATTERN" => {
+                let pattern = KeyPattern::from(String::from_utf8_lossy(tokens[0]).as_ref());
+                if tokens[1].eq_ignore_ascii_case(b"PERSIST") {
+                    return Ok(Command::ExpirePattern(pattern, None));
+                }
+                match String::from_utf8_lossy(tokens[1]).parse::<u64>() {
+                    Ok(ttl) => Ok(Command::ExpirePattern(pattern, Some(ttl))),
+                    Err(_) => Err(CommandError::InvalidTtlSpecified(String::from_utf8_lossy(tokens[1]).to_string()))
+                }
+            }
+            b"SCANVALUES" => {
+                // SCANVALUES <type> <min> <max> [LIMIT <n> | CURSOR <name> <ttl>]
+                if !is_numeric(&String::from_utf8_lossy(tokens[0])) {
+                    return Err(invalid(tokens[0]));
+                }
+                let ty = String::from_utf8_lossy(tokens[0]).to_string();
+                let bound = |token: &[u8], unbounded: &str| -> Result<Option<BigDecimal>, CommandError> {
+                    let text = String::from_utf8_lossy(token);
+                    if text.eq_ignore_ascii_case(unbounded) || (unbounded == "+inf" && text.eq_ignore_ascii_case("inf")) {
+                        return Ok(None);
+                    }
+                    text.parse::<BigDecimal>().map(Some).map_err(|_| invalid(token))
+                };
+                let min = bound(tokens[1], "-inf")?;
+                let max = bound(tokens[2], "+inf")?;
+                match &tokens[3..] {
+                    [] => Ok(Command::ScanValues(ty, min, max, None)),
+                    [option, n] if option.eq_ignore_ascii_case(b"LIMIT") => {
+                        let limit = String::from_utf8_lossy(n).parse::<usize>().map_err(|_| invalid(n))?;
+                        Ok(Command::ScanValues(ty, min, max, Some(limit)))
+                    }
+                    [option, name, ttl] if option.eq_ignore_ascii_case(b"CURSOR") => {
+                        let ttl = String::from_utf8_lossy(ttl).parse::<u64>()
+                            .map_err(|_| CommandError::InvalidTtlSpecified(String::from_utf8_lossy(ttl).to_string()))?;
+                        Ok(Command::ScanValuesCursor(ty, min, max, String::from_utf8_lossy(name).to_string(), ttl))
+                    }
+                    _ => Err(mismatch())
+                }
+            }
+            b"CURSOR" => {
+                let name = String::from_utf8_lossy(tokens[1]).to_string();
+                match (tokens[0].to_ascii_uppercase().as_slice(), tok_len) {
+                    (b"PAGE", 4) => {
+                        let page = String::from_utf8_lossy(tokens[2]).parse::<usize>();
+                        let size = String::from_utf8_lossy(tokens[3]).parse::<usize>();
+                        match (page, size) {
+                            (Ok(page), Ok(size)) => Ok(Command::CursorPage(name, page, size)),
+                            (Err(_), _) => Err(invalid(tokens[2])),
+                            (_, Err(_)) => Err(invalid(tokens[3]))
+                        }
+                    }
+                    (b"DEL", 2) => Ok(Command::CursorDel(name)),
+                    (b"DROP", 2) => Ok(Command::CursorDrop(name)),
+                    _ => Err(mismatch())
                 }
-                let mut mode = tokens[0].to_ascii_uppercase();
-                mode.pop();
-                match mode.as_slice() {
+            }
+            b"MODE" => {
+                match tokens[0].to_ascii_uppercase().as_slice() {
                     b"GLOB" => Ok(Command::Mode(SmirkSearchMode::Glob)),
                     b"REGEX" => Ok(Command::Mode(SmirkSearchMode::Regex)),
                     b"TRIE" => Ok(Command::Mode(SmirkSearchMode::Trie)),
-                    m => { println!("{:?}", m); return Err(CommandError::NoValidModeSpecified); }
+                    _ => Err(CommandError::NoValidModeSpecified(String::from_utf8_lossy(tokens[0]).to_string()))
                 }
             }
             b"TTL" => {
@@ -104,53 +1287,357 @@ impl Command {
                         if let Ok(ttl) = ttl {
                             Ok(Command::TtlSet(String::from_utf8_lossy(tokens[0]).to_string(), Some(ttl)))
                         } else {
-                            Err(CommandError::InvalidTtlSpecified)
+                            Err(CommandError::InvalidTtlSpecified(String::from_utf8_lossy(tokens[1]).to_string()))
                         }
                     }
-                    _ => Err(CommandError::ArgumentMismatch)
+                    _ => Err(mismatch())
                 }
             }
             b"DELTTL" => {
-                match tok_len {
-                    1 => Ok(Command::TtlSet(String::from_utf8_lossy(tokens[0]).to_string(), None)),
-                    _ => Err(CommandError::ArgumentMismatch)
-                }
+                Ok(Command::TtlSet(String::from_utf8_lossy(tokens[0]).to_string(), None))
             }
             b"EXISTS" => {
-                if tok_len != 1 {
-                    return Err(CommandError::ArgumentMismatch);
-                }
                 Ok(Command::Exists(String::from_utf8_lossy(tokens[0]).to_string()))
             }
             b"TYPE" => {
-                if tok_len != 1 {
-                    return Err(CommandError::ArgumentMismatch);
-                }
                 Ok(Command::Type(String::from_utf8_lossy(tokens[0]).to_string()))
             }
+            b"EVAL" => {
+                // EVAL <numkeys> [key ...] <script...>, the script running to the end of the line.
+                let numkeys = match tokens.first().map(|t| String::from_utf8_lossy(t).parse::<usize>()) {
+                    Some(Ok(numkeys)) if tok_len > numkeys + 1 => numkeys,
+                    _ => return Err(mismatch())
+                };
+                let keys = tokens[1..=numkeys].iter().map(|t| String::from_utf8_lossy(t).to_string()).collect();
+                let script = String::from_utf8_lossy(&tokens[numkeys + 1..].join(&b' ')).to_string();
+                Ok(Command::Eval(keys, script))
+            }
+            b"EVALSHA" => {
+                let numkeys = match tokens.get(1).map(|t| String::from_utf8_lossy(t).parse::<usize>()) {
+                    Some(Ok(numkeys)) if tok_len >= numkeys + 2 => numkeys,
+                    _ => return Err(mismatch())
+                };
+                let args: Vec<String> = tokens.iter().map(|t| String::from_utf8_lossy(t).to_string()).collect();
+                Ok(Command::EvalSha(args[0].clone(), args[2..numkeys + 2].to_vec(), args[numkeys + 2..].to_vec()))
+            }
+            b"SCRIPT" => {
+                let rest: Vec<String> = tokens[1..].iter().map(|t| String::from_utf8_lossy(t).to_string()).collect();
+                match (tokens[0].to_ascii_uppercase().as_slice(), tok_len) {
+                    (b"LOAD", n) if n > 1 => Ok(Command::ScriptLoad(rest.join(" "))),
+                    (b"EXISTS", n) if n > 1 => Ok(Command::ScriptExists(rest)),
+                    (b"FLUSH", 1) => Ok(Command::ScriptFlush),
+                    _ => Err(mismatch())
+                }
+            }
+            b"OBJECT" => {
+                Ok(Command::Object(String::from_utf8_lossy(tokens[0]).to_string()))
+            }
+            b"STAT" => {
+                Ok(Command::Stat(String::from_utf8_lossy(tokens[0]).to_string()))
+            }
+            b"HISTORY" => {
+                let key = tokens.first().map(|t| String::from_utf8_lossy(t).to_string());
+                match (key, tok_len) {
+                    (Some(key), 1) => Ok(Command::History(key, None)),
+                    (Some(key), 2) => {
+                        let count = String::from_utf8_lossy(tokens[1]).parse::<usize>().map_err(|_| invalid(tokens[1]))?;
+                        Ok(Command::History(key, Some(count)))
+                    }
+                    _ => Err(mismatch())
+                }
+            }
+            b"KEEPHISTORY" => {
+                let depth = String::from_utf8_lossy(tokens[1]).parse::<usize>().map_err(|_| invalid(tokens[1]))?;
+                Ok(Command::KeepHistory(String::from_utf8_lossy(tokens[0]).to_string(), depth))
+            }
+            b"RESTOREVERSION" => {
+                let version = String::from_utf8_lossy(tokens[1]).parse::<u64>().map_err(|_| invalid(tokens[1]))?;
+                Ok(Command::RestoreVersion(String::from_utf8_lossy(tokens[0]).to_string(), version))
+            }
+            b"MULTI" => Ok(Command::Multi),
+            b"EXEC" => Ok(Command::Exec),
+            b"DISCARD" => Ok(Command::Discard),
+            b"UNWATCH" => Ok(Command::Unwatch),
+            b"WATCH" => {
+                Ok(Command::Watch(tokens.iter().map(|t| String::from_utf8_lossy(t).to_string()).collect()))
+            }
+            b"TOUCH" => {
+                Ok(Command::Touch(tokens.iter().map(|t| String::from_utf8_lossy(t).to_string()).collect()))
+            }
+            b"STATUS" => {
+                Ok(Command::Status)
+            }
+            b"AUTH" => {
+                Ok(Command::Auth(String::from_utf8_lossy(tokens[0]).to_string(), Password(String::from_utf8_lossy(tokens[1]).to_string())))
+            }
+            b"INFO" => {
+                Ok(Command::Info)
+            }
+            b"PING" => {
+                Ok(Command::Ping(tokens.first().map(|message| String::from_utf8_lossy(message).to_string())))
+            }
+            b"ECHO" => {
+                Ok(Command::Echo(String::from_utf8_lossy(tokens[0]).to_string()))
+            }
+            b"HELP" => {
+                Ok(Command::Help(tokens.first().map(|name| String::from_utf8_lossy(name).to_uppercase())))
+            }
+            b"MODULE" => {
+                match (tokens[0].to_ascii_uppercase().as_slice(), tok_len) {
+                    (b"LIST", 1) => Ok(Command::ModuleList),
+                    (b"LOAD", 2) => Ok(Command::ModuleLoad(String::from_utf8_lossy(tokens[1]).to_string())),
+                    _ => Err(mismatch())
+                }
+            }
             b"QUIT" => {
                 Ok(Command::Quit)
             }
             b"SAVE" => {
                 Ok(Command::Save)
             }
-            b"ADD" => {
-                if tok_len < 2 {
-                    return Err(CommandError::ArgumentMismatch);
+            b"DUMP" => {
+                Ok(Command::Dump(String::from_utf8_lossy(tokens[0]).to_string()))
+            }
+            b"RESTORE" => {
+                // RESTORE <key> <ttl> <blob> [REPLACE]
+                let replace = match tok_len {
+                    3 => false,
+                    4 if tokens[3].eq_ignore_ascii_case(b"REPLACE") => true,
+                    _ => return Err(mismatch())
+                };
+                let ttl = String::from_utf8_lossy(tokens[1]).parse::<u64>().map_err(|_| invalid(tokens[1]))?;
+                Ok(Command::Restore(
+                    String::from_utf8_lossy(tokens[0]).to_string(),
+                    Some(ttl).filter(|ttl| *ttl > 0),
+                    String::from_utf8_lossy(tokens[2]).to_string(),
+                    replace
+                ))
+            }
+            b"MIGRATE" => {
+                // MIGRATE <host> <port> <key> [DESTROY]
+                let destroy = match tok_len {
+                    3 => false,
+                    4 if tokens[3].eq_ignore_ascii_case(b"DESTROY") => true,
+                    _ => return Err(mismatch())
+                };
+                let port = String::from_utf8_lossy(tokens[1]).parse::<u16>().map_err(|_| invalid(tokens[1]))?;
+                Ok(Command::Migrate(
+                    String::from_utf8_lossy(tokens[0]).to_string(),
+                    port,
+                    String::from_utf8_lossy(tokens[2]).to_string(),
+                    destroy
+                ))
+            }
+            b"VERIFY" => {
+                // VERIFY [<path> [JSON|CBOR]], against the save file without a path
+                let path = match tokens.first() {
+                    Some(path) if !path.is_empty() => Some(String::from_utf8_lossy(path).to_string()),
+                    Some(_) => return Err(mismatch()),
+                    None => None
+                };
+                let format = match tokens.get(1) {
+                    Some(format) => Some(String::from_utf8_lossy(format).parse::<SnapshotFormat>().map_err(|_| invalid(format))?),
+                    None => None
+                };
+                Ok(Command::Verify(path, format))
+            }
+            b"EXPORT" | b"IMPORT" => {
+                // EXPORT <path> [JSON|CBOR], likewise IMPORT
+                let path = match tokens.first() {
+                    Some(path) if !path.is_empty() => String::from_utf8_lossy(path).to_string(),
+                    _ => return Err(mismatch())
+                };
+                let format = match tokens.get(1) {
+                    Some(format) => Some(String::from_utf8_lossy(format).parse::<SnapshotFormat>().map_err(|_| invalid(format))?),
+                    None => None
+                };
+                if cmd.as_slice() == b"EXPORT" {
+                    Ok(Command::Export(path, format))
+                } else {
+                    Ok(Command::Import(path, format))
                 }
+            }
+            b"ADD" | b"SUB" | b"MUL" | b"DIV" => {
                 let ty = tokens[0];
-                let keys = tokens[1..]
-                            .into_iter()
+                // ADD <type> <key> [key ...] [SKIPMISSING]
+                let skip_missing = cmd.as_slice() == b"ADD" && tokens[tok_len - 1].eq_ignore_ascii_case(b"SKIPMISSING");
+                let key_tokens = &tokens[1..tok_len - skip_missing as usize];
+                if key_tokens.is_empty() {
+                    return Err(mismatch());
+                }
+                let keys = key_tokens
+                            .iter()
                             .map(|x| String::from_utf8_lossy(x).to_string())
                             .collect();
-                Ok(
-                    Command::Add(
-                        String::from_utf8_lossy(ty).to_string(),
-                        keys
-                    )
-                )
+                let ty = String::from_utf8_lossy(ty).to_string();
+                match cmd.as_slice() {
+                    b"SUB" => Ok(Command::Sub(ty, keys)),
+                    b"MUL" => Ok(Command::Mul(ty, keys)),
+                    b"DIV" => Ok(Command::Div(ty, keys)),
+                    _ => Ok(Command::Add(ty, keys, skip_missing))
+                }
+            }
+            b"AGG" => {
+                // AGG <op> <type> <key> [key ...] | AGG <op> <type> MATCH <pattern>
+                let aggregation = String::from_utf8_lossy(tokens[0]).parse::<Aggregation>().map_err(|_| invalid(tokens[0]))?;
+                let ty = String::from_utf8_lossy(tokens[1]).to_string();
+                match &tokens[2..] {
+                    [option, pattern] if option.eq_ignore_ascii_case(b"MATCH") => {
+                        Ok(Command::AggMatch(aggregation, ty, KeyPattern::from(String::from_utf8_lossy(pattern).as_ref())))
+                    }
+                    keys => Ok(Command::Agg(aggregation, ty, keys.iter().map(|key| String::from_utf8_lossy(key).to_string()).collect()))
+                }
+            }
+            b"ADDSTORE" | b"SUBSTORE" | b"MULSTORE" | b"DIVSTORE" => {
+                let destination = String::from_utf8_lossy(tokens[1]).to_string();
+                let mut arithmetic = vec![cmd[..3].to_vec(), tokens[0].to_vec()];
+                arithmetic.extend(tokens[2..].iter().map(|key| key.to_vec()));
+                Ok(Command::Store(destination, Box::new(Command::from_tokens(&arithmetic)?)))
+            }
+            b"FORMAT" => {
+                if !tokens[0].eq_ignore_ascii_case(b"FLOAT") {
+                    return Err(mismatch());
+                }
+                let format = match (tokens[1].to_ascii_uppercase().as_slice(), tok_len) {
+                    (b"DEFAULT", 2) => FloatFormat::Default,
+                    (b"EXACT", 2) => FloatFormat::Exact,
+                    (b"HEX", 2) => FloatFormat::Hex,
+                    (b"FIXED", 3) => {
+                        let places = String::from_utf8_lossy(tokens[2]).parse::<usize>();
+                        match places {
+                            Ok(places) => FloatFormat::Fixed(places),
+                            Err(_) => return Err(invalid(tokens[2]))
+                        }
+                    }
+                    _ => return Err(CommandError::InvalidFormatSpecified(String::from_utf8_lossy(&tokens[1..].join(&b' ')).to_string()))
+                };
+                Ok(Command::FormatFloat(format))
+            }
+            b"DRYRUN" => {
+                let command = Command::from_tokens(&arguments[1..])?;
+                if let Command::DryRun(_) = command {
+                    return Err(mismatch());
+                }
+                Ok(Command::DryRun(Box::new(command)))
+            }
+            b"WAIT" => {
+                let replicas = String::from_utf8_lossy(tokens[0]).parse::<u64>();
+                let timeout = String::from_utf8_lossy(tokens[1]).parse::<u64>();
+                match (replicas, timeout) {
+                    (Ok(replicas), Ok(timeout)) => Ok(Command::Wait(replicas, timeout)),
+                    (Err(_), _) => Err(invalid(tokens[0])),
+                    (_, Err(_)) => Err(invalid(tokens[1]))
+                }
+            }
+            b"SETLOCK" | b"EXTENDLOCK" => {
+                let key = String::from_utf8_lossy(tokens[0]).to_string();
+                let token = String::from_utf8_lossy(tokens[1]).to_string();
+                // A lock that never runs out would outlive a crashed holder, so a TTL is required.
+                let ttl = match String::from_utf8_lossy(tokens[2]).parse::<u64>() {
+                    Ok(ttl) if ttl > 0 => ttl,
+                    _ => return Err(invalid(tokens[2]))
+                };
+                if cmd.as_slice() == b"SETLOCK" {
+                    Ok(Command::SetLock(key, token, ttl))
+                } else {
+                    Ok(Command::ExtendLock(key, token, ttl))
+                }
+            }
+            b"RATELIMIT" => {
+                let max = String::from_utf8_lossy(tokens[1]).parse::<u64>().map_err(|_| invalid(tokens[1]))?;
+                let window = match String::from_utf8_lossy(tokens[2]).parse::<u64>() {
+                    Ok(window) if window > 0 => window,
+                    _ => return Err(invalid(tokens[2]))
+                };
+                Ok(Command::RateLimit(String::from_utf8_lossy(tokens[0]).to_string(), max, window))
+            }
+            b"RELEASELOCK" => Ok(Command::ReleaseLock(
+                String::from_utf8_lossy(tokens[0]).to_string(),
+                String::from_utf8_lossy(tokens[1]).to_string()
+            )),
+            b"WAITEXPIRE" => match String::from_utf8_lossy(tokens[1]).parse::<u64>() {
+                Ok(timeout) => Ok(Command::WaitExpire(String::from_utf8_lossy(tokens[0]).to_string(), timeout)),
+                Err(_) => Err(invalid(tokens[1]))
+            },
+            b"CONFIG" => {
+                let param = String::from_utf8_lossy(tokens[1]).to_lowercase();
+                match (tokens[0].to_ascii_uppercase().as_slice(), tok_len) {
+                    (b"GET", 2) => Ok(Command::ConfigGet(param)),
+                    (b"SET", 3) => Ok(Command::ConfigSet(param, String::from_utf8_lossy(tokens[2]).to_string())),
+                    _ => Err(mismatch())
+                }
+            }
+            b"HOTKEYS" => {
+                match tokens.first() {
+                    Some(n) => Ok(Command::HotKeys(String::from_utf8_lossy(n).parse::<usize>().map_err(|_| invalid(n))?)),
+                    None => Ok(Command::HotKeys(10))
+                }
+            }
+            b"SLOWLOG" => {
+                match (tokens[0].to_ascii_uppercase().as_slice(), tok_len) {
+                    (b"GET", 1) => Ok(Command::SlowLogGet(10)),
+                    (b"GET", 2) => {
+                        match String::from_utf8_lossy(tokens[1]).parse::<usize>() {
+                            Ok(count) => Ok(Command::SlowLogGet(count)),
+                            Err(_) => Err(invalid(tokens[1]))
+                        }
+                    }
+                    (b"LEN", 1) => Ok(Command::SlowLogLen),
+                    (b"RESET", 1) => Ok(Command::SlowLogReset),
+                    _ => Err(mismatch())
+                }
+            }
+            b"TRACE" => {
+                match (tokens[0].to_ascii_uppercase().as_slice(), tok_len) {
+                    (b"ON", 1) => Ok(Command::Trace(true)),
+                    (b"OFF", 1) => Ok(Command::Trace(false)),
+                    (b"GET", 2) => {
+                        match String::from_utf8_lossy(tokens[1]).parse::<u64>() {
+                            Ok(id) => Ok(Command::TraceGet(id)),
+                            Err(_) => Err(invalid(tokens[1]))
+                        }
+                    }
+                    (b"LIST", 1) => Ok(Command::TraceList(10)),
+                    (b"LIST", 2) => {
+                        match String::from_utf8_lossy(tokens[1]).parse::<usize>() {
+                            Ok(count) => Ok(Command::TraceList(count)),
+                            Err(_) => Err(invalid(tokens[1]))
+                        }
+                    }
+                    _ => Err(mismatch())
+                }
+            }
+            b"DEBUG" => {
+                match (tokens[0].to_ascii_uppercase().as_slice(), tok_len) {
+                    (b"SLEEP", 2) => {
+                        match String::from_utf8_lossy(tokens[1]).parse::<f64>() {
+                            Ok(seconds) if seconds.is_finite() && seconds >= 0.0 => Ok(Command::DebugSleep(seconds)),
+                            _ => Err(invalid(tokens[1]))
+                        }
+                    }
+                    (b"OBJECT", 2) => Ok(Command::DebugObject(String::from_utf8_lossy(tokens[1]).to_string())),
+                    (b"SET-EXPIRE-NOW", 2) => Ok(Command::DebugSetExpireNow(String::from_utf8_lossy(tokens[1]).to_string())),
+                    (b"SLEEP", _) | (b"OBJECT", _) | (b"SET-EXPIRE-NOW", _) => Err(mismatch()),
+                    _ => Err(CommandError::UnknownCommand(format!("{} {}", command_name, String::from_utf8_lossy(tokens[0]))))
+                }
+            }
+            b"CLIENT" => {
+                match (tokens[0].to_ascii_uppercase().as_slice(), tok_len) {
+                    (b"ID", 1) => Ok(Command::ClientId),
+                    (b"LIST", 1) => Ok(Command::ClientList),
+                    (b"KILL", 2) => Ok(Command::ClientKill(String::from_utf8_lossy(tokens[1]).to_string())),
+                    _ => Err(mismatch())
+                }
+            }
+            b"CLUSTER" => {
+                match (tokens[0].to_ascii_uppercase().as_slice(), tok_len) {
+                    (b"KEYSLOT", 2) => Ok(Command::ClusterKeySlot(String::from_utf8_lossy(tokens[1]).to_string())),
+                    (b"SLOTS", 1) => Ok(Command::ClusterSlots),
+                    (b"KEYSLOT", _) | (b"SLOTS", _) => Err(mismatch()),
+                    _ => Err(CommandError::UnknownCommand(format!("{} {}", command_name, String::from_utf8_lossy(tokens[0]))))
+                }
             }
-            _ => Err(CommandError::Unknown)
+            _ => Err(CommandError::UnknownCommand(command_name.clone()))
         }
     }
 }
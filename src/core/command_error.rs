@@ -1,8 +1,34 @@
-#[derive(Debug)]
+use thiserror::Error;
+
+#[derive(Debug, Error)]
 pub enum CommandError {
+    #[error("empty command")]
     NoInput,
-    ArgumentMismatch,
-    Unknown,
-    NoValidModeSpecified,
-    InvalidTtlSpecified
+    /// Command `String` got the wrong number of arguments, or arguments it can't make sense of.
+    #[error("wrong arguments for '{0}'")]
+    ArgumentMismatch(String),
+    /// Argument `param2` of command `param1` isn't a valid value, e.g. a score that isn't a number.
+    #[error("invalid argument '{1}' for '{0}'")]
+    InvalidArgument(String, String),
+    /// A key that wasn't valid UTF-8, with the invalid bytes replaced by U+FFFD.
+    #[error("key '{0}' isn't valid UTF-8")]
+    InvalidKey(String),
+    /// There's no command called `String`.
+    #[error("unknown command '{0}'")]
+    UnknownCommand(String),
+    #[error("invalid search mode '{0}', expected GLOB, REGEX or TRIE")]
+    NoValidModeSpecified(String),
+    #[error("invalid TTL '{0}', expected a number of seconds")]
+    InvalidTtlSpecified(String),
+    #[error("invalid float format '{0}', expected DEFAULT, EXACT, HEX or FIXED <places>")]
+    InvalidFormatSpecified(String),
+    /// A quoted argument starting at this byte offset was never closed.
+    #[error("unterminated quote starting at offset {0}")]
+    UnterminatedQuote(usize),
+    /// The backslash escape at this byte offset isn't one the tokenizer knows.
+    #[error("invalid escape sequence at offset {0}")]
+    InvalidEscape(usize),
+    /// A closing quote was followed by something other than whitespace, at this byte offset.
+    #[error("closing quote must be followed by whitespace at offset {0}")]
+    UnexpectedCharacter(usize)
 }
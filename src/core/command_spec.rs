@@ -0,0 +1,159 @@
+/// What a command is called, how many arguments it takes and how to use it.
+///
+/// `from_vec` checks the argument count against the spec before parsing the arguments, and HELP
+/// prints the syntax and summary.
+#[derive(Debug)]
+pub struct CommandSpec {
+    pub name: &'static str,
+    /// Fewest arguments after the name.
+    pub min_args: usize,
+    /// Most arguments after the name, `None` if there's no limit.
+    pub max_args: Option<usize>,
+    pub syntax: &'static str,
+    pub summary: &'static str
+}
+
+impl CommandSpec {
+    /// Whether the command can be given `count` arguments. Commands whose valid counts have gaps,
+    /// like ZADD's score and member pairs, check the rest when they parse.
+    pub fn accepts(&self, count: usize) -> bool {
+        count >= self.min_args && self.max_args.is_none_or(|max| count <= max)
+    }
+}
+
+const fn spec(name: &'static str, min_args: usize, max_args: Option<usize>, syntax: &'static str, summary: &'static str) -> CommandSpec {
+    CommandSpec { name, min_args, max_args, syntax, summary }
+}
+
+/// Every command `from_vec` understands, in alphabetical order.
+pub static COMMANDS: [CommandSpec; 121] = [
+    spec("ADD", 2, None, "ADD <type> <key> [key ...] [SKIPMISSING]", "Adds the values of the keys together as the type, counting missing keys as zero with SKIPMISSING."),
+    spec("ADDSTORE", 3, None, "ADDSTORE <type> <destination> <key> [key ...] [SKIPMISSING]", "Like ADD, storing the result at the destination."),
+    spec("AGG", 3, None, "AGG sum|min|max|avg|count <type> <key> [key ...] | AGG <op> <type> MATCH <pattern>", "Combines the values of the keys as the type. With MATCH, keys that don't hold the type are left out."),
+    spec("AUTH", 2, Some(2), "AUTH <user> <password>", "Logs in as a user, moving the connection into the user's namespace."),
+    spec("BF.ADD", 2, Some(2), "BF.ADD <key> <element>", "Adds an element to a Bloom filter, reserving one with the defaults if needed. Replies 1 if it's new."),
+    spec("BF.EXISTS", 2, Some(2), "BF.EXISTS <key> <element>", "Replies 1 if the element may be in the Bloom filter, 0 if it definitely isn't."),
+    spec("BF.RESERVE", 3, Some(3), "BF.RESERVE <key> <error_rate> <capacity>", "Creates an empty Bloom filter sized for capacity elements at the error rate."),
+    spec("BITFIELD", 4, None, "BITFIELD <key> [GET <type> <offset>] [SET <type> <offset> <value>] [INCRBY <type> <offset> <increment>] [OVERFLOW WRAP|SAT|FAIL]", "Reads and writes integers packed at bit offsets in a binary record."),
+    spec("BLPOP", 2, None, "BLPOP <key> [key ...] <timeout>", "Pops the first element of the first list that has one, waiting up to timeout milliseconds, or forever for 0, for one to be pushed."),
+    spec("BRPOP", 2, None, "BRPOP <key> [key ...] <timeout>", "Pops the last element of the first list that has one, waiting up to timeout milliseconds, or forever for 0, for one to be pushed."),
+    spec("CAST", 2, Some(2), "CAST <key> <type>", "Converts the value at the key to another type."),
+    spec("CHECKSUM", 1, Some(1), "CHECKSUM ON | OFF", "Makes every line the client sends end in * and its CRC32 in hex, and every reply end in a line of the same. Lines that don't match aren't run."),
+    spec("CLIENT", 1, Some(2), "CLIENT ID | LIST | KILL <id>", "Shows the current connection's ID, lists connections or closes one."),
+    spec("CLUSTER", 1, Some(2), "CLUSTER KEYSLOT <key> | SLOTS", "Shows the slot a key hashes to or which node owns which slots."),
+    spec("CONFIG", 2, Some(3), "CONFIG GET <parameter> | SET <parameter> <value>", "Reads or changes a configuration parameter."),
+    spec("COUNTER", 3, Some(4), "COUNTER <key> <i1-i64|u1-u63> <WRAP|SAT|FAIL> [value]", "Stores a counter of the width, which INCR and DECR keep in range following the overflow policy."),
+    spec("CURSOR", 2, Some(4), "CURSOR PAGE <name> <page> <size> | DEL <name> | DROP <name>", "Pages through, or drops, a cursor made by KEYS ... CURSOR."),
+    spec("DEBUG", 1, Some(2), "DEBUG SLEEP <seconds> | OBJECT <key> | SET-EXPIRE-NOW <key>", "Test support commands, only there when the server runs with --enable-debug."),
+    spec("DECR", 1, Some(1), "DECR <key>", "Subtracts 1 from the counter at the key, replying with its new value."),
+    spec("DECRBY", 2, Some(2), "DECRBY <key> <decrement>", "Subtracts from the counter at the key, replying with its new value."),
+    spec("DEL", 1, None, "DEL <key> [key ...] | DEL BYTAG <tag>", "Deletes keys and replies with how many existed."),
+    spec("DELPATTERN", 1, Some(1), "DELPATTERN [glob:|re:|pre:]<pattern>", "Deletes every key matching the pattern at once and replies with how many there were. A prefix picks the matcher instead of MODE."),
+    spec("DELTTL", 1, Some(1), "DELTTL <key>", "Removes the key's TTL so it never expires."),
+    spec("DISCARD", 0, Some(0), "DISCARD", "Drops the commands queued since MULTI."),
+    spec("DIV", 2, None, "DIV <type> <key> [key ...]", "Divides the value of the first key by the others as the type."),
+    spec("DIVSTORE", 3, None, "DIVSTORE <type> <destination> <key> [key ...]", "Like DIV, storing the result at the destination."),
+    spec("DRYRUN", 1, None, "DRYRUN <command> [argument ...]", "Describes what a command would change without running it."),
+    spec("DUMP", 1, Some(1), "DUMP <key>", "Serializes a record into a blob RESTORE can read."),
+    spec("ECHO", 1, Some(1), "ECHO <message>", "Replies with the message."),
+    spec("EVAL", 2, None, "EVAL <numkeys> [key ...] <script>", "Runs a Rhai script against the keys."),
+    spec("EVALSHA", 2, None, "EVALSHA <sha1> <numkeys> [key ...] [argument ...]", "Runs a script loaded with SCRIPT LOAD."),
+    spec("EXEC", 0, Some(0), "EXEC", "Runs the commands queued since MULTI."),
+    spec("EXISTS", 1, Some(1), "EXISTS <key>", "Replies 1 if the key exists, otherwise 0."),
+    spec("EXPIREPATTERN", 2, Some(2), "EXPIREPATTERN [glob:|re:|pre:]<pattern> <seconds|PERSIST>", "Sets the TTL of every key matching the pattern, or removes it with PERSIST, and replies with how many keys there were."),
+    spec("EXPORT", 1, Some(2), "EXPORT <path> [JSON|CBOR]", "Writes every key to a snapshot file."),
+    spec("EXTENDLOCK", 3, Some(3), "EXTENDLOCK <key> <token> <ttl>", "Gives a lock held with the token a new TTL in seconds."),
+    spec("FORMAT", 2, Some(3), "FORMAT FLOAT DEFAULT | EXACT | HEX | FIXED <places>", "Sets how this connection prints floats."),
+    spec("GEOADD", 4, None, "GEOADD <key> <longitude> <latitude> <member> [...]", "Adds members at positions to a geo set."),
+    spec("GEODIST", 3, Some(4), "GEODIST <key> <member> <member> [M|KM|MI|FT]", "Replies with the distance between two members."),
+    spec("GEOSEARCH", 6, None, "GEOSEARCH <key> FROMMEMBER <member> | FROMLONLAT <lon> <lat> BYRADIUS <radius> <unit> [ASC|DESC] [COUNT <n>] [WITHDIST]", "Finds the members within a radius."),
+    spec("GET", 1, None, "GET [type] <key> [DEFAULT <value>]", "Replies with the value at the key, optionally converted to a type."),
+    spec("GETCHUNKED", 1, Some(1), "GETCHUNKED <key>", "Replies with $<length> and then the bytes of a String or binary value, sent in chunks."),
+    spec("GETDEL", 1, Some(2), "GETDEL [type] <key>", "Replies with the value at the key like GET, and deletes the key."),
+    spec("GETEX", 1, Some(4), "GETEX [type] <key> [EX <seconds> | PERSIST]", "Replies with the value at the key like GET, and sets or removes its TTL."),
+    spec("GETRANGE", 3, Some(3), "GETRANGE <key> <start> <end>", "Replies with the bytes between two positions of a String or binary value."),
+    spec("HELP", 0, Some(1), "HELP [command]", "Lists the commands, or shows how to use one."),
+    spec("HISTORY", 1, Some(2), "HISTORY <key> [count]", "Lists the key's previous values, newest first."),
+    spec("HOTKEYS", 0, Some(1), "HOTKEYS [n]", "Lists the n most read and written keys, 10 by default, as <key> <reads> <writes>. Needs track-key-stats on."),
+    spec("IMPORT", 1, Some(2), "IMPORT <path> [JSON|CBOR]", "Loads the keys in a snapshot file."),
+    spec("INCR", 1, Some(1), "INCR <key>", "Adds 1 to the counter at the key, creating an i64 FAIL counter if needed, and replies with its new value."),
+    spec("INCRBY", 2, Some(2), "INCRBY <key> <increment>", "Adds to the counter at the key, replying with its new value."),
+    spec("INDEX", 1, Some(4), "INDEX CREATE <name> ON <field> | QUERY <name> <value> | DROP <name> | LIST", "Manages and queries metadata indexes."),
+    spec("INFO", 0, Some(0), "INFO", "Shows server statistics like connected and rejected clients."),
+    spec("JSON.GET", 1, Some(2), "JSON.GET <key> [path]", "Replies with the JSON at the path in the document."),
+    spec("JSON.SET", 3, None, "JSON.SET <key> <path> <json>", "Sets the value at the path in the document."),
+    spec("KEEPHISTORY", 2, Some(2), "KEEPHISTORY <key> <depth>", "Sets how many previous values to keep for the key."),
+    spec("KEYS", 1, Some(9), "KEYS [glob:|re:|pre:]<pattern> [SORT [ALPHA|LENGTH]] [MODIFIEDSINCE <unix time>] [OFFSET <n>] [LIMIT <n>] | [CURSOR <name> <ttl>]", "Lists the keys matching the pattern, a page of them, or saves them to a cursor. A prefix picks the matcher instead of MODE, SORT makes the order stable, MODIFIEDSINCE leaves out keys not written since then."),
+    spec("LLEN", 1, Some(1), "LLEN <key>", "Replies with the length of a list."),
+    spec("LPOP", 1, Some(1), "LPOP <key>", "Removes and replies with the first element of a list."),
+    spec("LPUSH", 2, None, "LPUSH <key> <element> [element ...]", "Pushes elements onto the start of a list, one at a time."),
+    spec("LRANGE", 3, Some(3), "LRANGE <key> <start> <stop>", "Lists the elements between two indexes."),
+    spec("MIGRATE", 3, Some(4), "MIGRATE <host> <port> <key> [DESTROY]", "Copies a key to another server."),
+    spec("MODE", 1, Some(1), "MODE GLOB | REGEX | TRIE", "Sets how KEYS patterns are matched."),
+    spec("MODULE", 1, Some(2), "MODULE LIST | LOAD <path>", "Lists the loaded modules, or loads one from a dynamic library."),
+    spec("MUL", 2, None, "MUL <type> <key> [key ...]", "Multiplies the values of the keys together as the type."),
+    spec("MULSTORE", 3, None, "MULSTORE <type> <destination> <key> [key ...]", "Like MUL, storing the result at the destination."),
+    spec("MULTI", 0, Some(0), "MULTI", "Starts queueing commands to run together with EXEC."),
+    spec("OBJECT", 1, Some(1), "OBJECT <key>", "Shows how the record at the key is stored."),
+    spec("PFADD", 2, None, "PFADD <key> <element> [element ...]", "Adds elements to a HyperLogLog."),
+    spec("PFCOUNT", 1, None, "PFCOUNT <key> [key ...]", "Estimates how many distinct elements the HyperLogLogs hold."),
+    spec("PFMERGE", 2, None, "PFMERGE <destination> <key> [key ...]", "Merges HyperLogLogs into the destination."),
+    spec("PING", 0, Some(1), "PING [message]", "Replies PONG, or the message, without touching any data."),
+    spec("QUIT", 0, Some(0), "QUIT", "Closes the connection."),
+    spec("RATELIMIT", 3, Some(3), "RATELIMIT <key> <max> <window>", "Counts a request against a limit of max per sliding window of seconds, replying allowed or denied and how many are left."),
+    spec("RELEASELOCK", 2, Some(2), "RELEASELOCK <key> <token>", "Frees a lock, only if it's held with the token."),
+    spec("RESTORE", 3, Some(4), "RESTORE <key> <ttl> <blob> [REPLACE]", "Recreates a record from a DUMP blob."),
+    spec("RESTOREVERSION", 2, Some(2), "RESTOREVERSION <key> <version>", "Puts back a previous value from the key's history."),
+    spec("RPOP", 1, Some(1), "RPOP <key>", "Removes and replies with the last element of a list."),
+    spec("RPUSH", 2, None, "RPUSH <key> <element> [element ...]", "Pushes elements onto the end of a list."),
+    spec("SAVE", 0, Some(0), "SAVE", "Writes the dataset to the save file now, rather than waiting for a save point."),
+    spec("SCANVALUES", 3, Some(6), "SCANVALUES <type> <min|-inf> <max|+inf> [LIMIT <n> | CURSOR <name> <ttl>]", "Lists the keys holding numbers of the type within a range, in key order. Looks at every key, so it's O(n)."),
+    spec("SCRIPT", 1, None, "SCRIPT LOAD <script> | EXISTS <sha1> [sha1 ...] | FLUSH", "Manages the script cache."),
+    spec("SET", 3, None, "SET <type> <key> <value> [EX <seconds>]", "Stores a value as the type."),
+    spec("SETCAS", 5, None, "SETCAS <type> <key> VERSION <n> | VALUE <old> <value>", "Stores a value only if the record hasn't changed."),
+    spec("SETLOCK", 3, Some(3), "SETLOCK <key> <token> <ttl>", "Takes a lock for ttl seconds, only if nobody holds it."),
+    spec("SETNULL", 2, Some(2), "SETNULL <type> <key>", "Stores a null of the type."),
+    spec("SETRANGE", 3, None, "SETRANGE <key> <offset> <value>", "Overwrites part of a String or binary value, padding it with zero bytes if needed."),
+    spec("SLOWLOG", 1, Some(2), "SLOWLOG GET [count] | LEN | RESET", "Reads or clears the log of slow commands."),
+    spec("STAT", 1, Some(1), "STAT <key>", "Shows when the key was created, last written and last read, in Unix time."),
+    spec("STATUS", 0, Some(0), "STATUS", "Shows whether the server has finished starting up."),
+    spec("SUB", 2, None, "SUB <type> <key> [key ...]", "Subtracts the values of the other keys from the first as the type."),
+    spec("SUBSTORE", 3, None, "SUBSTORE <type> <destination> <key> [key ...]", "Like SUB, storing the result at the destination."),
+    spec("TAG", 2, None, "TAG ADD <key> <tag> [tag ...] | DEL <key> <tag> [tag ...] | KEYS <tag> | LIST <key>", "Manages tags on keys."),
+    spec("TOUCH", 1, None, "TOUCH <key> [key ...]", "Updates the keys' last access time."),
+    spec("TRACE", 1, Some(2), "TRACE ON | OFF | GET <id> | LIST [count]", "Times where each of the client's commands spends its time, or reads back the timings."),
+    spec("TS.ADD", 3, Some(3), "TS.ADD <key> <timestamp|*> <value>", "Adds a sample to a time series, creating one if needed. * stamps it with the current time in milliseconds."),
+    spec("TS.CREATE", 1, Some(3), "TS.CREATE <key> [RETENTION <ms>]", "Creates an empty time series that keeps samples for the retention period, or forever."),
+    spec("TS.RANGE", 3, Some(6), "TS.RANGE <key> <from|-> <to|+> [AGG avg|min|max|sum|count <bucket ms>]", "Replies with a time series' samples in a range, optionally combined into buckets."),
+    spec("TTL", 1, Some(2), "TTL <key> [seconds]", "Replies with the key's TTL, or sets it."),
+    spec("TYPE", 1, Some(1), "TYPE <key>", "Replies with the type of the value at the key."),
+    spec("UNWATCH", 0, Some(0), "UNWATCH", "Forgets every watched key."),
+    spec("VERIFY", 0, Some(2), "VERIFY [<path> [JSON|CBOR]]", "Checks a snapshot, the save file by default, and lists the keys added (+), changed (~) and removed (-) since it was written."),
+    spec("VINDEX", 1, None, "VINDEX CREATE <name> PREFIX <prefix> DIM <n> [METRIC <metric>] | DROP <name> | LIST", "Manages vector indexes."),
+    spec("VSEARCH", 3, None, "VSEARCH <index> <k> <vector>", "Finds the k nearest vectors in an index."),
+    spec("WAIT", 2, Some(2), "WAIT <replicas> <timeout>", "Would wait for replicas to acknowledge writes, but there's no replication, so it's refused."),
+    spec("WAITEXPIRE", 2, Some(2), "WAITEXPIRE <key> <timeout>", "Waits up to timeout milliseconds, or forever for 0, for a key to expire or be deleted."),
+    spec("WATCH", 1, None, "WATCH <key> [key ...]", "Makes EXEC fail if the keys change first."),
+    spec("XACK", 3, None, "XACK <key> <group> <id> [id ...]", "Acknowledges entries, so they're no longer pending in the consumer group."),
+    spec("XADD", 4, None, "XADD <key> <id|*> <field> <value> [field value ...]", "Appends an entry to a stream."),
+    spec("XCLAIM", 5, None, "XCLAIM <key> <group> <consumer> <min-idle-ms> <id> [id ...]", "Hands entries pending for at least min-idle-ms over to the consumer."),
+    spec("XGROUP", 3, Some(5), "XGROUP CREATE <key> <group> <id|$> [MKSTREAM] | DESTROY <key> <group>", "Manages a stream's consumer groups."),
+    spec("XPENDING", 2, Some(2), "XPENDING <key> <group>", "Lists the entries delivered to the consumer group but not acknowledged yet."),
+    spec("XRANGE", 3, Some(5), "XRANGE <key> <start> <end> [COUNT <n>]", "Lists the stream entries between two IDs."),
+    spec("XREAD", 3, None, "XREAD [COUNT <n>] [BLOCK <ms>] STREAMS <key> [key ...] <id> [id ...]", "Reads entries after the IDs, optionally waiting for them."),
+    spec("XREADGROUP", 6, None, "XREADGROUP GROUP <group> <consumer> [COUNT <n>] [BLOCK <ms>] STREAMS <key> [key ...] <id|>> [...]", "Reads entries as a consumer in a group, new ones for > or its pending ones after an ID."),
+    spec("ZADD", 3, None, "ZADD <key> <score> <member> [score member ...]", "Adds members with scores to a sorted set."),
+    spec("ZINCRBY", 3, Some(3), "ZINCRBY <key> <increment> <member>", "Adds to a member's score."),
+    spec("ZRANGE", 3, Some(4), "ZRANGE <key> <start> <stop> [WITHSCORES]", "Lists the members between two ranks."),
+    spec("ZRANGEBYSCORE", 3, Some(4), "ZRANGEBYSCORE <key> <min> <max> [WITHSCORES]", "Lists the members between two scores."),
+    spec("ZREM", 2, None, "ZREM <key> <member> [member ...]", "Removes members from a sorted set."),
+    spec("ZSCORE", 2, Some(2), "ZSCORE <key> <member>", "Replies with a member's score.")
+];
+
+/// Looks a command up by its uppercase name.
+pub fn find(name: &str) -> Option<&'static CommandSpec> {
+    COMMANDS
+        .binary_search_by(|spec| spec.name.cmp(name))
+        .ok()
+        .map(|i| &COMMANDS[i])
+}
@@ -0,0 +1,45 @@
+use std::fmt;
+
+use super::bitfield::{fit, BitFieldType, Overflow};
+
+/// An integer of a set width and signedness, which INCR and DECR keep within that width following
+/// its overflow policy, whatever they're asked to add.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Counter {
+    value: i64,
+    pub ty: BitFieldType,
+    pub overflow: Overflow
+}
+
+impl Default for Counter {
+    /// What INCR and DECR create when the key doesn't exist: a 64 bit signed counter that refuses
+    /// to overflow.
+    fn default() -> Self {
+        Counter { value: 0, ty: BitFieldType { signed: true, bits: 64 }, overflow: Overflow::Fail }
+    }
+}
+
+impl Counter {
+    /// A counter starting at `value`, which has to fit `ty`.
+    pub fn new(ty: BitFieldType, overflow: Overflow, value: i64) -> Result<Counter, String> {
+        let value = fit(ty, value as i128, Overflow::Fail).ok_or(format!("{} doesn't fit in {}", value, ty))?;
+        Ok(Counter { value, ty, overflow })
+    }
+
+    pub fn value(&self) -> i64 {
+        self.value
+    }
+
+    /// Adds `by`, following the overflow policy if the result doesn't fit. Returns the new value,
+    /// or `None`, leaving the counter alone, if the policy is to fail.
+    pub fn incr_by(&mut self, by: i64) -> Option<i64> {
+        self.value = fit(self.ty, self.value as i128 + by as i128, self.overflow)?;
+        Some(self.value)
+    }
+}
+
+impl fmt::Display for Counter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.value)
+    }
+}
@@ -0,0 +1,116 @@
+/// How f32/f64 values are written back to a client.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum FloatFormat {
+    /// Rust's `Display`, the shortest decimal that parses back to the same value.
+    #[default]
+    Default,
+    /// Shortest round-trip digits in scientific notation, e.g. `1.5e-7`.
+    Exact,
+    /// C99 style hexadecimal floats, e.g. `0x1.8p+1`. Always bit-for-bit.
+    Hex,
+    /// A fixed number of decimal places. May lose precision.
+    Fixed(usize)
+}
+
+pub trait FloatFormattable {
+    fn format_with(&self, format: &FloatFormat) -> String;
+}
+
+impl FloatFormattable for f64 {
+    fn format_with(&self, format: &FloatFormat) -> String {
+        match format {
+            FloatFormat::Default => format!("{}", self),
+            FloatFormat::Exact => format!("{:e}", self),
+            FloatFormat::Hex => to_hex_float(*self),
+            FloatFormat::Fixed(places) => format!("{:.*}", places, self)
+        }
+    }
+}
+
+impl FloatFormattable for f32 {
+    fn format_with(&self, format: &FloatFormat) -> String {
+        match format {
+            FloatFormat::Default => format!("{}", self),
+            FloatFormat::Exact => format!("{:e}", self),
+            // Every f32 is exactly representable as an f64.
+            FloatFormat::Hex => to_hex_float(*self as f64),
+            FloatFormat::Fixed(places) => format!("{:.*}", places, self)
+        }
+    }
+}
+
+/// Formats a value as a hexadecimal float, e.g. `-0x1.91eb851eb851fp+1`.
+pub fn to_hex_float(value: f64) -> String {
+    if value.is_nan() {
+        return String::from("NaN");
+    }
+    if value.is_infinite() {
+        return String::from(if value < 0.0 { "-inf" } else { "inf" });
+    }
+
+    let bits = value.to_bits();
+    let sign = if bits >> 63 == 1 { "-" } else { "" };
+    let biased_exponent = ((bits >> 52) & 0x7ff) as i32;
+    let mantissa = bits & ((1u64 << 52) - 1);
+
+    let (lead, exponent) = match (biased_exponent, mantissa) {
+        (0, 0) => return format!("{}0x0p+0", sign),
+        (0, _) => (0, -1022),
+        _ => (1, biased_exponent - 1023)
+    };
+    let digits = format!("{:013x}", mantissa);
+    let digits = digits.trim_end_matches('0');
+    if digits.is_empty() {
+        format!("{}0x{}p{:+}", sign, lead, exponent)
+    } else {
+        format!("{}0x{}.{}p{:+}", sign, lead, digits, exponent)
+    }
+}
+
+/// Parses a hexadecimal float such as `0x1.8p+1`. Returns `None` for anything else.
+pub fn parse_hex_float(s: &str) -> Option<f64> {
+    let s = s.trim();
+    let (negative, s) = match s.as_bytes().first() {
+        Some(b'-') => (true, &s[1..]),
+        Some(b'+') => (false, &s[1..]),
+        _ => (false, s)
+    };
+    let s = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X"))?;
+    let (digits, exponent) = match s.find(['p', 'P']) {
+        Some(i) => (&s[..i], s[i + 1..].parse::<i32>().ok()?),
+        None => (s, 0)
+    };
+    let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+    if whole.is_empty() && fraction.is_empty() {
+        return None;
+    }
+
+    let mut mantissa: u64 = 0;
+    for c in whole.chars().chain(fraction.chars()) {
+        let digit = c.to_digit(16)? as u64;
+        if mantissa >> 52 != 0 {
+            // More significant digits than an f64 can hold exactly.
+            return None;
+        }
+        mantissa = (mantissa << 4) | digit;
+    }
+
+    let mut exponent = exponent.checked_sub(4 * fraction.len() as i32)?;
+    let mut value = mantissa as f64;
+    while exponent > 1023 {
+        value *= power_of_two(1023);
+        exponent -= 1023;
+    }
+    while exponent < -1022 {
+        value *= power_of_two(-1022);
+        exponent += 1022;
+    }
+    value *= power_of_two(exponent);
+
+    Some(if negative { -value } else { value })
+}
+
+/// Builds 2^exponent for exponents in the normal f64 range.
+fn power_of_two(exponent: i32) -> f64 {
+    f64::from_bits(((exponent + 1023) as u64) << 52)
+}
@@ -0,0 +1,189 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::str::FromStr;
+
+/// Bits of precision per coordinate. Interleaved, that's a 52 bit geohash, ~0.6m cells.
+const STEP: u32 = 26;
+const EARTH_RADIUS_M: f64 = 6372797.560856;
+
+/// The unit a GEODIST or GEOSEARCH distance is given or returned in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GeoUnit {
+    Meters,
+    Kilometers,
+    Miles,
+    Feet
+}
+
+impl GeoUnit {
+    pub fn meters(&self) -> f64 {
+        match self {
+            GeoUnit::Meters => 1.0,
+            GeoUnit::Kilometers => 1000.0,
+            GeoUnit::Miles => 1609.34,
+            GeoUnit::Feet => 0.3048
+        }
+    }
+}
+
+impl FromStr for GeoUnit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "m" => Ok(GeoUnit::Meters),
+            "km" => Ok(GeoUnit::Kilometers),
+            "mi" => Ok(GeoUnit::Miles),
+            "ft" => Ok(GeoUnit::Feet),
+            _ => Err(format!("Invalid unit \"{}\", expected m, km, mi or ft", s))
+        }
+    }
+}
+
+/// Where a GEOSEARCH is centered.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GeoOrigin {
+    Member(String),
+    LonLat(f64, f64)
+}
+
+/// The options of a GEOSEARCH ... BYRADIUS query.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeoSearch {
+    pub origin: GeoOrigin,
+    pub radius: f64,
+    pub unit: GeoUnit,
+    /// `Some(true)` for ASC, `Some(false)` for DESC, `None` leaves results in geohash order.
+    pub ascending: Option<bool>,
+    pub count: Option<usize>,
+    pub with_dist: bool
+}
+
+/// Checks a longitude/latitude pair is on the map.
+pub fn valid_lon_lat(lon: f64, lat: f64) -> bool {
+    (-180.0..=180.0).contains(&lon) && (-90.0..=90.0).contains(&lat)
+}
+
+/// Great-circle distance in meters.
+pub fn distance(lon1: f64, lat1: f64, lon2: f64, lat2: f64) -> f64 {
+    let (lat1, lat2) = (lat1.to_radians(), lat2.to_radians());
+    let half_dlat = (lat2 - lat1) / 2.0;
+    let half_dlon = (lon2 - lon1).to_radians() / 2.0;
+    let a = half_dlat.sin().powi(2) + lat1.cos() * lat2.cos() * half_dlon.sin().powi(2);
+    2.0 * EARTH_RADIUS_M * a.sqrt().asin()
+}
+
+/// Which of the `2^step` cells `value` falls in along a coordinate spanning `min..max`.
+fn cell(value: f64, min: f64, max: f64, step: u32) -> u64 {
+    let cells = (1u64 << step) as f64;
+    (((value - min) / (max - min) * cells) as u64).min((1u64 << step) - 1)
+}
+
+/// Interleaves the bits of two cell indexes, longitude in the odd positions.
+fn interleave(lat_cell: u64, lon_cell: u64, step: u32) -> u64 {
+    (0..step).fold(0, |hash, bit| {
+        hash | ((lat_cell >> bit) & 1) << (2 * bit) | ((lon_cell >> bit) & 1) << (2 * bit + 1)
+    })
+}
+
+pub fn geohash(lon: f64, lat: f64) -> u64 {
+    interleave(cell(lat, -90.0, 90.0, STEP), cell(lon, -180.0, 180.0, STEP), STEP)
+}
+
+/// Members with coordinates, indexed by geohash so nearby members sit next to each other.
+#[derive(Debug, Clone, Default)]
+pub struct GeoSet {
+    positions: HashMap<String, (f64, f64)>,
+    by_hash: BTreeSet<(u64, String)>
+}
+
+impl GeoSet {
+    pub fn new() -> GeoSet {
+        GeoSet::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    /// Adds a member or moves it. Returns true if the member is new.
+    pub fn add(&mut self, member: &str, lon: f64, lat: f64) -> bool {
+        let previous = self.positions.insert(member.to_string(), (lon, lat));
+        if let Some((lon, lat)) = previous {
+            self.by_hash.remove(&(geohash(lon, lat), member.to_string()));
+        }
+        self.by_hash.insert((geohash(lon, lat), member.to_string()));
+        previous.is_none()
+    }
+
+    /// Every member with its `(longitude, latitude)`, in no particular order.
+    pub fn members(&self) -> impl Iterator<Item = (&String, (f64, f64))> {
+        self.positions.iter().map(|(member, position)| (member, *position))
+    }
+
+    /// A member's `(longitude, latitude)`.
+    pub fn position(&self, member: &str) -> Option<(f64, f64)> {
+        self.positions.get(member).copied()
+    }
+
+    /// Members within `radius` meters of a point, with their distance in meters.
+    ///
+    /// Only the geohash cells overlapping the search area's bounding box are scanned, at the
+    /// finest precision where the box still covers just a few cells.
+    pub fn within_radius(&self, lon: f64, lat: f64, radius: f64) -> Vec<(&String, f64)> {
+        // Bounding box of the circle on the sphere. It spans every longitude if it reaches a pole.
+        let angle = radius / EARTH_RADIUS_M;
+        let lat_delta = angle.to_degrees();
+        let (lat_min, lat_max) = (lat - lat_delta, lat + lat_delta);
+        let lon_sin = angle.sin() / lat.to_radians().cos();
+        let (lon_min, lon_max) = if lat_min <= -90.0 || lat_max >= 90.0 || angle >= std::f64::consts::FRAC_PI_2 || lon_sin >= 1.0 {
+            (-180.0, 180.0)
+        } else {
+            let lon_delta = lon_sin.asin().to_degrees();
+            (lon - lon_delta, lon + lon_delta)
+        };
+        let (lat_min, lat_max) = (lat_min.max(-90.0), lat_max.min(90.0));
+
+        // The finest step where a cell is at least as big as the search box.
+        let span = (lat_max - lat_min).max((lon_max - lon_min) / 2.0);
+        let mut step = STEP;
+        while step > 1 && 180.0 / (1u64 << step) as f64 <= span {
+            step -= 1;
+        }
+        let shift = 2 * (STEP - step);
+
+        let lat_cells = cell(lat_min, -90.0, 90.0, step)..=cell(lat_max, -90.0, 90.0, step);
+        let mut lon_ranges = vec![(lon_min, lon_max)];
+        // Split a box crossing the antimeridian into its two halves.
+        if lon_min < -180.0 {
+            lon_ranges = vec![(-180.0, lon_max), (lon_min + 360.0, 180.0)];
+        } else if lon_max > 180.0 {
+            lon_ranges = vec![(lon_min, 180.0), (-180.0, lon_max - 360.0)];
+        }
+
+        let mut found = Vec::new();
+        let mut scanned = HashSet::new();
+        for lat_cell in lat_cells {
+            for (lon_min, lon_max) in &lon_ranges {
+                for lon_cell in cell(*lon_min, -180.0, 180.0, step)..=cell(*lon_max, -180.0, 180.0, step) {
+                    if !scanned.insert((lat_cell, lon_cell)) {
+                        continue;
+                    }
+                    let prefix = interleave(lat_cell, lon_cell, step) << shift;
+                    let end = prefix + (1u64 << shift);
+                    for (_, member) in self.by_hash.range((prefix, String::new())..(end, String::new())) {
+                        let (member_lon, member_lat) = self.positions[member];
+                        let dist = distance(lon, lat, member_lon, member_lat);
+                        if dist <= radius {
+                            found.push((member, dist));
+                        }
+                    }
+                }
+            }
+        }
+        found
+    }
+}
@@ -0,0 +1,81 @@
+/// log2 of the register count. 2^14 registers gives a standard error of about 0.81%.
+const PRECISION: u32 = 14;
+const REGISTERS: usize = 1 << PRECISION;
+
+/// A HyperLogLog sketch that estimates how many distinct elements were added, in a fixed 16KB.
+#[derive(Debug, Clone)]
+pub struct HyperLogLog {
+    registers: Vec<u8>
+}
+
+impl Default for HyperLogLog {
+    fn default() -> Self {
+        HyperLogLog { registers: vec![0; REGISTERS] }
+    }
+}
+
+impl HyperLogLog {
+    pub fn new() -> HyperLogLog {
+        HyperLogLog::default()
+    }
+
+    /// Adds an element. Returns true if the estimate may have changed.
+    pub fn add(&mut self, element: &[u8]) -> bool {
+        let hash = hash(element);
+        let index = (hash >> (64 - PRECISION)) as usize;
+        // Rank of the first set bit in the remaining bits, with a sentinel so it's never past the end.
+        let rank = ((hash << PRECISION) | (1 << (PRECISION - 1))).leading_zeros() as u8 + 1;
+        if rank > self.registers[index] {
+            self.registers[index] = rank;
+            return true;
+        }
+        false
+    }
+
+    /// The raw registers, one rank per bucket.
+    pub fn registers(&self) -> &[u8] {
+        &self.registers
+    }
+
+    /// Sets one register directly, e.g. when loading a sketch from a snapshot.
+    pub fn set_register(&mut self, index: usize, rank: u8) -> Result<(), String> {
+        let register = self.registers.get_mut(index).ok_or(format!("Register {} is out of range", index))?;
+        *register = rank;
+        Ok(())
+    }
+
+    /// Folds another sketch into this one, so it counts the union of both.
+    pub fn merge(&mut self, other: &HyperLogLog) {
+        for (register, other) in self.registers.iter_mut().zip(&other.registers) {
+            *register = (*register).max(*other);
+        }
+    }
+
+    pub fn count(&self) -> u64 {
+        let m = REGISTERS as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / m);
+        let sum: f64 = self.registers.iter().map(|&r| 2f64.powi(-(r as i32))).sum();
+        let estimate = alpha * m * m / sum;
+
+        let zeros = self.registers.iter().filter(|&&r| r == 0).count();
+        if estimate <= 2.5 * m && zeros > 0 {
+            // Linear counting is more accurate while many registers are still empty.
+            return (m * (m / zeros as f64).ln()).round() as u64;
+        }
+        estimate.round() as u64
+    }
+}
+
+/// FNV-1a followed by the SplitMix64 finalizer, so the high bits used for the register index are well mixed.
+pub(crate) fn hash(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for &byte in bytes {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash ^= hash >> 30;
+    hash = hash.wrapping_mul(0xbf58476d1ce4e5b9);
+    hash ^= hash >> 27;
+    hash = hash.wrapping_mul(0x94d049bb133111eb);
+    hash ^ (hash >> 31)
+}
@@ -0,0 +1,83 @@
+use serde_json::{Map, Value};
+
+/// One step into a JSON document: an object field or an array index.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PathSegment {
+    Field(String),
+    Index(usize)
+}
+
+/// Parses a path such as `$.user.emails[0]`, `user.emails[0]` or `[2].name`.
+///
+/// An empty path, `$` and `.` all refer to the whole document.
+pub fn parse_path(path: &str) -> Result<Vec<PathSegment>, String> {
+    let path = path.strip_prefix('$').unwrap_or(path);
+    let mut segments = Vec::new();
+    let mut chars = path.chars().peekable();
+    let mut field = String::new();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '.' => {
+                if !field.is_empty() {
+                    segments.push(PathSegment::Field(std::mem::take(&mut field)));
+                }
+            }
+            '[' => {
+                if !field.is_empty() {
+                    segments.push(PathSegment::Field(std::mem::take(&mut field)));
+                }
+                let mut index = String::new();
+                loop {
+                    match chars.next() {
+                        Some(']') => break,
+                        Some(c) => index.push(c),
+                        None => return Err(format!("Unclosed \"[\" in path \"{}\"", path))
+                    }
+                }
+                let index = index.parse::<usize>().map_err(|_| format!("Invalid index \"{}\" in path \"{}\"", index, path))?;
+                segments.push(PathSegment::Index(index));
+            }
+            c => field.push(c)
+        }
+    }
+    if !field.is_empty() {
+        segments.push(PathSegment::Field(field));
+    }
+    Ok(segments)
+}
+
+/// Returns the value at `path`, if every step of it exists.
+pub fn get_path<'a>(value: &'a Value, path: &[PathSegment]) -> Option<&'a Value> {
+    path.iter().try_fold(value, |value, segment| match segment {
+        PathSegment::Field(field) => value.get(field),
+        PathSegment::Index(index) => value.get(index)
+    })
+}
+
+/// Replaces the value at `path`, creating missing object fields along the way.
+///
+/// Array indexes must already exist, except one past the end, which appends.
+pub fn set_path(value: &mut Value, path: &[PathSegment], new_value: Value) -> Result<(), String> {
+    let Some((segment, rest)) = path.split_first() else {
+        *value = new_value;
+        return Ok(());
+    };
+    match segment {
+        PathSegment::Field(field) => {
+            if value.is_null() {
+                *value = Value::Object(Map::new());
+            }
+            let object = value.as_object_mut().ok_or(format!("Can't read field \"{}\" of a non-object", field))?;
+            set_path(object.entry(field.clone()).or_insert(Value::Null), rest, new_value)
+        }
+        PathSegment::Index(index) => {
+            let array = value.as_array_mut().ok_or(format!("Can't read index {} of a non-array", index))?;
+            if *index == array.len() {
+                array.push(Value::Null);
+            }
+            let element = array.get_mut(*index).ok_or(format!("Index {} is out of bounds", index))?;
+            set_path(element, rest, new_value)
+        }
+    }
+}
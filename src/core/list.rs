@@ -0,0 +1,72 @@
+use std::collections::VecDeque;
+use std::fmt;
+
+/// Which end of a list to push to or pop from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum End {
+    Left,
+    Right
+}
+
+/// Elements in the order they were pushed, added and taken from either end.
+#[derive(Debug, Clone, Default)]
+pub struct List {
+    elements: VecDeque<String>
+}
+
+impl List {
+    pub fn new() -> List {
+        List::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.elements.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.elements.is_empty()
+    }
+
+    /// Pushes elements one at a time, so pushing `a b c` on the left leaves `c` first. Returns
+    /// the new length.
+    pub fn push(&mut self, end: End, elements: &[String]) -> usize {
+        for element in elements {
+            match end {
+                End::Left => self.elements.push_front(element.clone()),
+                End::Right => self.elements.push_back(element.clone())
+            }
+        }
+        self.elements.len()
+    }
+
+    pub fn pop(&mut self, end: End) -> Option<String> {
+        match end {
+            End::Left => self.elements.pop_front(),
+            End::Right => self.elements.pop_back()
+        }
+    }
+
+    /// Elements by index from `start` to `stop` inclusive. Negative indexes count from the end,
+    /// -1 being the last.
+    pub fn range(&self, start: i64, stop: i64) -> Vec<&String> {
+        let len = self.len() as i64;
+        let start = if start < 0 { (len + start).max(0) } else { start };
+        let stop = if stop < 0 { len + stop } else { stop.min(len - 1) };
+        if start > stop {
+            return Vec::new();
+        }
+        self.elements.range(start as usize..=stop as usize).collect()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &String> {
+        self.elements.iter()
+    }
+}
+
+impl fmt::Display for List {
+    /// Writes the elements first to last.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let elements: Vec<&str> = self.elements.iter().map(String::as_str).collect();
+        write!(f, "{}", elements.join(" "))
+    }
+}
@@ -0,0 +1,79 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::str::FromStr;
+
+use super::record::Record;
+
+/// The record metadata a secondary index can be built on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MetadataField {
+    /// The Rust type the value is stored as, e.g. `i64` or `alloc::string::String`.
+    TypeName,
+    /// The type name the client used, e.g. `String`.
+    DesiredTypeName
+}
+
+impl MetadataField {
+    fn value_of<'a, T>(&self, record: &'a Record<T>) -> &'a str {
+        match self {
+            MetadataField::TypeName => &record.type_name,
+            MetadataField::DesiredTypeName => &record.desired_type_name
+        }
+    }
+}
+
+impl FromStr for MetadataField {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "type_name" => Ok(MetadataField::TypeName),
+            "desired_type_name" | "user_type" => Ok(MetadataField::DesiredTypeName),
+            _ => Err(format!("Invalid field \"{}\", expected type_name or desired_type_name", s))
+        }
+    }
+}
+
+impl fmt::Display for MetadataField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MetadataField::TypeName => write!(f, "type_name"),
+            MetadataField::DesiredTypeName => write!(f, "desired_type_name")
+        }
+    }
+}
+
+/// Keys grouped by the value of one metadata field, so INDEX QUERY doesn't have to scan the map.
+#[derive(Debug, Clone)]
+pub struct MetadataIndex {
+    pub field: MetadataField,
+    keys: HashMap<String, BTreeSet<String>>
+}
+
+impl MetadataIndex {
+    pub fn new(field: MetadataField) -> MetadataIndex {
+        MetadataIndex { field, keys: HashMap::new() }
+    }
+
+    pub fn insert<T>(&mut self, key: &str, record: &Record<T>) {
+        self.keys
+            .entry(self.field.value_of(record).to_string())
+            .or_default()
+            .insert(key.to_string());
+    }
+
+    pub fn remove<T>(&mut self, key: &str, record: &Record<T>) {
+        let value = self.field.value_of(record);
+        if let Some(keys) = self.keys.get_mut(value) {
+            keys.remove(key);
+            if keys.is_empty() {
+                self.keys.remove(value);
+            }
+        }
+    }
+
+    /// Every key whose field equals `value`, in order.
+    pub fn query(&self, value: &str) -> Vec<&String> {
+        self.keys.get(value).map(|keys| keys.iter().collect()).unwrap_or_default()
+    }
+}
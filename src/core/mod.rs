@@ -1,6 +1,30 @@
+pub mod backing_store;
+pub mod bitfield;
+pub mod bloom_filter;
+pub mod codec;
 pub mod command;
 pub mod command_error;
+pub mod command_spec;
+pub mod counter;
+pub mod float_format;
+pub mod geo;
+pub mod hyper_log_log;
+pub mod json_path;
+pub mod list;
+pub mod metadata_index;
+pub mod module;
+pub mod radix_tree;
 pub mod record;
+pub mod record_history;
+pub mod smirk_error;
 pub mod smirk_map;
 pub mod smirk_messages;
 pub mod smirk_search_mode;
+pub mod snapshot;
+pub mod snapshot_keys;
+pub mod sorted_set;
+pub mod stream;
+pub mod tag_index;
+pub mod timeseries;
+pub mod tokenizer;
+pub mod vector;
@@ -0,0 +1,214 @@
+use std::ffi::{c_char, CStr};
+use std::path::Path;
+use std::sync::RwLock;
+
+use libloading::Library;
+
+use super::codec::{self, ValueType};
+use super::command_spec::{self, CommandSpec};
+use super::smirk_map::SmirkMap;
+
+/// The smirk version, compiler and target a module library has to be built with. Modules share
+/// Rust types with the server, and Rust only lays those out the same way for the same code built
+/// by the same compiler for the same target, so a library built any other way can't be loaded.
+pub const ABI: &CStr = match CStr::from_bytes_with_nul(
+    concat!("smirk ", env!("CARGO_PKG_VERSION"), " built by ", env!("SMIRK_RUSTC_VERSION"), " for ", env!("SMIRK_TARGET"), "\0").as_bytes()
+) {
+    Ok(abi) => abi,
+    Err(_) => panic!("the module ABI string has a nul in it")
+};
+
+/// Runs a module command over its arguments, the command name left off, and returns the reply.
+/// Replies should end in a newline like the built-in commands' do. An error is sent to the client
+/// as a line of its own.
+pub type CommandHandler = fn(&mut SmirkMap, &[Vec<u8>]) -> Result<Vec<u8>, String>;
+
+/// A command a module adds to the server.
+pub struct ModuleCommand {
+    /// The name, arity and HELP text. The name should be uppercase.
+    pub spec: CommandSpec,
+    /// Which of the arguments are keys, so namespaces, key ACLs and cluster routing cover them.
+    pub keys: fn(&[Vec<u8>]) -> Vec<usize>,
+    pub handler: CommandHandler
+}
+
+/// For commands that don't touch any keys.
+pub fn no_keys(_: &[Vec<u8>]) -> Vec<usize> {
+    Vec::new()
+}
+
+/// For commands whose first argument is their only key.
+pub fn first_key(arguments: &[Vec<u8>]) -> Vec<usize> {
+    if arguments.is_empty() { Vec::new() } else { vec![0] }
+}
+
+/// Every argument is a key.
+pub fn all_keys(arguments: &[Vec<u8>]) -> Vec<usize> {
+    (0..arguments.len()).collect()
+}
+
+/// What a module's init function is handed to add its commands and types with. Nothing is
+/// added until the init function returns, and then only if none of the names are taken.
+pub struct Registrar {
+    name: String,
+    commands: Vec<ModuleCommand>,
+    types: Vec<(String, Box<dyn ValueType>)>
+}
+
+impl Registrar {
+    pub fn new(name: &str) -> Self {
+        Registrar { name: name.to_string(), commands: Vec::new(), types: Vec::new() }
+    }
+
+    pub fn command(&mut self, command: ModuleCommand) -> &mut Self {
+        self.commands.push(command);
+        self
+    }
+
+    /// Adds a type SET and GET take by `name`, as `codec::register` does.
+    pub fn value_type(&mut self, name: &str, value_type: impl ValueType + 'static) -> &mut Self {
+        self.types.push((name.to_string(), Box::new(value_type)));
+        self
+    }
+}
+
+/// A module that's been loaded, for MODULE LIST.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModuleInfo {
+    pub name: String,
+    /// The library it came from, `None` for modules the embedding program installed itself.
+    pub path: Option<String>,
+    pub commands: Vec<String>,
+    pub types: Vec<String>
+}
+
+static MODULES: RwLock<Vec<ModuleInfo>> = RwLock::new(Vec::new());
+static COMMANDS: RwLock<Vec<&'static ModuleCommand>> = RwLock::new(Vec::new());
+
+/// Adds a registrar's commands and types to the server. Modules are installed for the life of the
+/// process.
+pub fn install(registrar: Registrar) -> Result<(), String> {
+    install_from(registrar, None)
+}
+
+fn install_from(registrar: Registrar, path: Option<String>) -> Result<(), String> {
+    let mut modules = MODULES.write().unwrap();
+    let mut commands = COMMANDS.write().unwrap();
+    if modules.iter().any(|module| module.name == registrar.name) {
+        return Err(format!("A module called \"{}\" is already loaded", registrar.name));
+    }
+    for (i, command) in registrar.commands.iter().enumerate() {
+        let name = command.spec.name;
+        if name.is_empty() || name.contains(char::is_whitespace) || name != name.to_uppercase() {
+            return Err(format!("Module \"{}\" has a command with an invalid name \"{}\"", registrar.name, name));
+        }
+        let taken = command_spec::find(name).is_some()
+            || commands.iter().any(|command| command.spec.name == name)
+            || registrar.commands[..i].iter().any(|command| command.spec.name == name);
+        if taken {
+            return Err(format!("Module \"{}\" can't add {}, there's already a command called that", registrar.name, name));
+        }
+    }
+    for (i, (name, _)) in registrar.types.iter().enumerate() {
+        if codec::find(name).is_some() || registrar.types[..i].iter().any(|(other, _)| other == name) {
+            return Err(format!("Module \"{}\" can't add {}, there's already a type called that", registrar.name, name));
+        }
+    }
+
+    let info = ModuleInfo {
+        name: registrar.name,
+        path,
+        commands: registrar.commands.iter().map(|command| command.spec.name.to_string()).collect(),
+        types: registrar.types.iter().map(|(name, _)| name.clone()).collect()
+    };
+    for (name, value_type) in registrar.types {
+        codec::register_boxed(&name, value_type)?;
+    }
+    for command in registrar.commands {
+        commands.push(Box::leak(Box::new(command)));
+    }
+    modules.push(info);
+    Ok(())
+}
+
+/// Loads a module from a dynamic library and installs it, returning its name.
+///
+/// The library has to export its init function with `smirk_module!`, and be built with the same
+/// `ABI`. That's checked through a C function before anything of Rust's own ABI is touched. The
+/// module is named after the library's file, without a `lib` prefix.
+pub fn load(path: &Path) -> Result<String, String> {
+    let failed = |reason: String| format!("Couldn't load module {}: {}", path.display(), reason);
+    let name = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy())
+        .map(|stem| stem.strip_prefix("lib").unwrap_or(&stem).to_string())
+        .ok_or_else(|| failed(String::from("not a file")))?;
+    // SAFETY: loading a library runs its initialisers, which is what loading a module is asking for.
+    let library = unsafe { Library::new(path) }.map_err(|e| failed(e.to_string()))?;
+    // SAFETY: smirk_module! declares both functions with these types. The ABI function only
+    // returns a pointer to a static C string, and init is only called once it matches ours, so
+    // the Registrar it's handed has the layout it was built for.
+    let registrar = unsafe {
+        let abi = library
+            .get::<unsafe extern "C" fn() -> *const c_char>(b"smirk_module_abi")
+            .map_err(|_| failed(String::from("it wasn't declared with smirk_module!")))?;
+        let abi = CStr::from_ptr(abi());
+        if abi != ABI {
+            return Err(failed(format!("it's {}, this is {}", abi.to_string_lossy(), ABI.to_string_lossy())));
+        }
+        let init = library
+            .get::<unsafe extern "C" fn(*mut Registrar)>(b"smirk_module_init")
+            .map_err(|_| failed(String::from("it wasn't declared with smirk_module!")))?;
+        let mut registrar = Registrar::new(&name);
+        init(&mut registrar);
+        registrar
+    };
+    install_from(registrar, Some(path.display().to_string())).map_err(failed)?;
+    // The module's commands and types point into the library, so it stays loaded.
+    std::mem::forget(library);
+    Ok(name)
+}
+
+/// Looks up a command a module added.
+pub fn find_command(name: &str) -> Option<&'static ModuleCommand> {
+    COMMANDS.read().unwrap().iter().find(|command| command.spec.name == name).copied()
+}
+
+/// Every command modules have added, in the order they were loaded.
+pub fn commands() -> Vec<&'static ModuleCommand> {
+    COMMANDS.read().unwrap().clone()
+}
+
+/// Every module that's been loaded, in the order they were loaded.
+pub fn modules() -> Vec<ModuleInfo> {
+    MODULES.read().unwrap().clone()
+}
+
+/// Declares a module library's init function, which is handed a `Registrar` to add the module's
+/// commands and types to. Build the library as a `cdylib` and load it with `--load-module`.
+///
+/// ```ignore
+/// fn init(registrar: &mut smirk::core::module::Registrar) {
+///     registrar.command(ModuleCommand { spec: ..., keys: first_key, handler: search });
+/// }
+///
+/// smirk::smirk_module!(init);
+/// ```
+#[macro_export]
+macro_rules! smirk_module {
+    ($init:path) => {
+        #[no_mangle]
+        pub extern "C" fn smirk_module_abi() -> *const ::std::ffi::c_char {
+            $crate::core::module::ABI.as_ptr()
+        }
+
+        /// # Safety
+        ///
+        /// `registrar` has to point to a live `Registrar` laid out as this library expects, which
+        /// the server makes sure of by checking `smirk_module_abi` first.
+        #[no_mangle]
+        pub unsafe extern "C" fn smirk_module_init(registrar: *mut $crate::core::module::Registrar) {
+            $init(&mut *registrar)
+        }
+    };
+}
@@ -0,0 +1,261 @@
+use std::mem;
+
+/// A set of keys stored as a compressed trie, for prefix searches.
+///
+/// Runs of bytes with no branches in them share one edge, and every node counts the keys at or
+/// below it, so counting the keys under a prefix only walks the prefix. Edges are split on bytes,
+/// not chars, but a key is only ever read back whole, so it is always valid UTF-8 again.
+#[derive(Debug, Clone, Default)]
+pub struct RadixTree {
+    root: Node
+}
+
+#[derive(Debug, Clone, Default)]
+struct Node {
+    /// The bytes on the edge from the parent. Only the root's is empty.
+    label: Vec<u8>,
+    is_key: bool,
+    /// Keys ending here or below.
+    count: usize,
+    /// Sorted by the first byte of their labels, which no two children share.
+    children: Vec<Node>
+}
+
+impl Node {
+    fn leaf(label: &[u8]) -> Node {
+        Node { label: label.to_vec(), is_key: true, count: 1, children: Vec::new() }
+    }
+
+    fn child_index(&self, first: u8) -> Result<usize, usize> {
+        self.children.binary_search_by_key(&first, |child| child.label[0])
+    }
+
+    fn child(&self, first: u8) -> Option<&Node> {
+        self.child_index(first).ok().map(|i| &self.children[i])
+    }
+
+    /// Splits the label after `at` bytes, moving everything below into a new child.
+    fn split(&mut self, at: usize) {
+        let tail = Node {
+            label: self.label.split_off(at),
+            is_key: self.is_key,
+            count: self.count,
+            children: mem::take(&mut self.children)
+        };
+        self.is_key = false;
+        self.children = vec![tail];
+    }
+
+    /// Folds an only child into this node once it no longer needs its own branch.
+    fn merge_only_child(&mut self) {
+        if self.is_key || self.children.len() != 1 {
+            return;
+        }
+        let child = self.children.pop().unwrap();
+        self.label.extend(child.label);
+        self.is_key = child.is_key;
+        self.children = child.children;
+    }
+}
+
+fn common_prefix_len(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b).take_while(|(a, b)| a == b).count()
+}
+
+impl RadixTree {
+    pub fn new() -> RadixTree {
+        RadixTree::default()
+    }
+
+    /// How many keys are stored.
+    pub fn len(&self) -> usize {
+        self.root.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.count == 0
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        let mut node = &self.root;
+        let mut rest = key.as_bytes();
+        while let Some(&first) = rest.first() {
+            match node.child(first) {
+                Some(child) if rest.starts_with(&child.label) => {
+                    rest = &rest[child.label.len()..];
+                    node = child;
+                }
+                _ => return false
+            }
+        }
+        node.is_key
+    }
+
+    /// Adds a key. Returns false if it was already there. The empty key is allowed, and lives at the root.
+    pub fn insert(&mut self, key: &str) -> bool {
+        if self.contains(key) {
+            return false;
+        }
+        let mut node = &mut self.root;
+        let mut rest = key.as_bytes();
+        loop {
+            node.count += 1;
+            let Some(&first) = rest.first() else {
+                node.is_key = true;
+                return true;
+            };
+            let i = match node.child_index(first) {
+                Ok(i) => i,
+                Err(i) => {
+                    node.children.insert(i, Node::leaf(rest));
+                    return true;
+                }
+            };
+            let common = common_prefix_len(&node.children[i].label, rest);
+            if common < node.children[i].label.len() {
+                node.children[i].split(common);
+            }
+            rest = &rest[common..];
+            node = &mut node.children[i];
+        }
+    }
+
+    /// Removes a key, merging away any branch it leaves pointless. Returns false if it wasn't there.
+    pub fn remove(&mut self, key: &str) -> bool {
+        if !self.contains(key) {
+            return false;
+        }
+        let mut node = &mut self.root;
+        let mut rest = key.as_bytes();
+        let mut is_root = true;
+        loop {
+            node.count -= 1;
+            let Some(&first) = rest.first() else {
+                node.is_key = false;
+                break;
+            };
+            let i = node.child_index(first).unwrap();
+            if node.children[i].count == 1 {
+                // The key is the only one down this branch, so all of it goes.
+                node.children.remove(i);
+                break;
+            }
+            rest = &rest[node.children[i].label.len()..];
+            node = &mut node.children[i];
+            is_root = false;
+        }
+        if !is_root {
+            node.merge_only_child();
+        }
+        true
+    }
+
+    /// Finds the node holding every key that starts with `prefix`, along with the key bytes
+    /// leading down to it, which run past the prefix when it ends partway along an edge.
+    fn find(&self, prefix: &str) -> Option<(Vec<u8>, &Node)> {
+        let mut node = &self.root;
+        let mut path = Vec::new();
+        let mut rest = prefix.as_bytes();
+        while let Some(&first) = rest.first() {
+            let child = node.child(first)?;
+            let common = common_prefix_len(&child.label, rest);
+            if common < child.label.len() && common < rest.len() {
+                return None;
+            }
+            path.extend(&child.label);
+            rest = &rest[common..];
+            node = child;
+        }
+        Some((path, node))
+    }
+
+    /// How many keys start with `prefix`.
+    pub fn count_prefix(&self, prefix: &str) -> usize {
+        self.find(prefix).map_or(0, |(_, node)| node.count)
+    }
+
+    /// The keys starting with `prefix`, in byte order. They're built one at a time as the
+    /// iterator is walked, so nothing is collected up front.
+    pub fn keys_with_prefix(&self, prefix: &str) -> Keys<'_> {
+        match self.find(prefix) {
+            Some((path, node)) => Keys { stack: vec![(node, path.len() - node.label.len())], path },
+            None => Keys { stack: Vec::new(), path: Vec::new() }
+        }
+    }
+
+    /// The keys starting with `prefix` that sort after `after`, in byte order, for picking a
+    /// listing back up where an earlier one stopped.
+    pub fn keys_with_prefix_after<'a>(&'a self, prefix: &'a str, after: &str) -> impl Iterator<Item = String> + 'a {
+        let keys = if after < prefix {
+            self.keys_with_prefix(prefix)
+        } else if after.starts_with(prefix) {
+            self.keys_after(after.as_bytes())
+        } else {
+            Keys { stack: Vec::new(), path: Vec::new() }
+        };
+        keys.take_while(move |key| key.starts_with(prefix))
+    }
+
+    /// Every key that sorts after `after`. Walks down along `after`, leaving the subtrees that
+    /// branch off above it on the stack, so none of the keys before it are visited.
+    fn keys_after(&self, after: &[u8]) -> Keys<'_> {
+        let mut stack = Vec::new();
+        let mut path = Vec::new();
+        let mut node = &self.root;
+        loop {
+            let depth = path.len();
+            let Some(&first) = after.get(depth) else {
+                // `after` is this node's key, and everything below it sorts after that.
+                stack.extend(node.children.iter().rev().map(|child| (child, depth)));
+                break;
+            };
+            stack.extend(node.children.iter().rev().take_while(|child| child.label[0] > first).map(|child| (child, depth)));
+            let Some(child) = node.child(first) else {
+                break;
+            };
+            let rest = &after[depth..];
+            let common = common_prefix_len(&child.label, rest);
+            if common == child.label.len() {
+                path.extend(&child.label);
+                node = child;
+                continue;
+            }
+            // The edge leaves `after` partway along, either running past its end or at a byte that
+            // sorts above it, in which case the whole subtree comes after it.
+            if common == rest.len() || child.label[common] > rest[common] {
+                stack.push((child, depth));
+            }
+            break;
+        }
+        Keys { stack, path }
+    }
+
+    /// Every key, in byte order.
+    pub fn keys(&self) -> Keys<'_> {
+        self.keys_with_prefix("")
+    }
+}
+
+/// Iterator returned by [`RadixTree::keys_with_prefix`].
+pub struct Keys<'a> {
+    /// Nodes still to visit, each with the length of the path above its label.
+    stack: Vec<(&'a Node, usize)>,
+    path: Vec<u8>
+}
+
+impl Iterator for Keys<'_> {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        while let Some((node, depth)) = self.stack.pop() {
+            self.path.truncate(depth);
+            self.path.extend(&node.label);
+            let depth = self.path.len();
+            self.stack.extend(node.children.iter().rev().map(|child| (child, depth)));
+            if node.is_key {
+                return Some(String::from_utf8_lossy(&self.path).into_owned());
+            }
+        }
+        None
+    }
+}
@@ -1,16 +1,36 @@
-use std::time::SystemTime;
+use std::any::Any;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 
+/// A stored value. It's shared so a reader can keep hold of it after the map's lock is released,
+/// and a write copies it first if a reader still has it.
+pub type SharedValue = Arc<dyn Any + Send + Sync>;
+
+#[derive(Clone)]
 pub struct Record<T> {
     pub value: T,
     pub ttl: Option<u64>,
     pub ttl_start: SystemTime,
     pub type_name: String,
-    pub desired_type_name: String
+    pub desired_type_name: String,
+    /// When the key was first stored. Overwriting it with SET keeps this, deleting it doesn't.
+    pub created: SystemTime,
+    /// When the record was last written, the same moments its version changes.
+    pub modified: SystemTime,
+    /// When the value was last read or TOUCHed.
+    pub last_access: SystemTime,
+    /// Bumped from the map's counter on every write, so WATCH can tell if the record changed.
+    pub version: u64
 }
 
+/// An explicit null stored under a key, distinct from the key being absent.
+pub struct Null;
+
 pub trait RecordLike<T> {
     fn is_expired(&self) -> bool;
     fn get_ttl(&self) -> Option<u64>;
+    /// The moment the record expires, or `None` if it never does.
+    fn expires_at(&self) -> Option<SystemTime>;
 }
 
 impl<T> RecordLike<T> for Record<T> {
@@ -38,4 +58,79 @@ impl<T> RecordLike<T> for Record<T> {
         }
         None
     }
+    fn expires_at(&self) -> Option<SystemTime> {
+        self.ttl.map(|ttl| self.ttl_start + Duration::from_secs(ttl))
+    }
+}
+
+/// Whether a record has a TTL, and if so whether it has run out.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TtlState {
+    Persistent,
+    Expiring,
+    Expired
+}
+
+/// A read-only view of a record that hides its boxed value.
+pub struct RecordView<'a> {
+    key: &'a String,
+    record: &'a Record<SharedValue>
+}
+
+impl<'a> RecordView<'a> {
+    pub fn new(key: &'a String, record: &'a Record<SharedValue>) -> Self {
+        RecordView { key, record }
+    }
+
+    pub fn key(&self) -> &'a str {
+        self.key
+    }
+
+    /// The Rust type the value is stored as, e.g. `i64` or `Vec<u8>`.
+    pub fn type_name(&self) -> &'a str {
+        &self.record.type_name
+    }
+
+    /// The type name the client asked for when it set the value.
+    pub fn desired_type_name(&self) -> &'a str {
+        &self.record.desired_type_name
+    }
+
+    pub fn created(&self) -> SystemTime {
+        self.record.created
+    }
+
+    pub fn modified(&self) -> SystemTime {
+        self.record.modified
+    }
+
+    pub fn last_access(&self) -> SystemTime {
+        self.record.last_access
+    }
+
+    /// Seconds since the record was last read or TOUCHed.
+    pub fn idle(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(self.record.last_access)
+            .unwrap_or_default()
+            .as_secs()
+    }
+
+    /// Seconds until the record expires, or `None` if it never does.
+    pub fn ttl(&self) -> Option<u64> {
+        self.record.get_ttl()
+    }
+
+    pub fn ttl_state(&self) -> TtlState {
+        match self.record.ttl {
+            None => TtlState::Persistent,
+            Some(_) if self.record.is_expired() => TtlState::Expired,
+            Some(_) => TtlState::Expiring
+        }
+    }
+
+    /// The value, if it is stored as a `T`.
+    pub fn value<T: 'static>(&self) -> Option<&'a T> {
+        self.record.value.downcast_ref::<T>()
+    }
 }
@@ -0,0 +1,64 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::SystemTime;
+
+/// A value a key used to hold, kept as text so it can be set again.
+#[derive(Debug, Clone)]
+pub struct HistoryEntry {
+    pub version: u64,
+    /// When this value was written.
+    pub written: SystemTime,
+    pub desired_type_name: String,
+    /// The value as GET prints it, `None` for a null.
+    pub value: Option<String>
+}
+
+/// The last few values of keys with history turned on, newest first.
+///
+/// History is off unless `default_depth` or a key's own depth is above zero.
+#[derive(Debug, Default)]
+pub struct RecordHistory {
+    pub default_depth: usize,
+    depths: HashMap<String, usize>,
+    entries: HashMap<String, VecDeque<HistoryEntry>>
+}
+
+impl RecordHistory {
+    /// How many old values are kept for key.
+    pub fn depth(&self, key: &str) -> usize {
+        self.depths.get(key).copied().unwrap_or(self.default_depth)
+    }
+
+    /// Overrides how many old values are kept for one key. `0` turns its history off.
+    pub fn set_depth(&mut self, key: &str, depth: usize) {
+        self.depths.insert(key.to_string(), depth);
+        self.truncate(key);
+    }
+
+    /// Keeps a value a key is about to lose, dropping the oldest once the key's depth is reached.
+    pub fn push(&mut self, key: &str, entry: HistoryEntry) {
+        if self.depth(key) == 0 {
+            return;
+        }
+        self.entries.entry(key.to_string()).or_default().push_front(entry);
+        self.truncate(key);
+    }
+
+    /// Up to `count` old values of key, newest first.
+    pub fn get(&self, key: &str, count: usize) -> Vec<&HistoryEntry> {
+        self.entries.get(key).map(|entries| entries.iter().take(count).collect()).unwrap_or_default()
+    }
+
+    pub fn find(&self, key: &str, version: u64) -> Option<&HistoryEntry> {
+        self.entries.get(key)?.iter().find(|entry| entry.version == version)
+    }
+
+    fn truncate(&mut self, key: &str) {
+        let depth = self.depth(key);
+        if let Some(entries) = self.entries.get_mut(key) {
+            entries.truncate(depth);
+            if entries.is_empty() {
+                self.entries.remove(key);
+            }
+        }
+    }
+}
@@ -0,0 +1,22 @@
+use std::io;
+
+use thiserror::Error;
+
+use super::command_error::CommandError;
+use super::smirk_messages::SmirkMessages;
+
+/// Anything that stops a command from being handled.
+///
+/// Handlers return it instead of panicking, and the client gets it back as `-ERR <message>`.
+#[derive(Debug, Error)]
+pub enum SmirkError {
+    #[error(transparent)]
+    Command(#[from] CommandError),
+    /// A KEYS pattern that doesn't compile in the current search mode, and why.
+    #[error("invalid pattern '{0}': {1}")]
+    InvalidPattern(String, String),
+    #[error("{}", .0.to_string().trim_end())]
+    Data(#[from] SmirkMessages),
+    #[error(transparent)]
+    Io(#[from] io::Error)
+}
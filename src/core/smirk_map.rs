@@ -1,22 +1,241 @@
-use std::any::{Any, type_name};
-use std::collections::HashMap;
+use std::any::type_name;
+use std::collections::{HashMap, HashSet};
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
 use std::str::FromStr;
-use std::time::SystemTime;
+use std::sync::Arc;
+use std::sync::mpsc::Sender;
+use std::time::{Duration, SystemTime};
 
-use num::CheckedAdd;
+use bigdecimal::BigDecimal;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use num::{CheckedAdd, CheckedDiv, CheckedMul, CheckedSub, Float, Zero};
 
+use super::bitfield::{self, BitFieldOp, BitFieldType, Overflow};
+use super::bloom_filter::BloomFilter;
+use super::codec::{self, Codec};
+use super::float_format::FloatFormat;
+use super::counter::Counter;
+use super::float_format::parse_hex_float;
+use super::geo::GeoSet;
+use super::hyper_log_log::HyperLogLog;
+use super::json_path::{get_path, parse_path, set_path};
+use super::list::{End, List};
+use super::metadata_index::MetadataIndex;
+use super::radix_tree::RadixTree;
 use super::smirk_messages::SmirkMessages;
 use super::smirk_search_mode::SmirkSearchMode;
-use super::record::{ Record, RecordLike };
-use trie::Trie;
+use super::sorted_set::SortedSet;
+use super::stream::{Stream, StreamFields, StreamId};
+use super::tag_index::TagIndex;
+use super::timeseries::TimeSeries;
+use super::vector::{Vector, VectorIndex};
+use super::record_history::{HistoryEntry, RecordHistory};
+use super::record::{ Null, Record, RecordLike, RecordView, SharedValue, TtlState };
+
+/// Reads a stored value as a `T`, telling a null apart from a value of another type.
+pub fn downcast<'a, T: 'static>(key: &str, value: &'a SharedValue) -> Result<&'a T, SmirkMessages> {
+    if let Some(real_value) = value.downcast_ref::<T>() {
+        return Ok(real_value);
+    }
+    if value.is::<Null>() {
+        return Err(SmirkMessages::NullValue(String::from(key)));
+    }
+    Err(SmirkMessages::TypeMismatch(String::from(key), type_name::<T>().to_string()))
+}
+
+/// The bytes of a String or binary value.
+pub fn shared_bytes<'a>(key: &str, value: &'a SharedValue) -> Result<&'a [u8], SmirkMessages> {
+    match downcast::<Vec<u8>>(key, value) {
+        Ok(bytes) => Ok(bytes.as_slice()),
+        Err(SmirkMessages::TypeMismatch(_, _)) => Ok(downcast::<String>(key, value)?.as_bytes()),
+        Err(e) => Err(e)
+    }
+}
+
+/// `value_len` for the values whose length doesn't come from their text, `None` for the rest.
+fn own_len(value: &SharedValue) -> Option<usize> {
+    if let Some(bytes) = value.downcast_ref::<Vec<u8>>() {
+        return Some(bytes.len());
+    }
+    if let Some(text) = value.downcast_ref::<String>() {
+        return Some(text.len());
+    }
+    if let Some(set) = value.downcast_ref::<SortedSet>() {
+        return Some(set.len());
+    }
+    if let Some(stream) = value.downcast_ref::<Stream>() {
+        return Some(stream.len());
+    }
+    if let Some(list) = value.downcast_ref::<List>() {
+        return Some(list.len());
+    }
+    if let Some(set) = value.downcast_ref::<GeoSet>() {
+        return Some(set.len());
+    }
+    if let Some(vector) = value.downcast_ref::<Vector>() {
+        return Some(vector.0.len());
+    }
+    if let Some(hll) = value.downcast_ref::<HyperLogLog>() {
+        return Some(hll.count() as usize);
+    }
+    if let Some(filter) = value.downcast_ref::<BloomFilter>() {
+        return Some(filter.items() as usize);
+    }
+    if let Some(series) = value.downcast_ref::<TimeSeries>() {
+        return Some(series.len());
+    }
+    if value.is::<Null>() {
+        return Some(0);
+    }
+    None
+}
+
+/// Renders a stored value as text, the same way GET writes it.
+pub fn render(key: &str, value: &SharedValue) -> Result<String, SmirkMessages> {
+    if let Some(rendered) = codec::CODECS.iter().find_map(|codec| (codec.renderer)(value, &FloatFormat::Default)) {
+        return Ok(rendered);
+    }
+    if let Some(set) = value.downcast_ref::<SortedSet>() {
+        return Ok(set.to_string());
+    }
+    if let Some(list) = value.downcast_ref::<List>() {
+        return Ok(list.to_string());
+    }
+    if let Some(counter) = value.downcast_ref::<Counter>() {
+        return Ok(counter.to_string());
+    }
+    if let Some(value) = value.downcast_ref::<Vec<u8>>() {
+        return Ok(String::from_utf8_lossy(value).to_string());
+    }
+    if let Some(serialized) = codec::registered().iter().find_map(|value_type| value_type.serialize(value, &FloatFormat::Default)) {
+        return Ok(String::from_utf8_lossy(&serialized).to_string());
+    }
+    if value.is::<Null>() {
+        return Err(SmirkMessages::NullValue(String::from(key)));
+    }
+    Err(SmirkMessages::TypeMismatch(String::from(key), String::from("String")))
+}
+
+/// A stored value as a `T` to change in place, or `None` if it's another type.
+///
+/// If a reader outside the lock still holds the value, it's copied first so the reader keeps
+/// seeing the value as it was.
+fn make_mut<T: Clone + Send + Sync + 'static>(value: &mut SharedValue) -> Option<&mut T> {
+    if Arc::get_mut(value).is_none() {
+        let copy = value.downcast_ref::<T>()?.clone();
+        *value = Arc::new(copy);
+    }
+    Arc::get_mut(value)?.downcast_mut::<T>()
+}
+
+/// Whether SCANVALUES can compare values of the type SET calls `ty`: the ones with arithmetic.
+pub fn is_numeric(ty: &str) -> bool {
+    codec::CODECS.iter().any(|codec| codec.name == ty && codec.arithmetic.is_some())
+}
+
+/// The value as a BigDecimal, if it's stored as the codec's type. NaN and the infinities aren't
+/// numbers in any range, and don't parse as one.
+fn numeric_value(value: &SharedValue, codec: &Codec) -> Option<BigDecimal> {
+    (codec.renderer)(value, &FloatFormat::Default)?.parse().ok()
+}
+
+/// The most bytes SETRANGE will grow a record to.
+pub const MAX_RANGE_BYTES: usize = 512 * 1024 * 1024;
+
+/// What SETCAS expects a record to still be before it overwrites it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CasExpected {
+    /// The record's version. `0` expects the key not to exist.
+    Version(u64),
+    /// The record's value, as GET prints it.
+    Value(String)
+}
 
 pub struct SmirkMap {
     pub search_mode: SmirkSearchMode,
-    pub map: HashMap<String, Record<Box<dyn Any + Send>>>,
-    pub trie: Trie<String>
+    pub map: HashMap<String, Record<SharedValue>>,
+    pub trie: RadixTree,
+    /// Named VSEARCH indexes.
+    pub vector_indexes: HashMap<String, VectorIndex>,
+    /// Named INDEX secondary indexes, kept up to date as records are stored and removed.
+    pub metadata_indexes: HashMap<String, MetadataIndex>,
+    /// TTL in seconds given to values written without one. `None` keeps them forever.
+    pub default_ttl: Option<u64>,
+    /// Up to this many seconds are added at random to every TTL set, so keys written together
+    /// don't all expire together.
+    pub ttl_jitter: u64,
+    /// The longest TTL in seconds a key may be given, after jitter. `None` means no limit. Keys
+    /// without a TTL are left alone.
+    pub max_ttl: Option<u64>,
+    /// TAG tags. They survive the key being overwritten and go when it is deleted.
+    pub tags: TagIndex,
+    /// Old values of keys with history turned on, for HISTORY and RESTOREVERSION.
+    pub history: RecordHistory,
+    /// Writes and deletes since the map was created, for save points to count changes with.
+    pub changes: u64,
+    /// Records removed since the map was created, so blocked clients waiting on keys to go can
+    /// tell when to look again.
+    pub removals: u64,
+    /// Overwritten values at least this long, as `value_len` measures them, are sent to
+    /// `reclaimer` to be dropped instead of being dropped under the map's lock. `0` turns it off.
+    pub lazy_free_threshold: usize,
+    /// Where values too big to drop in passing go, if anywhere.
+    pub reclaimer: Option<Sender<SharedValue>>,
+    /// Values sent to `reclaimer` since the map was created.
+    pub lazy_frees: u64,
+    /// Keys whose records have a TTL, so expired ones can be found without looking at every key.
+    expiring: HashSet<String>,
+    /// The last version handed to a record. Versions are never reused, even across keys.
+    last_version: u64
 }
 
 impl SmirkMap {
+    /// An empty SmirkMap that searches keys with `search_mode`.
+    pub fn new(search_mode: SmirkSearchMode) -> SmirkMap {
+        SmirkMap {
+            search_mode,
+            map: HashMap::new(),
+            trie: RadixTree::new(),
+            vector_indexes: HashMap::new(),
+            metadata_indexes: HashMap::new(),
+            default_ttl: None,
+            ttl_jitter: 0,
+            max_ttl: None,
+            tags: TagIndex::new(),
+            history: RecordHistory::default(),
+            changes: 0,
+            removals: 0,
+            lazy_free_threshold: 0,
+            reclaimer: None,
+            lazy_frees: 0,
+            expiring: HashSet::new(),
+            last_version: 0
+        }
+    }
+
+    /// An empty SmirkMap that searches and gives out TTLs the way this one does, for records to be
+    /// built in apart from it and then moved in with `absorb`.
+    pub fn empty_like(&self) -> SmirkMap {
+        let mut empty = SmirkMap::new(self.search_mode);
+        empty.default_ttl = self.default_ttl;
+        empty.ttl_jitter = self.ttl_jitter;
+        empty.max_ttl = self.max_ttl;
+        empty
+    }
+
+    /// Moves every record of other into this map, replacing records at the same keys, and
+    /// returns how many there were.
+    pub fn absorb(&mut self, other: SmirkMap) -> usize {
+        let count = other.map.len();
+        for (key, record) in other.map {
+            self.insert_record(&key, record);
+        }
+        count
+    }
+
     /// Retrieves a value from the SmirkMap.
     ///
     /// # Arguments
@@ -29,38 +248,322 @@ impl SmirkMap {
     ///
     /// * `Err(String)`: The error message.
     pub fn get<'a, T: 'static>(&'a self, key: &String) -> Result<&'a T, SmirkMessages> {
-        if let Some(record) = self.map.get(key) {
-            if let Some(real_value) = record.value.downcast_ref::<T>() {
-                return Ok(real_value);
-            }
-            return Err(SmirkMessages::TypeMismatch(String::from(key), type_name::<T>().to_string()));
+        match self.map.get(key) {
+            Some(record) => downcast(key, &record.value),
+            None => Err(SmirkMessages::KeyNotFound(String::from(key)))
         }
+    }
 
-        return Err(SmirkMessages::KeyNotFound(String::from(key)));
+    /// The value at key, shared rather than borrowed so it can still be read once the map's lock
+    /// is released. Read it with `downcast`, `shared_bytes` or `render`.
+    pub fn get_shared(&self, key: &String) -> Result<SharedValue, SmirkMessages> {
+        self.map
+            .get(key)
+            .map(|record| record.value.clone())
+            .ok_or(SmirkMessages::KeyNotFound(String::from(key)))
     }
 
     pub fn binary_set(
         &mut self,
-        key: &String,
+        key: &str,
         value: Vec<u8>,
-        desired_type_name: &String,
+        desired_type_name: &str,
     ) -> Result<SmirkMessages, SmirkMessages> {
-        let record: Record<Box<dyn Any + Send + 'static>> = Record {
-            value: Box::new(value.clone()),
-            ttl: None,
+        let record: Record<SharedValue> = Record {
+            value: Arc::new(value.clone()),
+            ttl: self.default_ttl.map(|ttl| self.apply_ttl_policy(ttl)),
             ttl_start: SystemTime::now(),
             type_name: "Vec<u8>".to_string(),
-            desired_type_name: desired_type_name.clone(),
+            desired_type_name: desired_type_name.to_string(),
+            created: self.created(key),
+            modified: SystemTime::now(),
+            last_access: SystemTime::now(),
+            version: 0
         };
 
-        self.map.insert(key.clone(), record);
+        self.insert_record(key, record);
         Ok(SmirkMessages::SetKey(
-            key.clone(),
+            key.to_string(),
             "Vec<u8>".to_string(),
-            desired_type_name.clone(),
+            desired_type_name.to_string(),
         ))
     }
 
+    /// Stores a record at key, keeping the trie and metadata indexes in step with the map.
+    fn insert_record(&mut self, key: &str, mut record: Record<SharedValue>) {
+        record.version = self.next_version();
+        record.modified = SystemTime::now();
+        self.remember(key);
+        match self.map.get(key) {
+            Some(old) => self.metadata_indexes.values_mut().for_each(|index| index.remove(key, old)),
+            None => {
+                self.trie.insert(key);
+            }
+        }
+        self.metadata_indexes.values_mut().for_each(|index| index.insert(key, &record));
+        if let Some(old) = self.map.insert(key.to_string(), record) {
+            self.release(old.value);
+        }
+        self.track_expiry(key);
+    }
+
+    /// Lets go of an overwritten value, sending it to the reclaimer if it's big enough that
+    /// dropping it would hold up the map.
+    fn release(&mut self, value: SharedValue) {
+        // A value something else still holds isn't freed when the map lets go of it anyway.
+        if self.lazy_free_threshold == 0 || Arc::strong_count(&value) > 1 {
+            return;
+        }
+        if own_len(&value).is_some_and(|len| len >= self.lazy_free_threshold) {
+            if let Some(reclaimer) = &self.reclaimer {
+                if reclaimer.send(value).is_ok() {
+                    self.lazy_frees += 1;
+                }
+            }
+        }
+    }
+
+    /// Removes the record at key from the map, the trie and the metadata indexes.
+    fn remove_record(&mut self, key: &str) -> Option<Record<SharedValue>> {
+        let record = self.map.remove(key)?;
+        self.changes += 1;
+        self.removals += 1;
+        self.expiring.remove(key);
+        self.trie.remove(key);
+        self.metadata_indexes.values_mut().for_each(|index| index.remove(key, &record));
+        Some(record)
+    }
+
+    /// Notes whether the record at key has a TTL, after it's stored or its TTL changes.
+    fn track_expiry(&mut self, key: &str) {
+        match self.map.get(key) {
+            Some(record) if record.ttl.is_some() => {
+                self.expiring.insert(key.to_string());
+            }
+            _ => {
+                self.expiring.remove(key);
+            }
+        }
+    }
+
+    /// Deletes every key whose TTL has run out, returning how many there were.
+    pub fn remove_expired(&mut self) -> usize {
+        let expired: Vec<String> = self.expiring
+            .iter()
+            .filter(|key| self.map.get(*key).is_some_and(|record| record.is_expired()))
+            .cloned()
+            .collect();
+        for key in &expired {
+            self.del(key);
+        }
+        expired.len()
+    }
+
+    /// Whether key holds a lock taken with token that hasn't run out.
+    pub fn lock_held_by(&self, key: &String, token: &str) -> bool {
+        let live = self.map.get(key).is_some_and(|record| !record.is_expired());
+        live && matches!(self.get_as_string(key), Ok(ref holder) if holder == token)
+    }
+
+    /// Takes the lock at key for token, for ttl seconds, unless someone else holds it.
+    ///
+    /// A lock is a String key holding its token, so anything else at key, or a lock that has run out
+    /// but not been swept yet, counts as free.
+    pub fn set_lock(&mut self, key: &String, token: &str, ttl: u64) -> Result<(), SmirkMessages> {
+        match self.map.get(key) {
+            Some(record) if !record.is_expired() => return Err(SmirkMessages::LockHeld(key.clone())),
+            Some(_) => {
+                self.del(key);
+            }
+            None => {}
+        }
+        self.set_typed(key, token.as_bytes().to_vec(), &String::from("String"))?;
+        self.set_expires_at(key, SystemTime::now() + Duration::from_secs(ttl));
+        Ok(())
+    }
+
+    /// Frees the lock at key, if token holds it.
+    pub fn release_lock(&mut self, key: &String, token: &str) -> Result<(), SmirkMessages> {
+        if !self.lock_held_by(key, token) {
+            return Err(SmirkMessages::LockNotHeld(key.clone()));
+        }
+        self.del(key);
+        Ok(())
+    }
+
+    /// Gives the lock at key ttl seconds from now, if token holds it.
+    pub fn extend_lock(&mut self, key: &String, token: &str, ttl: u64) -> Result<(), SmirkMessages> {
+        if !self.lock_held_by(key, token) {
+            return Err(SmirkMessages::LockNotHeld(key.clone()));
+        }
+        self.set_expires_at(key, SystemTime::now() + Duration::from_secs(ttl));
+        Ok(())
+    }
+
+    /// Counts one request against the rate limit at key, allowing at most `max` in any `window`.
+    ///
+    /// The limit is a sliding window, estimated from the counts of the current fixed window and the
+    /// one before it, weighted by how much of the previous window the sliding one still covers. It's
+    /// kept at key as Json, `{"window": <start in ms>, "count": n, "previous": n}`, expiring once
+    /// both windows have passed.
+    ///
+    /// # Returns
+    ///
+    /// * Whether the request is allowed, and how many more are before the limit is reached.
+    pub fn rate_limit(&mut self, key: &String, max: u64, window: Duration) -> Result<(bool, u64), SmirkMessages> {
+        let window_ms = (window.as_millis() as u64).max(1);
+        let (current, mut count, previous, used) = self.rate_window(key, window_ms)?;
+        let allowed = used < max;
+        if allowed {
+            count += 1;
+        }
+        let state = serde_json::json!({"window": current, "count": count, "previous": previous});
+        self.set_value(key, state, &String::from("Json"));
+        self.set_expires_at(key, SystemTime::UNIX_EPOCH + Duration::from_millis(current + 2 * window_ms));
+        Ok((allowed, max.saturating_sub(used + u64::from(allowed))))
+    }
+
+    /// What `rate_limit` would answer now, without counting the request.
+    pub fn rate_limit_peek(&self, key: &String, max: u64, window: Duration) -> Result<(bool, u64), SmirkMessages> {
+        let (_, _, _, used) = self.rate_window(key, (window.as_millis() as u64).max(1))?;
+        let allowed = used < max;
+        Ok((allowed, max.saturating_sub(used + u64::from(allowed))))
+    }
+
+    /// The rate limit at key as it stands: the start of the current window, the requests counted
+    /// in it and in the one before, and how many of those the sliding window still covers.
+    fn rate_window(&self, key: &String, window_ms: u64) -> Result<(u64, u64, u64, u64), SmirkMessages> {
+        let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_millis() as u64;
+        let current = now - now % window_ms;
+        let (count, previous) = match self.map.get(key) {
+            Some(record) if !record.is_expired() => {
+                let state = downcast::<Value>(key, &record.value)?;
+                let field = |name: &str| state[name].as_u64().unwrap_or(0);
+                match field("window") {
+                    start if start == current => (field("count"), field("previous")),
+                    start if start + window_ms == current => (0, field("count")),
+                    _ => (0, 0)
+                }
+            }
+            _ => (0, 0)
+        };
+        let overlap = 1.0 - (now - current) as f64 / window_ms as f64;
+        let used = (previous as f64 * overlap).floor() as u64 + count;
+        Ok((current, count, previous, used))
+    }
+
+    /// Keeps the value at key in its history before it's overwritten or deleted.
+    ///
+    /// Collections and binary values that aren't text can't be set back from text, so they are
+    /// left out.
+    fn remember(&mut self, key: &str) {
+        if self.history.depth(key) == 0 {
+            return;
+        }
+        let Some(record) = self.map.get(key) else {
+            return;
+        };
+        let value = &record.value;
+        let collection = value.is::<SortedSet>() || value.is::<Stream>() || value.is::<GeoSet>() || value.is::<TimeSeries>() || value.is::<List>();
+        if collection || value.is::<HyperLogLog>() || value.is::<BloomFilter>() || value.is::<Counter>() {
+            return;
+        }
+        if matches!(value.downcast_ref::<Vec<u8>>(), Some(bytes) if std::str::from_utf8(bytes).is_err()) {
+            return;
+        }
+        let value = match self.get_as_string(&key.to_string()) {
+            Ok(value) => Some(value),
+            Err(SmirkMessages::NullValue(_)) => None,
+            Err(_) => return
+        };
+        let entry = HistoryEntry {
+            version: record.version,
+            written: record.modified,
+            desired_type_name: record.desired_type_name.clone(),
+            value
+        };
+        self.history.push(key, entry);
+    }
+
+    /// Sets key back to an old value from its history. The value being replaced goes into the
+    /// history in turn, so a restore can itself be undone.
+    pub fn restore_version(&mut self, key: &String, version: u64) -> Result<SmirkMessages, SmirkMessages> {
+        let entry = self
+            .history
+            .find(key, version)
+            .cloned()
+            .ok_or(SmirkMessages::VersionNotFound(key.clone(), version))?;
+        match entry.value {
+            Some(value) => self.set_typed(key, value.into_bytes(), &entry.desired_type_name),
+            None => Ok(self.set_null(key, &entry.desired_type_name))
+        }
+    }
+
+    fn next_version(&mut self) -> u64 {
+        self.changes += 1;
+        self.last_version += 1;
+        self.last_version
+    }
+
+    /// The version of the record at key, or `None` if there isn't one.
+    pub fn version(&self, key: &str) -> Option<u64> {
+        self.map.get(key).map(|record| record.version)
+    }
+
+    /// Marks the record at key as changed in place.
+    fn bump_version(&mut self, key: &str) {
+        let version = self.next_version();
+        if let Some(record) = self.map.get_mut(key) {
+            record.version = version;
+            record.modified = SystemTime::now();
+        }
+    }
+
+    /// When the key was created, for a record about to replace the one there. Now if there's no
+    /// live record to replace.
+    fn created(&self, key: &str) -> SystemTime {
+        match self.map.get(key) {
+            Some(record) if !record.is_expired() => record.created,
+            _ => SystemTime::now()
+        }
+    }
+
+    /// Whether the record at key is still what `expected` says.
+    pub fn cas_matches(&self, key: &String, expected: &CasExpected) -> bool {
+        match expected {
+            CasExpected::Version(version) => self.version(key).unwrap_or(0) == *version,
+            CasExpected::Value(value) => matches!(self.get_as_string(key), Ok(ref current) if current == value)
+        }
+    }
+
+    /// Sets key like `set_typed`, but only if the record is still what `expected` says.
+    ///
+    /// # Returns
+    ///
+    /// * The record's new version, or `CasMismatch` if it had changed.
+    pub fn set_cas(
+        &mut self,
+        key: &String,
+        expected: &CasExpected,
+        value: Vec<u8>,
+        type_name: &String
+    ) -> Result<u64, SmirkMessages> {
+        if !self.cas_matches(key, expected) {
+            return Err(SmirkMessages::CasMismatch(key.clone()));
+        }
+        self.set_typed(key, value, type_name)?;
+        Ok(self.version(key).unwrap_or(0))
+    }
+
+    /// Creates (or rebuilds) a metadata index from every record currently stored.
+    pub fn create_metadata_index(&mut self, name: &str, mut index: MetadataIndex) -> usize {
+        for (key, record) in &self.map {
+            index.insert(key, record);
+        }
+        self.metadata_indexes.insert(name.to_string(), index);
+        self.map.len()
+    }
+
     /// Sets a value in the SmirkMap at key.
     ///
     /// # Arguments
@@ -68,52 +571,648 @@ impl SmirkMap {
     /// * `key`: A `&String` representing the key to be fetched.
     ///
     /// * `value`: A `T` value to be stored in the map with `key`.
-    pub fn set<'a, T: Send + 'static>(
+    pub fn set<T: Send + Sync + FromStr + 'static>(
         &mut self,
         key: &String,
         value: Vec<u8>,
         desired_type_name: &String
-        ) -> Result<SmirkMessages, SmirkMessages> where T: FromStr {
+        ) -> Result<SmirkMessages, SmirkMessages> {
         let result: Result<T, <T as FromStr>::Err> =
             String::from_utf8_lossy(&value).to_string().parse::<T>();
         if let Ok(value) = result {
-            let record: Record<Box<dyn Any + Send>> = Record {
-                value: Box::new(value),
-                ttl: None,
-                ttl_start: SystemTime::now(),
-                type_name: String::from(type_name::<T>()),
-                desired_type_name: String::from(desired_type_name)
-            };
-            self.map.insert(key.to_owned(), record);
-            self.trie.add(key, Some("".to_string()));
-            return Ok(
-                SmirkMessages::SetKey(
+            Ok(self.set_value(key, value, desired_type_name))
+        } else {
+            Err(SmirkMessages::ParseError(String::from(key), String::from_utf8_lossy(&value).to_string(), String::from(type_name::<T>())))
+        }
+    }
+    /// Stores an already typed value at key, e.g. the result of ADDSTORE.
+    pub fn set_value<T: Send + Sync + 'static>(&mut self, key: &String, value: T, desired_type_name: &String) -> SmirkMessages {
+        self.set_shared(key, Arc::new(value), type_name::<T>(), desired_type_name)
+    }
+    /// Stores a value whose type is only known by name, as a codec parses it.
+    pub fn set_shared(&mut self, key: &String, value: SharedValue, type_name: &str, desired_type_name: &String) -> SmirkMessages {
+        let record: Record<SharedValue> = Record {
+            value,
+            ttl: self.default_ttl.map(|ttl| self.apply_ttl_policy(ttl)),
+            ttl_start: SystemTime::now(),
+            type_name: String::from(type_name),
+            desired_type_name: String::from(desired_type_name),
+            created: self.created(key),
+            modified: SystemTime::now(),
+            last_access: SystemTime::now(),
+            version: 0
+        };
+        self.insert_record(key, record);
+        SmirkMessages::SetKey(String::from(key), String::from(type_name), String::from(desired_type_name))
+    }
+    /// Stores an explicit null at key, remembering the type the user meant it to have.
+    pub fn set_null(&mut self, key: &String, desired_type_name: &String) -> SmirkMessages {
+        let record: Record<SharedValue> = Record {
+            value: Arc::new(Null),
+            ttl: self.default_ttl.map(|ttl| self.apply_ttl_policy(ttl)),
+            ttl_start: SystemTime::now(),
+            type_name: String::from("null"),
+            desired_type_name: String::from(desired_type_name),
+            created: self.created(key),
+            modified: SystemTime::now(),
+            last_access: SystemTime::now(),
+            version: 0
+        };
+        self.insert_record(key, record);
+        SmirkMessages::SetKey(String::from(key), String::from("null"), String::from(desired_type_name))
+    }
+    /// Stores any serializable value at key, for embedders with types of their own.
+    ///
+    /// The value is encoded as CBOR and kept as a binary record named "Cbor", so snapshots save it
+    /// like any other binary value. Read it back with `get_serde`.
+    pub fn set_serde<T: Serialize>(&mut self, key: &str, value: &T) -> Result<SmirkMessages, SmirkMessages> {
+        let mut bytes = Vec::new();
+        ciborium::into_writer(value, &mut bytes).map_err(|e| SmirkMessages::SerdeError(key.to_string(), e.to_string()))?;
+        self.binary_set(key, bytes, "Cbor")
+    }
+
+    /// Decodes a value stored with `set_serde`, or any binary record holding CBOR.
+    pub fn get_serde<T: DeserializeOwned>(&self, key: &String) -> Result<T, SmirkMessages> {
+        let bytes = self.get::<Vec<u8>>(key)?;
+        ciborium::from_reader(bytes.as_slice()).map_err(|e| SmirkMessages::SerdeError(key.clone(), e.to_string()))
+    }
+
+    /// Sets a value in the SmirkMap at key, parsing it with the codec for `type_name`.
+    ///
+    /// Type names without a codec are stored as binary `Vec<u8>` records.
+    pub fn set_typed(
+        &mut self,
+        key: &String,
+        value: Vec<u8>,
+        type_name: &String
+    ) -> Result<SmirkMessages, SmirkMessages> {
+        match codec::find(type_name) {
+            Some(codec) => match codec.parse(&value) {
+                Some(parsed) => Ok(self.set_shared(key, parsed, codec.rust_type(), type_name)),
+                None => Err(SmirkMessages::ParseError(
                     String::from(key),
-                    String::from(type_name::<T>()),
-                    String::from(desired_type_name)
-                    )
-                );
+                    String::from_utf8_lossy(&value).to_string(),
+                    String::from(codec.rust_type())
+                ))
+            },
+            None => self.binary_set(key, value, type_name)
+        }
+    }
+
+    /// Renders the value stored at key as text, the same way GET writes it.
+    pub fn get_as_string(&self, key: &String) -> Result<String, SmirkMessages> {
+        render(key, &self.get_record(key)?.value)
+    }
+
+    /// Renders the part of the Json document at key found at `path`, e.g. `user.emails[0]`.
+    pub fn json_get(&self, key: &String, path: &str) -> Result<String, SmirkMessages> {
+        let document = self.get::<Value>(key)?;
+        let path = parse_path(path).map_err(|e| SmirkMessages::JsonPathError(key.clone(), e))?;
+        get_path(document, &path)
+            .map(|value| value.to_string())
+            .ok_or(SmirkMessages::JsonPathError(key.clone(), String::from("Path not found")))
+    }
+
+    /// Replaces the part of the Json document at key found at `path` with `value`.
+    ///
+    /// Missing object fields along the path are created, and so is the document itself.
+    pub fn json_set(&mut self, key: &String, path: &str, value: Vec<u8>) -> Result<SmirkMessages, SmirkMessages> {
+        let text = String::from_utf8_lossy(&value).to_string();
+        let new_value = text
+            .parse::<Value>()
+            .map_err(|_| SmirkMessages::ParseError(key.clone(), text.clone(), String::from(type_name::<Value>())))?;
+        let path = parse_path(path).map_err(|e| SmirkMessages::JsonPathError(key.clone(), e))?;
+
+        if !self.exists(key) {
+            let mut document = Value::Null;
+            set_path(&mut document, &path, new_value).map_err(|e| SmirkMessages::JsonPathError(key.clone(), e))?;
+            return Ok(self.set_value(key, document, &String::from("Json")));
+        }
+
+        let record = self.map.get_mut(key).unwrap();
+        let desired_type_name = record.desired_type_name.clone();
+        let document = make_mut::<Value>(&mut record.value)
+            .ok_or(SmirkMessages::TypeMismatch(key.clone(), String::from(type_name::<Value>())))?;
+        // Work on a copy so a path that fails halfway doesn't leave new empty objects behind.
+        let mut updated = document.clone();
+        set_path(&mut updated, &path, new_value).map_err(|e| SmirkMessages::JsonPathError(key.clone(), e))?;
+        *document = updated;
+        self.bump_version(key);
+        Ok(SmirkMessages::SetKey(key.clone(), String::from(type_name::<Value>()), desired_type_name))
+    }
+
+    /// Changes the value at key as a `T` in place, starting from an empty one if the key doesn't
+    /// exist. Nothing is stored and the key's version doesn't move unless `change` succeeds, so
+    /// WATCH doesn't see a failed write as a change.
+    fn update<T: Default + Clone + Send + Sync + 'static, R>(
+        &mut self,
+        key: &String,
+        desired_type_name: &str,
+        change: impl FnOnce(&mut T) -> Result<R, SmirkMessages>
+    ) -> Result<R, SmirkMessages> {
+        if !self.exists(key) {
+            let mut value = T::default();
+            let result = change(&mut value)?;
+            self.set_value(key, value, &String::from(desired_type_name));
+            return Ok(result);
+        }
+        let record = self.map.get_mut(key).unwrap();
+        let value = make_mut::<T>(&mut record.value).ok_or(SmirkMessages::TypeMismatch(key.clone(), String::from(type_name::<T>())))?;
+        let result = change(value)?;
+        self.bump_version(key);
+        Ok(result)
+    }
+
+    /// Adds or updates members of the sorted set at key. Returns how many members were new.
+    pub fn zadd(&mut self, key: &String, members: &[(f64, String)]) -> Result<usize, SmirkMessages> {
+        self.update(key, "SortedSet", |set: &mut SortedSet| Ok(members.iter().filter(|(score, member)| set.add(member, *score)).count()))
+    }
+
+    /// Adds `increment` to a member's score and returns the new score. Fails if it wouldn't be
+    /// finite.
+    pub fn zincrby(&mut self, key: &String, increment: f64, member: &str) -> Result<f64, SmirkMessages> {
+        self.update(key, "SortedSet", |set: &mut SortedSet| {
+            set.incr(member, increment).map_err(|e| SmirkMessages::ScoreError(key.clone(), e))
+        })
+    }
+
+    /// Pushes elements onto one end of the list at key, creating the list if needed. Returns its
+    /// new length.
+    pub fn push(&mut self, key: &String, end: End, elements: &[String]) -> Result<usize, SmirkMessages> {
+        self.update(key, "List", |list: &mut List| Ok(list.push(end, elements)))
+    }
+
+    /// Pops an element off one end of the list at key, deleting the key once it's empty. A
+    /// missing key has nothing to pop.
+    pub fn pop(&mut self, key: &String, end: End) -> Result<Option<String>, SmirkMessages> {
+        if !self.exists(key) {
+            return Ok(None);
+        }
+        let (element, empty) = self.update(key, "List", |list: &mut List| Ok((list.pop(end), list.is_empty())))?;
+        if empty {
+            self.del(key);
+        }
+        Ok(element)
+    }
+
+    /// Removes members from the sorted set at key, deleting the key once it's empty.
+    pub fn zrem(&mut self, key: &String, members: &[String]) -> Result<usize, SmirkMessages> {
+        if !self.exists(key) {
+            return Ok(0);
+        }
+        let (removed, empty) = self.update(key, "SortedSet", |set: &mut SortedSet| {
+            Ok((members.iter().filter(|member| set.remove(member)).count(), set.is_empty()))
+        })?;
+        if empty {
+            self.del(key);
+        }
+        Ok(removed)
+    }
+
+    /// Finds the `k` Vector records in an index nearest to `query`, nearest first, with their
+    /// distance. Only keys in `namespace` are searched, since indexes cover the whole server.
+    pub fn vsearch(&self, index: &String, query: &Vector, k: usize, namespace: &str) -> Result<Vec<(&str, f32)>, SmirkMessages> {
+        let index = self.vector_indexes.get(index).ok_or(SmirkMessages::IndexNotFound(index.clone()))?;
+        if query.0.len() != index.dim {
+            return Err(SmirkMessages::VectorDimensionError(index.dim, query.0.len()));
+        }
+        let mut nearest: Vec<(&str, f32)> = self
+            .iter_typed::<Vector>(&index.prefix)
+            .filter(|(key, vector)| key.starts_with(namespace) && vector.0.len() == index.dim)
+            .map(|(key, vector)| (key, index.metric.distance(&query.0, &vector.0)))
+            .collect();
+        nearest.sort_by(|a, b| a.1.total_cmp(&b.1));
+        nearest.truncate(k);
+        Ok(nearest)
+    }
+
+    /// Adds or moves members of the geo set at key. Returns how many members were new.
+    pub fn geoadd(&mut self, key: &String, members: &[(f64, f64, String)]) -> Result<usize, SmirkMessages> {
+        self.update(key, "GeoSet", |set: &mut GeoSet| Ok(members.iter().filter(|(lon, lat, member)| set.add(member, *lon, *lat)).count()))
+    }
+
+    /// Appends an entry to the stream at key, creating the stream if needed. Returns the entry's ID.
+    pub fn xadd(&mut self, key: &String, id: Option<StreamId>, fields: StreamFields) -> Result<StreamId, SmirkMessages> {
+        self.update(key, "Stream", |stream: &mut Stream| {
+            stream.add(id, fields).map_err(|e| SmirkMessages::StreamIdError(key.clone(), e))
+        })
+    }
+
+    /// Changes the stream at key in place, which unlike with XADD has to exist already. Its
+    /// consumer groups' errors are reported against the key.
+    fn update_stream<R>(&mut self, key: &String, change: impl FnOnce(&mut Stream) -> Result<R, String>) -> Result<R, SmirkMessages> {
+        if !self.exists(key) {
+            return Err(SmirkMessages::KeyNotFound(key.clone()));
+        }
+        self.update(key, "Stream", |stream: &mut Stream| change(stream).map_err(|e| SmirkMessages::ConsumerGroupError(key.clone(), e)))
+    }
+
+    /// Adds a consumer group to the stream at key, delivering the entries after `start`, or only
+    /// ones added from now on for `None`. `mkstream` creates the stream if it doesn't exist.
+    pub fn xgroup_create(&mut self, key: &String, group: &str, start: Option<StreamId>, mkstream: bool) -> Result<(), SmirkMessages> {
+        let create = |stream: &mut Stream| {
+            let start = start.unwrap_or(stream.last_id());
+            stream.create_group(group, start)
+        };
+        if mkstream {
+            self.update(key, "Stream", |stream: &mut Stream| create(stream).map_err(|e| SmirkMessages::ConsumerGroupError(key.clone(), e)))
         } else {
-            return Err(SmirkMessages::ParseError(String::from(key), String::from_utf8_lossy(&value).to_string(), String::from(type_name::<T>())));
+            self.update_stream(key, create)
+        }
+    }
+
+    /// Removes a consumer group from the stream at key. Returns whether it existed.
+    pub fn xgroup_destroy(&mut self, key: &String, group: &str) -> Result<bool, SmirkMessages> {
+        self.update_stream(key, |stream| Ok(stream.destroy_group(group)))
+    }
+
+    /// Reads the stream at key for `consumer` in `group`: entries no consumer in the group has had
+    /// yet for `None`, otherwise the consumer's own pending entries after the ID.
+    pub fn xreadgroup(
+        &mut self,
+        key: &String,
+        group: &str,
+        consumer: &str,
+        id: Option<StreamId>,
+        count: Option<usize>
+    ) -> Result<Vec<(StreamId, StreamFields)>, SmirkMessages> {
+        let error = |e| SmirkMessages::ConsumerGroupError(key.clone(), e);
+        let stream = self.get::<Stream>(key)?;
+        match id {
+            Some(id) => stream.pending_for(group, consumer, id, count).map_err(error),
+            // Checked first so a consumer polling an idle stream doesn't count as a change.
+            None if !stream.has_undelivered(group).map_err(error)? => Ok(Vec::new()),
+            None => self.update_stream(key, |stream| stream.deliver(group, consumer, count))
+        }
+    }
+
+    /// Acknowledges entries of the stream at key for `group`. Returns how many were pending.
+    pub fn xack(&mut self, key: &String, group: &str, ids: &[StreamId]) -> Result<usize, SmirkMessages> {
+        self.update_stream(key, |stream| stream.ack(group, ids))
+    }
+
+    /// Hands entries of the stream at key pending in `group` for at least `min_idle` milliseconds
+    /// over to `consumer`. Returns the entries claimed.
+    pub fn xclaim(
+        &mut self,
+        key: &String,
+        group: &str,
+        consumer: &str,
+        min_idle: u64,
+        ids: &[StreamId]
+    ) -> Result<Vec<(StreamId, StreamFields)>, SmirkMessages> {
+        self.update_stream(key, |stream| stream.claim(group, consumer, min_idle, ids))
+    }
+
+    /// Stores a counter at key, replacing whatever was there.
+    pub fn set_counter(&mut self, key: &String, ty: BitFieldType, overflow: Overflow, value: i64) -> Result<(), SmirkMessages> {
+        let counter = Counter::new(ty, overflow, value).map_err(|e| SmirkMessages::CounterError(key.clone(), e))?;
+        self.set_value(key, counter, &String::from("Counter"));
+        Ok(())
+    }
+
+    /// Adds `by` to the counter at key, creating a default one if needed. Returns the new value.
+    pub fn incr_by(&mut self, key: &String, by: i64) -> Result<i64, SmirkMessages> {
+        self.update(key, "Counter", |counter: &mut Counter| {
+            counter.incr_by(by).ok_or(SmirkMessages::CounterOverflow(key.clone(), counter.ty.to_string()))
+        })
+    }
+
+    /// Stores an empty Bloom filter at key, sized for `capacity` elements at `error_rate`.
+    pub fn bf_reserve(&mut self, key: &String, error_rate: f64, capacity: u64) -> Result<(), SmirkMessages> {
+        if self.exists(key) {
+            return Err(SmirkMessages::BloomFilterError(key.clone(), String::from("the key already exists")));
+        }
+        let filter = BloomFilter::new(capacity, error_rate).map_err(|e| SmirkMessages::BloomFilterError(key.clone(), e))?;
+        self.set_value(key, filter, &String::from("BloomFilter"));
+        Ok(())
+    }
+
+    /// Adds an element to the Bloom filter at key, reserving one with the default error rate and
+    /// capacity if needed. Returns true if it wasn't there already.
+    pub fn bf_add(&mut self, key: &String, element: &str) -> Result<bool, SmirkMessages> {
+        self.update(key, "BloomFilter", |filter: &mut BloomFilter| Ok(filter.add(element.as_bytes())))
+    }
+
+    /// Whether the element may be in the Bloom filter at key. A missing key has nothing in it.
+    pub fn bf_exists(&self, key: &String, element: &str) -> Result<bool, SmirkMessages> {
+        match self.get::<BloomFilter>(key) {
+            Ok(filter) => Ok(filter.contains(element.as_bytes())),
+            Err(SmirkMessages::KeyNotFound(_)) => Ok(false),
+            Err(e) => Err(e)
         }
     }
+
+    /// Stores an empty time series at key that keeps `retention` milliseconds of samples.
+    pub fn ts_create(&mut self, key: &String, retention: Option<u64>) -> Result<(), SmirkMessages> {
+        if self.exists(key) {
+            return Err(SmirkMessages::TimeSeriesError(key.clone(), String::from("the key already exists")));
+        }
+        self.set_value(key, TimeSeries::new(retention), &String::from("TimeSeries"));
+        Ok(())
+    }
+
+    /// Adds a sample to the time series at key, creating one that keeps everything if needed.
+    /// A `None` timestamp means now. Returns the timestamp used.
+    pub fn ts_add(&mut self, key: &String, timestamp: Option<u64>, value: f64) -> Result<u64, SmirkMessages> {
+        let timestamp = timestamp.unwrap_or_else(|| SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_millis() as u64);
+        self.update(key, "TimeSeries", |series: &mut TimeSeries| {
+            series.add(timestamp, value).map_err(|e| SmirkMessages::TimeSeriesError(key.clone(), e))
+        })?;
+        Ok(timestamp)
+    }
+
+    /// Keys under `prefix` holding a number of type `ty` from `min` to `max` inclusive, in key
+    /// order, stopping after `limit` of them. `None` bounds are open.
+    ///
+    /// There's no index on values, so this looks at every record: O(n) in the size of the map.
+    pub fn scan_values(&self, prefix: &str, ty: &str, min: &Option<BigDecimal>, max: &Option<BigDecimal>, limit: Option<usize>) -> Vec<String> {
+        let Some(codec) = codec::CODECS.iter().find(|codec| codec.name == ty) else {
+            return Vec::new();
+        };
+        let mut keys: Vec<&String> = self.map
+            .iter()
+            .filter(|(key, record)| key.starts_with(prefix) && !record.is_expired())
+            .filter(|(_, record)| numeric_value(&record.value, codec).is_some_and(|n| {
+                min.as_ref().is_none_or(|min| &n >= min) && max.as_ref().is_none_or(|max| &n <= max)
+            }))
+            .map(|(key, _)| key)
+            .collect();
+        keys.sort();
+        keys.into_iter().take(limit.unwrap_or(usize::MAX)).cloned().collect()
+    }
+
+    /// Adds elements to the HyperLogLog at key. Returns true if its estimate may have changed.
+    pub fn pfadd(&mut self, key: &String, elements: &[String]) -> Result<bool, SmirkMessages> {
+        let created = !self.exists(key);
+        self.update(key, "HyperLogLog", |hll: &mut HyperLogLog| {
+            let mut changed = created;
+            for element in elements {
+                changed |= hll.add(element.as_bytes());
+            }
+            Ok(changed)
+        })
+    }
+
+    /// Estimates the number of distinct elements across the HyperLogLogs at keys. Missing keys count as empty.
+    pub fn pfcount(&self, keys: &[String]) -> Result<u64, SmirkMessages> {
+        if let [key] = keys {
+            return self.get::<HyperLogLog>(key).map(|hll| hll.count()).or_else(|e| match e {
+                SmirkMessages::KeyNotFound(_) => Ok(0),
+                e => Err(e)
+            });
+        }
+        Ok(self.union(keys)?.count())
+    }
+
+    /// Stores the union of the HyperLogLogs at `sources` in `destination`, including whatever it already held.
+    pub fn pfmerge(&mut self, destination: &String, sources: &[String]) -> Result<(), SmirkMessages> {
+        let union = self.union(sources)?;
+        self.update(destination, "HyperLogLog", |hll: &mut HyperLogLog| {
+            hll.merge(&union);
+            Ok(())
+        })
+    }
+
+    fn union(&self, keys: &[String]) -> Result<HyperLogLog, SmirkMessages> {
+        let mut union = HyperLogLog::new();
+        for key in keys.iter().filter(|key| self.exists(key)) {
+            union.merge(self.get::<HyperLogLog>(key)?);
+        }
+        Ok(union)
+    }
+
+    /// The bytes of the String or binary record at key.
+    pub fn get_bytes(&self, key: &String) -> Result<&[u8], SmirkMessages> {
+        shared_bytes(key, &self.get_record(key)?.value)
+    }
+
+    /// The bytes of the String or binary record at key from `start` to `end` inclusive.
+    ///
+    /// Negative positions count back from the end, and the range is clamped to the value, so it
+    /// may be empty. A missing key is empty too.
+    pub fn get_range(&self, key: &String, start: i64, end: i64) -> Result<&[u8], SmirkMessages> {
+        let bytes = match self.get_bytes(key) {
+            Ok(bytes) => bytes,
+            Err(SmirkMessages::KeyNotFound(_)) => return Ok(&[]),
+            Err(e) => return Err(e)
+        };
+        let len = bytes.len() as i64;
+        let start = if start < 0 { (start + len).max(0) } else { start };
+        let end = if end < 0 { end + len } else { end.min(len - 1) };
+        if start > end {
+            return Ok(&[]);
+        }
+        Ok(&bytes[start as usize..=end as usize])
+    }
+
+    /// Overwrites the String or binary record at key with `data` from byte `offset`, padding
+    /// with zero bytes if the value was shorter. Returns the value's new length.
+    ///
+    /// A missing key is created as a binary record, unless `data` is empty. A String record
+    /// is left alone if the patch would split a character.
+    pub fn set_range(&mut self, key: &String, offset: usize, data: &[u8]) -> Result<usize, SmirkMessages> {
+        if data.is_empty() {
+            return self.get_range(key, 0, -1).map(|bytes| bytes.len());
+        }
+        if offset.checked_add(data.len()).is_none_or(|end| end > MAX_RANGE_BYTES) {
+            return Err(SmirkMessages::RangeError(key.clone(), format!("it would be longer than {} bytes", MAX_RANGE_BYTES)));
+        }
+        let patch = |bytes: &mut Vec<u8>| {
+            if bytes.len() < offset + data.len() {
+                bytes.resize(offset + data.len(), 0);
+            }
+            bytes[offset..offset + data.len()].copy_from_slice(data);
+        };
+        if let Ok(text) = self.get::<String>(key) {
+            let mut bytes = text.as_bytes().to_vec();
+            patch(&mut bytes);
+            let text = String::from_utf8(bytes)
+                .map_err(|_| SmirkMessages::RangeError(key.clone(), String::from("the result isn't valid UTF-8")))?;
+            let len = text.len();
+            self.update(key, "String", |value: &mut String| {
+                *value = text;
+                Ok(())
+            })?;
+            return Ok(len);
+        }
+        if !self.exists(key) {
+            self.binary_set(key, Vec::new(), "binary")?;
+        }
+        self.update(key, "binary", |bytes: &mut Vec<u8>| {
+            patch(bytes);
+            Ok(bytes.len())
+        })
+    }
+
+    /// Runs BITFIELD steps against the binary record at key, one reply per GET, SET or INCRBY.
+    ///
+    /// A missing key reads as all zeros, and is only created if a step writes to it.
+    pub fn bitfield(&mut self, key: &String, ops: &[BitFieldOp]) -> Result<Vec<Option<i64>>, SmirkMessages> {
+        if !ops.iter().any(BitFieldOp::writes) {
+            let bytes = match self.get::<Vec<u8>>(key) {
+                Ok(bytes) => bytes.as_slice(),
+                Err(SmirkMessages::KeyNotFound(_)) => &[],
+                Err(e) => return Err(e)
+            };
+            return Ok(ops
+                .iter()
+                .filter_map(|op| match *op {
+                    BitFieldOp::Get(ty, offset) => Some(Some(bitfield::get(bytes, ty, offset))),
+                    _ => None
+                })
+                .collect());
+        }
+        if !self.exists(key) {
+            self.binary_set(key, Vec::new(), "binary")?;
+        }
+        self.update(key, "binary", |bytes: &mut Vec<u8>| Ok(bitfield::apply(bytes, ops)))
+    }
+
+    /// Converts the value stored at key to `type_name` in place, e.g. String "42" to i64.
+    ///
+    /// The conversion goes through the value's text form and is refused if it would lose
+    /// information, e.g. i64 9007199254740993 to f64, or f64 0.1 to f32. The record keeps
+    /// its TTL, and is left untouched when the conversion fails.
+    pub fn cast(&mut self, key: &String, type_name: &String) -> Result<SmirkMessages, SmirkMessages> {
+        let record = self.get_record(key)?;
+        let from_type = record.type_name.clone();
+        let cast_error = || SmirkMessages::CastError(key.clone(), from_type.clone(), type_name.clone());
+        if let Some(bytes) = record.value.downcast_ref::<Vec<u8>>() {
+            if std::str::from_utf8(bytes).is_err() {
+                return Err(cast_error());
+            }
+        }
+        if let Some(value) = record.value.downcast_ref::<f64>() {
+            // Both print as "0.1", so the text comparison below can't catch this narrowing.
+            if type_name == "f32" && !value.is_nan() && (*value as f32) as f64 != *value {
+                return Err(cast_error());
+            }
+        }
+
+        let text = self.get_as_string(key)?;
+        let old = self.remove_record(key).unwrap();
+        match self.set_typed(key, text.clone().into_bytes(), type_name) {
+            Ok(result) => {
+                if !matches!(self.get_as_string(key), Ok(ref converted) if *converted == text) {
+                    self.insert_record(key, old);
+                    return Err(cast_error());
+                }
+                if let Some(record) = self.map.get_mut(key) {
+                    record.ttl = old.ttl;
+                    record.ttl_start = old.ttl_start;
+                    record.created = old.created;
+                    record.last_access = old.last_access;
+                }
+                self.track_expiry(key);
+                Ok(result)
+            }
+            Err(e) => {
+                self.insert_record(key, old);
+                match e {
+                    SmirkMessages::ParseError(..) => Err(cast_error()),
+                    e => Err(e)
+                }
+            }
+        }
+    }
+
+    /// Moves the record at `from` to `to`, replacing anything already stored at `to`. Tags move
+    /// with the record.
+    pub fn rename(&mut self, from: &str, to: &str) -> Result<(), SmirkMessages> {
+        let tags: Vec<String> = self.tags.tags(from).into_iter().cloned().collect();
+        let record = self.remove_record(from).ok_or(SmirkMessages::KeyNotFound(from.to_string()))?;
+        self.tags.remove_key(to);
+        self.insert_record(to, record);
+        self.tags.add(to, &tags);
+        Ok(())
+    }
+
+    /// Tags an existing key. Returns how many of the tags are new to it.
+    pub fn tag(&mut self, key: &str, tags: &[String]) -> Result<usize, SmirkMessages> {
+        if !self.map.contains_key(key) {
+            return Err(SmirkMessages::KeyNotFound(key.to_string()));
+        }
+        Ok(self.tags.add(key, tags))
+    }
+
+    /// Deletes every key carrying `tag`. Returns how many were deleted.
+    pub fn del_by_tag(&mut self, tag: &str) -> u64 {
+        let keys: Vec<String> = self.tags.keys(tag).into_iter().cloned().collect();
+        keys.iter().map(|key| self.del(key)).sum()
+    }
+    /// Iterates over every record whose key starts with `prefix`, expired ones included.
+    ///
+    /// # Arguments
+    ///
+    /// * `prefix`: Only keys starting with this are visited. Use `""` for the whole map.
+    ///
+    /// # Returns
+    ///
+    /// * An iterator of `RecordView`s, in no particular order. Filter on
+    ///   `type_name()` or `ttl_state()` to narrow it down.
+    pub fn iter_prefix<'a>(&'a self, prefix: &'a str) -> impl Iterator<Item = RecordView<'a>> + 'a {
+        self.map
+            .iter()
+            .filter(move |(key, _)| key.starts_with(prefix))
+            .map(|(key, record)| RecordView::new(key, record))
+    }
+
+    /// Iterates over the live values of type `T` whose key starts with `prefix`.
+    ///
+    /// # Arguments
+    ///
+    /// * `prefix`: Only keys starting with this are visited. Use `""` for the whole map.
+    ///
+    /// # Returns
+    ///
+    /// * An iterator of `(key, &T)` pairs. Records of other types and expired records are skipped.
+    pub fn iter_typed<'a, T: 'static>(&'a self, prefix: &'a str) -> impl Iterator<Item = (&'a str, &'a T)> + 'a {
+        self.iter_prefix(prefix)
+            .filter(|view| view.ttl_state() != TtlState::Expired)
+            .filter_map(|view| view.value::<T>().map(|value| (view.key(), value)))
+    }
+
+    /// Marks keys as just accessed. Returns how many of them exist.
+    pub fn touch(&mut self, keys: &[String]) -> usize {
+        let now = SystemTime::now();
+        let mut touched = 0;
+        for key in keys {
+            if let Some(record) = self.map.get_mut(key) {
+                record.last_access = now;
+                touched += 1;
+            }
+        }
+        touched
+    }
+
+    /// How big the value at key is: bytes for text and binary values, members or entries for
+    /// collections (estimated for HyperLogLogs), dimensions for vectors and the printed length
+    /// of anything else.
+    pub fn value_len(&self, key: &String) -> Result<usize, SmirkMessages> {
+        let record = self.get_record(key)?;
+        match own_len(&record.value) {
+            Some(len) => Ok(len),
+            None => self.get_as_string(key).map(|value| value.len())
+        }
+    }
+
     pub fn exists(&self, key: &String) -> bool {
-        return self.map.contains_key(key);
+        self.map.contains_key(key)
     }
-    pub fn get_record(&self, key: &String) -> Result<&Record<Box<dyn Any + Send>>, SmirkMessages> {
+    pub fn get_record(&self, key: &String) -> Result<&Record<SharedValue>, SmirkMessages> {
         if self.exists(key) {
             return Ok(self.map.get(key).unwrap());
         }
 
         Err(SmirkMessages::KeyNotFound(key.clone()))
     }
-    pub fn del(&mut self, key: &String) -> u64 {
-        if self.map.contains_key(key) {
-            self.map.remove(key);
-            self.trie.remove(key);
-            1
-        } else {
-            0
+    pub fn del(&mut self, key: &str) -> u64 {
+        self.tags.remove_key(key);
+        self.remember(key);
+        match self.remove_record(key) {
+            Some(_) => 1,
+            None => 0
         }
     }
     pub fn ttl(&self, key: &String) -> Result<Option<u64>, String> {
@@ -122,47 +1221,174 @@ impl SmirkMap {
         }
         Err(format!("Key \"{}\" was not found", key))
     }
-    pub fn set_ttl(&mut self, key: &String, ttl: &Option<u64>) {
+    /// The TTL a key asking for `ttl` seconds gets, with `ttl_jitter` added and capped at `max_ttl`.
+    pub fn apply_ttl_policy(&self, ttl: u64) -> u64 {
+        let jitter = match self.ttl_jitter {
+            0 => 0,
+            jitter => RandomState::new().hash_one(SystemTime::now()) % (jitter + 1)
+        };
+        let ttl = ttl.saturating_add(jitter);
+        self.max_ttl.map_or(ttl, |max| ttl.min(max))
+    }
+    /// Sets the TTL of the record at key, counted from now, after applying the TTL policy. Returns
+    /// the TTL it got, or `None` without changing anything if there's no record at key.
+    pub fn set_ttl(&mut self, key: &String, ttl: &Option<u64>) -> Option<u64> {
+        if !self.exists(key) {
+            return None;
+        }
+        let ttl = ttl.map(|ttl| self.apply_ttl_policy(ttl));
+        self.bump_version(key);
+        if let Some(record) = self.map.get_mut(key) {
+            record.ttl = ttl;
+            record.ttl_start = SystemTime::now();
+        }
+        self.track_expiry(key);
+        ttl
+    }
+    /// Makes the record at key expire at a moment rather than after a number of seconds.
+    pub fn set_expires_at(&mut self, key: &String, expires_at: SystemTime) {
+        self.bump_version(key);
         if let Some(record) = self.map.get_mut(key) {
-            record.ttl = *ttl;
+            let remaining = expires_at.duration_since(SystemTime::now()).unwrap_or_default();
+            // TTLs are whole seconds, so they're counted from far enough back to run out right at expires_at.
+            let ttl = remaining.as_secs() + u64::from(remaining.subsec_nanos() > 0);
+            record.ttl = Some(ttl);
+            record.ttl_start = expires_at - Duration::from_secs(ttl);
         }
+        self.track_expiry(key);
     }
     pub fn set_search_mode(&mut self, mode: SmirkSearchMode) {
         self.search_mode = mode;
     }
-    pub fn add_float<T: std::ops::Add<Output = T> + Default + Copy + 'static>(
-        &mut self,
-        keys: Vec<String>
+    /// The value at `key` as an operand for ADD, SUB, MUL or DIV.
+    ///
+    /// Fails with `KeyNotFound`, `TypeMismatch` or `NullOperand`, each naming the key, so the
+    /// client knows which of its keys was the problem.
+    fn operand<T: 'static>(&self, key: &String) -> Result<&T, SmirkMessages> {
+        self.get::<T>(key).map_err(|e| match e {
+            SmirkMessages::NullValue(key) => SmirkMessages::NullOperand(key),
+            e => e
+        })
+    }
+
+    /// The values at keys as T, for AGG. With `skip_mismatched`, keys that are missing, null or
+    /// hold another type are left out rather than failing.
+    pub fn operands<T: 'static>(&self, keys: &[String], skip_mismatched: bool) -> Result<Vec<&T>, SmirkMessages> {
+        let mut values = Vec::new();
+        for key in keys {
+            match self.operand::<T>(key) {
+                Ok(value) => values.push(value),
+                Err(_) if skip_mismatched => {}
+                Err(e) => return Err(e)
+            }
+        }
+        Ok(values)
+    }
+
+    /// Adds up the values at every key without overflow checks, for floats and BigDecimal.
+    ///
+    /// With `skip_missing`, keys that don't exist count as zero.
+    pub fn sum<T: std::ops::Add<Output = T> + Default + Clone + 'static>(
+        &self,
+        keys: Vec<String>,
+        skip_missing: bool
     ) -> Result<T, SmirkMessages> {
         let mut total: T = T::default();
         for key in keys {
-            if let Ok(val) = self.get::<T>(&key) {
-                let cloned_val = val.clone();
-                total = total + cloned_val;
-            } else {
-                return Err(SmirkMessages::ParseError(key, String::from("").to_string(), String::from(type_name::<T>()).to_string()));
+            match self.operand::<T>(&key) {
+                Ok(val) => total = total + val.clone(),
+                Err(SmirkMessages::KeyNotFound(_)) if skip_missing => {}
+                Err(e) => return Err(e)
             }
         }
-        return Ok(total);
+        Ok(total)
     }
 
+    /// Adds up the values at every key, failing rather than overflowing.
+    ///
+    /// With `skip_missing`, keys that don't exist count as zero.
     pub fn add<T: CheckedAdd<Output = T> + Default + 'static>(
-        &mut self,
-        keys: Vec<String>
+        &self,
+        keys: Vec<String>,
+        skip_missing: bool
     ) -> Result<T, SmirkMessages> {
         let mut total: T = T::default();
         for key in keys {
-            if let Ok(val) = self.get::<T>(&key) {
-                let t = val.checked_add(&total);
-                if let Some(new_total) = t {
-                   total = new_total;
-                } else {
-                    return Err(SmirkMessages::AddOverflowError());
-                }
-            } else {
-                return Err(SmirkMessages::ParseError(key, String::from("").to_string(), String::from(type_name::<T>()).to_string()));
+            match self.operand::<T>(&key) {
+                Ok(val) => total = val.checked_add(&total).ok_or(SmirkMessages::AddOverflowError())?,
+                Err(SmirkMessages::KeyNotFound(_)) if skip_missing => {}
+                Err(e) => return Err(e)
             }
         }
-        return Ok(total);
+        Ok(total)
+    }
+
+    /// Subtracts the values at the remaining keys from the value at the first key.
+    pub fn sub<T: CheckedSub<Output = T> + Clone + 'static>(
+        &self,
+        keys: Vec<String>
+    ) -> Result<T, SmirkMessages> {
+        self.fold(keys, |total: &T, val: &T, _| {
+            total.checked_sub(val).ok_or(SmirkMessages::OverflowError(String::from("subtract")))
+        })
+    }
+
+    /// Multiplies the values at every key together.
+    pub fn mul<T: CheckedMul<Output = T> + Clone + 'static>(
+        &self,
+        keys: Vec<String>
+    ) -> Result<T, SmirkMessages> {
+        self.fold(keys, |total: &T, val: &T, _| {
+            total.checked_mul(val).ok_or(SmirkMessages::OverflowError(String::from("multiply")))
+        })
+    }
+
+    /// Divides the value at the first key by the values at the remaining keys, in order.
+    pub fn div<T: CheckedDiv<Output = T> + Zero + Clone + 'static>(
+        &self,
+        keys: Vec<String>
+    ) -> Result<T, SmirkMessages> {
+        self.fold(keys, |total: &T, val: &T, key: &String| {
+            if val.is_zero() {
+                return Err(SmirkMessages::DivideByZeroError(key.clone()));
+            }
+            total.checked_div(val).ok_or(SmirkMessages::OverflowError(String::from("divide")))
+        })
+    }
+
+    /// Float counterpart of `sub`, `mul` and `div`. Follows IEEE 754, so dividing by zero gives inf or NaN.
+    pub fn fold_float<T: Float + 'static>(
+        &self,
+        keys: Vec<String>,
+        op: fn(T, T) -> T
+    ) -> Result<T, SmirkMessages> {
+        self.fold(keys, |total: &T, val: &T, _| Ok(op(*total, *val)))
+    }
+
+    /// Starts from the value at the first key and combines it with the value at each following key.
+    ///
+    /// `op` gets the running total, the next value and the key it came from.
+    pub fn fold<T: Clone + 'static, F: Fn(&T, &T, &String) -> Result<T, SmirkMessages>>(
+        &self,
+        keys: Vec<String>,
+        op: F
+    ) -> Result<T, SmirkMessages> {
+        let mut total: Option<T> = None;
+        for key in keys {
+            let val = self.operand::<T>(&key)?;
+            total = Some(match total {
+                Some(total) => op(&total, val, &key)?,
+                None => val.clone()
+            });
+        }
+        total.ok_or(SmirkMessages::KeyNotFound(String::from("")))
+    }
+}
+
+/// Rewrites a hexadecimal float literal into its exact decimal form so it can be parsed by `FromStr`.
+pub fn normalize_float_literal(value: Vec<u8>) -> Vec<u8> {
+    match parse_hex_float(&String::from_utf8_lossy(&value)) {
+        Some(f) => f.to_string().into_bytes(),
+        None => value
     }
 }
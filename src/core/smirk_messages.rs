@@ -1,3 +1,6 @@
+use std::fmt;
+
+#[derive(Debug)]
 pub enum SmirkMessages {
     /// Positive Messages :)
     SetKey(String, String, String),
@@ -8,19 +11,113 @@ pub enum SmirkMessages {
     /// `String` is the map key.
     KeyNotFound(String),
 
+    /// The key exists but holds an explicit null.
+    ///
+    /// `String` is the map key.
+    NullValue(String),
+
     /// This means the value stored in key `param1`
     ///
     ///
     TypeMismatch(String, String),
 
     ParseError(String, String, String),
-    AddOverflowError()
+
+    /// Converting key `param1` from type `param2` to type `param3` would lose information.
+    CastError(String, String, String),
+    AddOverflowError(),
+
+    /// A JSON.GET or JSON.SET path on key `param1` was invalid or didn't exist. `param2` says why.
+    JsonPathError(String, String),
+
+    /// Member `param2` isn't in the sorted set at key `param1`.
+    MemberNotFound(String, String),
+
+    /// XADD was given an ID for key `param1` that can't be used. `param2` says why.
+    StreamIdError(String, String),
+
+    /// COUNTER couldn't set the counter at key `param1`. `param2` says why.
+    CounterError(String, String),
+
+    /// INCR or DECR would take the counter at key `param1` past what its type `param2` holds, and
+    /// its overflow policy is FAIL.
+    CounterOverflow(String, String),
+
+    /// BF.RESERVE couldn't make a Bloom filter at key `param1`. `param2` says why.
+    BloomFilterError(String, String),
+
+    /// TS.CREATE or TS.ADD couldn't use the time series at key `param1`. `param2` says why.
+    TimeSeriesError(String, String),
+
+    /// A value couldn't be encoded to or decoded from the CBOR record at key `param1`. `param2`
+    /// says why.
+    SerdeError(String, String),
+
+    /// A consumer group command on stream `param1` failed. `param2` says why.
+    ConsumerGroupError(String, String),
+
+    /// There's no index named `String`.
+    IndexNotFound(String),
+
+    /// A vector had `param2` dimensions where its index expects `param1`.
+    VectorDimensionError(usize, usize),
+
+    /// SUB, MUL or DIV overflowed. `String` is the verb, e.g. "subtract".
+    OverflowError(String),
+
+    /// DIV hit a zero divisor. `String` is the key holding it.
+    DivideByZeroError(String),
+
+    /// SETCAS found key `String` changed since the client last saw it.
+    CasMismatch(String),
+
+    /// SETLOCK found the lock at key `String` already taken.
+    LockHeld(String),
+
+    /// RELEASELOCK or EXTENDLOCK was given a token that doesn't hold the lock at key `String`.
+    LockNotHeld(String),
+
+    /// Key `param1` has no version `param2` in its history.
+    VersionNotFound(String, u64),
+
+    /// ADD, SUB, MUL or DIV was given key `String`, which holds a null.
+    NullOperand(String),
+
+    /// SETRANGE couldn't patch key `param1`. `param2` says why.
+    RangeError(String, String),
+
+    /// ZINCRBY couldn't change a score in the sorted set at key `param1`. `param2` says why.
+    ScoreError(String, String)
 }
 
-impl ToString for SmirkMessages {
-    fn to_string(&self) -> String {
-        match self {
+impl fmt::Display for SmirkMessages {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = match self {
             SmirkMessages::AddOverflowError() => "Cannot add these. It's an overflow.\n".to_owned(),
+            SmirkMessages::OverflowError(verb) => format!("Cannot {} these. It's an overflow.\n", verb),
+            SmirkMessages::JsonPathError(key, reason) => format!("Json path error on key \"{}\": {}.\n", key, reason),
+            SmirkMessages::MemberNotFound(key, member) => format!("Member \"{}\" not found in key \"{}\".\n", member, key),
+            SmirkMessages::StreamIdError(key, reason) => format!("Can't add to stream \"{}\": {}.\n", key, reason),
+            SmirkMessages::CounterError(key, reason) => format!("Can't set counter \"{}\": {}.\n", key, reason),
+            SmirkMessages::CounterOverflow(key, ty) => format!("Counter \"{}\" would overflow {}, so it wasn't changed.\n", key, ty),
+            SmirkMessages::BloomFilterError(key, reason) => format!("Can't reserve a Bloom filter at key \"{}\": {}.\n", key, reason),
+            SmirkMessages::TimeSeriesError(key, reason) => format!("Can't use time series \"{}\": {}.\n", key, reason),
+            SmirkMessages::SerdeError(key, reason) => format!("Can't convert the value at key \"{}\": {}.\n", key, reason),
+            SmirkMessages::ConsumerGroupError(key, reason) => format!("Stream \"{}\" has {}.\n", key, reason),
+            SmirkMessages::IndexNotFound(name) => format!("Index \"{}\" not found.\n", name),
+            SmirkMessages::VectorDimensionError(expected, got) => format!(
+                "Expected a vector with {} dimensions, got {}.\n",
+                expected,
+                got
+                ),
+            SmirkMessages::DivideByZeroError(key) => format!("Cannot divide by key \"{}\". It's zero.\n", key),
+            SmirkMessages::VersionNotFound(key, version) => format!("Version {} of key \"{}\" is not in its history.\n", version, key),
+            SmirkMessages::NullOperand(key) => format!("Key \"{}\" holds a null, which can't be used in arithmetic.\n", key),
+            SmirkMessages::RangeError(key, reason) => format!("Can't set a range of key \"{}\": {}.\n", key, reason),
+            SmirkMessages::ScoreError(key, reason) => format!("Can't change sorted set \"{}\": {}.\n", key, reason),
+            SmirkMessages::CasMismatch(key) => format!("Key \"{}\" has changed. Nothing was set.\n", key),
+            SmirkMessages::LockHeld(key) => format!("Lock \"{}\" is already held.\n", key),
+            SmirkMessages::LockNotHeld(key) => format!("Lock \"{}\" isn't held with that token.\n", key),
             SmirkMessages::SetKey(
                 key,
                 registered_type_name,
@@ -35,6 +132,13 @@ impl ToString for SmirkMessages {
                 "Key \"{}\" not found.\n",
                 key
                 ),
+            SmirkMessages::NullValue(_) => "(null)\n".to_owned(),
+            SmirkMessages::CastError(key, from_type, to_type) => format!(
+                "Casting key \"{}\" from \"{}\" to \"{}\" would lose information.\n",
+                key,
+                from_type,
+                to_type
+                ),
                 Self::TypeMismatch(key, desired_type) => format!(
                     "Couldn't downcast the value stored in key \"{}\" to type \"{}\".\n",
                     key,
@@ -46,6 +150,20 @@ impl ToString for SmirkMessages {
                         value,
                         desired_type
                         )
-        }
+        };
+        write!(f, "{}", message)
     }
 }
+
+impl std::error::Error for SmirkMessages {}
+
+/// Whether a reply line is one of the failures a script is most likely to need to notice: a key
+/// that wasn't found, a value of the wrong type, a value that didn't parse as its type, or one
+/// that couldn't be cast. They aren't sent as `-ERR` replies, so this is how smirk-client tells
+/// them from values.
+pub fn is_failure(line: &str) -> bool {
+    (line.starts_with("Key \"") && line.ends_with("\" not found."))
+        || line.starts_with("Couldn't downcast the value stored in key \"")
+        || (line.starts_with("Setting key \"") && line.contains("\" failed. Could not parse \""))
+        || (line.starts_with("Casting key \"") && line.ends_with("\" would lose information."))
+}
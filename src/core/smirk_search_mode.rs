@@ -1,6 +1,90 @@
-#[derive(Debug)]
+use std::fmt;
+use std::str::FromStr;
+use std::time::SystemTime;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum SmirkSearchMode {
     Glob,
     Regex,
     Trie
 }
+
+/// The order KEYS lists its matches in, picked with `SORT [ALPHA|LENGTH]`.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum KeyOrder {
+    /// Whatever order the matcher finds them in, which for the map can change as keys come and go.
+    #[default]
+    Unordered,
+    /// Lexicographic, byte by byte. The trie finds keys in this order anyway.
+    Alpha,
+    /// Shortest first in chars, ties broken alphabetically.
+    Length
+}
+
+impl KeyOrder {
+    pub fn sort<K: AsRef<str>>(&self, keys: &mut [K]) {
+        match self {
+            KeyOrder::Unordered => {}
+            KeyOrder::Alpha => keys.sort_unstable_by(|a, b| a.as_ref().cmp(b.as_ref())),
+            KeyOrder::Length => keys.sort_unstable_by(|a, b| {
+                let (a, b) = (a.as_ref(), b.as_ref());
+                (a.chars().count(), a).cmp(&(b.chars().count(), b))
+            })
+        }
+    }
+}
+
+impl FromStr for KeyOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_uppercase().as_str() {
+            "ALPHA" => Ok(KeyOrder::Alpha),
+            "LENGTH" => Ok(KeyOrder::Length),
+            _ => Err(format!("Invalid order \"{}\", expected ALPHA or LENGTH", s))
+        }
+    }
+}
+
+/// A KEYS pattern, optionally starting with the matcher to use for just this query: `glob:`,
+/// `re:` or `pre:` for a trie prefix. Without one, the server's MODE applies.
+///
+/// A pattern that really starts with one of those needs the matcher spelled out, e.g.
+/// `glob:glob:*`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyPattern {
+    pub mode: Option<SmirkSearchMode>,
+    pub pattern: String,
+    /// Only keys written at or after this match, from KEYS' `MODIFIEDSINCE`.
+    pub modified_since: Option<SystemTime>
+}
+
+impl KeyPattern {
+    /// The matcher to use, `default` if the pattern didn't name one.
+    pub fn mode_or(&self, default: SmirkSearchMode) -> SmirkSearchMode {
+        self.mode.unwrap_or(default)
+    }
+}
+
+impl From<&str> for KeyPattern {
+    fn from(s: &str) -> Self {
+        let prefixes = [("glob:", SmirkSearchMode::Glob), ("re:", SmirkSearchMode::Regex), ("pre:", SmirkSearchMode::Trie)];
+        for (prefix, mode) in prefixes {
+            if let Some(pattern) = s.strip_prefix(prefix) {
+                return KeyPattern { mode: Some(mode), pattern: pattern.to_string(), modified_since: None };
+            }
+        }
+        KeyPattern { mode: None, pattern: s.to_string(), modified_since: None }
+    }
+}
+
+impl fmt::Display for KeyPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.mode {
+            Some(SmirkSearchMode::Glob) => write!(f, "glob:{}", self.pattern),
+            Some(SmirkSearchMode::Regex) => write!(f, "re:{}", self.pattern),
+            Some(SmirkSearchMode::Trie) => write!(f, "pre:{}", self.pattern),
+            None => write!(f, "{}", self.pattern)
+        }
+    }
+}
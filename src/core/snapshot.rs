@@ -0,0 +1,562 @@
+use std::any::type_name;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde_json::{json, Map, Value};
+
+use super::geo::GeoSet;
+use super::bitfield::{BitFieldType, Overflow};
+use super::bloom_filter::BloomFilter;
+use super::codec::{self, ValueType};
+use super::counter::Counter;
+use super::float_format::FloatFormat;
+use super::hyper_log_log::HyperLogLog;
+use super::list::{End, List};
+use super::record::{Record, RecordLike, SharedValue};
+use super::smirk_map::{SmirkMap, render};
+use super::smirk_messages::SmirkMessages;
+use super::snapshot_keys::SnapshotKeys;
+use super::sorted_set::SortedSet;
+use super::stream::{ConsumerGroup, PendingEntry, Stream, StreamFields, StreamId};
+use super::timeseries::TimeSeries;
+
+/// Bumped whenever the snapshot layout changes in a way older readers can't handle.
+pub const SNAPSHOT_VERSION: u64 = 2;
+
+/// The file formats EXPORT and IMPORT read and write. Both hold the same document.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SnapshotFormat {
+    Json,
+    Cbor
+}
+
+impl SnapshotFormat {
+    /// Picks the format from a file extension, JSON unless it ends in `.cbor`.
+    pub fn from_path(path: &str) -> SnapshotFormat {
+        if path.to_lowercase().ends_with(".cbor") {
+            return SnapshotFormat::Cbor;
+        }
+        SnapshotFormat::Json
+    }
+}
+
+impl FromStr for SnapshotFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "json" => Ok(SnapshotFormat::Json),
+            "cbor" => Ok(SnapshotFormat::Cbor),
+            _ => Err(format!("Invalid snapshot format \"{}\", expected json or cbor", s))
+        }
+    }
+}
+
+/// Describes one record as `{"type", "user_type", "expires_at", "value"}`.
+///
+/// `type` is the stored Rust type and decides how `value` is laid out: text as GET prints it for
+/// scalars, the document itself for Json, arrays for collections and byte arrays for binary
+/// values that aren't UTF-8. `user_type` is kept alongside it, so a record comes back as the
+/// same Rust type under the same name whatever it was called. `expires_at` is in milliseconds
+/// since the Unix epoch, so time spent saved still counts against a TTL.
+pub fn record_to_json(smirk_map: &SmirkMap, key: &String) -> Result<Value, SmirkMessages> {
+    describe(key, smirk_map.get_record(key)?)
+}
+
+/// JSON has no infinities or NaN, so those floats are written as text, the way GET prints them.
+fn float_to_json(float: f64) -> Value {
+    match float.is_finite() {
+        true => json!(float),
+        false => json!(float.to_string())
+    }
+}
+
+fn float_from_json(value: &Value) -> Option<f64> {
+    match value {
+        Value::String(text) => text.parse().ok(),
+        value => value.as_f64()
+    }
+}
+
+/// Describes a consumer group, its pending entries as `[id, consumer, delivered_at, deliveries]`.
+fn group_to_json(group: &ConsumerGroup) -> Value {
+    let pending = group.pending.iter().map(|(id, entry)| json!([id.to_string(), entry.consumer, entry.delivered_at, entry.deliveries]));
+    json!({"last_delivered": group.last_delivered.to_string(), "pending": pending.collect::<Vec<Value>>()})
+}
+
+fn group_from_json(value: &Value) -> Option<ConsumerGroup> {
+    let mut group = ConsumerGroup { last_delivered: value["last_delivered"].as_str()?.parse().ok()?, ..Default::default() };
+    for entry in value["pending"].as_array()? {
+        let (id, consumer, delivered_at, deliveries) = serde_json::from_value::<(String, String, u64, u64)>(entry.clone()).ok()?;
+        group.pending.insert(id.parse().ok()?, PendingEntry { consumer, delivered_at, deliveries });
+    }
+    Some(group)
+}
+
+/// `record_to_json` for a record that's already been taken out of the map.
+fn describe(key: &str, record: &Record<SharedValue>) -> Result<Value, SmirkMessages> {
+    let value = &record.value;
+    let encoded = if let Some(bytes) = value.downcast_ref::<Vec<u8>>() {
+        match std::str::from_utf8(bytes) {
+            Ok(text) => json!(text),
+            Err(_) => json!(bytes)
+        }
+    } else if let Some(document) = value.downcast_ref::<Value>() {
+        document.clone()
+    } else if let Some(set) = value.downcast_ref::<SortedSet>() {
+        json!(set.range(0, -1).iter().map(|(member, score)| json!([member, float_to_json(*score)])).collect::<Vec<Value>>())
+    } else if let Some(stream) = value.downcast_ref::<Stream>() {
+        let entries = stream.range(StreamId::MIN, StreamId::MAX, None);
+        let entries = json!(entries.iter().map(|(id, fields)| json!({"id": id.to_string(), "fields": fields})).collect::<Vec<Value>>());
+        // Streams without consumer groups are written as just their entries, like before groups existed.
+        if stream.groups().is_empty() {
+            entries
+        } else {
+            let groups: serde_json::Map<String, Value> =
+                stream.groups().iter().map(|(name, group)| (name.clone(), group_to_json(group))).collect();
+            json!({"entries": entries, "groups": groups})
+        }
+    } else if let Some(list) = value.downcast_ref::<List>() {
+        json!(list.iter().collect::<Vec<&String>>())
+    } else if let Some(set) = value.downcast_ref::<GeoSet>() {
+        json!(set.members().map(|(member, (lon, lat))| json!([member, lon, lat])).collect::<Vec<Value>>())
+    } else if let Some(hll) = value.downcast_ref::<HyperLogLog>() {
+        // Most registers stay empty, so only the set ones are written, as [index, rank] pairs.
+        let registers = hll.registers().iter().enumerate().filter(|(_, rank)| **rank > 0);
+        json!(registers.map(|(index, rank)| json!([index, rank])).collect::<Vec<Value>>())
+    } else if let Some(counter) = value.downcast_ref::<Counter>() {
+        json!({"type": counter.ty.to_string(), "overflow": counter.overflow.to_string(), "value": counter.value()})
+    } else if let Some(filter) = value.downcast_ref::<BloomFilter>() {
+        json!({"capacity": filter.capacity(), "error_rate": filter.error_rate(), "items": filter.items(), "bits": filter.bits()})
+    } else if let Some(series) = value.downcast_ref::<TimeSeries>() {
+        let samples = series.range(0, u64::MAX);
+        json!({"retention": series.retention, "samples": samples.iter().map(|(timestamp, value)| json!([timestamp, float_to_json(*value)])).collect::<Vec<Value>>()})
+    } else if let Some(serialized) = codec::registered().iter().find_map(|value_type| value_type.serialize(value, &FloatFormat::Default)) {
+        // Registered types are written like binary values, so serializations that aren't text survive.
+        match String::from_utf8(serialized) {
+            Ok(text) => json!(text),
+            Err(e) => json!(e.into_bytes())
+        }
+    } else {
+        match render(key, value) {
+            Ok(text) => json!(text),
+            Err(SmirkMessages::NullValue(_)) => Value::Null,
+            Err(e) => return Err(e)
+        }
+    };
+    Ok(json!({
+        "type": record.type_name,
+        "user_type": record.desired_type_name,
+        "expires_at": record.expires_at().map(|at| at.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64),
+        "value": encoded
+    }))
+}
+
+/// Stores a record described by `record_to_json` at key, replacing whatever is there.
+///
+/// Descriptions from before snapshot version 2 have a `ttl` in seconds rather than `expires_at`,
+/// and it's counted from now.
+///
+/// # Returns
+///
+/// * Whether the record was stored. One that expired while it was saved isn't, and the key is
+///   left deleted.
+pub fn record_from_json(smirk_map: &mut SmirkMap, key: &String, entry: &Value) -> Result<bool, String> {
+    let invalid = |what: &str| format!("Key \"{}\" has an invalid {}", key, what);
+    let stored_type = entry["type"].as_str().ok_or(invalid("type"))?;
+    let user_type = entry["user_type"].as_str().ok_or(invalid("user_type"))?.to_string();
+    let ttl = match &entry["ttl"] {
+        Value::Null => None,
+        ttl => Some(ttl.as_u64().ok_or(invalid("ttl"))?)
+    };
+    let expires_at = match &entry["expires_at"] {
+        Value::Null => None,
+        at => Some(UNIX_EPOCH + Duration::from_millis(at.as_u64().ok_or(invalid("expires_at"))?))
+    };
+    if expires_at.is_some_and(|at| at <= SystemTime::now()) {
+        smirk_map.del(key);
+        return Ok(false);
+    }
+    let value = &entry["value"];
+
+    if stored_type == "null" {
+        smirk_map.set_null(key, &user_type);
+    } else if stored_type == "Vec<u8>" {
+        let bytes = match value {
+            Value::String(text) => text.clone().into_bytes(),
+            value => serde_json::from_value::<Vec<u8>>(value.clone()).map_err(|_| invalid("binary value"))?
+        };
+        smirk_map.binary_set(key, bytes, &user_type).map_err(|e| e.to_string())?;
+    } else if stored_type == type_name::<Value>() {
+        smirk_map.set_value(key, value.clone(), &user_type);
+    } else if stored_type == type_name::<SortedSet>() {
+        let members = serde_json::from_value::<Vec<(String, Value)>>(value.clone()).map_err(|_| invalid("sorted set"))?;
+        let mut set = SortedSet::new();
+        for (member, score) in members {
+            set.add(&member, float_from_json(&score).ok_or(invalid("sorted set score"))?);
+        }
+        smirk_map.set_value(key, set, &user_type);
+    } else if stored_type == type_name::<Stream>() {
+        let entries = value.as_array().or(value["entries"].as_array()).ok_or(invalid("stream"))?;
+        let mut stream = Stream::new();
+        for entry in entries {
+            let id = entry["id"].as_str().and_then(|id| id.parse::<StreamId>().ok()).ok_or(invalid("stream ID"))?;
+            let fields = serde_json::from_value::<StreamFields>(entry["fields"].clone()).map_err(|_| invalid("stream entry"))?;
+            stream.add(Some(id), fields).map_err(|e| format!("Key \"{}\": {}", key, e))?;
+        }
+        if let Some(groups) = value["groups"].as_object() {
+            for (name, group) in groups {
+                stream.restore_group(name, group_from_json(group).ok_or(invalid("consumer group"))?);
+            }
+        }
+        smirk_map.set_value(key, stream, &user_type);
+    } else if stored_type == type_name::<List>() {
+        let elements = serde_json::from_value::<Vec<String>>(value.clone()).map_err(|_| invalid("list"))?;
+        let mut list = List::new();
+        list.push(End::Right, &elements);
+        smirk_map.set_value(key, list, &user_type);
+    } else if stored_type == type_name::<GeoSet>() {
+        let members = serde_json::from_value::<Vec<(String, f64, f64)>>(value.clone()).map_err(|_| invalid("geo set"))?;
+        let mut set = GeoSet::new();
+        for (member, lon, lat) in members {
+            set.add(&member, lon, lat);
+        }
+        smirk_map.set_value(key, set, &user_type);
+    } else if stored_type == type_name::<HyperLogLog>() {
+        let registers = serde_json::from_value::<Vec<(usize, u8)>>(value.clone()).map_err(|_| invalid("HyperLogLog"))?;
+        let mut hll = HyperLogLog::new();
+        for (index, rank) in registers {
+            hll.set_register(index, rank).map_err(|e| format!("Key \"{}\": {}", key, e))?;
+        }
+        smirk_map.set_value(key, hll, &user_type);
+    } else if stored_type == type_name::<Counter>() {
+        let ty = value["type"].as_str().and_then(|ty| ty.parse::<BitFieldType>().ok()).ok_or(invalid("counter"))?;
+        let overflow = value["overflow"].as_str().and_then(|overflow| overflow.parse::<Overflow>().ok()).ok_or(invalid("counter"))?;
+        let counter = Counter::new(ty, overflow, value["value"].as_i64().ok_or(invalid("counter"))?)
+            .map_err(|e| format!("Key \"{}\": {}", key, e))?;
+        smirk_map.set_value(key, counter, &user_type);
+    } else if stored_type == type_name::<BloomFilter>() {
+        let field = |name: &str| value[name].as_u64().ok_or(invalid("Bloom filter"));
+        let error_rate = value["error_rate"].as_f64().ok_or(invalid("Bloom filter"))?;
+        let bits = serde_json::from_value::<Vec<u64>>(value["bits"].clone()).map_err(|_| invalid("Bloom filter"))?;
+        let filter = BloomFilter::from_parts(field("capacity")?, error_rate, field("items")?, bits)
+            .map_err(|e| format!("Key \"{}\": {}", key, e))?;
+        smirk_map.set_value(key, filter, &user_type);
+    } else if stored_type == type_name::<TimeSeries>() {
+        let retention = match &value["retention"] {
+            Value::Null => None,
+            retention => Some(retention.as_u64().ok_or(invalid("time series"))?)
+        };
+        let samples = value["samples"].as_array().ok_or(invalid("time series"))?;
+        let mut series = TimeSeries::new(retention);
+        for sample in samples {
+            let timestamp = sample[0].as_u64().ok_or(invalid("time series"))?;
+            let value = float_from_json(&sample[1]).ok_or(invalid("time series"))?;
+            series.add(timestamp, value).map_err(|e| format!("Key \"{}\": {}", key, e))?;
+        }
+        smirk_map.set_value(key, series, &user_type);
+    } else if let Some(value_type) = codec::registered().into_iter().find(|value_type| value_type.rust_type() == stored_type) {
+        let bytes = match value {
+            Value::String(text) => text.clone().into_bytes(),
+            value => serde_json::from_value::<Vec<u8>>(value.clone()).map_err(|_| invalid("value"))?
+        };
+        let parsed = value_type.parse(&bytes).ok_or(invalid("value"))?;
+        smirk_map.set_shared(key, parsed, stored_type, &user_type);
+    } else {
+        let text = value.as_str().ok_or(invalid("value"))?;
+        restore_scalar(smirk_map, key, stored_type, text, &user_type)?;
+    }
+    match expires_at {
+        Some(at) => smirk_map.set_expires_at(key, at),
+        // Set even when absent, so a key saved without a TTL doesn't pick up the default one.
+        None => {
+            smirk_map.set_ttl(key, &ttl);
+        }
+    }
+    Ok(true)
+}
+
+/// Parses a scalar back as the type it was stored as, which needn't be the one its user type
+/// would pick.
+fn restore_scalar(smirk_map: &mut SmirkMap, key: &String, stored_type: &str, text: &str, user_type: &String) -> Result<(), String> {
+    if let Some(codec) = codec::CODECS.iter().find(|codec| codec.rust_type() == stored_type) {
+        let value = codec.parse(text.as_bytes())
+            .ok_or_else(|| SmirkMessages::ParseError(key.clone(), text.to_string(), stored_type.to_string()).to_string().trim_end().to_string())?;
+        smirk_map.set_shared(key, value, stored_type, user_type);
+        return Ok(());
+    }
+    Err(format!("Key \"{}\" has a type this server doesn't know, {}", key, stored_type))
+}
+
+/// The whole keyspace as `{"version", "keys": {key: record}}`. Expired keys are left out.
+pub fn export(smirk_map: &SmirkMap) -> Value {
+    export_records(capture(smirk_map))
+}
+
+/// Copies out every live record, sorted by key. Values are shared rather than copied, so this is
+/// quick enough to do under the map's lock and leave `export_records` to run outside it.
+pub fn capture(smirk_map: &SmirkMap) -> Vec<(String, Record<SharedValue>)> {
+    let mut live: Vec<(String, Record<SharedValue>)> = smirk_map
+        .map
+        .iter()
+        .filter(|(_, record)| !record.is_expired())
+        .map(|(key, record)| (key.clone(), record.clone()))
+        .collect();
+    live.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+    live
+}
+
+/// `export` for records taken with `capture`.
+pub fn export_records(records: Vec<(String, Record<SharedValue>)>) -> Value {
+    let mut keys = Map::new();
+    for (key, record) in records {
+        if let Ok(record) = describe(&key, &record) {
+            keys.insert(key, record);
+        }
+    }
+    json!({ "version": SNAPSHOT_VERSION, "keys": keys })
+}
+
+/// Loads every key of a snapshot made by `export`, overwriting keys that already exist.
+///
+/// # Returns
+///
+/// * How many keys were loaded. Keys that fail are skipped, and the first failure is returned
+///   with the count if there was one. Keys that expired while saved aren't loaded or counted.
+pub fn import(smirk_map: &mut SmirkMap, snapshot: &Value) -> Result<usize, (usize, String)> {
+    import_sharded(smirk_map, snapshot, 1, &AtomicUsize::new(0))
+}
+
+/// `import`, with the keys split into `shards` that are parsed on threads of their own, each into
+/// a map that's merged into smirk_map once every shard is done. `progress` counts the keys parsed
+/// so far, whether or not they were loaded.
+pub fn import_sharded(smirk_map: &mut SmirkMap, snapshot: &Value, shards: usize, progress: &AtomicUsize) -> Result<usize, (usize, String)> {
+    match snapshot["version"].as_u64() {
+        Some(version) if version <= SNAPSHOT_VERSION => {}
+        Some(version) => return Err((0, format!("Snapshot version {} is newer than this server understands", version))),
+        None => return Err((0, String::from("Not a smirk snapshot")))
+    }
+    let keys: Vec<(&String, &Value)> = snapshot["keys"].as_object().ok_or((0, String::from("Snapshot has no keys")))?.iter().collect();
+    let shard_len = keys.len().div_ceil(shards.max(1)).max(1);
+    let parsed: Vec<Shard> = thread::scope(|scope| {
+        let workers: Vec<_> = keys
+            .chunks(shard_len)
+            .map(|entries| {
+                let map = smirk_map.empty_like();
+                scope.spawn(move || load_shard(map, entries, progress))
+            })
+            .collect();
+        workers.into_iter().map(|worker| worker.join().expect("a snapshot shard panicked")).collect()
+    });
+    let mut loaded = 0;
+    let mut first_error = None;
+    for shard in parsed {
+        for key in &shard.expired {
+            smirk_map.del(key);
+        }
+        loaded += smirk_map.absorb(shard.map);
+        if let Some(e) = shard.first_error {
+            first_error.get_or_insert(e);
+        }
+    }
+    match first_error {
+        Some(e) => Err((loaded, e)),
+        None => Ok(loaded)
+    }
+}
+
+/// The records of one shard of a snapshot, and the keys in it that expired while saved.
+struct Shard {
+    map: SmirkMap,
+    expired: Vec<String>,
+    first_error: Option<String>
+}
+
+fn load_shard(mut map: SmirkMap, entries: &[(&String, &Value)], progress: &AtomicUsize) -> Shard {
+    let mut expired = Vec::new();
+    let mut first_error = None;
+    for (key, entry) in entries {
+        match record_from_json(&mut map, key, entry) {
+            Ok(true) => {}
+            Ok(false) => expired.push(key.to_string()),
+            Err(e) => {
+                first_error.get_or_insert(e);
+            }
+        }
+        progress.fetch_add(1, Ordering::Relaxed);
+    }
+    Shard { map, expired, first_error }
+}
+
+/// Serializes one record for RESTORE: its `record_to_json` description as CBOR, hex encoded so
+/// it fits in a single token.
+pub fn dump(smirk_map: &SmirkMap, key: &String) -> Result<String, SmirkMessages> {
+    let mut record = record_to_json(smirk_map, key)?;
+    record["version"] = json!(SNAPSHOT_VERSION);
+    let mut bytes = Vec::new();
+    ciborium::into_writer(&record, &mut bytes).expect("writing CBOR to memory can't fail");
+    Ok(bytes.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+/// Recreates a record from a DUMP blob at key. The blob's own TTL is replaced by `ttl`.
+pub fn restore(smirk_map: &mut SmirkMap, key: &String, ttl: Option<u64>, blob: &str) -> Result<(), String> {
+    let invalid = || String::from("Invalid DUMP payload");
+    if !blob.len().is_multiple_of(2) {
+        return Err(invalid());
+    }
+    let bytes = (0..blob.len())
+        .step_by(2)
+        .map(|i| blob.get(i..i + 2).and_then(|pair| u8::from_str_radix(pair, 16).ok()))
+        .collect::<Option<Vec<u8>>>()
+        .ok_or_else(invalid)?;
+    let mut record: Value = ciborium::from_reader(bytes.as_slice()).map_err(|_| invalid())?;
+    match record["version"].as_u64() {
+        Some(version) if version <= SNAPSHOT_VERSION => {}
+        _ => return Err(invalid())
+    }
+    record["ttl"] = json!(ttl);
+    record["expires_at"] = Value::Null;
+    record_from_json(smirk_map, key, &record).map(|_| ())
+}
+
+/// The keys that differ between two snapshots, each list sorted.
+#[derive(Debug, Default, PartialEq)]
+pub struct SnapshotDiff {
+    pub added: Vec<String>,
+    pub changed: Vec<String>,
+    pub removed: Vec<String>
+}
+
+impl SnapshotDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.changed.is_empty() && self.removed.is_empty()
+    }
+
+    /// How many keys were added, changed and removed, e.g. `1 added, 0 changed, 2 removed`.
+    pub fn summary(&self) -> String {
+        format!("{} added, {} changed, {} removed", self.added.len(), self.changed.len(), self.removed.len())
+    }
+}
+
+/// One line per key, `+ key` for added, `~ key` for changed and `- key` for removed.
+impl fmt::Display for SnapshotDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (mark, keys) in [("+", &self.added), ("~", &self.changed), ("-", &self.removed)] {
+            for key in keys {
+                writeln!(f, "{} {}", mark, key)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// SHA-1 of every key's record in a snapshot made by `export`. A record's TTL is part of it.
+pub fn key_checksums(snapshot: &Value) -> BTreeMap<String, String> {
+    let Some(keys) = snapshot["keys"].as_object() else {
+        return BTreeMap::new();
+    };
+    keys.iter().map(|(key, record)| (key.clone(), checksum(record))).collect()
+}
+
+/// The keys added, changed and removed going from snapshot `before` to snapshot `after`.
+pub fn diff(before: &Value, after: &Value) -> SnapshotDiff {
+    let before = key_checksums(before);
+    let after = key_checksums(after);
+    let mut diff = SnapshotDiff::default();
+    for (key, sum) in &after {
+        match before.get(key) {
+            None => diff.added.push(key.clone()),
+            Some(old) if old != sum => diff.changed.push(key.clone()),
+            Some(_) => {}
+        }
+    }
+    diff.removed = before.keys().filter(|key| !after.contains_key(*key)).cloned().collect();
+    diff
+}
+
+/// Snapshots written so far, to give each one its own temporary file.
+static WRITES: AtomicU64 = AtomicU64::new(0);
+
+/// SHA-1 of a snapshot document, or part of one, taken over its compact JSON whatever format it's
+/// stored in.
+fn checksum(snapshot: &Value) -> String {
+    let bytes = serde_json::to_vec(snapshot).expect("writing JSON to memory can't fail");
+    sha1_smol::Sha1::from(bytes).digest().to_string()
+}
+
+/// Writes a snapshot made by `export`, embedding a checksum of it for `read_snapshot` to verify,
+/// and encrypting it if there are `keys`.
+///
+/// The file is written aside, synced to disk and only then renamed over `path`, so a crash or a
+/// full disk partway through leaves the last good snapshot in place.
+pub fn write_snapshot(path: &str, format: SnapshotFormat, mut snapshot: Value, keys: &SnapshotKeys) -> Result<(), String> {
+    let sum = checksum(&snapshot);
+    snapshot["checksum"] = json!(sum);
+    let partial = format!("{}.{}.tmp", path, WRITES.fetch_add(1, Ordering::Relaxed));
+    let written = write_file(&partial, format, &snapshot, keys).and_then(|_| fs::rename(&partial, path).map_err(|e| e.to_string()));
+    if written.is_err() {
+        fs::remove_file(&partial).ok();
+    }
+    written?;
+    // The rename itself only survives a crash once the directory holding it is synced too.
+    let dir = Path::new(path).parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    if let Ok(dir) = File::open(dir) {
+        dir.sync_all().ok();
+    }
+    Ok(())
+}
+
+fn write_file(path: &str, format: SnapshotFormat, snapshot: &Value, keys: &SnapshotKeys) -> Result<(), String> {
+    let mut writer = BufWriter::new(File::create(path).map_err(|e| e.to_string())?);
+    // Encryption needs the whole document, so only plain snapshots are written as they're serialized.
+    if keys.is_empty() {
+        write_document(&mut writer, format, snapshot)?;
+    } else {
+        let mut plain = Vec::new();
+        write_document(&mut plain, format, snapshot)?;
+        writer.write_all(&keys.encrypt(plain)?).map_err(|e| e.to_string())?;
+    }
+    writer.flush().map_err(|e| e.to_string())?;
+    writer.get_ref().sync_all().map_err(|e| e.to_string())
+}
+
+fn write_document(writer: &mut impl Write, format: SnapshotFormat, snapshot: &Value) -> Result<(), String> {
+    match format {
+        SnapshotFormat::Json => serde_json::to_writer_pretty(writer, snapshot).map_err(|e| e.to_string()),
+        SnapshotFormat::Cbor => ciborium::into_writer(snapshot, writer).map_err(|e| e.to_string())
+    }
+}
+
+/// Reads a snapshot written by `write_snapshot`, refusing it if it doesn't match its checksum.
+/// With `repair` a mismatch is only logged, and whatever still loads is loaded. Snapshots from
+/// before checksums were added have nothing to verify and are read as they are.
+///
+/// Encrypted snapshots are decrypted with whichever of `keys` wrote them. A snapshot that fails
+/// to decrypt is refused even with `repair`, since nothing in it can be trusted.
+pub fn read_snapshot(path: &str, format: SnapshotFormat, repair: bool, keys: &SnapshotKeys) -> Result<Value, String> {
+    let bytes = keys.decrypt(fs::read(path).map_err(|e| e.to_string())?)?;
+    let read = match format {
+        SnapshotFormat::Json => serde_json::from_slice(&bytes).map_err(|e| e.to_string()),
+        SnapshotFormat::Cbor => ciborium::from_reader(bytes.as_slice()).map_err(|e| e.to_string())
+    };
+    let mut snapshot: Value = read.map_err(|e| format!("Snapshot is corrupt or truncated and can't be read ({})", e))?;
+    let expected = snapshot.as_object_mut().and_then(|snapshot| snapshot.remove("checksum"));
+    let Some(expected) = expected else {
+        return Ok(snapshot);
+    };
+    if expected.as_str() == Some(checksum(&snapshot).as_str()) {
+        return Ok(snapshot);
+    }
+    if !repair {
+        return Err(String::from("Snapshot doesn't match its checksum, so it's corrupt. Use --repair to load what's left of it"));
+    }
+    log::warn!("Snapshot \"{}\" doesn't match its checksum, loading it anyway to repair it", path);
+    Ok(snapshot)
+}
@@ -0,0 +1,111 @@
+use std::fmt;
+use std::fs;
+
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+
+/// What an encrypted snapshot starts with, ahead of the ID of its key and its nonce.
+const MAGIC: &[u8] = b"SMIRKAES1";
+const KEY_ID_LEN: usize = 8;
+const NONCE_LEN: usize = 12;
+
+/// The AES-256-GCM keys snapshots are encrypted with, read from `--snapshot-key-file`.
+///
+/// The file has one key per line as 64 hex digits, and blank lines and `#` comments are skipped.
+/// New snapshots are encrypted with the first key and read back with whichever key wrote them,
+/// so a key is rotated by putting the new one first and keeping the old ones below it until
+/// every snapshot has been written again. With no keys, snapshots are plain files.
+#[derive(Clone, Default)]
+pub struct SnapshotKeys {
+    keys: Vec<[u8; 32]>
+}
+
+/// Keys never end up in logs.
+impl fmt::Debug for SnapshotKeys {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SnapshotKeys({} keys)", self.keys.len())
+    }
+}
+
+impl SnapshotKeys {
+    pub fn from_file(path: &str) -> Result<SnapshotKeys, String> {
+        let contents = fs::read_to_string(path).map_err(|e| format!("Couldn't read snapshot keys from \"{}\": {}", path, e))?;
+        let mut keys = Vec::new();
+        for (i, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let key = parse_key(line).ok_or(format!("Line {} of \"{}\" isn't a key, expected 64 hex digits", i + 1, path))?;
+            keys.push(key);
+        }
+        if keys.is_empty() {
+            return Err(format!("There are no keys in \"{}\"", path));
+        }
+        Ok(SnapshotKeys { keys })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Encrypts a snapshot's bytes with the first key, or hands them back as they are without one.
+    pub fn encrypt(&self, plain: Vec<u8>) -> Result<Vec<u8>, String> {
+        let Some(key) = self.keys.first() else {
+            return Ok(plain);
+        };
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let sealed = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key))
+            .encrypt(&nonce, plain.as_slice())
+            .map_err(|_| String::from("Couldn't encrypt the snapshot"))?;
+        let mut encrypted = Vec::with_capacity(MAGIC.len() + KEY_ID_LEN + NONCE_LEN + sealed.len());
+        encrypted.extend_from_slice(MAGIC);
+        encrypted.extend_from_slice(&key_id(key));
+        encrypted.extend_from_slice(&nonce);
+        encrypted.extend(sealed);
+        Ok(encrypted)
+    }
+
+    /// Decrypts a snapshot's bytes with the key that wrote them. Snapshots that were never
+    /// encrypted are handed back as they are, so turning encryption on doesn't strand old ones.
+    pub fn decrypt(&self, bytes: Vec<u8>) -> Result<Vec<u8>, String> {
+        let Some(rest) = bytes.strip_prefix(MAGIC) else {
+            return Ok(bytes);
+        };
+        if self.keys.is_empty() {
+            return Err(String::from("Snapshot is encrypted, start with --snapshot-key-file to read it"));
+        }
+        if rest.len() < KEY_ID_LEN + NONCE_LEN {
+            return Err(String::from("Snapshot is corrupt or truncated and can't be read (no nonce)"));
+        }
+        let (id, rest) = rest.split_at(KEY_ID_LEN);
+        let (nonce, sealed) = rest.split_at(NONCE_LEN);
+        let key = self
+            .keys
+            .iter()
+            .find(|key| key_id(key) == id)
+            .ok_or(String::from("Snapshot was encrypted with a key that isn't in the key file"))?;
+        Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key))
+            .decrypt(Nonce::from_slice(nonce), sealed)
+            .map_err(|_| String::from("Snapshot doesn't decrypt, so it's corrupt or was tampered with"))
+    }
+}
+
+fn parse_key(hex: &str) -> Option<[u8; 32]> {
+    if hex.len() != 64 || !hex.is_ascii() {
+        return None;
+    }
+    let mut key = [0; 32];
+    for (i, byte) in key.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(key)
+}
+
+/// Names a key in the snapshots it writes without giving anything away about it.
+fn key_id(key: &[u8; 32]) -> [u8; KEY_ID_LEN] {
+    let digest = sha1_smol::Sha1::from(key).digest().bytes();
+    let mut id = [0; KEY_ID_LEN];
+    id.copy_from_slice(&digest[..KEY_ID_LEN]);
+    id
+}
@@ -0,0 +1,129 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::ops::Bound;
+
+/// An f64 with a total order, so it can key a BTreeSet.
+#[derive(Debug, Clone, Copy)]
+struct Score(f64);
+
+impl PartialEq for Score {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.total_cmp(&other.0) == Ordering::Equal
+    }
+}
+
+impl Eq for Score {}
+
+impl PartialOrd for Score {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Score {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+/// A set of unique members ordered by score, ties broken by member name.
+///
+/// Members are kept both in a score index for range queries and a lookup table for ZSCORE.
+#[derive(Debug, Clone, Default)]
+pub struct SortedSet {
+    scores: HashMap<String, f64>,
+    ordered: BTreeSet<(Score, String)>
+}
+
+impl SortedSet {
+    pub fn new() -> SortedSet {
+        SortedSet::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.scores.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scores.is_empty()
+    }
+
+    /// Adds a member or updates its score. Returns true if the member is new.
+    pub fn add(&mut self, member: &str, score: f64) -> bool {
+        let previous = self.scores.insert(member.to_string(), score);
+        if let Some(previous) = previous {
+            self.ordered.remove(&(Score(previous), member.to_string()));
+        }
+        self.ordered.insert((Score(score), member.to_string()));
+        previous.is_none()
+    }
+
+    pub fn score(&self, member: &str) -> Option<f64> {
+        self.scores.get(member).copied()
+    }
+
+    /// What a member's score would be with `increment` added, starting from 0 if it isn't in the
+    /// set yet. Fails if that isn't a finite number, which a score has to be.
+    pub fn incremented(&self, member: &str, increment: f64) -> Result<f64, String> {
+        let score = self.score(member).unwrap_or(0.0) + increment;
+        if !score.is_finite() {
+            return Err(format!("the score of \"{}\" would become {}, which isn't a finite number", member, score));
+        }
+        Ok(score)
+    }
+
+    /// Adds `increment` to a member's score, leaving it as it was if that fails `incremented`.
+    pub fn incr(&mut self, member: &str, increment: f64) -> Result<f64, String> {
+        let score = self.incremented(member, increment)?;
+        self.add(member, score);
+        Ok(score)
+    }
+
+    /// Removes a member. Returns true if it was in the set.
+    pub fn remove(&mut self, member: &str) -> bool {
+        match self.scores.remove(member) {
+            Some(score) => {
+                self.ordered.remove(&(Score(score), member.to_string()));
+                true
+            }
+            None => false
+        }
+    }
+
+    /// Members by rank from `start` to `stop` inclusive. Negative ranks count from the end, -1 being the last.
+    pub fn range(&self, start: i64, stop: i64) -> Vec<(&String, f64)> {
+        let len = self.len() as i64;
+        let start = if start < 0 { (len + start).max(0) } else { start };
+        let stop = if stop < 0 { len + stop } else { stop.min(len - 1) };
+        if start > stop {
+            return Vec::new();
+        }
+        self.ordered
+            .iter()
+            .skip(start as usize)
+            .take((stop - start + 1) as usize)
+            .map(|(score, member)| (member, score.0))
+            .collect()
+    }
+
+    /// Members whose score is between `min` and `max` inclusive, lowest first.
+    pub fn range_by_score(&self, min: f64, max: f64) -> Vec<(&String, f64)> {
+        if min > max {
+            return Vec::new();
+        }
+        self.ordered
+            .range((Bound::Included((Score(min), String::new())), Bound::Unbounded))
+            .take_while(|(score, _)| score.0 <= max)
+            .map(|(score, member)| (member, score.0))
+            .collect()
+    }
+}
+
+impl fmt::Display for SortedSet {
+    /// Writes `member score` pairs, lowest score first.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let pairs: Vec<String> = self.ordered.iter().map(|(score, member)| format!("{} {}", member, score.0)).collect();
+        write!(f, "{}", pairs.join(" "))
+    }
+}
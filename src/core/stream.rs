@@ -0,0 +1,281 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::Bound;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A stream entry ID, `<milliseconds>-<sequence>`. IDs only ever grow within a stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct StreamId {
+    pub ms: u64,
+    pub seq: u64
+}
+
+impl StreamId {
+    pub const MIN: StreamId = StreamId { ms: 0, seq: 0 };
+    pub const MAX: StreamId = StreamId { ms: u64::MAX, seq: u64::MAX };
+
+    /// Parses a range bound for XRANGE. `-` and `+` are the smallest and largest IDs, and a bare
+    /// `<ms>` covers every sequence number in that millisecond.
+    pub fn parse_bound(s: &str, is_end: bool) -> Result<StreamId, String> {
+        match s {
+            "-" => Ok(StreamId::MIN),
+            "+" => Ok(StreamId::MAX),
+            s if !s.contains('-') => {
+                let ms = s.parse::<u64>().map_err(|_| format!("Invalid stream ID \"{}\"", s))?;
+                Ok(StreamId { ms, seq: if is_end { u64::MAX } else { 0 } })
+            }
+            s => s.parse()
+        }
+    }
+}
+
+impl FromStr for StreamId {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (ms, seq) = s.split_once('-').unwrap_or((s, "0"));
+        match (ms.parse::<u64>(), seq.parse::<u64>()) {
+            (Ok(ms), Ok(seq)) => Ok(StreamId { ms, seq }),
+            _ => Err(format!("Invalid stream ID \"{}\"", s))
+        }
+    }
+}
+
+impl fmt::Display for StreamId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.ms, self.seq)
+    }
+}
+
+/// The field/value pairs of one stream entry.
+pub type StreamFields = Vec<(String, String)>;
+
+/// Milliseconds since the Unix epoch.
+fn now_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0)
+}
+
+/// An entry a group delivered to one of its consumers, which it hasn't acknowledged yet.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PendingEntry {
+    pub consumer: String,
+    /// When it was last delivered, in milliseconds since the Unix epoch.
+    pub delivered_at: u64,
+    /// How many times it's been delivered, counting claims.
+    pub deliveries: u64
+}
+
+impl PendingEntry {
+    /// How long ago the entry was last delivered, in milliseconds.
+    pub fn idle(&self) -> u64 {
+        now_ms().saturating_sub(self.delivered_at)
+    }
+}
+
+/// A consumer group, sharing out a stream's entries between its consumers so each is only
+/// delivered to one of them, and remembering which haven't been acknowledged.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ConsumerGroup {
+    /// The newest entry delivered to any consumer in the group.
+    pub last_delivered: StreamId,
+    pub pending: BTreeMap<StreamId, PendingEntry>
+}
+
+/// An append-only log of field/value entries, ordered by ID.
+#[derive(Debug, Clone, Default)]
+pub struct Stream {
+    entries: BTreeMap<StreamId, StreamFields>,
+    last_id: StreamId,
+    groups: BTreeMap<String, ConsumerGroup>
+}
+
+impl Stream {
+    pub fn new() -> Stream {
+        Stream::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The ID of the newest entry ever added, `0-0` for a new stream.
+    pub fn last_id(&self) -> StreamId {
+        self.last_id
+    }
+
+    /// Appends an entry and returns its ID.
+    ///
+    /// Without an explicit `id` one is generated from the clock, bumping the sequence number when
+    /// the clock hasn't moved past the last entry. Explicit IDs must be greater than the last one.
+    pub fn add(&mut self, id: Option<StreamId>, fields: StreamFields) -> Result<StreamId, String> {
+        let id = match id {
+            Some(id) if id <= self.last_id => {
+                return Err(format!("ID {} is not greater than the last ID {}", id, self.last_id));
+            }
+            Some(id) => id,
+            None => {
+                let now = now_ms();
+                if now > self.last_id.ms {
+                    StreamId { ms: now, seq: 0 }
+                } else if let Some(seq) = self.last_id.seq.checked_add(1) {
+                    StreamId { ms: self.last_id.ms, seq }
+                } else if let Some(ms) = self.last_id.ms.checked_add(1) {
+                    StreamId { ms, seq: 0 }
+                } else {
+                    return Err(format!("No IDs are left after the last ID {}", self.last_id));
+                }
+            }
+        };
+        self.entries.insert(id, fields);
+        self.last_id = id;
+        Ok(id)
+    }
+
+    /// Entries with IDs from `start` to `end` inclusive, oldest first.
+    pub fn range(&self, start: StreamId, end: StreamId, count: Option<usize>) -> Vec<(&StreamId, &StreamFields)> {
+        if start > end {
+            return Vec::new();
+        }
+        self.entries.range(start..=end).take(count.unwrap_or(usize::MAX)).collect()
+    }
+
+    /// Entries with IDs strictly greater than `id`, oldest first.
+    pub fn after(&self, id: StreamId, count: Option<usize>) -> Vec<(&StreamId, &StreamFields)> {
+        self.entries
+            .range((Bound::Excluded(id), Bound::Unbounded))
+            .take(count.unwrap_or(usize::MAX))
+            .collect()
+    }
+
+    pub fn groups(&self) -> &BTreeMap<String, ConsumerGroup> {
+        &self.groups
+    }
+
+    fn group_mut(&mut self, name: &str) -> Result<&mut ConsumerGroup, String> {
+        self.groups.get_mut(name).ok_or(format!("no consumer group \"{}\"", name))
+    }
+
+    /// Whether a consumer group called `name` can be added, which it can't if there's one already.
+    pub fn can_create_group(&self, name: &str) -> Result<(), String> {
+        match self.groups.contains_key(name) {
+            true => Err(format!("a consumer group \"{}\" already", name)),
+            false => Ok(())
+        }
+    }
+
+    /// Adds a consumer group that delivers the entries after `start`.
+    pub fn create_group(&mut self, name: &str, start: StreamId) -> Result<(), String> {
+        self.can_create_group(name)?;
+        self.groups.insert(name.to_string(), ConsumerGroup { last_delivered: start, pending: BTreeMap::new() });
+        Ok(())
+    }
+
+    /// Puts back a group as it was saved.
+    pub fn restore_group(&mut self, name: &str, group: ConsumerGroup) {
+        self.groups.insert(name.to_string(), group);
+    }
+
+    /// Removes a consumer group and everything pending in it. Returns whether it existed.
+    pub fn destroy_group(&mut self, name: &str) -> bool {
+        self.groups.remove(name).is_some()
+    }
+
+    fn group(&self, name: &str) -> Result<&ConsumerGroup, String> {
+        self.groups.get(name).ok_or(format!("no consumer group \"{}\"", name))
+    }
+
+    /// The entries delivered to `group` that haven't been acknowledged, by ID.
+    pub fn pending(&self, group: &str) -> Result<&BTreeMap<StreamId, PendingEntry>, String> {
+        Ok(&self.group(group)?.pending)
+    }
+
+    /// Whether `group` has entries none of its consumers have been delivered yet.
+    pub fn has_undelivered(&self, group: &str) -> Result<bool, String> {
+        Ok(self.last_id > self.group(group)?.last_delivered)
+    }
+
+    /// Delivers `consumer` entries no consumer in `group` has had yet, oldest first. They stay
+    /// pending for it until they're acknowledged.
+    pub fn deliver(&mut self, group: &str, consumer: &str, count: Option<usize>) -> Result<Vec<(StreamId, StreamFields)>, String> {
+        let entries = &self.entries;
+        let group = self.groups.get_mut(group).ok_or(format!("no consumer group \"{}\"", group))?;
+        let delivered: Vec<(StreamId, StreamFields)> = entries
+            .range((Bound::Excluded(group.last_delivered), Bound::Unbounded))
+            .take(count.unwrap_or(usize::MAX))
+            .map(|(id, fields)| (*id, fields.clone()))
+            .collect();
+        let delivered_at = now_ms();
+        for (id, _) in &delivered {
+            group.pending.insert(*id, PendingEntry { consumer: consumer.to_string(), delivered_at, deliveries: 1 });
+            group.last_delivered = *id;
+        }
+        Ok(delivered)
+    }
+
+    /// The entries after `id` that `consumer` has pending in `group`, so a consumer that restarts
+    /// can pick up where it left off.
+    pub fn pending_for(
+        &self,
+        group: &str,
+        consumer: &str,
+        id: StreamId,
+        count: Option<usize>
+    ) -> Result<Vec<(StreamId, StreamFields)>, String> {
+        Ok(self
+            .group(group)?
+            .pending
+            .range((Bound::Excluded(id), Bound::Unbounded))
+            .filter(|(_, pending)| pending.consumer == consumer)
+            .filter_map(|(id, _)| self.entries.get(id).map(|fields| (*id, fields.clone())))
+            .take(count.unwrap_or(usize::MAX))
+            .collect())
+    }
+
+    /// Acknowledges entries, so they're no longer pending in `group`. Returns how many were.
+    pub fn ack(&mut self, group: &str, ids: &[StreamId]) -> Result<usize, String> {
+        let group = self.group_mut(group)?;
+        Ok(ids.iter().filter(|id| group.pending.remove(id).is_some()).count())
+    }
+
+    /// Hands pending entries that have sat unacknowledged for at least `min_idle` milliseconds over
+    /// to `consumer`, as if they'd just been delivered to it, so the work of a consumer that died
+    /// isn't lost. Entries that don't qualify are skipped.
+    pub fn claim(
+        &mut self,
+        group: &str,
+        consumer: &str,
+        min_idle: u64,
+        ids: &[StreamId]
+    ) -> Result<Vec<(StreamId, StreamFields)>, String> {
+        let entries = &self.entries;
+        let group = self.groups.get_mut(group).ok_or(format!("no consumer group \"{}\"", group))?;
+        let now = now_ms();
+        let mut claimed = Vec::new();
+        for id in ids {
+            let Some(pending) = group.pending.get_mut(id) else { continue };
+            if pending.idle() < min_idle {
+                continue;
+            }
+            let Some(fields) = entries.get(id) else { continue };
+            pending.consumer = consumer.to_string();
+            pending.delivered_at = now;
+            pending.deliveries += 1;
+            claimed.push((*id, fields.clone()));
+        }
+        Ok(claimed)
+    }
+}
+
+/// Formats an entry as one line, `<id> <field> <value> ...`.
+pub fn format_entry(id: &StreamId, fields: &StreamFields) -> String {
+    let mut line = id.to_string();
+    for (field, value) in fields {
+        line.push_str(&format!(" {} {}", field, value));
+    }
+    line
+}
@@ -0,0 +1,70 @@
+use std::collections::{BTreeSet, HashMap};
+
+/// Free-form string tags attached to keys, indexed both ways so TAG KEYS and DEL BYTAG don't
+/// have to scan the map.
+#[derive(Debug, Clone, Default)]
+pub struct TagIndex {
+    by_tag: HashMap<String, BTreeSet<String>>,
+    by_key: HashMap<String, BTreeSet<String>>
+}
+
+impl TagIndex {
+    pub fn new() -> TagIndex {
+        TagIndex::default()
+    }
+
+    /// Tags a key. Returns how many of the tags are new to it.
+    pub fn add(&mut self, key: &str, tags: &[String]) -> usize {
+        let mut added = 0;
+        for tag in tags {
+            if self.by_key.entry(key.to_string()).or_default().insert(tag.clone()) {
+                self.by_tag.entry(tag.clone()).or_default().insert(key.to_string());
+                added += 1;
+            }
+        }
+        added
+    }
+
+    /// Untags a key. Returns how many of the tags it actually had.
+    pub fn remove(&mut self, key: &str, tags: &[String]) -> usize {
+        let Some(key_tags) = self.by_key.get_mut(key) else {
+            return 0;
+        };
+        let untagged: Vec<&String> = tags.iter().filter(|tag| key_tags.remove(*tag)).collect();
+        if key_tags.is_empty() {
+            self.by_key.remove(key);
+        }
+        for tag in &untagged {
+            self.untag(tag, key);
+        }
+        untagged.len()
+    }
+
+    /// Drops every tag on a key, returning them.
+    pub fn remove_key(&mut self, key: &str) -> BTreeSet<String> {
+        let tags = self.by_key.remove(key).unwrap_or_default();
+        for tag in &tags {
+            self.untag(tag, key);
+        }
+        tags
+    }
+
+    /// Every key carrying `tag`, in order.
+    pub fn keys(&self, tag: &str) -> Vec<&String> {
+        self.by_tag.get(tag).map(|keys| keys.iter().collect()).unwrap_or_default()
+    }
+
+    /// Every tag on `key`, in order.
+    pub fn tags(&self, key: &str) -> Vec<&String> {
+        self.by_key.get(key).map(|tags| tags.iter().collect()).unwrap_or_default()
+    }
+
+    fn untag(&mut self, tag: &str, key: &str) {
+        if let Some(keys) = self.by_tag.get_mut(tag) {
+            keys.remove(key);
+            if keys.is_empty() {
+                self.by_tag.remove(tag);
+            }
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// How TS.RANGE combines the samples in each bucket, and how AGG combines values.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Aggregation {
+    Avg,
+    Min,
+    Max,
+    Sum,
+    Count
+}
+
+impl FromStr for Aggregation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "avg" | "mean" => Ok(Aggregation::Avg),
+            "min" => Ok(Aggregation::Min),
+            "max" => Ok(Aggregation::Max),
+            "sum" => Ok(Aggregation::Sum),
+            "count" => Ok(Aggregation::Count),
+            _ => Err(format!("Invalid aggregation \"{}\", expected avg, min, max, sum or count", s))
+        }
+    }
+}
+
+impl fmt::Display for Aggregation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Aggregation::Avg => "avg",
+            Aggregation::Min => "min",
+            Aggregation::Max => "max",
+            Aggregation::Sum => "sum",
+            Aggregation::Count => "count"
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl Aggregation {
+    fn apply(&self, values: &[f64]) -> f64 {
+        match self {
+            Aggregation::Avg => values.iter().sum::<f64>() / values.len() as f64,
+            Aggregation::Min => values.iter().copied().fold(f64::INFINITY, f64::min),
+            Aggregation::Max => values.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+            Aggregation::Sum => values.iter().sum(),
+            Aggregation::Count => values.len() as f64
+        }
+    }
+}
+
+/// Samples of a value over time, keyed by timestamp in milliseconds. Adding a sample at a
+/// timestamp that already has one replaces it.
+///
+/// With a retention period, samples older than that before the newest one are pruned as new ones
+/// are added, and samples that old are refused.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TimeSeries {
+    samples: BTreeMap<u64, f64>,
+    /// How many milliseconds of samples to keep, or `None` to keep them all.
+    pub retention: Option<u64>
+}
+
+impl TimeSeries {
+    pub fn new(retention: Option<u64>) -> TimeSeries {
+        TimeSeries { samples: BTreeMap::new(), retention }
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// The oldest timestamp the retention period still keeps, if it's pruning anything.
+    fn cutoff(&self) -> Option<u64> {
+        let newest = *self.samples.keys().next_back()?;
+        Some(newest.saturating_sub(self.retention?))
+    }
+
+    /// Whether a sample at `timestamp` can be added, which it can't if it's older than the
+    /// retention period keeps.
+    pub fn accepts(&self, timestamp: u64) -> Result<(), String> {
+        match self.cutoff() {
+            Some(cutoff) if timestamp < cutoff => Err(format!("timestamp {} is older than the retention period keeps", timestamp)),
+            _ => Ok(())
+        }
+    }
+
+    pub fn add(&mut self, timestamp: u64, value: f64) -> Result<(), String> {
+        self.accepts(timestamp)?;
+        self.samples.insert(timestamp, value);
+        if let Some(cutoff) = self.cutoff() {
+            self.samples = self.samples.split_off(&cutoff);
+        }
+        Ok(())
+    }
+
+    /// Samples from `from` to `to` inclusive, oldest first.
+    pub fn range(&self, from: u64, to: u64) -> Vec<(u64, f64)> {
+        if from > to {
+            return Vec::new();
+        }
+        self.samples.range(from..=to).map(|(timestamp, value)| (*timestamp, *value)).collect()
+    }
+
+    /// Samples from `from` to `to` inclusive, grouped into buckets `bucket` milliseconds wide and
+    /// combined with `aggregation`. Each bucket is stamped with its start, a multiple of `bucket`,
+    /// and buckets without samples are left out.
+    pub fn aggregate(&self, from: u64, to: u64, aggregation: Aggregation, bucket: u64) -> Vec<(u64, f64)> {
+        let mut buckets: Vec<(u64, Vec<f64>)> = Vec::new();
+        for (timestamp, value) in self.range(from, to) {
+            let start = timestamp - timestamp % bucket;
+            match buckets.last_mut() {
+                Some((last, values)) if *last == start => values.push(value),
+                _ => buckets.push((start, vec![value]))
+            }
+        }
+        buckets.into_iter().map(|(start, values)| (start, aggregation.apply(&values))).collect()
+    }
+}
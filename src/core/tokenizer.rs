@@ -0,0 +1,107 @@
+use super::command_error::CommandError;
+
+/// Splits a command line into arguments.
+///
+/// Arguments are separated by runs of spaces and tabs, and whitespace at either end of the line is
+/// ignored. An argument that starts with a double quote runs to the matching closing quote and may
+/// contain whitespace and the escapes `\n`, `\r`, `\t`, `\"`, `\\` and `\xNN`. Single quotes work
+/// the same way but only understand `\'`, so everything else inside them is taken literally.
+/// Quotes anywhere else in an argument, like in `{"a":1}`, are just characters.
+///
+/// Errors carry the byte offset in `line` where parsing went wrong.
+pub fn tokenize(line: &[u8]) -> Result<Vec<Vec<u8>>, CommandError> {
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+    loop {
+        while line.get(pos).is_some_and(|b| is_separator(*b)) {
+            pos += 1;
+        }
+        let (token, end) = match line.get(pos) {
+            None => return Ok(tokens),
+            Some(&quote) if quote == b'"' || quote == b'\'' => quoted(line, pos)?,
+            Some(_) => {
+                let end = line[pos..].iter().position(|b| is_separator(*b)).map_or(line.len(), |n| pos + n);
+                (line[pos..end].to_vec(), end)
+            }
+        };
+        match line.get(end) {
+            Some(&b) if !is_separator(b) => return Err(CommandError::UnexpectedCharacter(end)),
+            _ => tokens.push(token)
+        }
+        pos = end;
+    }
+}
+
+fn is_separator(b: u8) -> bool {
+    b == b' ' || b == b'\t'
+}
+
+/// Reads the quoted argument starting at `start`, returning it and the offset just past its
+/// closing quote.
+fn quoted(line: &[u8], start: usize) -> Result<(Vec<u8>, usize), CommandError> {
+    let quote = line[start];
+    let mut token = Vec::new();
+    let mut pos = start + 1;
+    loop {
+        match line.get(pos) {
+            None => return Err(CommandError::UnterminatedQuote(start)),
+            Some(&b) if b == quote => return Ok((token, pos + 1)),
+            Some(b'\\') if quote == b'\'' => {
+                if line.get(pos + 1) == Some(&b'\'') {
+                    token.push(b'\'');
+                    pos += 2;
+                } else {
+                    token.push(b'\\');
+                    pos += 1;
+                }
+            }
+            Some(b'\\') => {
+                let (byte, length) = match line.get(pos + 1) {
+                    Some(b'n') => (b'\n', 2),
+                    Some(b'r') => (b'\r', 2),
+                    Some(b't') => (b'\t', 2),
+                    Some(b'"') => (b'"', 2),
+                    Some(b'\\') => (b'\\', 2),
+                    Some(b'x') => {
+                        let hex = line.get(pos + 2..pos + 4).and_then(|hex| std::str::from_utf8(hex).ok());
+                        match hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                            Some(byte) => (byte, 4),
+                            None => return Err(CommandError::InvalidEscape(pos))
+                        }
+                    }
+                    _ => return Err(CommandError::InvalidEscape(pos))
+                };
+                token.push(byte);
+                pos += length;
+            }
+            Some(&b) => {
+                token.push(b);
+                pos += 1;
+            }
+        }
+    }
+}
+
+/// Quotes an argument so `tokenize` reads it back unchanged, leaving plain words as they are.
+pub fn quote(argument: &str) -> String {
+    let plain = !argument.is_empty()
+        && !argument.starts_with(['"', '\''])
+        && !argument.bytes().any(|b| b == b' ' || b.is_ascii_control());
+    if plain {
+        return argument.to_string();
+    }
+    let mut quoted = String::from("\"");
+    for c in argument.chars() {
+        match c {
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if c.is_ascii_control() => quoted.push_str(&format!("\\x{:02x}", c as u8)),
+            c => quoted.push(c)
+        }
+    }
+    quoted.push('"');
+    quoted
+}
@@ -0,0 +1,98 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// A fixed-dimension embedding, written as comma separated floats, e.g. `0.1,0.2,0.3`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Vector(pub Vec<f32>);
+
+impl FromStr for Vector {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let s = s.strip_prefix('[').and_then(|s| s.strip_suffix(']')).unwrap_or(s);
+        let values = s
+            .split(',')
+            .map(|v| v.trim().parse::<f32>().ok().filter(|v| v.is_finite()))
+            .collect::<Option<Vec<f32>>>()
+            .ok_or(format!("Invalid vector \"{}\"", s))?;
+        Ok(Vector(values))
+    }
+}
+
+impl fmt::Display for Vector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let values: Vec<String> = self.0.iter().map(|v| v.to_string()).collect();
+        write!(f, "{}", values.join(","))
+    }
+}
+
+/// How VSEARCH measures how far apart two vectors are. Smaller is nearer for both.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VectorMetric {
+    /// `1 - cosine similarity`, from 0 (same direction) to 2 (opposite).
+    Cosine,
+    Euclidean
+}
+
+impl FromStr for VectorMetric {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "cosine" => Ok(VectorMetric::Cosine),
+            "euclidean" | "l2" => Ok(VectorMetric::Euclidean),
+            _ => Err(format!("Invalid metric \"{}\", expected cosine or euclidean", s))
+        }
+    }
+}
+
+impl fmt::Display for VectorMetric {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VectorMetric::Cosine => write!(f, "cosine"),
+            VectorMetric::Euclidean => write!(f, "euclidean")
+        }
+    }
+}
+
+impl VectorMetric {
+    pub fn distance(&self, a: &[f32], b: &[f32]) -> f32 {
+        match self {
+            VectorMetric::Cosine => {
+                let norms = (dot(a, a) * dot(b, b)).sqrt();
+                if norms == 0.0 {
+                    return 1.0;
+                }
+                1.0 - dot(a, b) / norms
+            }
+            VectorMetric::Euclidean => {
+                let diff: Vec<f32> = a.iter().zip(b).map(|(a, b)| a - b).collect();
+                dot(&diff, &diff).sqrt()
+            }
+        }
+    }
+}
+
+/// Dot product over fixed-width lanes, which the compiler turns into SIMD instructions.
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    const LANES: usize = 8;
+    let mut sums = [0.0f32; LANES];
+    let (a_chunks, b_chunks) = (a.chunks_exact(LANES), b.chunks_exact(LANES));
+    let tail: f32 = a_chunks.remainder().iter().zip(b_chunks.remainder()).map(|(a, b)| a * b).sum();
+    for (a, b) in a_chunks.zip(b_chunks) {
+        for ((sum, a), b) in sums.iter_mut().zip(a).zip(b) {
+            *sum += a * b;
+        }
+    }
+    sums.iter().sum::<f32>() + tail
+}
+
+/// A named set of Vector records that VSEARCH scans: every key starting with `prefix` holding a
+/// Vector of `dim` dimensions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VectorIndex {
+    pub prefix: String,
+    pub dim: usize,
+    pub metric: VectorMetric
+}
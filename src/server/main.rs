@@ -1,248 +1,2022 @@
 use std::{
-    collections::HashMap,
-    net::{TcpListener,TcpStream},
-    io::{Write, BufReader, BufRead}, sync::{Arc, Mutex, MutexGuard}, str::FromStr, fmt::Display
+    any::type_name, net::{SocketAddr, TcpListener}, path::Path,
+    io::{self, Write, BufReader, BufWriter}, sync::{mpsc, Arc, Mutex, MutexGuard, RwLock}, thread::sleep,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH}
 };
 
+mod smirk_access;
+mod smirk_auth;
+mod smirk_backing;
+mod smirk_blocking;
+mod smirk_checksum;
+mod smirk_clients;
+mod smirk_cluster;
+mod smirk_commands;
 mod smirk_config;
-use num::{CheckedAdd, BigInt};
+mod smirk_expiry;
+mod smirk_http;
+mod smirk_cursors;
+mod smirk_lazyfree;
+mod smirk_logger;
+mod smirk_migrations;
+mod smirk_proxy;
+mod smirk_remote;
+mod smirk_responses;
+mod smirk_saver;
+mod smirk_scripting;
+mod smirk_seed;
+mod smirk_session;
+mod smirk_slowlog;
+mod smirk_startup;
+mod smirk_state;
+mod smirk_stats;
+mod smirk_stream;
+mod smirk_trace;
+use serde_json::Value;
+use smirk::core::backing_store::{BackingStore, DirectoryStore};
+use smirk::core::bitfield::BitFieldOp;
+use smirk::core::bloom_filter::BloomFilter;
+use smirk::core::codec::{self, Arithmetic, ValueType};
 use smirk::core::command::Command;
-use smirk::core::smirk_search_mode::SmirkSearchMode;
-use smirk::core::smirk_map::SmirkMap;
+use smirk::core::command_error::CommandError;
+use smirk::core::command_spec::{self, CommandSpec};
+use smirk::core::counter::Counter;
+use smirk::core::float_format::{FloatFormat, FloatFormattable};
+use smirk::core::smirk_search_mode::{KeyOrder, KeyPattern, SmirkSearchMode};
+use smirk::core::snapshot::{self, SnapshotFormat};
+use smirk::core::snapshot_keys::SnapshotKeys;
+use smirk::core::metadata_index::MetadataIndex;
+use smirk::core::module;
+use smirk::core::record::{RecordLike, RecordView};
+use smirk::core::geo::{GeoOrigin, GeoSet, distance};
+use smirk::core::hyper_log_log::HyperLogLog;
+use smirk::core::list::{End, List};
+use smirk::core::smirk_error::SmirkError;
+use smirk::core::smirk_map::{MAX_RANGE_BYTES, SmirkMap, downcast, render, shared_bytes};
+use smirk::core::record::SharedValue;
+use smirk::core::smirk_messages::SmirkMessages;
+use smirk::core::sorted_set::SortedSet;
+use smirk::core::stream::{Stream, StreamId, format_entry};
+use smirk::core::timeseries::{Aggregation, TimeSeries};
+use smirk_blocking::SmirkBlocking;
+use smirk_clients::SmirkClients;
+use smirk_cluster::{SmirkCluster, SlotOwner, key_slot};
+use smirk_cursors::SmirkCursors;
+use smirk_lazyfree::SmirkLazyFree;
 use smirk_config::SmirkConfig;
+use smirk_responses::Responses;
+use smirk_saver::SmirkSaver;
+use smirk_scripting::{SmirkScripts, eval_script};
+use smirk_session::SmirkSession;
+use smirk_slowlog::SmirkSlowLog;
+use smirk_stats::SmirkStats;
+use smirk_trace::SmirkTraces;
+use smirk_startup::SmirkStartup;
+use smirk_state::SmirkState;
+use smirk_stream::{LineRead, SmirkStream};
 use regex::Regex;
-use trie::Trie;
 
 fn main() {
-    let config: SmirkConfig = SmirkConfig::get_runtime_config();
-    let server_data = SmirkMap {
-        search_mode: config.default_key_search_method,
-        map: HashMap::new(),
-        trie: Trie::default()
-    };
+    let mut config: SmirkConfig = SmirkConfig::get_runtime_config();
+    if let Err(e) = smirk_logger::init(config.log_level, &config.log_file) {
+        eprintln!("{}", e);
+    }
+    // Refusing to start beats writing snapshots in the clear that were meant to be encrypted.
+    if let Some(path) = &config.snapshot_key_file {
+        config.snapshot_keys = SnapshotKeys::from_file(path).unwrap_or_else(|e| panic!("{}", e));
+    }
+    // Modules go first, so their types are there when the dataset is read.
+    for path in &config.load_module {
+        let name = module::load(Path::new(path)).unwrap_or_else(|e| panic!("{}", e));
+        log::info!("Loaded module {} from {}", name, path);
+    }
+    let mut server_data = SmirkMap::new(config.default_key_search_method);
+    server_data.default_ttl = config.default_ttl;
+    server_data.ttl_jitter = config.ttl_jitter;
+    server_data.max_ttl = config.max_ttl;
+    server_data.history.default_depth = config.history_depth;
+    server_data.lazy_free_threshold = config.lazy_free_threshold;
+    let (reclaimer, reclaimed) = mpsc::channel();
+    server_data.reclaimer = Some(reclaimer);
 
-    let listener = TcpListener::bind(format!("127.0.0.1:{}", config.port)).expect(format!("Failed to bind to port {}", config.port).as_str());
-    println!("Server listening on port {}", config.port);
+    let backing_store = config.backing_dir.as_deref().map(|dir| {
+        let store = DirectoryStore::new(dir).unwrap_or_else(|e| panic!("{}", e));
+        log::info!("Reading and writing keys through to {}", dir);
+        Box::new(store) as Box<dyn BackingStore>
+    });
+
+    let port = config.port;
+    let state = Arc::new(SmirkState {
+        cluster: SmirkCluster {
+            address: format!("127.0.0.1:{}", config.port),
+            slots: config.cluster_slots.clone(),
+            nodes: config.cluster_nodes.clone()
+        },
+        config: RwLock::new(config),
+        cursors: Mutex::new(SmirkCursors::default()),
+        slowlog: Mutex::new(SmirkSlowLog::default()),
+        traces: Mutex::new(SmirkTraces::default()),
+        stats: Mutex::new(SmirkStats::default()),
+        clients: Mutex::new(SmirkClients::default()),
+        startup: SmirkStartup::default(),
+        blocking: SmirkBlocking::default(),
+        scripts: Mutex::new(SmirkScripts::default()),
+        saver: SmirkSaver::default(),
+        lazy_free: SmirkLazyFree::default(),
+        backing_store
+    });
+
+    let listener = TcpListener::bind(format!("127.0.0.1:{}", port)).unwrap_or_else(|_| panic!("Failed to bind to port {}", port));
+    log::info!("Server listening on port {}", port);
+    if state.cluster.is_enabled() {
+        log::info!("Cluster mode enabled. Slot layout:\n{}", state.cluster.describe_slots().trim_end());
+    }
     let threadsafe_server_data = Arc::new(Mutex::new(server_data));
 
-    for stream in listener.incoming() {
+    {
+        let threadsafe_server_data = threadsafe_server_data.clone();
+        let state = state.clone();
+        std::thread::spawn(move || {
+            prepare_data(&threadsafe_server_data, &state);
+            // Whatever startup loaded came from disk, so it doesn't count towards a save point.
+            state.saver.saved(threadsafe_server_data.lock().unwrap().changes);
+            smirk_saver::run(&threadsafe_server_data, &state);
+        });
+    }
+
+    {
+        let threadsafe_server_data = threadsafe_server_data.clone();
+        let state = state.clone();
+        std::thread::spawn(move || smirk_expiry::run(&threadsafe_server_data, &state));
+    }
 
+    {
+        let state = state.clone();
+        std::thread::spawn(move || smirk_lazyfree::run(reclaimed, &state));
+    }
+
+    let http_port = state.config.read().unwrap().http_port;
+    if let Some(http_port) = http_port {
+        let threadsafe_server_data = threadsafe_server_data.clone();
+        let state = state.clone();
+        std::thread::spawn(move || smirk_http::serve(http_port, threadsafe_server_data, state));
+    }
+
+    let unixsocket = state.config.read().unwrap().unixsocket.clone();
+    if let Some(path) = unixsocket {
+        listen_on_unix_socket(path, &threadsafe_server_data, &state);
+    }
+
+    for stream in listener.incoming() {
         match stream {
-            Ok(stream) => {
-                println!("New client connected: {:?}", stream.peer_addr());
-                let threadsafe_server_data = threadsafe_server_data.clone();
-                std::thread::spawn(move || {
-                    handle_client(stream, &threadsafe_server_data);
-                });
+            Ok(stream) => spawn_client(SmirkStream::Tcp(stream), &threadsafe_server_data, &state),
+            Err(e) => {
+                log::error!("Error accepting connection: {}", e);
+            }
+        }
+    }
+}
+
+/// How long a load balancer has to send its PROXY protocol header once it has connected.
+const PROXY_HEADER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Serves a new connection on its own thread.
+///
+/// With proxy-protocol on, the header with the client's real address is read on that thread
+/// before the connection is admitted, since a balancer that never sends it mustn't hold up accepting.
+/// Only TCP peers in trusted-proxy get that far, anyone else could send a header with any address.
+fn spawn_client(stream: SmirkStream, threadsafe_server_data: &Arc<Mutex<SmirkMap>>, state: &Arc<SmirkState>) {
+    let threadsafe_server_data = threadsafe_server_data.clone();
+    let state = state.clone();
+    let (proxy_protocol, trusted) = {
+        let config = state.config.read().unwrap();
+        let trusted = stream.ip().is_none_or(|ip| config.trusted_proxies.iter().any(|cidr| cidr.contains(ip)));
+        (config.proxy_protocol, trusted)
+    };
+    if proxy_protocol && !trusted {
+        state.clients.lock().unwrap().denied += 1;
+        log::warn!("Refused {}, it isn't a trusted proxy", stream.peer());
+        stream.shutdown(std::net::Shutdown::Both).ok();
+        return;
+    }
+    if !proxy_protocol {
+        if let Some((client_id, peer)) = admit_client(&stream, None, &state) {
+            std::thread::spawn(move || {
+                handle_client(stream, client_id, peer, &threadsafe_server_data, &state);
+            });
+        }
+        return;
+    }
+    std::thread::spawn(move || {
+        let header = stream.set_read_timeout(Some(PROXY_HEADER_TIMEOUT)).and_then(|_| smirk_proxy::read_header(&stream));
+        match header {
+            Ok(source) => {
+                if let Some((client_id, peer)) = admit_client(&stream, source, &state) {
+                    handle_client(stream, client_id, peer, &threadsafe_server_data, &state);
+                }
+            }
+            Err(e) => {
+                log::warn!("Closing {}: {}", stream.peer(), e);
+                stream.shutdown(std::net::Shutdown::Both).ok();
+            }
+        }
+    });
+}
+
+/// Registers a new connection and returns its client id and the address it's known by, which is
+/// `source` when a PROXY protocol header gave one.
+///
+/// Once max-clients are connected, new connections are told so and closed straight away rather
+/// than each tying up a thread. Connections from addresses allow-ip and deny-ip keep out are
+/// closed without a word, like a firewall would.
+fn admit_client(stream: &SmirkStream, source: Option<SocketAddr>, state: &SmirkState) -> Option<(u64, String)> {
+    let peer = source.map(|source| source.to_string()).unwrap_or_else(|| stream.peer());
+    let ip = source.map(|source| source.ip()).or_else(|| stream.ip());
+    let (max_clients, keepalive, permitted) = {
+        let config = state.config.read().unwrap();
+        let permitted = ip.is_none_or(|ip| smirk_access::allowed(ip, &config.allow_ip, &config.deny_ip));
+        (config.max_clients, config.tcp_keepalive, permitted)
+    };
+    let mut clients = state.clients.lock().unwrap();
+    if !permitted {
+        clients.denied += 1;
+        drop(clients);
+        log::warn!("Refused {}, its address isn't allowed", peer);
+        stream.shutdown(std::net::Shutdown::Both).ok();
+        return None;
+    }
+    if max_clients > 0 && clients.count() >= max_clients {
+        clients.rejected += 1;
+        drop(clients);
+        log::warn!("Rejected {}, already serving {} clients", peer, max_clients);
+        // The socket is new, so this short write lands in an empty send buffer and can't block.
+        if let Err(e) = (&*stream).write_all(b"-ERR max clients reached\n") {
+            log::debug!("Couldn't tell {} it was rejected: {}", peer, e);
+        }
+        stream.shutdown(std::net::Shutdown::Both).ok();
+        return None;
+    }
+    let client_id = match stream.try_clone() {
+        Ok(handle) => clients.register(handle, &peer),
+        Err(e) => {
+            log::warn!("Couldn't register client {}: {}", peer, e);
+            0
+        }
+    };
+    drop(clients);
+
+    log::info!("New client connected: {}", peer);
+    if let Err(e) = stream.set_keepalive(Some(keepalive).filter(|k| *k > 0).map(Duration::from_secs)) {
+        log::warn!("Couldn't set keepalive for {}: {}", peer, e);
+    }
+    Some((client_id, peer))
+}
+
+/// Accepts clients on a unix socket from a background thread, alongside the TCP listener.
+///
+/// A socket file left behind by an earlier run is removed first, otherwise binding would fail.
+#[cfg(unix)]
+fn listen_on_unix_socket(path: String, threadsafe_server_data: &Arc<Mutex<SmirkMap>>, state: &Arc<SmirkState>) {
+    use std::os::unix::fs::FileTypeExt;
+    use std::os::unix::net::UnixListener;
+
+    if std::fs::metadata(&path).map(|m| m.file_type().is_socket()).unwrap_or(false) {
+        if let Err(e) = std::fs::remove_file(&path) {
+            log::warn!("Couldn't remove the old unix socket {}: {}", path, e);
+        }
+    }
+    let listener = UnixListener::bind(&path).unwrap_or_else(|e| panic!("Failed to bind to unix socket {}: {}", path, e));
+    log::info!("Server listening on unix socket {}", path);
+
+    let threadsafe_server_data = threadsafe_server_data.clone();
+    let state = state.clone();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => spawn_client(SmirkStream::Unix(stream), &threadsafe_server_data, &state),
+                Err(e) => {
+                    log::error!("Error accepting unix socket connection: {}", e);
+                }
+            }
+        }
+    });
+}
+
+#[cfg(not(unix))]
+fn listen_on_unix_socket(path: String, _: &Arc<Mutex<SmirkMap>>, _: &Arc<SmirkState>) {
+    log::error!("Unix sockets aren't supported on this platform, not listening on {}", path);
+}
+
+/// Copies key to another server through DUMP and RESTORE, and for DESTROY deletes it here once
+/// the other server has it.
+///
+/// The map is only locked to dump the key and again to delete it, never while the other server
+/// is being talked to. A key written to in between is kept, so the write isn't lost.
+fn migrate(
+    stream: &mut Vec<u8>,
+    threadsafe_server_data: &Arc<Mutex<SmirkMap>>,
+    host: &str,
+    port: u16,
+    key: &String,
+    destroy: bool,
+    state: &SmirkState
+) {
+    let failed = |e: &str| format!("Couldn't migrate key \"{}\" to {}:{}: {}.\n", key, host, port, e);
+    if smirk_remote::is_local(host, port, state.config.read().unwrap().port) {
+        stream.write_all(failed("that's this server").as_bytes()).unwrap();
+        return;
+    }
+    let dumped = {
+        let smirk_map = threadsafe_server_data.lock().unwrap();
+        snapshot::dump(&smirk_map, key)
+            .map(|blob| (blob, smirk_map.ttl(key).ok().flatten(), smirk_map.version(key)))
+            .map_err(|e| e.to_string().trim_end().trim_end_matches('.').to_string())
+    };
+    let transferred = dumped.and_then(|(blob, ttl, version)| smirk_remote::restore_on(host, port, key, ttl, &blob, false).map(|()| version));
+    let reply = match transferred {
+        Err(e) => failed(&e),
+        Ok(_) if !destroy => String::from("OK\n"),
+        Ok(version) => {
+            let mut smirk_map = threadsafe_server_data.lock().unwrap();
+            if smirk_map.version(key) == version {
+                smirk_map.del(key);
+                state.blocking.wake_removed(|key| !smirk_map.exists(key));
+                String::from("OK\n")
+            } else {
+                format!("Copied key \"{}\" to {}:{}, but kept it here because it changed meanwhile.\n", key, host, port)
+            }
+        }
+    };
+    stream.write_all(reply.as_bytes()).unwrap();
+}
+
+/// How often a snapshot import's progress is printed.
+const IMPORT_PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Runs the startup work that has to finish before commands are served, then marks the server ready.
+fn prepare_data(threadsafe_server_data: &Arc<Mutex<SmirkMap>>, state: &SmirkState) {
+    let (import, repair, keys) = {
+        let config = state.config.read().unwrap();
+        (config.import.clone(), config.repair, config.snapshot_keys.clone())
+    };
+    if let Some(path) = import {
+        state.startup.set_phase("reading snapshot");
+        let loaded = snapshot::read_snapshot(&path, SnapshotFormat::from_path(&path), repair, &keys)
+            .map_err(|e| (0, e))
+            .and_then(|loaded| {
+                state.startup.start_import(loaded["keys"].as_object().map_or(0, |keys| keys.len()));
+                let shards = std::thread::available_parallelism().map_or(1, |threads| threads.get());
+                let (finished, wait) = mpsc::channel::<()>();
+                std::thread::scope(|scope| {
+                    scope.spawn(|| report_import_progress(&state.startup, wait));
+                    // Records are loaded into a map of their own and moved in at the end, so the
+                    // live map isn't locked while the snapshot is parsed.
+                    let mut imported_map = threadsafe_server_data.lock().unwrap().empty_like();
+                    let imported = snapshot::import_sharded(&mut imported_map, &loaded, shards, state.startup.imported());
+                    threadsafe_server_data.lock().unwrap().absorb(imported_map);
+                    drop(finished);
+                    imported
+                })
+            });
+        match loaded {
+            Ok(count) => log::info!("Imported {} keys from \"{}\"", count, path),
+            Err((count, e)) => log::error!("Imported {} keys from \"{}\": {}", count, path, e)
+        }
+    }
+    let migrations = state.config.read().unwrap().migrations.clone();
+    if let Some(migrations) = migrations {
+        state.startup.set_phase("running migrations");
+        let mut smirk_map = threadsafe_server_data.lock().unwrap();
+        if let Err(e) = smirk_migrations::run_migrations(&migrations, &mut smirk_map) {
+            log::error!("{}", e);
+        }
+    }
+    let seed = state.config.read().unwrap().seed.clone();
+    if let Some(path) = seed {
+        state.startup.set_phase("loading seed keys");
+        let mut smirk_map = threadsafe_server_data.lock().unwrap();
+        match smirk_seed::load_seed(&path, &mut smirk_map) {
+            Ok((stored, errors)) => {
+                log::info!("Seeded {} keys from \"{}\"", stored, path);
+                for error in errors {
+                    log::error!("Couldn't seed a key: {}", error.trim_end());
+                }
+            }
+            Err(e) => log::error!("{}", e)
+        }
+    }
+    state.startup.set_ready();
+}
+
+/// Logs how far a snapshot import has got every `IMPORT_PROGRESS_INTERVAL` until `finished` is
+/// dropped.
+fn report_import_progress(startup: &SmirkStartup, finished: mpsc::Receiver<()>) {
+    while let Err(mpsc::RecvTimeoutError::Timeout) = finished.recv_timeout(IMPORT_PROGRESS_INTERVAL) {
+        if let Some(progress) = startup.import_progress() {
+            log::info!("Importing snapshot: {}", progress);
+        }
+    }
+}
+
+trait Streamable {
+    fn write_to_stream(&self, stream: &mut Vec<u8>);
+}
+
+impl Streamable for String {
+    fn write_to_stream(&self, stream: &mut Vec<u8>) {
+        writeln!(stream, "{}", self).unwrap();
+    }
+}
+
+impl Streamable for Vec<u8> {
+    fn write_to_stream(&self, stream: &mut Vec<u8>) {
+        stream.write_all(self).unwrap();
+        stream.write_all("\n".as_bytes()).unwrap();
+    }
+}
+
+fn get_value_and_write_to_stream<T: Streamable + 'static>(
+    stream: &mut Vec<u8>,
+    value: &Result<SharedValue, SmirkMessages>,
+    key: &str,
+    default: &Option<Vec<u8>>
+) -> bool {
+    match (value, default) {
+        (Ok(value), _) => match downcast::<T>(key, value) {
+            Ok(d) => {
+                d.write_to_stream(stream);
+                return true;
+            }
+            Err(e) => stream.write_all(e.to_string().as_bytes()).unwrap()
+        },
+        (Err(SmirkMessages::KeyNotFound(_)), Some(default)) => default.write_to_stream(stream),
+        (Err(e), _) => stream.write_all(e.to_string().as_bytes()).unwrap()
+    }
+    false
+}
+
+/// Writes the value using the type it was stored as, so the client doesn't have to name it.
+fn get_any_and_write_to_stream(
+    stream: &mut Vec<u8>,
+    value: &Result<SharedValue, SmirkMessages>,
+    key: &str,
+    default: &Option<Vec<u8>>,
+    format: &FloatFormat
+) -> bool {
+    let value = match (value, default) {
+        (Ok(value), _) => value,
+        (Err(_), Some(default)) => {
+            default.write_to_stream(stream);
+            return false;
+        }
+        (Err(e), None) => {
+            stream.write_all(e.to_string().as_bytes()).unwrap();
+            return false;
+        }
+    };
+    if codec::CODECS.iter().any(|codec| codec.write(value, format, stream)) {
+        stream.push(b'\n');
+    } else if let Some(value) = value.downcast_ref::<Vec<u8>>() {
+        value.write_to_stream(stream);
+    } else {
+        match render(key, value) {
+            Ok(value) => value.write_to_stream(stream),
+            Err(e) => {
+                stream.write_all(e.to_string().as_bytes()).unwrap();
+                return false;
+            }
+        }
+    }
+    true
+}
+
+/// Whether GET writes the value's bytes as they're stored, so it can be queued with
+/// `Responses::push_verbatim` rather than copied.
+fn writes_verbatim(command: &Command, value: &SharedValue) -> bool {
+    match command {
+        Command::GetAny(_, _) => true,
+        Command::Get(t, _, _) => match codec::find(t) {
+            Some(codec) => codec.rust_type() == type_name::<String>() && value.is::<String>(),
+            None => value.is::<Vec<u8>>()
+        },
+        _ => false
+    }
+}
+
+/// Writes the reply to GET from a value taken out of the map with `get_shared`.
+///
+/// It doesn't need the map, so a large value can be written once the lock is released.
+///
+/// Returns whether the key's value was written, rather than an error or the default.
+fn write_get(stream: &mut Vec<u8>, command: &Command, value: &Result<SharedValue, SmirkMessages>, format: &FloatFormat) -> bool {
+    let (t, k, d) = match command {
+        Command::Get(t, k, d) => (t, k, d),
+        Command::GetAny(k, d) => return get_any_and_write_to_stream(stream, value, k, d, format),
+        _ => return false
+    };
+    let Some(codec) = codec::find(t) else {
+        return get_value_and_write_to_stream::<Vec<u8>>(stream, value, k, d);
+    };
+    match (value, d) {
+        (Ok(value), _) if codec.write(value, format, stream) => {
+            stream.push(b'\n');
+            return true;
+        }
+        (Ok(value), _) => {
+            if let Err(e) = codec.render(k, value, format) {
+                stream.write_all(e.to_string().as_bytes()).unwrap();
+            }
+        }
+        (Err(SmirkMessages::KeyNotFound(_)), Some(default)) => default.write_to_stream(stream),
+        (Err(e), _) => stream.write_all(e.to_string().as_bytes()).unwrap()
+    }
+    false
+}
+
+/// Runs ADD, SUB, MUL or DIV and writes the result, also storing it at `destination` for the STORE variants.
+fn arithmetic_and_write_to_stream(
+    stream: &mut Vec<u8>,
+    smirk_map: &mut MutexGuard<'_, SmirkMap>,
+    command: &Command,
+    destination: Option<&String>,
+    format: &FloatFormat
+) {
+    let (t, arithmetic, keys) = match command {
+        Command::Add(t, keys, skip) => (t, Arithmetic::Add(*skip), keys),
+        Command::Sub(t, keys) => (t, Arithmetic::Sub, keys),
+        Command::Mul(t, keys) => (t, Arithmetic::Mul, keys),
+        Command::Div(t, keys) => (t, Arithmetic::Div, keys),
+        _ => return
+    };
+    let result = codec::find(t).and_then(|codec| codec.arithmetic(smirk_map, arithmetic, keys.clone()).map(|result| (codec, result)));
+    match result {
+        Some((codec, Ok(total))) => {
+            codec.serialize(&total, format).unwrap_or_default().write_to_stream(stream);
+            if let Some(destination) = destination {
+                smirk_map.set_shared(destination, total, codec.rust_type(), t);
+            }
+        }
+        Some((_, Err(e))) => stream.write_all(e.to_string().as_bytes()).unwrap(),
+        None => stream.write_all(format!("Cannot do arithmetic on type \"{}\".\n", t).as_bytes()).unwrap()
+    }
+}
+
+/// Runs AGG over the values at keys as the type `t`. With `skip_mismatched`, as for AGG ... MATCH,
+/// keys that don't hold the type are left out rather than failing.
+fn aggregate_and_write_to_stream(
+    stream: &mut Vec<u8>,
+    smirk_map: &SmirkMap,
+    aggregation: Aggregation,
+    t: &str,
+    keys: &[String],
+    skip_mismatched: bool,
+    format: &FloatFormat
+) {
+    let result = codec::find(t).and_then(|codec| codec.aggregate(smirk_map, aggregation, keys, skip_mismatched, format));
+    match result {
+        Some(Ok(result)) => stream.write_all(format!("{}\n", result).as_bytes()).unwrap(),
+        Some(Err(e)) => stream.write_all(e.to_string().as_bytes()).unwrap(),
+        None => stream.write_all(format!("Cannot do arithmetic on type \"{}\".\n", t).as_bytes()).unwrap()
+    }
+}
+
+/// Collects the keys in `namespace` matching `pattern`, using the map's current search mode unless
+/// the pattern names its own.
+///
+/// The pattern is matched against keys without the namespace, but they're returned with it.
+fn matching_keys(smirk_map: &SmirkMap, pattern: &KeyPattern, namespace: &str) -> Result<Vec<String>, SmirkError> {
+    let mut keys = Vec::new();
+    visit_matching_keys(smirk_map, pattern, namespace, |key| {
+        keys.push(key.to_string());
+        Ok(())
+    })?;
+    Ok(keys)
+}
+
+/// `matching_keys` without the keys that have expired but not been swept away yet, which changing
+/// the TTL of would bring back.
+fn live_matching_keys(smirk_map: &SmirkMap, pattern: &KeyPattern, namespace: &str) -> Result<Vec<String>, SmirkError> {
+    let mut keys = matching_keys(smirk_map, pattern, namespace)?;
+    keys.retain(|key| smirk_map.get_record(key).is_ok_and(|record| !record.is_expired()));
+    Ok(keys)
+}
+
+/// Calls `visit` with each key `matching_keys` would return, without collecting them first.
+/// Stops at the first error `visit` returns.
+fn visit_matching_keys(
+    smirk_map: &SmirkMap,
+    pattern: &KeyPattern,
+    namespace: &str,
+    mut visit: impl FnMut(&str) -> Result<(), SmirkError>
+) -> Result<(), SmirkError> {
+    // MODIFIEDSINCE is checked on each match rather than narrowing the search.
+    let mut visit = |k: &str| match pattern.modified_since {
+        Some(since) if smirk_map.map.get(k).is_none_or(|record| record.modified < since) => Ok(()),
+        _ => visit(k)
+    };
+    let key = pattern.pattern.as_str();
+    // Regex errors point at the problem over several lines, and a reply has to fit on one.
+    let invalid = |e: String| SmirkError::InvalidPattern(key.to_string(), e.lines().last().unwrap_or_default().trim_start_matches("error: ").to_string());
+    match pattern.mode_or(smirk_map.search_mode) {
+        SmirkSearchMode::Glob => {
+            let pattern = glob::Pattern::new(key).map_err(|e| invalid(e.to_string()))?;
+            smirk_map
+                .map.keys()
+                .filter(|k| k.strip_prefix(namespace).is_some_and(|k| pattern.matches(k)))
+                .try_for_each(|k| visit(k))
+        }
+        SmirkSearchMode::Regex => {
+            let pattern = Regex::new(key).map_err(|e| invalid(e.to_string()))?;
+            smirk_map
+                .map.keys()
+                .filter(|k| k.strip_prefix(namespace).is_some_and(|k| pattern.is_match(k)))
+                .try_for_each(|k| visit(k))
+        },
+        SmirkSearchMode::Trie => {
+            smirk_map.trie.keys_with_prefix(&format!("{}{}", namespace, key)).try_for_each(|k| visit(&k))
+        }
+    }
+}
+
+/// How many matches KEYS finds each time it takes the map lock.
+const KEYS_BATCH: usize = 1000;
+
+/// Up to `count` of the keys `matching_keys` would return that sort after `after`, in byte order.
+/// Every key is in the trie whatever the search mode, so a listing can be picked up again after
+/// the map lock has been released.
+fn matching_keys_after(smirk_map: &SmirkMap, pattern: &KeyPattern, namespace: &str, after: Option<&str>, count: usize) -> Result<Vec<String>, SmirkError> {
+    let key = pattern.pattern.as_str();
+    let invalid = |e: String| SmirkError::InvalidPattern(key.to_string(), e.lines().last().unwrap_or_default().trim_start_matches("error: ").to_string());
+    let matcher: Box<dyn Fn(&str) -> bool> = match pattern.mode_or(smirk_map.search_mode) {
+        SmirkSearchMode::Glob => {
+            let pattern = glob::Pattern::new(key).map_err(|e| invalid(e.to_string()))?;
+            Box::new(move |k| pattern.matches(k))
+        }
+        SmirkSearchMode::Regex => {
+            let pattern = Regex::new(key).map_err(|e| invalid(e.to_string()))?;
+            Box::new(move |k| pattern.is_match(k))
+        }
+        SmirkSearchMode::Trie => Box::new(|_| true)
+    };
+    let prefix = match pattern.mode_or(smirk_map.search_mode) {
+        SmirkSearchMode::Trie => format!("{}{}", namespace, key),
+        _ => namespace.to_string()
+    };
+    Ok(smirk_map
+        .trie
+        .keys_with_prefix_after(&prefix, after.unwrap_or_default())
+        .filter(|k| matcher(&k[namespace.len()..]))
+        .filter(|k| pattern.modified_since.is_none_or(|since| smirk_map.map.get(k).is_some_and(|record| record.modified >= since)))
+        .take(count)
+        .collect())
+}
+
+/// Writes the KEYS reply from the matches `batches` hands over a batch at a time, each one
+/// picking up after the last key of the one before, until it hands over an empty one. Callers
+/// take the map lock only while finding a batch, so it isn't held while the reply is written.
+/// Sorting by length needs every match first, so those are gathered up before anything is written.
+fn write_keys(
+    writer: &mut impl Write,
+    pattern: &KeyPattern,
+    order: KeyOrder,
+    offset: usize,
+    limit: Option<usize>,
+    namespace: &str,
+    mut batches: impl FnMut(Option<&str>) -> Result<Vec<String>, SmirkError>
+) -> Result<(), SmirkError> {
+    // Every match is counted, but only the page asked for is written.
+    let (mut total, mut shown) = (0, 0);
+    let mut write_match = |matched: &str| -> Result<(), SmirkError> {
+        if total >= offset && limit.is_none_or(|limit| shown < limit) {
+            writeln!(writer, "{}", matched.strip_prefix(namespace).unwrap_or(matched))?;
+            shown += 1;
+        }
+        total += 1;
+        Ok(())
+    };
+    // Batches come in byte order, which is what ALPHA asks for and unordered listings don't mind.
+    let mut sorted = Vec::new();
+    let mut batch = batches(None)?;
+    while let Some(last) = batch.last().cloned() {
+        match order {
+            KeyOrder::Length => sorted.extend(batch),
+            KeyOrder::Alpha | KeyOrder::Unordered => batch.iter().try_for_each(|key| write_match(key))?
+        }
+        batch = batches(Some(&last))?;
+    }
+    order.sort(&mut sorted);
+    sorted.iter().try_for_each(|key| write_match(key))?;
+    if total == 0 {
+        writeln!(writer, "No matches for key query \"{}\" were found.", pattern)?;
+    } else if offset > 0 || limit.is_some() {
+        writeln!(writer, "Showing {} of {} matching keys.", shown, total)?;
+    }
+    Ok(())
+}
+
+/// Whether a command affects the whole server rather than a client's own keys, so clients in a
+/// namespace may not run it.
+fn server_wide(command: &Command) -> bool {
+    match command {
+        Command::DryRun(command) => server_wide(command),
+        command => matches!(
+            command,
+            Command::Mode(_)
+                | Command::Save
+                | Command::Export(..)
+                | Command::Import(..)
+                | Command::Verify(..)
+                | Command::ConfigGet(_)
+                | Command::ConfigSet(..)
+                | Command::Info
+                | Command::ClientList
+                | Command::ClientKill(_)
+                | Command::SlowLogGet(_)
+                | Command::SlowLogReset
+                | Command::TraceGet(_)
+                | Command::TraceList(_)
+                | Command::IndexCreate(..)
+                | Command::IndexDrop(_)
+                | Command::VIndexCreate(..)
+                | Command::VIndexDrop(_)
+                | Command::ScriptFlush
+                | Command::ModuleLoad(_)
+                | Command::DebugSleep(_)
+        )
+    }
+}
+
+/// Whether a command is one of the DEBUG family, which is only there with --enable-debug.
+fn debug_command(command: &Command) -> bool {
+    matches!(command, Command::DebugSleep(_) | Command::DebugObject(_) | Command::DebugSetExpireNow(_))
+}
+
+fn eval_and_write_to_stream(
+    stream: &mut Vec<u8>,
+    smirk_map: &mut SmirkMap,
+    ast: &rhai::AST,
+    keys: &[String],
+    args: &[String],
+    namespace: &str,
+    state: &SmirkState
+) {
+    let time_limit = Duration::from_millis(state.config.read().unwrap().script_time_limit);
+    match eval_script(smirk_map, ast, keys, args, namespace, time_limit) {
+        Ok(lines) => {
+            for line in lines {
+                stream.write_all(format!("{}\n", line).as_bytes()).unwrap();
+            }
+        }
+        Err(e) => stream.write_all(format!("{}.\n", e).as_bytes()).unwrap()
+    }
+    // A script may have written to streams or lists someone is blocked on.
+    state.blocking.notify();
+}
+
+/// Reports what a mutating command would do without applying it.
+fn dry_run_command(stream: &mut Vec<u8>, command: &Command, smirk_map: &SmirkMap, namespace: &str) {
+    let report = match command {
+        Command::Set(t, k, v, ttl) => {
+            let expiry = match ttl.or(smirk_map.default_ttl) {
+                Some(ttl) => format!(", expiring in {} seconds", ttl),
+                None => String::new()
+            };
+            let parses = codec::find(t).is_none_or(|codec| codec.parse(v).is_some());
+            if !parses {
+                format!("Would fail: could not parse \"{}\" into \"{}\".\n", String::from_utf8_lossy(v), t)
+            } else if smirk_map.exists(k) {
+                format!("Would overwrite key \"{}\" with a {} value{}.\n", k, t, expiry)
+            } else {
+                format!("Would create key \"{}\" with a {} value{}.\n", k, t, expiry)
+            }
+        }
+        Command::RestoreVersion(k, version) => {
+            match smirk_map.history.find(k, *version) {
+                Some(entry) => format!("Would set key \"{}\" back to version {} as a {} value.\n", k, version, entry.desired_type_name),
+                None => format!("Would fail: version {} of key \"{}\" is not in its history.\n", version, k)
+            }
+        }
+        Command::SetLock(k, _, ttl) => {
+            if smirk_map.get_record(k).is_ok_and(|record| !record.is_expired()) {
+                format!("Would do nothing: lock \"{}\" is already held.\n", k)
+            } else {
+                format!("Would take lock \"{}\" for {} seconds.\n", k, ttl)
+            }
+        }
+        Command::ReleaseLock(k, token) | Command::ExtendLock(k, token, _) if !smirk_map.lock_held_by(k, token) => {
+            format!("Would do nothing: lock \"{}\" isn't held with that token.\n", k)
+        }
+        Command::ReleaseLock(k, _) => format!("Would free lock \"{}\".\n", k),
+        Command::ExtendLock(k, _, ttl) => format!("Would give lock \"{}\" {} more seconds.\n", k, ttl),
+        Command::SetCas(t, k, expected, _) => {
+            if smirk_map.cas_matches(k, expected) {
+                format!("Would set key \"{}\" to a {} value.\n", k, t)
+            } else {
+                format!("Would do nothing: key \"{}\" has changed.\n", k)
+            }
+        }
+        Command::Migrate(host, port, k, destroy) => {
+            match (smirk_map.exists(k), destroy) {
+                (false, _) => format!("Would fail: key \"{}\" was not found.\n", k),
+                (true, true) => format!("Would move key \"{}\" to {}:{}.\n", k, host, port),
+                (true, false) => format!("Would copy key \"{}\" to {}:{}.\n", k, host, port)
+            }
+        }
+        Command::Restore(k, _, _, replace) => {
+            if !smirk_map.exists(k) {
+                format!("Would create key \"{}\" from a DUMP payload.\n", k)
+            } else if *replace {
+                format!("Would overwrite key \"{}\" from a DUMP payload.\n", k)
+            } else {
+                format!("Would fail: key \"{}\" already exists.\n", k)
+            }
+        }
+        Command::SetNull(t, k) => {
+            if smirk_map.exists(k) {
+                format!("Would overwrite key \"{}\" with a {} null.\n", k, t)
+            } else {
+                format!("Would create key \"{}\" with a {} null.\n", k, t)
+            }
+        }
+        Command::Del(keys) => {
+            let existing = keys.iter().filter(|k| smirk_map.exists(k)).count();
+            format!("Would delete {} of {} keys.\n", existing, keys.len())
+        }
+        Command::DelByTag(tag) => {
+            format!("Would delete {} keys tagged \"{}\".\n", smirk_map.tags.keys(tag).len(), tag)
+        }
+        Command::DelPattern(pattern) => match matching_keys(smirk_map, pattern, namespace) {
+            Ok(keys) => format!("Would delete {} keys matching \"{}\".\n", keys.len(), pattern),
+            Err(e) => format!("-ERR {}\n", e)
+        },
+        Command::ExpirePattern(pattern, ttl) => match (live_matching_keys(smirk_map, pattern, namespace), ttl) {
+            (Ok(keys), Some(ttl)) => format!("Would expire {} keys matching \"{}\" in {} seconds.\n", keys.len(), pattern, ttl),
+            (Ok(keys), None) => format!("Would remove the TTL from {} keys matching \"{}\".\n", keys.len(), pattern),
+            (Err(e), _) => format!("-ERR {}\n", e)
+        },
+        Command::GetDel(_, k) => {
+            if smirk_map.exists(k) {
+                format!("Would delete key \"{}\" after reading it.\n", k)
+            } else {
+                format!("Would do nothing: key \"{}\" does not exist.\n", k)
+            }
+        }
+        Command::GetEx(_, k, ttl) => {
+            match (smirk_map.exists(k), ttl) {
+                (false, _) => format!("Would do nothing: key \"{}\" does not exist.\n", k),
+                (true, Some(Some(ttl))) => format!("Would expire key \"{}\" in {} seconds after reading it.\n", k, ttl),
+                (true, Some(None)) => format!("Would remove the TTL from key \"{}\" after reading it.\n", k),
+                (true, None) => format!("Would only read key \"{}\".\n", k)
+            }
+        }
+        Command::TtlSet(k, ttl) => {
+            match (smirk_map.exists(k), ttl) {
+                (false, _) => format!("Would do nothing: key \"{}\" does not exist.\n", k),
+                (true, Some(ttl)) => format!("Would expire key \"{}\" in {} seconds.\n", k, ttl),
+                (true, None) => format!("Would remove the TTL from key \"{}\".\n", k)
+            }
+        }
+        Command::JsonSet(k, path, v) => {
+            if String::from_utf8_lossy(v).parse::<Value>().is_err() {
+                format!("Would fail: could not parse \"{}\" as Json.\n", String::from_utf8_lossy(v))
+            } else if smirk_map.exists(k) {
+                format!("Would update path \"{}\" of key \"{}\".\n", path, k)
+            } else {
+                format!("Would create key \"{}\" with a Json value.\n", k)
+            }
+        }
+        Command::GeoAdd(k, members) => {
+            match smirk_map.get::<GeoSet>(k) {
+                Ok(set) => {
+                    let new = members.iter().filter(|(_, _, member)| set.position(member).is_none()).count();
+                    format!("Would add {} new members to key \"{}\" and move {}.\n", new, k, members.len() - new)
+                }
+                Err(SmirkMessages::KeyNotFound(_)) => format!("Would create key \"{}\" with {} members.\n", k, members.len()),
+                Err(e) => format!("Would fail: {}", e)
+            }
+        }
+        Command::XAdd(k, id, _) => {
+            match (smirk_map.get::<Stream>(k), id) {
+                (Ok(entries), Some(id)) if *id <= entries.last_id() => {
+                    format!("Would fail: ID {} is not greater than the last ID {}.\n", id, entries.last_id())
+                }
+                (Ok(_), _) => format!("Would append an entry to stream \"{}\".\n", k),
+                (Err(SmirkMessages::KeyNotFound(_)), _) => format!("Would create stream \"{}\" with one entry.\n", k),
+                (Err(e), _) => format!("Would fail: {}", e)
+            }
+        }
+        Command::ZAdd(k, members) => {
+            match smirk_map.get::<SortedSet>(k) {
+                Ok(set) => {
+                    let new = members.iter().filter(|(_, member)| set.score(member).is_none()).count();
+                    format!("Would add {} new members to key \"{}\" and update {}.\n", new, k, members.len() - new)
+                }
+                Err(SmirkMessages::KeyNotFound(_)) => format!("Would create key \"{}\" with {} members.\n", k, members.len()),
+                Err(e) => format!("Would fail: {}", e)
+            }
+        }
+        Command::ZIncrBy(k, increment, member) => {
+            match smirk_map.get::<SortedSet>(k) {
+                Ok(set) => match set.incremented(member, *increment) {
+                    Ok(score) => format!("Would change the score of \"{}\" to {}.\n", member, score),
+                    Err(e) => format!("Would fail: {}", SmirkMessages::ScoreError(k.clone(), e))
+                },
+                Err(SmirkMessages::KeyNotFound(_)) => format!("Would create key \"{}\" with member \"{}\".\n", k, member),
+                Err(e) => format!("Would fail: {}", e)
+            }
+        }
+        Command::ZRem(k, members) => {
+            match smirk_map.get::<SortedSet>(k) {
+                Ok(set) => {
+                    let existing = members.iter().filter(|member| set.score(member).is_some()).count();
+                    format!("Would remove {} of {} members.\n", existing, members.len())
+                }
+                Err(SmirkMessages::KeyNotFound(_)) => format!("Would do nothing: key \"{}\" does not exist.\n", k),
+                Err(e) => format!("Would fail: {}", e)
+            }
+        }
+        Command::Push(_, k, elements) => {
+            match smirk_map.get::<List>(k) {
+                Ok(list) => format!("Would push {} elements onto list \"{}\", leaving it {} long.\n", elements.len(), k, list.len() + elements.len()),
+                Err(SmirkMessages::KeyNotFound(_)) => format!("Would create list \"{}\" with {} elements.\n", k, elements.len()),
+                Err(e) => format!("Would fail: {}", e)
+            }
+        }
+        Command::Pop(_, k) => {
+            match smirk_map.get::<List>(k) {
+                Ok(list) if list.len() == 1 => format!("Would pop the last element of list \"{}\" and delete it.\n", k),
+                Ok(_) => format!("Would pop an element off list \"{}\".\n", k),
+                Err(SmirkMessages::KeyNotFound(_)) => format!("Would do nothing: key \"{}\" does not exist.\n", k),
+                Err(e) => format!("Would fail: {}", e)
+            }
+        }
+        Command::BitField(k, ops) if ops.iter().any(BitFieldOp::writes) => {
+            let writes = ops.iter().filter(|op| op.writes()).count();
+            match smirk_map.get::<Vec<u8>>(k) {
+                Ok(_) => format!("Would write {} fields of key \"{}\".\n", writes, k),
+                Err(SmirkMessages::KeyNotFound(_)) => format!("Would create key \"{}\" and write {} fields.\n", k, writes),
+                Err(e) => format!("Would fail: {}", e)
+            }
+        }
+        Command::SetRange(k, offset, data) => {
+            let end = usize::checked_add(*offset, data.len()).filter(|end| *end <= MAX_RANGE_BYTES);
+            match (smirk_map.get_range(k, 0, -1), end) {
+                (Ok(_), _) if data.is_empty() => format!("Would do nothing: there are no bytes to write to key \"{}\".\n", k),
+                (Ok(_), None) => {
+                    let reason = format!("it would be longer than {} bytes", MAX_RANGE_BYTES);
+                    format!("Would fail: {}", SmirkMessages::RangeError(k.clone(), reason))
+                }
+                (Ok(bytes), Some(end)) if smirk_map.exists(k) => {
+                    format!("Would write {} bytes to key \"{}\" at offset {}, leaving it {} bytes long.\n", data.len(), k, offset, bytes.len().max(end))
+                }
+                (Ok(_), Some(end)) => format!("Would create key \"{}\" with {} bytes.\n", k, end),
+                (Err(e), _) => format!("Would fail: {}", e)
+            }
+        }
+        Command::Cast(k, t) => {
+            match smirk_map.get_record(k) {
+                Ok(record) => format!("Would cast key \"{}\" from {} to {}.\n", k, record.type_name, t),
+                Err(e) => format!("Would fail: {}", e)
+            }
+        }
+        Command::Store(destination, inner) => {
+            let verb = match inner.as_ref() {
+                Command::Add(_, _, _) => "add",
+                Command::Sub(_, _) => "subtract",
+                Command::Mul(_, _) => "multiply",
+                _ => "divide"
+            };
+            let keys = inner.keys();
+            if smirk_map.exists(destination) {
+                format!("Would {} {} keys and overwrite key \"{}\" with the result.\n", verb, keys.len(), destination)
+            } else {
+                format!("Would {} {} keys and create key \"{}\" with the result.\n", verb, keys.len(), destination)
+            }
+        }
+        Command::Add(t, keys, _) | Command::Sub(t, keys) | Command::Mul(t, keys) | Command::Div(t, keys) => {
+            let arithmetic = match command {
+                Command::Add(_, _, skip) => Arithmetic::Add(*skip),
+                Command::Sub(_, _) => Arithmetic::Sub,
+                Command::Mul(_, _) => Arithmetic::Mul,
+                _ => Arithmetic::Div
+            };
+            match codec::find(t).and_then(|codec| codec.arithmetic(smirk_map, arithmetic, keys.clone())) {
+                Some(Ok(_)) => format!("Would only read {} keys, nothing would change.\n", keys.len()),
+                Some(Err(e)) => format!("Would fail: {}", e),
+                None => format!("Would fail: cannot do arithmetic on type \"{}\".\n", t)
+            }
+        }
+        Command::IncrBy(k, by) => {
+            let counter = match smirk_map.get::<Counter>(k) {
+                Ok(counter) => Ok(*counter),
+                Err(SmirkMessages::KeyNotFound(_)) => Ok(Counter::default()),
+                Err(e) => Err(e)
+            };
+            match counter {
+                Ok(mut counter) => match counter.incr_by(*by) {
+                    Some(value) if smirk_map.exists(k) => format!("Would change counter \"{}\" to {}.\n", k, value),
+                    Some(value) => format!("Would create counter \"{}\" at {}.\n", k, value),
+                    None => format!("Would fail: {}", SmirkMessages::CounterOverflow(k.clone(), counter.ty.to_string()))
+                },
+                Err(e) => format!("Would fail: {}", e)
+            }
+        }
+        Command::PfAdd(k, elements) => {
+            match smirk_map.get::<HyperLogLog>(k) {
+                Ok(hll) => {
+                    let mut hll = hll.clone();
+                    let changed = elements.iter().fold(false, |changed, element| hll.add(element.as_bytes()) | changed);
+                    match changed {
+                        true => format!("Would add {} elements to key \"{}\", changing its estimate.\n", elements.len(), k),
+                        false => format!("Would do nothing: key \"{}\" already has those elements.\n", k)
+                    }
+                }
+                Err(SmirkMessages::KeyNotFound(_)) => format!("Would create key \"{}\" with {} elements.\n", k, elements.len()),
+                Err(e) => format!("Would fail: {}", e)
+            }
+        }
+        Command::PfMerge(destination, sources) => {
+            let existing: Vec<&String> = sources.iter().filter(|source| smirk_map.exists(source)).collect();
+            let readable = existing.iter().try_for_each(|source| smirk_map.get::<HyperLogLog>(source).map(|_| ()));
+            match readable.and_then(|()| smirk_map.get::<HyperLogLog>(destination).map(|_| ())) {
+                Ok(()) => format!("Would merge {} keys into key \"{}\".\n", existing.len(), destination),
+                Err(SmirkMessages::KeyNotFound(_)) => format!("Would create key \"{}\" from {} keys.\n", destination, existing.len()),
+                Err(e) => format!("Would fail: {}", e)
+            }
+        }
+        Command::BfReserve(k, error_rate, capacity) => {
+            let bits = match smirk_map.exists(k) {
+                true => Err(String::from("the key already exists")),
+                false => BloomFilter::bits_for(*capacity, *error_rate)
+            };
+            match bits {
+                Ok(bits) => format!("Would reserve a Bloom filter of {} bits at key \"{}\".\n", bits, k),
+                Err(e) => format!("Would fail: {}", SmirkMessages::BloomFilterError(k.clone(), e))
+            }
+        }
+        Command::BfAdd(k, element) => {
+            match smirk_map.get::<BloomFilter>(k) {
+                Ok(filter) if filter.contains(element.as_bytes()) => format!("Would do nothing: key \"{}\" may already have \"{}\".\n", k, element),
+                Ok(_) => format!("Would add \"{}\" to key \"{}\".\n", element, k),
+                Err(SmirkMessages::KeyNotFound(_)) => format!("Would reserve a Bloom filter at key \"{}\" and add \"{}\".\n", k, element),
+                Err(e) => format!("Would fail: {}", e)
+            }
+        }
+        Command::TsCreate(k, retention) => {
+            match (smirk_map.exists(k), retention) {
+                (true, _) => format!("Would fail: {}", SmirkMessages::TimeSeriesError(k.clone(), String::from("the key already exists"))),
+                (false, Some(retention)) => format!("Would create time series \"{}\" keeping {} ms of samples.\n", k, retention),
+                (false, None) => format!("Would create time series \"{}\" keeping every sample.\n", k)
+            }
+        }
+        Command::TsAdd(k, timestamp, _) => {
+            match smirk_map.get::<TimeSeries>(k) {
+                Ok(series) => match timestamp.map_or(Ok(()), |timestamp| series.accepts(timestamp)) {
+                    Ok(()) => format!("Would add a sample to time series \"{}\".\n", k),
+                    Err(e) => format!("Would fail: {}", SmirkMessages::TimeSeriesError(k.clone(), e))
+                },
+                Err(SmirkMessages::KeyNotFound(_)) => format!("Would create time series \"{}\" with one sample.\n", k),
+                Err(e) => format!("Would fail: {}", e)
+            }
+        }
+        Command::XGroupCreate(k, group, _, mkstream) => {
+            match smirk_map.get::<Stream>(k) {
+                Ok(entries) => match entries.can_create_group(group) {
+                    Ok(()) => format!("Would create consumer group \"{}\" on stream \"{}\".\n", group, k),
+                    Err(e) => format!("Would fail: {}", SmirkMessages::ConsumerGroupError(k.clone(), e))
+                },
+                Err(SmirkMessages::KeyNotFound(_)) if *mkstream => format!("Would create stream \"{}\" with consumer group \"{}\".\n", k, group),
+                Err(e) => format!("Would fail: {}", e)
+            }
+        }
+        Command::XGroupDestroy(k, group) => {
+            match smirk_map.get::<Stream>(k).map(|entries| entries.pending(group).map(|pending| pending.len())) {
+                Ok(Ok(pending)) => format!("Would destroy consumer group \"{}\" and its {} pending entries.\n", group, pending),
+                Ok(Err(_)) => format!("Would do nothing: stream \"{}\" has no consumer group \"{}\".\n", k, group),
+                Err(e) => format!("Would fail: {}", e)
+            }
+        }
+        Command::XAck(k, group, ids) => {
+            match smirk_map.get::<Stream>(k).map(|entries| entries.pending(group)) {
+                Ok(Ok(pending)) => {
+                    let acked = ids.iter().filter(|id| pending.contains_key(id)).count();
+                    format!("Would acknowledge {} of {} entries.\n", acked, ids.len())
+                }
+                Ok(Err(e)) => format!("Would fail: {}", SmirkMessages::ConsumerGroupError(k.clone(), e)),
+                Err(e) => format!("Would fail: {}", e)
+            }
+        }
+        Command::XClaim(k, group, consumer, min_idle, ids) => {
+            match smirk_map.get::<Stream>(k).map(|entries| (entries, entries.pending(group))) {
+                Ok((entries, Ok(pending))) => {
+                    let claimed = ids
+                        .iter()
+                        .filter(|id| pending.get(id).is_some_and(|entry| entry.idle() >= *min_idle))
+                        .filter(|id| !entries.range(**id, **id, Some(1)).is_empty())
+                        .count();
+                    format!("Would hand {} of {} entries to consumer \"{}\".\n", claimed, ids.len(), consumer)
+                }
+                Ok((_, Err(e))) => format!("Would fail: {}", SmirkMessages::ConsumerGroupError(k.clone(), e)),
+                Err(e) => format!("Would fail: {}", e)
+            }
+        }
+        Command::TagAdd(k, tags) => {
+            if smirk_map.map.contains_key(k) {
+                let existing = smirk_map.tags.tags(k);
+                let new = tags.iter().filter(|tag| !existing.contains(tag)).count();
+                format!("Would add {} new tags to key \"{}\".\n", new, k)
+            } else {
+                format!("Would fail: {}", SmirkMessages::KeyNotFound(k.clone()))
+            }
+        }
+        Command::TagDel(k, tags) => {
+            let existing = smirk_map.tags.tags(k);
+            let removed = tags.iter().filter(|tag| existing.contains(tag)).count();
+            format!("Would remove {} of {} tags from key \"{}\".\n", removed, tags.len(), k)
+        }
+        Command::RateLimit(k, max, window) => match smirk_map.rate_limit_peek(k, *max, Duration::from_secs(*window)) {
+            Ok((true, remaining)) => format!("Would allow the request to \"{}\", leaving {} more.\n", k, remaining),
+            Ok((false, _)) => format!("Would deny the request to \"{}\".\n", k),
+            Err(e) => format!("Would fail: {}", e)
+        },
+        Command::KeepHistory(k, depth) => {
+            format!("Would keep {} old values of key \"{}\" instead of {}.\n", depth, k, smirk_map.history.depth(k))
+        }
+        Command::Mode(mode) => format!("Would switch the key search mode from {:?} to {:?}.\n", smirk_map.search_mode, mode),
+        _ => String::from("This command has no dry-run support, so nothing was run.\n")
+    };
+    stream.write_all(report.as_bytes()).unwrap();
+}
+
+/// Writes sorted set members one per line, followed by their scores if asked for.
+fn write_members(stream: &mut Vec<u8>, members: Vec<(&String, f64)>, with_scores: bool, format: &FloatFormat) {
+    if members.is_empty() {
+        stream.write_all("No members in range.\n".as_bytes()).unwrap();
+    }
+    for (member, score) in members {
+        if with_scores {
+            stream.write_all(format!("{} {}\n", member, score.format_with(format)).as_bytes()).unwrap();
+        } else {
+            stream.write_all(format!("{}\n", member).as_bytes()).unwrap();
+        }
+    }
+}
+
+/// Writes every entry newer than the requested IDs, one `<key> <id> <field> <value> ...` line each.
+///
+/// Returns false if there was nothing to read.
+fn write_stream_entries(
+    stream: &mut Vec<u8>,
+    smirk_map: &SmirkMap,
+    count: Option<usize>,
+    streams: &[(String, Option<StreamId>)],
+    namespace: &str
+) -> Result<bool, SmirkMessages> {
+    let mut lines = Vec::new();
+    for (key, id) in streams {
+        let entries = match smirk_map.get::<Stream>(key) {
+            Ok(entries) => entries,
+            Err(SmirkMessages::KeyNotFound(_)) => continue,
+            Err(e) => return Err(e)
+        };
+        for (id, fields) in entries.after(id.unwrap_or(entries.last_id()), count) {
+            lines.push(format!("{} {}\n", key.strip_prefix(namespace).unwrap_or(key), format_entry(id, fields)));
+        }
+    }
+    stream.write_all(lines.concat().as_bytes()).unwrap();
+    Ok(!lines.is_empty())
+}
+
+/// Writes the entries XREADGROUP reads for a consumer, one line each like XREAD's.
+///
+/// Returns whether there were any.
+fn write_group_entries(
+    stream: &mut Vec<u8>,
+    smirk_map: &mut SmirkMap,
+    group: &str,
+    consumer: &str,
+    count: Option<usize>,
+    streams: &[(String, Option<StreamId>)],
+    namespace: &str
+) -> Result<bool, SmirkMessages> {
+    let mut lines = Vec::new();
+    for (key, id) in streams {
+        for (id, fields) in smirk_map.xreadgroup(key, group, consumer, *id, count)? {
+            lines.push(format!("{} {}\n", key.strip_prefix(namespace).unwrap_or(key), format_entry(&id, &fields)));
+        }
+    }
+    stream.write_all(lines.concat().as_bytes()).unwrap();
+    Ok(!lines.is_empty())
+}
+
+/// Writes a GETCHUNKED reply: `$<length>`, the value in writes of at most `chunk_size` bytes,
+/// then a newline.
+fn write_chunked(writer: &mut impl Write, value: &[u8], chunk_size: usize) -> io::Result<()> {
+    writer.write_all(format!("${}\n", value.len()).as_bytes())?;
+    for chunk in value.chunks(chunk_size) {
+        writer.write_all(chunk)?;
+    }
+    writer.write_all(b"\n")
+}
+
+/// Runs XREAD BLOCK, waiting for new entries without holding the map lock.
+fn xread_blocking(
+    stream: &mut Vec<u8>,
+    threadsafe_server_data: &Arc<Mutex<SmirkMap>>,
+    count: Option<usize>,
+    block: u64,
+    streams: &[(String, Option<StreamId>)],
+    namespace: &str,
+    state: &SmirkState
+) {
+    let deadline = (block > 0).then(|| Instant::now() + Duration::from_millis(block));
+    // Pin `$` to the newest entry as of now, so entries added while waiting are the ones returned.
+    let streams: Vec<(String, Option<StreamId>)> = {
+        let smirk_map = threadsafe_server_data.lock().unwrap();
+        streams
+            .iter()
+            .map(|(key, id)| {
+                let latest = smirk_map.get::<Stream>(key).map(|s| s.last_id()).unwrap_or(StreamId::MIN);
+                (key.clone(), Some(id.unwrap_or(latest)))
+            })
+            .collect()
+    };
+    loop {
+        let version = state.blocking.version();
+        match write_stream_entries(stream, &threadsafe_server_data.lock().unwrap(), count, &streams, namespace) {
+            Ok(true) => return,
+            Ok(false) => {}
+            Err(e) => return stream.write_all(e.to_string().as_bytes()).unwrap()
+        }
+        let remaining = deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+        if remaining == Some(Duration::ZERO) {
+            return stream.write_all("No new entries.\n".as_bytes()).unwrap();
+        }
+        state.blocking.wait(version, remaining);
+    }
+}
+
+/// Runs XREADGROUP BLOCK, waiting for entries new to the group without holding the map lock.
+///
+/// Reading a consumer's pending entries never waits, since nothing new can become pending for it
+/// in the meantime.
+fn xreadgroup_blocking(
+    stream: &mut Vec<u8>,
+    threadsafe_server_data: &Arc<Mutex<SmirkMap>>,
+    command: &Command,
+    namespace: &str,
+    state: &SmirkState
+) {
+    let Command::XReadGroup(group, consumer, count, Some(block), streams) = command else {
+        return;
+    };
+    let deadline = (*block > 0).then(|| Instant::now() + Duration::from_millis(*block));
+    let history = streams.iter().any(|(_, id)| id.is_some());
+    loop {
+        let version = state.blocking.version();
+        let read = write_group_entries(stream, &mut threadsafe_server_data.lock().unwrap(), group, consumer, *count, streams, namespace);
+        match read {
+            Ok(true) => return,
+            Ok(false) => {}
+            Err(e) => return stream.write_all(e.to_string().as_bytes()).unwrap()
+        }
+        let remaining = deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+        if history || remaining == Some(Duration::ZERO) {
+            return stream.write_all("No new entries.\n".as_bytes()).unwrap();
+        }
+        state.blocking.wait(version, remaining);
+    }
+}
+
+/// Runs WAITEXPIRE, waiting for the key to expire or be deleted without holding the map lock.
+fn wait_expire(
+    stream: &mut Vec<u8>,
+    threadsafe_server_data: &Arc<Mutex<SmirkMap>>,
+    key: &String,
+    timeout: u64,
+    namespace: &str,
+    state: &SmirkState
+) {
+    let name = key.strip_prefix(namespace).unwrap_or(key);
+    let waiter = {
+        let smirk_map = threadsafe_server_data.lock().unwrap();
+        if !smirk_map.exists(key) {
+            return stream.write_all(format!("Key \"{}\" is gone.\n", name).as_bytes()).unwrap();
+        }
+        state.blocking.wait_for_key(key)
+    };
+    if waiter.wait((timeout > 0).then(|| Duration::from_millis(timeout))) {
+        stream.write_all(format!("Key \"{}\" is gone.\n", name).as_bytes()).unwrap();
+    } else {
+        state.blocking.forget(key, &waiter);
+        stream.write_all(format!("Key \"{}\" is still there.\n", name).as_bytes()).unwrap();
+    }
+}
+
+/// Pops an element off the first of keys with one, writing a `<key> <element>` line.
+///
+/// Returns false if none of them had one.
+fn pop_first(stream: &mut Vec<u8>, smirk_map: &mut SmirkMap, end: End, keys: &[String], namespace: &str) -> Result<bool, SmirkMessages> {
+    for key in keys {
+        if let Some(element) = smirk_map.pop(key, end)? {
+            stream.write_all(format!("{} {}\n", key.strip_prefix(namespace).unwrap_or(key), element).as_bytes()).unwrap();
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Runs BLPOP or BRPOP, waiting for a push to one of the keys without holding the map lock.
+fn pop_blocking(
+    stream: &mut Vec<u8>,
+    threadsafe_server_data: &Arc<Mutex<SmirkMap>>,
+    end: End,
+    keys: &[String],
+    timeout: u64,
+    namespace: &str,
+    state: &SmirkState
+) {
+    let deadline = (timeout > 0).then(|| Instant::now() + Duration::from_millis(timeout));
+    let key_refs: Vec<&String> = keys.iter().collect();
+    loop {
+        let waiter = {
+            let mut smirk_map = threadsafe_server_data.lock().unwrap();
+            let removals = smirk_map.removals;
+            let popped = smirk_backing::through_store(state, &mut smirk_map, &key_refs, |smirk_map| {
+                pop_first(stream, smirk_map, end, keys, namespace)
+            });
+            if smirk_map.removals != removals {
+                state.blocking.wake_removed(|key| !smirk_map.exists(key));
+            }
+            match popped {
+                Ok(true) => return record_access(state, &key_refs, true),
+                // Listed while the map is still locked, so a push can't slip in first unseen.
+                Ok(false) => state.blocking.wait_for_push(keys),
+                Err(e) => return stream.write_all(e.to_string().as_bytes()).unwrap()
+            }
+        };
+        let remaining = deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+        let pushed = remaining != Some(Duration::ZERO) && waiter.wait(remaining);
+        state.blocking.forget_push(keys, &waiter);
+        if !pushed {
+            return stream.write_all("No elements to pop.\n".as_bytes()).unwrap();
+        }
+    }
+}
+
+/// Checks that every key of a command belongs to this node.
+///
+/// Returns the redirect to send back to the client when a key is owned elsewhere.
+fn cluster_redirect(cluster: &SmirkCluster, command: &Command) -> Option<String> {
+    slot_redirect(cluster, &command.keys())
+}
+
+/// The MOVED or CLUSTERDOWN line for the first of `keys` this node doesn't serve, if any.
+fn slot_redirect(cluster: &SmirkCluster, keys: &[&String]) -> Option<String> {
+    if !cluster.is_enabled() {
+        return None;
+    }
+    for key in keys {
+        let slot = key_slot(key);
+        match cluster.owner(slot) {
+            SlotOwner::Local => {}
+            SlotOwner::Remote(node) => return Some(format!("MOVED {} {}\n", slot, node.address)),
+            SlotOwner::Unassigned => return Some(format!("CLUSTERDOWN Hash slot {} is not served by any node.\n", slot))
+        }
+    }
+    None
+}
+
+/// The namespaced keys, tags and cursor names a command's reply can mention, which for EXEC are
+/// those of the commands it runs. Outside a namespace there's nothing to take off them.
+fn reply_names(command: &mut Command, session: &mut SmirkSession) -> Vec<String> {
+    if session.namespace.is_empty() {
+        return Vec::new();
+    }
+    let mut names = command.names();
+    if let (Command::Exec, Some(queued)) = (&command, &mut session.transaction) {
+        names.extend(queued.iter_mut().flat_map(Command::names));
+    }
+    names
+}
+
+/// Runs a command with the keys it names read through from, and written through to, the backing
+/// store if there is one.
+fn run_command(
+    stream: &mut Vec<u8>,
+    command: &Command,
+    smirk_map: &mut MutexGuard<SmirkMap>,
+    session: &mut SmirkSession,
+    state: &SmirkState
+) -> Result<(), SmirkError> {
+    let changes = smirk_map.changes;
+    let result = smirk_backing::through_store(state, smirk_map, &command.keys(), |smirk_map| {
+        process_command(stream, command, smirk_map, session, state)
+    });
+    record_access(state, &command.keys(), smirk_map.changes != changes);
+    result
+}
+
+/// Counts a command's reads or writes of keys for INFO, and for HOTKEYS if it's on.
+fn record_access(state: &SmirkState, keys: &[&String], write: bool) {
+    let per_key = state.config.read().unwrap().track_key_stats;
+    state.stats.lock().unwrap().record(keys, write, per_key);
+}
+
+fn process_command(
+    stream: &mut Vec<u8>,
+    command: &Command,
+    smirk_map: &mut MutexGuard<SmirkMap>,
+    session: &mut SmirkSession,
+    state: &SmirkState
+) -> Result<(), SmirkError> {
+    match command {
+        Command::Set(t, k, v, ttl) => {
+            let result = smirk_map.set_typed(k, v.to_vec(), t);
+            match result {
+                Ok(success) => match ttl {
+                    Some(asked) => {
+                        // Tell the client when the TTL policy gave the key a different TTL than it asked for.
+                        match smirk_map.set_ttl(k, ttl) {
+                            Some(given) if given != *asked => {
+                                let success = success.to_string();
+                                stream.write_all(format!("{}, TTL: {} seconds\n", success.trim_end(), given).as_bytes()).unwrap()
+                            }
+                            _ => stream.write_all(success.to_string().as_bytes()).unwrap()
+                        }
+                    }
+                    None => stream.write_all(success.to_string().as_bytes()).unwrap()
+                },
+                Err(e) => stream.write_all(e.to_string().as_bytes()).unwrap()
+            }
+        }
+        Command::SetCas(t, k, expected, v) => {
+            match smirk_map.set_cas(k, expected, v.to_vec(), t) {
+                Ok(version) => stream.write_all(format!("{}\n", version).as_bytes()).unwrap(),
+                Err(e) => stream.write_all(e.to_string().as_bytes()).unwrap()
+            }
+        }
+        Command::RateLimit(k, max, window) => match smirk_map.rate_limit(k, *max, Duration::from_secs(*window)) {
+            Ok((true, remaining)) => stream.write_all(format!("allowed {}\n", remaining).as_bytes()).unwrap(),
+            Ok((false, remaining)) => stream.write_all(format!("denied {}\n", remaining).as_bytes()).unwrap(),
+            Err(e) => stream.write_all(e.to_string().as_bytes()).unwrap()
+        },
+        Command::SetLock(k, token, ttl) => match smirk_map.set_lock(k, token, *ttl) {
+            Ok(()) => stream.write_all("OK\n".as_bytes()).unwrap(),
+            Err(e) => stream.write_all(e.to_string().as_bytes()).unwrap()
+        },
+        Command::ReleaseLock(k, token) => match smirk_map.release_lock(k, token) {
+            Ok(()) => stream.write_all("OK\n".as_bytes()).unwrap(),
+            Err(e) => stream.write_all(e.to_string().as_bytes()).unwrap()
+        },
+        Command::ExtendLock(k, token, ttl) => match smirk_map.extend_lock(k, token, *ttl) {
+            Ok(()) => stream.write_all("OK\n".as_bytes()).unwrap(),
+            Err(e) => stream.write_all(e.to_string().as_bytes()).unwrap()
+        },
+        Command::Get(_, k, _) | Command::GetAny(k, _) => {
+            write_get(stream, command, &smirk_map.get_shared(k), &session.float_format);
+            smirk_map.touch(std::slice::from_ref(k));
+        }
+        Command::GetDel(t, k) | Command::GetEx(t, k, _) => {
+            // Answered the way GET would answer, before the key changes under the same lock.
+            let get = match t {
+                Some(t) => Command::Get(t.clone(), k.clone(), None),
+                None => Command::GetAny(k.clone(), None)
+            };
+            // A value that can't be read as the type is left alone, like it would be by GET.
+            if write_get(stream, &get, &smirk_map.get_shared(k), &session.float_format) {
+                smirk_map.touch(std::slice::from_ref(k));
+                match command {
+                    Command::GetDel(_, _) => {
+                        smirk_map.del(k);
+                    }
+                    Command::GetEx(_, _, Some(Some(secs))) => {
+                        let secs = smirk_map.apply_ttl_policy(*secs);
+                        smirk_map.set_expires_at(k, SystemTime::now() + Duration::from_secs(secs));
+                    }
+                    Command::GetEx(_, _, Some(None)) => {
+                        smirk_map.set_ttl(k, &None);
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Command::IndexCreate(name, field) => {
+            let indexed = smirk_map.create_metadata_index(name, MetadataIndex::new(*field));
+            stream.write_all(format!("Indexed {} keys.\n", indexed).as_bytes()).unwrap();
+        }
+        Command::IndexQuery(name, value) => {
+            match smirk_map.metadata_indexes.get(name) {
+                Some(index) => {
+                    // Indexes cover the whole server, so keys from other namespaces are left out.
+                    let keys: Vec<&str> = index.query(value).into_iter().filter_map(|key| key.strip_prefix(&session.namespace)).collect();
+                    if keys.is_empty() {
+                        stream.write_all(format!("No keys with {} \"{}\" were found.\n", index.field, value).as_bytes()).unwrap();
+                    }
+                    for key in keys {
+                        stream.write_all(format!("{}\n", key).as_bytes()).unwrap();
+                    }
+                }
+                None => stream.write_all(SmirkMessages::IndexNotFound(name.clone()).to_string().as_bytes()).unwrap()
+            }
+        }
+        Command::IndexDrop(name) => {
+            let dropped = smirk_map.metadata_indexes.remove(name).is_some();
+            stream.write_all(format!("{}\n", dropped as u8).as_bytes()).unwrap();
+        }
+        Command::IndexList => {
+            let mut names: Vec<&String> = smirk_map.metadata_indexes.keys().collect();
+            names.sort();
+            if names.is_empty() {
+                stream.write_all("No indexes.\n".as_bytes()).unwrap();
+            }
+            for name in names {
+                stream.write_all(format!("{} on={}\n", name, smirk_map.metadata_indexes[name].field).as_bytes()).unwrap();
+            }
+        }
+        Command::VIndexCreate(name, index) => {
+            smirk_map.vector_indexes.insert(name.clone(), index.clone());
+            stream.write_all("OK\n".as_bytes()).unwrap();
+        }
+        Command::VIndexDrop(name) => {
+            let dropped = smirk_map.vector_indexes.remove(name).is_some();
+            stream.write_all(format!("{}\n", dropped as u8).as_bytes()).unwrap();
+        }
+        Command::VIndexList => {
+            let mut names: Vec<&String> = smirk_map.vector_indexes.keys().collect();
+            names.sort();
+            if names.is_empty() {
+                stream.write_all("No vector indexes.\n".as_bytes()).unwrap();
+            }
+            for name in names {
+                let index = &smirk_map.vector_indexes[name];
+                stream.write_all(format!("{} prefix={} dim={} metric={}\n", name, index.prefix, index.dim, index.metric).as_bytes()).unwrap();
+            }
+        }
+        Command::VSearch(name, k, query) => {
+            match smirk_map.vsearch(name, query, *k, &session.namespace) {
+                Ok(nearest) => {
+                    let nearest: Vec<_> = nearest
+                        .iter()
+                        .map(|(key, distance)| (key.strip_prefix(session.namespace.as_str()).unwrap_or(key), distance))
+                        .collect();
+                    if nearest.is_empty() {
+                        stream.write_all("No vectors found.\n".as_bytes()).unwrap();
+                    }
+                    for (key, distance) in nearest {
+                        stream.write_all(format!("{} {}\n", key, distance).as_bytes()).unwrap();
+                    }
+                }
+                Err(e) => stream.write_all(e.to_string().as_bytes()).unwrap()
+            }
+        }
+        Command::GeoAdd(k, members) => {
+            match smirk_map.geoadd(k, members) {
+                Ok(added) => stream.write_all(format!("{}\n", added).as_bytes()).unwrap(),
+                Err(e) => stream.write_all(e.to_string().as_bytes()).unwrap()
+            }
+        }
+        Command::GeoDist(k, from, to, unit) => {
+            let reply = smirk_map.get::<GeoSet>(k).and_then(|set| {
+                let position = |member: &String| {
+                    set.position(member).ok_or(SmirkMessages::MemberNotFound(k.clone(), member.clone()))
+                };
+                let ((lon1, lat1), (lon2, lat2)) = (position(from)?, position(to)?);
+                Ok(format!("{:.4}\n", distance(lon1, lat1, lon2, lat2) / unit.meters()))
+            });
+            match reply {
+                Ok(reply) => stream.write_all(reply.as_bytes()).unwrap(),
+                Err(e) => stream.write_all(e.to_string().as_bytes()).unwrap()
+            }
+        }
+        Command::GeoSearch(k, search) => {
+            let found = smirk_map.get::<GeoSet>(k).and_then(|set| {
+                let (lon, lat) = match &search.origin {
+                    GeoOrigin::LonLat(lon, lat) => (*lon, *lat),
+                    GeoOrigin::Member(member) => {
+                        set.position(member).ok_or(SmirkMessages::MemberNotFound(k.clone(), member.clone()))?
+                    }
+                };
+                let mut found = set.within_radius(lon, lat, search.radius * search.unit.meters());
+                match search.ascending {
+                    Some(true) => found.sort_by(|a, b| a.1.total_cmp(&b.1)),
+                    Some(false) => found.sort_by(|a, b| b.1.total_cmp(&a.1)),
+                    None => {}
+                }
+                found.truncate(search.count.unwrap_or(usize::MAX));
+                Ok(found
+                    .into_iter()
+                    .map(|(member, dist)| match search.with_dist {
+                        true => format!("{} {:.4}\n", member, dist / search.unit.meters()),
+                        false => format!("{}\n", member)
+                    })
+                    .collect::<Vec<String>>())
+            });
+            match found {
+                Ok(lines) if lines.is_empty() => stream.write_all("No members in range.\n".as_bytes()).unwrap(),
+                Ok(lines) => stream.write_all(lines.concat().as_bytes()).unwrap(),
+                Err(e) => stream.write_all(e.to_string().as_bytes()).unwrap()
+            }
+        }
+        Command::XAdd(k, id, fields) => {
+            match smirk_map.xadd(k, *id, fields.clone()) {
+                Ok(id) => {
+                    stream.write_all(format!("{}\n", id).as_bytes()).unwrap();
+                    state.blocking.notify();
+                }
+                Err(e) => stream.write_all(e.to_string().as_bytes()).unwrap()
+            }
+        }
+        Command::XRange(k, start, end, count) => {
+            match smirk_map.get::<Stream>(k) {
+                Ok(entries) => {
+                    let entries = entries.range(*start, *end, *count);
+                    if entries.is_empty() {
+                        stream.write_all("No entries in range.\n".as_bytes()).unwrap();
+                    }
+                    for (id, fields) in entries {
+                        stream.write_all(format!("{}\n", format_entry(id, fields)).as_bytes()).unwrap();
+                    }
+                }
+                Err(e) => stream.write_all(e.to_string().as_bytes()).unwrap()
+            }
+        }
+        Command::XRead(count, _, streams) => {
+            match write_stream_entries(stream, smirk_map, *count, streams, &session.namespace) {
+                Ok(true) => {}
+                Ok(false) => stream.write_all("No new entries.\n".as_bytes()).unwrap(),
+                Err(e) => stream.write_all(e.to_string().as_bytes()).unwrap()
+            }
+        }
+        Command::XReadGroup(group, consumer, count, _, streams) => {
+            match write_group_entries(stream, smirk_map, group, consumer, *count, streams, &session.namespace) {
+                Ok(true) => {}
+                Ok(false) => stream.write_all("No new entries.\n".as_bytes()).unwrap(),
+                Err(e) => stream.write_all(e.to_string().as_bytes()).unwrap()
+            }
+        }
+        Command::XGroupCreate(k, group, start, mkstream) => match smirk_map.xgroup_create(k, group, *start, *mkstream) {
+            Ok(()) => stream.write_all("OK\n".as_bytes()).unwrap(),
+            Err(e) => stream.write_all(e.to_string().as_bytes()).unwrap()
+        },
+        Command::XGroupDestroy(k, group) => match smirk_map.xgroup_destroy(k, group) {
+            Ok(destroyed) => stream.write_all(format!("{}\n", destroyed as u8).as_bytes()).unwrap(),
+            Err(e) => stream.write_all(e.to_string().as_bytes()).unwrap()
+        },
+        Command::XAck(k, group, ids) => match smirk_map.xack(k, group, ids) {
+            Ok(acked) => stream.write_all(format!("{}\n", acked).as_bytes()).unwrap(),
+            Err(e) => stream.write_all(e.to_string().as_bytes()).unwrap()
+        },
+        Command::XClaim(k, group, consumer, min_idle, ids) => match smirk_map.xclaim(k, group, consumer, *min_idle, ids) {
+            Ok(claimed) if claimed.is_empty() => stream.write_all("No entries claimed.\n".as_bytes()).unwrap(),
+            Ok(claimed) => {
+                let lines: Vec<String> = claimed.iter().map(|(id, fields)| format!("{}\n", format_entry(id, fields))).collect();
+                stream.write_all(lines.concat().as_bytes()).unwrap();
+            }
+            Err(e) => stream.write_all(e.to_string().as_bytes()).unwrap()
+        },
+        Command::XPending(k, group) => {
+            let pending = smirk_map
+                .get::<Stream>(k)
+                .and_then(|entries| entries.pending(group).map_err(|e| SmirkMessages::ConsumerGroupError(k.clone(), e)));
+            match pending {
+                Ok(pending) if pending.is_empty() => stream.write_all("No pending entries.\n".as_bytes()).unwrap(),
+                Ok(pending) => {
+                    // <id> <consumer> <idle ms> <deliveries>, oldest first.
+                    let lines: Vec<String> = pending
+                        .iter()
+                        .map(|(id, entry)| format!("{} {} {} {}\n", id, entry.consumer, entry.idle(), entry.deliveries))
+                        .collect();
+                    stream.write_all(lines.concat().as_bytes()).unwrap();
+                }
+                Err(e) => stream.write_all(e.to_string().as_bytes()).unwrap()
+            }
+        }
+        Command::Counter(k, ty, overflow, value) => match smirk_map.set_counter(k, *ty, *overflow, *value) {
+            Ok(()) => stream.write_all("OK\n".as_bytes()).unwrap(),
+            Err(e) => stream.write_all(e.to_string().as_bytes()).unwrap()
+        },
+        Command::IncrBy(k, by) => match smirk_map.incr_by(k, *by) {
+            Ok(value) => stream.write_all(format!("{}\n", value).as_bytes()).unwrap(),
+            Err(e) => stream.write_all(e.to_string().as_bytes()).unwrap()
+        },
+        Command::BfReserve(k, error_rate, capacity) => match smirk_map.bf_reserve(k, *error_rate, *capacity) {
+            Ok(()) => stream.write_all("OK\n".as_bytes()).unwrap(),
+            Err(e) => stream.write_all(e.to_string().as_bytes()).unwrap()
+        },
+        Command::BfAdd(k, element) => match smirk_map.bf_add(k, element) {
+            Ok(added) => stream.write_all(format!("{}\n", added as u8).as_bytes()).unwrap(),
+            Err(e) => stream.write_all(e.to_string().as_bytes()).unwrap()
+        },
+        Command::BfExists(k, element) => match smirk_map.bf_exists(k, element) {
+            Ok(found) => stream.write_all(format!("{}\n", found as u8).as_bytes()).unwrap(),
+            Err(e) => stream.write_all(e.to_string().as_bytes()).unwrap()
+        },
+        Command::TsCreate(k, retention) => match smirk_map.ts_create(k, *retention) {
+            Ok(()) => stream.write_all("OK\n".as_bytes()).unwrap(),
+            Err(e) => stream.write_all(e.to_string().as_bytes()).unwrap()
+        },
+        Command::TsAdd(k, timestamp, value) => match smirk_map.ts_add(k, *timestamp, *value) {
+            Ok(timestamp) => stream.write_all(format!("{}\n", timestamp).as_bytes()).unwrap(),
+            Err(e) => stream.write_all(e.to_string().as_bytes()).unwrap()
+        },
+        Command::TsRange(k, from, to, aggregation) => {
+            match smirk_map.get::<TimeSeries>(k) {
+                Ok(series) => {
+                    let samples = match aggregation {
+                        Some((aggregation, bucket)) => series.aggregate(*from, *to, *aggregation, *bucket),
+                        None => series.range(*from, *to)
+                    };
+                    if samples.is_empty() {
+                        stream.write_all("No samples in range.\n".as_bytes()).unwrap();
+                    }
+                    // <timestamp> <value>, oldest first.
+                    for (timestamp, value) in samples {
+                        stream.write_all(format!("{} {}\n", timestamp, value.format_with(&session.float_format)).as_bytes()).unwrap();
+                    }
+                }
+                Err(e) => stream.write_all(e.to_string().as_bytes()).unwrap()
+            }
+        }
+        Command::PfAdd(k, elements) => {
+            match smirk_map.pfadd(k, elements) {
+                Ok(changed) => stream.write_all(format!("{}\n", changed as u8).as_bytes()).unwrap(),
+                Err(e) => stream.write_all(e.to_string().as_bytes()).unwrap()
+            }
+        }
+        Command::BitField(k, ops) => {
+            match smirk_map.bitfield(k, ops) {
+                Ok(replies) => {
+                    for reply in replies {
+                        match reply {
+                            Some(value) => stream.write_all(format!("{}\n", value).as_bytes()).unwrap(),
+                            None => stream.write_all("(null)\n".as_bytes()).unwrap()
+                        }
+                    }
+                }
+                Err(e) => stream.write_all(e.to_string().as_bytes()).unwrap()
+            }
+        }
+        Command::PfCount(keys) => {
+            match smirk_map.pfcount(keys) {
+                Ok(count) => stream.write_all(format!("{}\n", count).as_bytes()).unwrap(),
+                Err(e) => stream.write_all(e.to_string().as_bytes()).unwrap()
+            }
+        }
+        Command::PfMerge(destination, sources) => {
+            match smirk_map.pfmerge(destination, sources) {
+                Ok(()) => stream.write_all("OK\n".as_bytes()).unwrap(),
+                Err(e) => stream.write_all(e.to_string().as_bytes()).unwrap()
+            }
+        }
+        Command::ZAdd(k, members) => {
+            match smirk_map.zadd(k, members) {
+                Ok(added) => stream.write_all(format!("{}\n", added).as_bytes()).unwrap(),
+                Err(e) => stream.write_all(e.to_string().as_bytes()).unwrap()
+            }
+        }
+        Command::ZScore(k, member) => {
+            match smirk_map.get::<SortedSet>(k) {
+                Ok(set) => match set.score(member) {
+                    Some(score) => stream.write_all(format!("{}\n", score.format_with(&session.float_format)).as_bytes()).unwrap(),
+                    None => stream.write_all(SmirkMessages::MemberNotFound(k.clone(), member.clone()).to_string().as_bytes()).unwrap()
+                },
+                Err(e) => stream.write_all(e.to_string().as_bytes()).unwrap()
+            }
+        }
+        Command::ZRange(k, start, stop, with_scores) => {
+            match smirk_map.get::<SortedSet>(k) {
+                Ok(set) => write_members(stream, set.range(*start, *stop), *with_scores, &session.float_format),
+                Err(e) => stream.write_all(e.to_string().as_bytes()).unwrap()
+            }
+        }
+        Command::ZRangeByScore(k, min, max, with_scores) => {
+            match smirk_map.get::<SortedSet>(k) {
+                Ok(set) => write_members(stream, set.range_by_score(*min, *max), *with_scores, &session.float_format),
+                Err(e) => stream.write_all(e.to_string().as_bytes()).unwrap()
+            }
+        }
+        Command::ZRem(k, members) => {
+            match smirk_map.zrem(k, members) {
+                Ok(removed) => stream.write_all(format!("{}\n", removed).as_bytes()).unwrap(),
+                Err(e) => stream.write_all(e.to_string().as_bytes()).unwrap()
+            }
+        }
+        Command::ZIncrBy(k, increment, member) => {
+            match smirk_map.zincrby(k, *increment, member) {
+                Ok(score) => stream.write_all(format!("{}\n", score.format_with(&session.float_format)).as_bytes()).unwrap(),
+                Err(e) => stream.write_all(e.to_string().as_bytes()).unwrap()
+            }
+        }
+        Command::Push(end, k, elements) => {
+            match smirk_map.push(k, *end, elements) {
+                Ok(len) => {
+                    state.blocking.wake_pushed(k);
+                    stream.write_all(format!("{}\n", len).as_bytes()).unwrap()
+                }
+                Err(e) => stream.write_all(e.to_string().as_bytes()).unwrap()
             }
-            Err(e) => {
-                eprintln!("Error accepting connection: {}", e);
+        }
+        Command::Pop(end, k) => {
+            match smirk_map.pop(k, *end) {
+                Ok(Some(element)) => stream.write_all(format!("{}\n", element).as_bytes()).unwrap(),
+                Ok(None) => stream.write_all(SmirkMessages::KeyNotFound(k.clone()).to_string().as_bytes()).unwrap(),
+                Err(e) => stream.write_all(e.to_string().as_bytes()).unwrap()
             }
         }
-    }
-}
-
-trait Streamable {
-    fn write_to_stream(&self, stream: &mut TcpStream);
-}
-
-macro_rules! impl_streamable_for_display {
-    ($($ty:ty),*) => {
-        $(
-            impl Streamable for $ty {
-                fn write_to_stream(&self, stream: &mut TcpStream) {
-                    write!(stream, "{}\n", self).unwrap();
+        Command::BlockingPop(end, keys, _) => {
+            // Inside MULTI there's no waiting, so this only pops what's already there.
+            match pop_first(stream, smirk_map, *end, keys, &session.namespace) {
+                Ok(true) => {}
+                Ok(false) => stream.write_all("No elements to pop.\n".as_bytes()).unwrap(),
+                Err(e) => stream.write_all(e.to_string().as_bytes()).unwrap()
+            }
+        }
+        Command::LLen(k) => {
+            match smirk_map.get::<List>(k) {
+                Ok(list) => stream.write_all(format!("{}\n", list.len()).as_bytes()).unwrap(),
+                Err(SmirkMessages::KeyNotFound(_)) => stream.write_all("0\n".as_bytes()).unwrap(),
+                Err(e) => stream.write_all(e.to_string().as_bytes()).unwrap()
+            }
+        }
+        Command::LRange(k, start, stop) => {
+            match smirk_map.get::<List>(k) {
+                Ok(list) => {
+                    let elements = list.range(*start, *stop);
+                    if elements.is_empty() {
+                        stream.write_all("No elements in range.\n".as_bytes()).unwrap();
+                    }
+                    for element in elements {
+                        stream.write_all(format!("{}\n", element).as_bytes()).unwrap();
+                    }
                 }
+                Err(e) => stream.write_all(e.to_string().as_bytes()).unwrap()
             }
-        )*
-    };
-}
-
-impl_streamable_for_display!(
-    i8, i16, i32, i64, i128, isize,
-    u8, u16, u32, u64, u128, usize,
-    f32, f64, bool, char, String, BigInt
-);
-
-impl Streamable for Vec<u8> {
-    fn write_to_stream(&self, stream: &mut TcpStream) {
-        stream.write_all(self).unwrap();
-        stream.write_all("\n".as_bytes()).unwrap();
-    }
-}
-
-fn get_value_and_write_to_stream<T: Streamable + 'static>(
-    stream: &mut TcpStream,
-    smirk_map: &MutexGuard<'_, SmirkMap>,
-    key: &String
-) {
-    let result = smirk_map.get::<T>(&key.to_owned());
-    if let Ok(d) = result {
-        d.write_to_stream(stream);
-    } else if let Err(s) = result {
-        stream.write_all(s.to_string().as_bytes()).unwrap();
-    }
-}
-
-fn set_value_and_write_to_stream<T: Send + FromStr + 'static>(
-    stream: &mut TcpStream,
-    smirk_map: &mut MutexGuard<'_, SmirkMap>,
-    key: &String,
-    value: Vec<u8>,
-    desired_type_name: &String
-) {
-    let result = smirk_map.set::<T>(key, value, desired_type_name);
-    if let Ok(success) = result {
-        stream.write_all(success.to_string().as_bytes()).unwrap();
-    } else if let Err(e) = result {
-        stream.write_all(e.to_string().as_bytes()).unwrap();
-    }
-}
-
-fn set_binary_value_and_write_to_stream(
-    stream: &mut TcpStream,
-    smirk_map: &mut MutexGuard<'_, SmirkMap>,
-    key: &String,
-    value: Vec<u8>,
-    desired_type_name: &String
-) {
-    let result = smirk_map.binary_set(key, value, desired_type_name);
-    if let Ok(success) = result {
-        stream.write_all(success.to_string().as_bytes()).unwrap();
-    } else if let Err(e) = result {
-        stream.write_all(e.to_string().as_bytes()).unwrap();
-    }
-}
-
-fn add_float_and_write_to_stream<T: std::ops::Add<Output = T> + Default + Copy + Display + 'static>(
-    stream: &mut TcpStream,
-    smirk_map: &mut MutexGuard<'_, SmirkMap>,
-    keys: Vec<String>
-) {
-    let total = smirk_map.add_float::<T>(keys);
-    if let Ok(total) = total {
-        stream.write_all(total.to_string().as_bytes()).unwrap();
-    } else if let Err(e) = total {
-        stream.write_all(e.to_string().as_bytes()).unwrap();
-    }
-}
-
-fn add_and_write_to_stream<T: CheckedAdd<Output = T> + Default + Display + 'static>(
-    stream: &mut TcpStream,
-    smirk_map: &mut MutexGuard<'_, SmirkMap>,
-    keys: Vec<String>
-) {
-    let total = smirk_map.add::<T>(keys);
-    if let Ok(total) = total {
-        stream.write_all(total.to_string().as_bytes()).unwrap();
-    } else if let Err(e) = total {
-        stream.write_all(e.to_string().as_bytes()).unwrap();
-    }
-}
-
-fn process_command(stream: &mut TcpStream, command: &Command, smirk_map: &mut MutexGuard<SmirkMap>) {
-    match command {
-        Command::Set(t, k, v) => {
-            match t.as_str() {
-                "i8" => { set_value_and_write_to_stream::<i8>(stream, smirk_map, k, v.to_vec(), t); }
-                "i16" => { set_value_and_write_to_stream::<i16>(stream, smirk_map, k, v.to_vec(), t); }
-                "i32" => { set_value_and_write_to_stream::<i32>(stream, smirk_map, k, v.to_vec(), t); }
-                "i64" => { set_value_and_write_to_stream::<i64>(stream, smirk_map, k, v.to_vec(), t); }
-                "i128" => { set_value_and_write_to_stream::<i128>(stream, smirk_map, k, v.to_vec(), t); }
-                "u8" => { set_value_and_write_to_stream::<u8>(stream, smirk_map, k, v.to_vec(), t); }
-                "u16" => { set_value_and_write_to_stream::<u16>(stream, smirk_map, k, v.to_vec(), t); }
-                "u32" => { set_value_and_write_to_stream::<u32>(stream, smirk_map, k, v.to_vec(), t); }
-                "u64" => { set_value_and_write_to_stream::<u64>(stream, smirk_map, k, v.to_vec(), t); }
-                "u128" => { set_value_and_write_to_stream::<u128>(stream, smirk_map, k, v.to_vec(), t); }
-                "isize" => { set_value_and_write_to_stream::<isize>(stream, smirk_map, k, v.to_vec(), t); }
-                "usize" => { set_value_and_write_to_stream::<usize>(stream, smirk_map, k, v.to_vec(), t); }
-                "BigInt" => { set_value_and_write_to_stream::<BigInt>(stream, smirk_map, k, v.to_vec(), t); }
-                "f32" => { set_value_and_write_to_stream::<f32>(stream, smirk_map, k, v.to_vec(), t); }
-                "f64" => { set_value_and_write_to_stream::<f64>(stream, smirk_map, k, v.to_vec(), t); }
-                "bool" => { set_value_and_write_to_stream::<bool>(stream, smirk_map, k, v.to_vec(), t); }
-                "char" => { set_value_and_write_to_stream::<char>(stream, smirk_map, k, v.to_vec(), t); }
-                "String" => { set_value_and_write_to_stream::<String>(stream, smirk_map, k, v.to_vec(), t); }
-                _ => { set_binary_value_and_write_to_stream(stream, smirk_map, k, v.to_vec(), t); }
-            }
-        }
-        Command::Get(t, k) => {
-            match t.as_str() {
-                "i8" => { get_value_and_write_to_stream::<i8>(stream, &smirk_map, k); }
-                "i16" => { get_value_and_write_to_stream::<i16>(stream, &smirk_map, k); }
-                "i32" => { get_value_and_write_to_stream::<i32>(stream, &smirk_map, k); }
-                "i64" => { get_value_and_write_to_stream::<i64>(stream, &smirk_map, k); }
-                "i128" => { get_value_and_write_to_stream::<i128>(stream, &smirk_map, k); }
-                "u8" => { get_value_and_write_to_stream::<u8>(stream, &smirk_map, k); }
-                "u16" => { get_value_and_write_to_stream::<u16>(stream, &smirk_map, k); }
-                "u32" => { get_value_and_write_to_stream::<u32>(stream, &smirk_map, k); }
-                "u64" => { get_value_and_write_to_stream::<u64>(stream, &smirk_map, k); }
-                "u128" => { get_value_and_write_to_stream::<u128>(stream, &smirk_map, k); }
-                "isize" => { get_value_and_write_to_stream::<isize>(stream, &smirk_map, k); }
-                "usize" => { get_value_and_write_to_stream::<usize>(stream, &smirk_map, k); }
-                "BigInt" => { get_value_and_write_to_stream::<BigInt>(stream, &smirk_map, k); }
-                "f32" => { get_value_and_write_to_stream::<f32>(stream, &smirk_map, k); }
-                "f64" => { get_value_and_write_to_stream::<f64>(stream, &smirk_map, k); }
-                "bool" => { get_value_and_write_to_stream::<bool>(stream, &smirk_map, k); }
-                "char" => { get_value_and_write_to_stream::<char>(stream, &smirk_map, k); }
-                "String" => { get_value_and_write_to_stream::<String>(stream, &smirk_map, k); }
-                _ => { get_value_and_write_to_stream::<Vec<u8>>(stream, &smirk_map, k); }
+        }
+        Command::GetChunked(k) => {
+            match smirk_map.get_bytes(k) {
+                Ok(bytes) => write_chunked(stream, bytes, bytes.len().max(1))?,
+                Err(e) => stream.write_all(e.to_string().as_bytes()).unwrap()
+            }
+        }
+        Command::GetRange(k, start, end) => {
+            match smirk_map.get_range(k, *start, *end) {
+                Ok(bytes) => bytes.to_vec().write_to_stream(stream),
+                Err(e) => stream.write_all(e.to_string().as_bytes()).unwrap()
+            }
+        }
+        Command::SetRange(k, offset, data) => {
+            match smirk_map.set_range(k, *offset, data) {
+                Ok(len) => stream.write_all(format!("{}\n", len).as_bytes()).unwrap(),
+                Err(e) => stream.write_all(e.to_string().as_bytes()).unwrap()
+            }
+        }
+        Command::JsonGet(k, path) => {
+            match smirk_map.json_get(k, path) {
+                Ok(value) => value.write_to_stream(stream),
+                Err(e) => stream.write_all(e.to_string().as_bytes()).unwrap()
+            }
+        }
+        Command::JsonSet(k, path, v) => {
+            match smirk_map.json_set(k, path, v.clone()) {
+                Ok(success) => stream.write_all(success.to_string().as_bytes()).unwrap(),
+                Err(e) => stream.write_all(e.to_string().as_bytes()).unwrap()
+            }
+        }
+        Command::Cast(k, t) => {
+            match smirk_map.cast(k, t) {
+                Ok(success) => stream.write_all(success.to_string().as_bytes()).unwrap(),
+                Err(e) => stream.write_all(e.to_string().as_bytes()).unwrap()
             }
         }
+        Command::SetNull(t, k) => {
+            stream.write_all(smirk_map.set_null(k, t).to_string().as_bytes()).unwrap();
+        }
         Command::Del(keys) => {
-            let deleted: u64 = keys.into_iter().map(|k| smirk_map.del(k)).sum();
-            stream.write_all(format!("{}", deleted).as_bytes()).unwrap();
-        }
-        Command::Keys(key) => {
-            match smirk_map.search_mode {
-                SmirkSearchMode::Glob => {
-                    let pattern = glob::Pattern::new(key).unwrap();
-                    let matching_keys: Vec<String> = smirk_map
-                        .map.keys().into_iter()
-                        .filter(|k| pattern.matches(k))
-                        .cloned()
-                        .collect();
-                    if matching_keys.len() == 0 {
-                        stream.write_all(format!("No matches for key query \"{}\" were found.\n", key).as_bytes()).unwrap();
-                    } else {
-                        let matched = matching_keys.join("\n");
-                        stream.write_all(format!("{}\n", matched).as_bytes()).unwrap();
-                    }
+            let deleted: u64 = keys.iter().map(|k| smirk_map.del(k)).sum();
+            stream.write_all(format!("{}\n", deleted).as_bytes()).unwrap();
+        }
+        Command::DelByTag(tag) => {
+            stream.write_all(format!("{}\n", smirk_map.del_by_tag(tag)).as_bytes()).unwrap();
+        }
+        Command::DelPattern(pattern) => {
+            // Matching and deleting happen under the one lock, so no client sees only some gone.
+            let keys = matching_keys(smirk_map, pattern, &session.namespace)?;
+            let deleted: u64 = keys.iter().map(|key| smirk_map.del(key)).sum();
+            smirk_backing::write_keys(state, smirk_map, &keys);
+            stream.write_all(format!("{}\n", deleted).as_bytes()).unwrap();
+        }
+        Command::ExpirePattern(pattern, ttl) => {
+            let keys = live_matching_keys(smirk_map, pattern, &session.namespace)?;
+            for key in &keys {
+                smirk_map.set_ttl(key, ttl);
+            }
+            smirk_backing::write_keys(state, smirk_map, &keys);
+            stream.write_all(format!("{}\n", keys.len()).as_bytes()).unwrap();
+        }
+        Command::TagAdd(key, tags) => {
+            match smirk_map.tag(key, tags) {
+                Ok(added) => stream.write_all(format!("{}\n", added).as_bytes()).unwrap(),
+                Err(e) => stream.write_all(e.to_string().as_bytes()).unwrap()
+            }
+        }
+        Command::TagDel(key, tags) => {
+            stream.write_all(format!("{}\n", smirk_map.tags.remove(key, tags)).as_bytes()).unwrap();
+        }
+        Command::TagKeys(tag) => {
+            let keys = smirk_map.tags.keys(tag);
+            let tag = tag.strip_prefix(&session.namespace).unwrap_or(tag);
+            if keys.is_empty() {
+                stream.write_all(format!("No keys tagged \"{}\" were found.\n", tag).as_bytes()).unwrap();
+            }
+            for key in keys {
+                stream.write_all(format!("{}\n", key.strip_prefix(&session.namespace).unwrap_or(key)).as_bytes()).unwrap();
+            }
+        }
+        Command::TagList(key) => {
+            let tags = smirk_map.tags.tags(key);
+            if tags.is_empty() {
+                stream.write_all(format!("No tags on key \"{}\".\n", key.strip_prefix(&session.namespace).unwrap_or(key)).as_bytes()).unwrap();
+            }
+            for tag in tags {
+                stream.write_all(format!("{}\n", tag.strip_prefix(&session.namespace).unwrap_or(tag)).as_bytes()).unwrap();
+            }
+        }
+        Command::Keys(key, order, offset, limit) => {
+            write_keys(stream, key, *order, *offset, *limit, &session.namespace, |after| {
+                matching_keys_after(smirk_map, key, &session.namespace, after, KEYS_BATCH)
+            })?;
+        }
+        Command::KeysCursor(key, order, name, ttl) => {
+            let mut matching_keys = matching_keys(smirk_map, key, &session.namespace)?;
+            order.sort(&mut matching_keys);
+            let count = matching_keys.len();
+            state.cursors.lock().unwrap().create(name, matching_keys, *ttl);
+            let name = name.strip_prefix(&session.namespace).unwrap_or(name);
+            stream.write_all(format!("Cursor \"{}\" holds {} keys for {} seconds.\n", name, count, ttl).as_bytes()).unwrap();
+        }
+        Command::ScanValues(ty, min, max, limit) => {
+            let keys = smirk_map.scan_values(&session.namespace, ty, min, max, *limit);
+            if keys.is_empty() {
+                stream.write_all(format!("No {} values in range.\n", ty).as_bytes()).unwrap();
+            }
+            for key in keys {
+                stream.write_all(format!("{}\n", key.strip_prefix(&session.namespace).unwrap_or(&key)).as_bytes()).unwrap();
+            }
+        }
+        Command::ScanValuesCursor(ty, min, max, name, ttl) => {
+            let keys = smirk_map.scan_values(&session.namespace, ty, min, max, None);
+            let count = keys.len();
+            state.cursors.lock().unwrap().create(name, keys, *ttl);
+            let name = name.strip_prefix(&session.namespace).unwrap_or(name);
+            stream.write_all(format!("Cursor \"{}\" holds {} keys for {} seconds.\n", name, count, ttl).as_bytes()).unwrap();
+        }
+        Command::CursorPage(name, page, size) => {
+            let mut cursors = state.cursors.lock().unwrap();
+            let name_shown = name.strip_prefix(&session.namespace).unwrap_or(name);
+            if let Some(cursor) = cursors.get(name) {
+                let keys = cursor.page(*page, *size);
+                if keys.is_empty() {
+                    stream.write_all(format!("Page {} of cursor \"{}\" is empty.\n", page, name_shown).as_bytes()).unwrap();
                 }
-                SmirkSearchMode::Regex => {
-                    let pattern = Regex::new(key).unwrap();
-                    let matching_keys: Vec<String> = smirk_map
-                        .map.keys().into_iter()
-                        .filter(|k| pattern.is_match(k))
-                        .cloned()
-                        .collect();
-                    if matching_keys.len() == 0 {
-                        stream.write_all(format!("No matches for key query \"{}\" were found.\n", key).as_bytes()).unwrap();
-                    } else {
-                        let matched = matching_keys.join("\n");
-                        stream.write_all(format!("{}\n", matched).as_bytes()).unwrap();
-                    }
-                },
-                SmirkSearchMode::Trie => {
-                    let results = smirk_map.trie.get_keys_under_prefix(key.as_str());
-                    if results.len() == 0 {
-                        stream.write_all(format!("No matches for key query \"{}\" were found.\n", key).as_bytes()).unwrap();
-                    } else {
-                        for result in results {
-                            stream.write_all(format!("{}\n", result).as_bytes()).unwrap();
-                        }
-                    }
+                for key in keys {
+                    stream.write_all(format!("{}\n", key.strip_prefix(&session.namespace).unwrap_or(key)).as_bytes()).unwrap();
                 }
+            } else {
+                stream.write_all(format!("Cursor \"{}\" does not exist or has expired.\n", name_shown).as_bytes()).unwrap();
+            }
+        }
+        Command::CursorDel(name) => {
+            let mut cursors = state.cursors.lock().unwrap();
+            if let Some(cursor) = cursors.get(name) {
+                let deleted: u64 = cursor.keys.iter().map(|k| smirk_map.del(k)).sum();
+                stream.write_all(format!("{}\n", deleted).as_bytes()).unwrap();
+            } else {
+                stream.write_all(format!("Cursor \"{}\" does not exist or has expired.\n", name).as_bytes()).unwrap();
             }
         }
+        Command::CursorDrop(name) => {
+            let dropped = state.cursors.lock().unwrap().remove(name).is_some();
+            stream.write_all(format!("{}\n", dropped as u8).as_bytes()).unwrap();
+        }
         Command::Mode(mode) => {
             smirk_map.set_search_mode(match mode {
                 SmirkSearchMode::Glob => SmirkSearchMode::Glob,
                 SmirkSearchMode::Regex => SmirkSearchMode::Regex,
                 SmirkSearchMode::Trie => SmirkSearchMode::Trie
             });
+            stream.write_all("OK\n".as_bytes()).unwrap();
+        }
+        Command::TtlSet(key, _) if !smirk_map.exists(key) => {
+            stream.write_all(SmirkMessages::KeyNotFound(key.clone()).to_string().as_bytes()).unwrap();
         }
         Command::TtlSet(key, ttl) => {
-            smirk_map.set_ttl(key, ttl);
+            match smirk_map.set_ttl(key, ttl) {
+                Some(given) if Some(given) != *ttl => {
+                    stream.write_all(format!("OK, TTL: {} seconds\n", given).as_bytes()).unwrap()
+                }
+                _ => stream.write_all("OK\n".as_bytes()).unwrap()
+            }
         }
         Command::TtlGet(key) => {
             let smttl = smirk_map.ttl(&String::from(key));
@@ -277,66 +2051,803 @@ fn process_command(stream: &mut TcpStream, command: &Command, smirk_map: &mut Mu
                 stream.write_all(s.to_string().as_bytes()).unwrap();
             }
         }
+        Command::Eval(keys, source) => {
+            let loaded = {
+                let mut scripts = state.scripts.lock().unwrap();
+                scripts.load(source).map(|sha| scripts.get(&sha))
+            };
+            match loaded {
+                Ok(Some(ast)) => eval_and_write_to_stream(stream, smirk_map, &ast, keys, &[], &session.namespace, state),
+                Ok(None) => {}
+                Err(e) => stream.write_all(format!("{}.\n", e).as_bytes()).unwrap()
+            }
+        }
+        Command::EvalSha(sha, keys, args) => {
+            let ast = state.scripts.lock().unwrap().get(sha);
+            match ast {
+                Some(ast) => eval_and_write_to_stream(stream, smirk_map, &ast, keys, args, &session.namespace, state),
+                None => stream.write_all(format!("No script with SHA1 \"{}\" was found.\n", sha).as_bytes()).unwrap()
+            }
+        }
+        Command::ScriptLoad(source) => {
+            match state.scripts.lock().unwrap().load(source) {
+                Ok(sha) => stream.write_all(format!("{}\n", sha).as_bytes()).unwrap(),
+                Err(e) => stream.write_all(format!("{}.\n", e).as_bytes()).unwrap()
+            }
+        }
+        Command::ScriptExists(shas) => {
+            let scripts = state.scripts.lock().unwrap();
+            for sha in shas {
+                stream.write_all(format!("{}\n", scripts.exists(sha) as u8).as_bytes()).unwrap();
+            }
+        }
+        Command::ScriptFlush => {
+            state.scripts.lock().unwrap().flush();
+            stream.write_all("OK\n".as_bytes()).unwrap();
+        }
+        Command::History(key, count) => {
+            let entries = smirk_map.history.get(key, count.unwrap_or(usize::MAX));
+            if entries.is_empty() {
+                stream.write_all(format!("No history for key \"{}\".\n", key).as_bytes()).unwrap();
+            }
+            for entry in entries {
+                let written = entry.written.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+                let value = entry.value.as_deref().unwrap_or("(null)");
+                stream.write_all(format!("{} {} {} {}\n", entry.version, written, entry.desired_type_name, value).as_bytes()).unwrap();
+            }
+        }
+        Command::KeepHistory(key, depth) => {
+            smirk_map.history.set_depth(key, *depth);
+            stream.write_all("OK\n".as_bytes()).unwrap();
+        }
+        Command::RestoreVersion(key, version) => {
+            match smirk_map.restore_version(key, *version) {
+                Ok(message) | Err(message) => stream.write_all(message.to_string().as_bytes()).unwrap()
+            }
+        }
+        Command::Multi => {
+            if session.transaction.is_some() {
+                stream.write_all("MULTI calls can't be nested.\n".as_bytes()).unwrap();
+            } else {
+                session.transaction = Some(Vec::new());
+                stream.write_all("OK\n".as_bytes()).unwrap();
+            }
+        }
+        Command::Exec => {
+            let watched = std::mem::take(&mut session.watched);
+            match session.transaction.take() {
+                None => stream.write_all("EXEC without MULTI.\n".as_bytes()).unwrap(),
+                Some(_) if watched.iter().any(|(key, version)| smirk_map.version(key) != *version) => {
+                    stream.write_all("Transaction aborted: a watched key changed.\n".as_bytes()).unwrap();
+                }
+                Some(queued) if queued.is_empty() => stream.write_all("No commands were queued.\n".as_bytes()).unwrap(),
+                Some(queued) => {
+                    // The map stays locked for the whole transaction, so it runs atomically.
+                    for command in &queued {
+                        if let Err(e) = run_command(stream, command, smirk_map, session, state) {
+                            stream.write_all(format!("-ERR {}\n", e).as_bytes()).unwrap();
+                        }
+                    }
+                }
+            }
+        }
+        Command::Discard => {
+            session.watched.clear();
+            match session.transaction.take() {
+                Some(_) => stream.write_all("OK\n".as_bytes()).unwrap(),
+                None => stream.write_all("DISCARD without MULTI.\n".as_bytes()).unwrap()
+            }
+        }
+        Command::Watch(keys) => {
+            if session.transaction.is_some() {
+                stream.write_all("WATCH inside MULTI is not allowed.\n".as_bytes()).unwrap();
+            } else {
+                for key in keys {
+                    session.watched.insert(key.clone(), smirk_map.version(key));
+                }
+                stream.write_all("OK\n".as_bytes()).unwrap();
+            }
+        }
+        Command::Unwatch => {
+            session.watched.clear();
+            stream.write_all("OK\n".as_bytes()).unwrap();
+        }
+        Command::Stat(key) => {
+            match smirk_map.get_record(key) {
+                Ok(record) => {
+                    let view = RecordView::new(key, record);
+                    let secs = |time: SystemTime| time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+                    let fields = [("created", view.created()), ("modified", view.modified()), ("last-access", view.last_access())];
+                    for (field, time) in fields {
+                        stream.write_all(format!("{} {}\n", field, secs(time)).as_bytes()).unwrap();
+                    }
+                }
+                Err(e) => stream.write_all(e.to_string().as_bytes()).unwrap()
+            }
+        }
+        Command::Object(key) => {
+            match (smirk_map.get_record(key), smirk_map.value_len(key)) {
+                (Ok(record), length) => {
+                    let view = RecordView::new(key, record);
+                    let secs = |time: SystemTime| time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs().to_string();
+                    let ttl = view.ttl().map(|ttl| ttl as i64).unwrap_or(-1);
+                    let fields = [
+                        ("stored-type", view.type_name().to_string()),
+                        ("user-type", view.desired_type_name().to_string()),
+                        ("length", length.map(|l| l.to_string()).unwrap_or(String::from("-1"))),
+                        ("ttl", ttl.to_string()),
+                        ("created", secs(view.created())),
+                        ("modified", secs(view.modified())),
+                        ("idle", view.idle().to_string()),
+                        ("version", record.version.to_string())
+                    ];
+                    for (field, value) in fields {
+                        stream.write_all(format!("{} {}\n", field, value).as_bytes()).unwrap();
+                    }
+                }
+                (Err(e), _) => stream.write_all(e.to_string().as_bytes()).unwrap()
+            }
+        }
+        Command::Touch(keys) => {
+            stream.write_all(format!("{}\n", smirk_map.touch(keys)).as_bytes()).unwrap();
+        }
+        Command::Dump(key) => {
+            match snapshot::dump(smirk_map, key) {
+                Ok(blob) => stream.write_all(format!("{}\n", blob).as_bytes()).unwrap(),
+                Err(e) => stream.write_all(e.to_string().as_bytes()).unwrap()
+            }
+        }
+        Command::Restore(key, ttl, blob, replace) => {
+            if !replace && smirk_map.exists(key) {
+                stream.write_all(format!("Key \"{}\" already exists. Use REPLACE to overwrite it.\n", key).as_bytes()).unwrap();
+            } else {
+                match snapshot::restore(smirk_map, key, *ttl, blob) {
+                    Ok(()) => {
+                        state.blocking.notify();
+                        stream.write_all("OK\n".as_bytes()).unwrap();
+                    }
+                    Err(e) => stream.write_all(format!("{}.\n", e).as_bytes()).unwrap()
+                }
+            }
+        }
+        Command::Migrate(..) => {
+            // Outside a transaction MIGRATE never gets here, it's run by `migrate` without the
+            // lock. A transaction holds the lock throughout, so it can't wait on another server.
+            stream.write_all("MIGRATE inside MULTI is not allowed.\n".as_bytes()).unwrap();
+        }
+        Command::Export(path, format) => {
+            let format = format.unwrap_or(SnapshotFormat::from_path(path));
+            let snapshot = snapshot::export(smirk_map);
+            let count = snapshot["keys"].as_object().map(|keys| keys.len()).unwrap_or(0);
+            let keys = state.config.read().unwrap().snapshot_keys.clone();
+            match snapshot::write_snapshot(path, format, snapshot, &keys) {
+                Ok(()) => stream.write_all(format!("Exported {} keys to \"{}\".\n", count, path).as_bytes()).unwrap(),
+                Err(e) => stream.write_all(format!("Couldn't export to \"{}\": {}.\n", path, e).as_bytes()).unwrap()
+            }
+        }
+        Command::Import(path, format) => {
+            let format = format.unwrap_or(SnapshotFormat::from_path(path));
+            let (repair, keys) = {
+                let config = state.config.read().unwrap();
+                (config.repair, config.snapshot_keys.clone())
+            };
+            let reply = match snapshot::read_snapshot(path, format, repair, &keys) {
+                Ok(loaded) => match snapshot::import(smirk_map, &loaded) {
+                    Ok(count) => format!("Imported {} keys.\n", count),
+                    Err((count, e)) => format!("Imported {} keys. {}.\n", count, e)
+                },
+                Err(e) => format!("Couldn't import from \"{}\": {}.\n", path, e)
+            };
+            state.blocking.notify();
+            stream.write_all(reply.as_bytes()).unwrap();
+        }
+        Command::Verify(path, format) => {
+            let (path, keys) = {
+                let config = state.config.read().unwrap();
+                (path.clone().unwrap_or_else(|| config.save_file.clone()), config.snapshot_keys.clone())
+            };
+            let format = format.unwrap_or(SnapshotFormat::from_path(&path));
+            match snapshot::read_snapshot(&path, format, false, &keys) {
+                Ok(saved) => {
+                    let diff = snapshot::diff(&saved, &snapshot::export(smirk_map));
+                    stream.write_all(diff.to_string().as_bytes()).unwrap();
+                    stream.write_all(format!("{} since \"{}\".\n", diff.summary(), path).as_bytes()).unwrap();
+                }
+                Err(e) => stream.write_all(format!("Couldn't verify against \"{}\": {}.\n", path, e).as_bytes()).unwrap()
+            }
+        }
         Command::Save => {
-            stream.write_all("Saving a dump of all keys.".as_bytes()).unwrap();
-            todo!();
+            // Unlike a save point this holds the lock throughout, like EXPORT does.
+            let path = state.config.read().unwrap().save_file.clone();
+            match smirk_saver::write(state, snapshot::capture(smirk_map), smirk_map.changes) {
+                Ok(count) => stream.write_all(format!("Saved {} keys to \"{}\".\n", count, path).as_bytes()).unwrap(),
+                Err(e) => stream.write_all(format!("Couldn't save to \"{}\": {}.\n", path, e).as_bytes()).unwrap()
+            }
         }
         Command::Quit => {
+            // The connection itself is shut down by handle_client once this reply is flushed.
             stream.write_all("Bye.\n".as_bytes()).unwrap();
-            let shutdown = stream.shutdown(std::net::Shutdown::Both);
-            if let Err(e) = shutdown {
-                stream.write_all(format!("Hmm. It seems like we're having problems shutting down the stream. {}", e).as_bytes()).unwrap();
+        }
+        Command::Add(_, _, _) | Command::Sub(_, _) | Command::Mul(_, _) | Command::Div(_, _) => {
+            arithmetic_and_write_to_stream(stream, smirk_map, command, None, &session.float_format);
+        }
+        Command::Agg(aggregation, t, keys) => {
+            aggregate_and_write_to_stream(stream, smirk_map, *aggregation, t, keys, false, &session.float_format);
+        }
+        Command::AggMatch(aggregation, t, pattern) => {
+            let keys = matching_keys(smirk_map, pattern, &session.namespace)?;
+            aggregate_and_write_to_stream(stream, smirk_map, *aggregation, t, &keys, true, &session.float_format);
+        }
+        Command::Store(destination, inner) => {
+            arithmetic_and_write_to_stream(stream, smirk_map, inner, Some(destination), &session.float_format);
+        }
+        Command::Checksum(on) => {
+            session.checksum = *on;
+            stream.write_all("OK\n".as_bytes()).unwrap();
+        }
+        Command::FormatFloat(format) => {
+            session.float_format = *format;
+            stream.write_all(format!("Float format set to {:?}.\n", format).as_bytes()).unwrap();
+        }
+        Command::WaitExpire(key, _) => {
+            // Inside MULTI there's no waiting, so this only says whether the key is gone yet.
+            let name = key.strip_prefix(&session.namespace).unwrap_or(key);
+            let state = if smirk_map.exists(key) { "still there" } else { "gone" };
+            stream.write_all(format!("Key \"{}\" is {}.\n", name, state).as_bytes()).unwrap();
+        }
+        Command::Wait(_, _) => {
+            // There's no replication, so no replica could ever acknowledge a write. Saying so is
+            // better than a count of 0 a client could take for replicas falling behind.
+            stream.write_all("-ERR WAIT isn't supported, there are no replicas\n".as_bytes()).unwrap();
+        }
+        Command::DryRun(command) => {
+            dry_run_command(stream, command, smirk_map, &session.namespace);
+        }
+        Command::ConfigGet(pattern) => {
+            let params = state.config.read().unwrap().matching(pattern);
+            if params.is_empty() {
+                stream.write_all(format!("No config parameters match \"{}\".\n", pattern).as_bytes()).unwrap();
+            }
+            for (param, value) in params {
+                stream.write_all(format!("{} {}\n", param, value).as_bytes()).unwrap();
+            }
+        }
+        Command::ConfigSet(param, value) => {
+            let mut config = state.config.write().unwrap();
+            match config.set(param, value) {
+                Ok(()) => {
+                    if param == "default-key-search-type" {
+                        smirk_map.set_search_mode(config.default_key_search_method);
+                    }
+                    if param == "default-ttl" {
+                        smirk_map.default_ttl = config.default_ttl;
+                    }
+                    if param == "ttl-jitter" {
+                        smirk_map.ttl_jitter = config.ttl_jitter;
+                    }
+                    if param == "max-ttl" {
+                        smirk_map.max_ttl = config.max_ttl;
+                    }
+                    if param == "track-key-stats" && !config.track_key_stats {
+                        state.stats.lock().unwrap().clear_keys();
+                    }
+                    if param == "history-depth" {
+                        smirk_map.history.default_depth = config.history_depth;
+                    }
+                    if param == "lazy-free-threshold" {
+                        smirk_map.lazy_free_threshold = config.lazy_free_threshold;
+                    }
+                    stream.write_all("OK\n".as_bytes()).unwrap();
+                }
+                Err(e) => stream.write_all(format!("{}.\n", e).as_bytes()).unwrap()
+            }
+        }
+        Command::SlowLogGet(count) => {
+            let slowlog = state.slowlog.lock().unwrap();
+            for entry in slowlog.get(*count) {
+                stream.write_all(format!(
+                    "{} {} {} {} {}\n",
+                    entry.id,
+                    entry.timestamp,
+                    entry.duration.as_micros(),
+                    entry.client,
+                    entry.command
+                ).as_bytes()).unwrap();
+            }
+            if slowlog.len() == 0 {
+                stream.write_all("The slow log is empty.\n".as_bytes()).unwrap();
+            }
+        }
+        Command::HotKeys(n) => {
+            if !state.config.read().unwrap().track_key_stats {
+                stream.write_all("Key statistics are off. Turn them on with CONFIG SET track-key-stats yes.\n".as_bytes()).unwrap();
+                return Ok(());
+            }
+            let hottest = state.stats.lock().unwrap().hottest(*n, &session.namespace, |key| smirk_map.exists(key));
+            if hottest.is_empty() {
+                stream.write_all("No keys have been read or written yet.\n".as_bytes()).unwrap();
+            }
+            // <key> <reads> <writes>, most accessed first.
+            for (key, stats) in hottest {
+                let key = key.strip_prefix(&session.namespace).unwrap_or(&key);
+                stream.write_all(format!("{} {} {}\n", key, stats.reads, stats.writes).as_bytes()).unwrap();
+            }
+        }
+        Command::Trace(on) => {
+            session.trace = *on;
+            stream.write_all("OK\n".as_bytes()).unwrap();
+        }
+        Command::TraceGet(id) => {
+            match state.traces.lock().unwrap().get(*id) {
+                Some(trace) => stream.write_all(format!("{}\n", trace).as_bytes()).unwrap(),
+                None => stream.write_all(format!("Trace {} not found.\n", id).as_bytes()).unwrap()
+            }
+        }
+        Command::TraceList(count) => {
+            let traces = state.traces.lock().unwrap();
+            let mut listed = 0;
+            for trace in traces.recent(*count) {
+                stream.write_all(format!("{}\n", trace).as_bytes()).unwrap();
+                listed += 1;
+            }
+            if listed == 0 {
+                stream.write_all("No commands have been traced.\n".as_bytes()).unwrap();
+            }
+        }
+        Command::SlowLogLen => {
+            stream.write_all(format!("{}\n", state.slowlog.lock().unwrap().len()).as_bytes()).unwrap();
+        }
+        Command::SlowLogReset => {
+            state.slowlog.lock().unwrap().reset();
+            stream.write_all("OK\n".as_bytes()).unwrap();
+        }
+        Command::DebugSleep(seconds) => {
+            // The map stays locked throughout, so everyone else waits too, as for a slow command.
+            sleep(Duration::from_secs_f64(*seconds));
+            stream.write_all("OK\n".as_bytes()).unwrap();
+        }
+        Command::DebugObject(key) => {
+            match smirk_map.get_record(key) {
+                Ok(record) => {
+                    let millis = |time: SystemTime| time.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis().to_string();
+                    let size = snapshot::dump(smirk_map, key).map(|blob| (blob.len() / 2).to_string()).unwrap_or(String::from("-1"));
+                    let fields = [
+                        ("stored-type", record.type_name.clone()),
+                        ("user-type", record.desired_type_name.clone()),
+                        ("version", record.version.to_string()),
+                        ("ttl", record.ttl.map(|ttl| ttl.to_string()).unwrap_or(String::from("-1"))),
+                        ("ttl-start", millis(record.ttl_start)),
+                        ("expired", String::from(if record.is_expired() { "yes" } else { "no" })),
+                        ("created", millis(record.created)),
+                        ("modified", millis(record.modified)),
+                        ("last-access", millis(record.last_access)),
+                        ("shared", Arc::strong_count(&record.value).to_string()),
+                        ("serialized-size", size)
+                    ];
+                    for (field, value) in fields {
+                        stream.write_all(format!("{} {}\n", field, value).as_bytes()).unwrap();
+                    }
+                }
+                Err(e) => stream.write_all(e.to_string().as_bytes()).unwrap()
+            }
+        }
+        Command::DebugSetExpireNow(key) => {
+            if smirk_map.exists(key) {
+                smirk_map.del(key);
+                stream.write_all("OK\n".as_bytes()).unwrap();
+            } else {
+                stream.write_all(SmirkMessages::KeyNotFound(key.clone()).to_string().as_bytes()).unwrap();
+            }
+        }
+        Command::ClientId => {
+            stream.write_all(format!("{}\n", session.client_id).as_bytes()).unwrap();
+        }
+        Command::ModuleList => {
+            let modules = module::modules();
+            if modules.is_empty() {
+                stream.write_all("No modules are loaded.\n".as_bytes()).unwrap();
+            }
+            for module in modules {
+                stream.write_all(format!(
+                    "name={} path={} commands={} types={}\n",
+                    module.name,
+                    module.path.unwrap_or_default(),
+                    module.commands.join(","),
+                    module.types.join(",")
+                ).as_bytes()).unwrap();
+            }
+        }
+        Command::ModuleLoad(path) => {
+            match module::load(Path::new(path)) {
+                Ok(name) => {
+                    log::info!("Loaded module {} from {}", name, path);
+                    stream.write_all(format!("Loaded module {}.\n", name).as_bytes()).unwrap()
+                }
+                Err(e) => stream.write_all(format!("{}.\n", e).as_bytes()).unwrap()
+            }
+        }
+        Command::Module(name, arguments, keys) => {
+            // Keys come back from the command with any namespace prefixed to them.
+            let mut arguments = arguments.clone();
+            for (i, key) in keys {
+                arguments[*i] = key.clone().into_bytes();
+            }
+            if let Some(command) = module::find_command(name) {
+                match (command.handler)(smirk_map, &arguments) {
+                    Ok(reply) => stream.write_all(&reply).unwrap(),
+                    Err(e) => stream.write_all(format!("{}.\n", e).as_bytes()).unwrap()
+                }
             }
         }
-        Command::Add(t, k) => {
-            match t.as_str() {
-                "i8" => { add_and_write_to_stream::<i8>(stream, smirk_map, k.clone()) }
-                "i16" => { add_and_write_to_stream::<i16>(stream, smirk_map, k.clone())  }
-                "i32" => { add_and_write_to_stream::<i32>(stream, smirk_map, k.clone())  }
-                "i64" => { add_and_write_to_stream::<i64>(stream, smirk_map, k.clone())  }
-                "i128" => { add_and_write_to_stream::<i128>(stream, smirk_map, k.clone())  }
-                "isize" => { add_and_write_to_stream::<isize>(stream, smirk_map, k.clone())  }
-                "u8" => { add_and_write_to_stream::<u8>(stream, smirk_map, k.clone())  }
-                "u16" => { add_and_write_to_stream::<u16>(stream, smirk_map, k.clone())  }
-                "u32" => { add_and_write_to_stream::<u32>(stream, smirk_map, k.clone())  }
-                "u64" => { add_and_write_to_stream::<u64>(stream, smirk_map, k.clone())  }
-                "u128" => { add_and_write_to_stream::<u128>(stream, smirk_map, k.clone())  }
-                "usize" => { add_and_write_to_stream::<usize>(stream, smirk_map, k.clone())  }
-                "BigInt" => { add_and_write_to_stream::<BigInt>(stream, smirk_map, k.clone())  }
-                "f32" => { add_float_and_write_to_stream::<f32>(stream, smirk_map, k.clone())  }
-                "f64" => { add_float_and_write_to_stream::<f64>(stream, smirk_map, k.clone())  }
-                _ => { }
+        Command::ClientList => {
+            for client in state.clients.lock().unwrap().list() {
+                stream.write_all(client.describe().as_bytes()).unwrap();
             }
         }
+        Command::ClientKill(target) => {
+            let killed = state.clients.lock().unwrap().kill(target);
+            stream.write_all(format!("{}\n", killed).as_bytes()).unwrap();
+        }
+        Command::Status => {
+            stream.write_all(state.startup.describe().as_bytes()).unwrap();
+        }
+        Command::Ping(None) => {
+            stream.write_all("PONG\n".as_bytes()).unwrap();
+        }
+        Command::Ping(Some(message)) | Command::Echo(message) => {
+            stream.write_all(format!("{}\n", message).as_bytes()).unwrap();
+        }
+        Command::Auth(name, password) => {
+            let config = state.config.read().unwrap();
+            if config.users.is_empty() {
+                stream.write_all("-ERR AUTH used but no users are configured\n".as_bytes()).unwrap();
+            } else if let Some(user) = smirk_auth::authenticate(&config.users, name, &password.0) {
+                session.user = Some(user.name.clone());
+                session.namespace = user.namespace.clone();
+                stream.write_all("OK\n".as_bytes()).unwrap();
+            } else {
+                stream.write_all("-ERR invalid user name or password\n".as_bytes()).unwrap();
+            }
+        }
+        Command::Info => {
+            let max_clients = state.config.read().unwrap().max_clients;
+            let clients = state.clients.lock().unwrap();
+            let totals = state.stats.lock().unwrap().totals;
+            // Reads per write, or all reads if nothing has been written.
+            let ratio = match totals.writes {
+                0 => totals.reads as f64,
+                writes => totals.reads as f64 / writes as f64
+            };
+            let info = format!(
+                "uptime_seconds:{}\nconnected_clients:{}\nmax_clients:{}\ntotal_connections:{}\nrejected_connections:{}\ndenied_connections:{}\nkeys:{}\nchanges_since_last_save:{}\nlazyfree_deferred:{}\nlazyfree_pending:{}\ndb0:keys={},reads={},writes={},read_write_ratio={:.2}\n",
+                state.startup.uptime().as_secs(),
+                clients.count(),
+                max_clients,
+                clients.total_accepted(),
+                clients.rejected,
+                clients.denied,
+                smirk_map.map.len(),
+                state.saver.unsaved(smirk_map.changes),
+                smirk_map.lazy_frees,
+                smirk_map.lazy_frees.saturating_sub(state.lazy_free.freed()),
+                smirk_map.map.len(),
+                totals.reads,
+                totals.writes,
+                ratio
+            );
+            stream.write_all(info.as_bytes()).unwrap();
+        }
+        Command::Help(None) => {
+            let commands = module::commands();
+            let specs: Vec<&CommandSpec> = command_spec::COMMANDS.iter().chain(commands.iter().map(|command| &command.spec)).collect();
+            let width = specs.iter().map(|spec| spec.name.len()).max().unwrap_or(0);
+            for spec in specs {
+                stream.write_all(format!("{:<width$} {}\n", spec.name, spec.summary, width = width).as_bytes()).unwrap();
+            }
+        }
+        Command::Help(Some(name)) => {
+            match command_spec::find(name).or_else(|| module::find_command(name).map(|command| &command.spec)) {
+                Some(spec) => stream.write_all(format!("{}\n{}\n", spec.syntax, spec.summary).as_bytes()).unwrap(),
+                None => stream.write_all(format!("Unknown command \"{}\".\n", name).as_bytes()).unwrap()
+            }
+        }
+        Command::ClusterKeySlot(key) => {
+            stream.write_all(format!("{}\n", key_slot(key)).as_bytes()).unwrap();
+        }
+        Command::ClusterSlots => {
+            if state.cluster.is_enabled() {
+                stream.write_all(state.cluster.describe_slots().as_bytes()).unwrap();
+            } else {
+                stream.write_all("Cluster mode is not enabled.\n".as_bytes()).unwrap();
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Adds a command to the slow log if it ran longer than the configured threshold.
+fn record_if_slow(state: &SmirkState, peer: &str, command: &str, elapsed: Duration) {
+    let (threshold, max_len) = {
+        let config = state.config.read().unwrap();
+        (config.slowlog_log_slower_than, config.slowlog_max_len)
+    };
+    if elapsed.as_micros() > threshold as u128 {
+        log::warn!("Slow command from {} took {}us: {}", peer, elapsed.as_micros(), command);
+        state.slowlog.lock().unwrap().record(peer, command, elapsed, max_len);
     }
 }
 
-fn handle_client(stream: TcpStream, threadsafe_server_data: &Arc<Mutex<SmirkMap>>) {
+/// Serves one connection.
+///
+/// Each command's reply is buffered and written in the order the commands arrived.
+/// When a client pipelines several commands the replies are batched into a single
+/// write once every command already read from the socket has been processed.
+/// The map lock is only held while a single command executes.
+fn handle_client(stream: SmirkStream, client_id: u64, peer: String, threadsafe_server_data: &Arc<Mutex<SmirkMap>>, state: &SmirkState) {
     let mut bufreader = BufReader::new(&stream);
+    let mut writer = &stream;
 
-    let mut smirk_map = threadsafe_server_data.lock().unwrap();
+    let mut session = SmirkSession { client_id, ..SmirkSession::default() };
+    let mut responses = Responses::default();
+    // IDs of the traced commands whose replies are still waiting in `responses`.
+    let mut traced: Vec<u64> = Vec::new();
     loop {
         let mut line: Vec<u8> = Vec::new();
 
-        match bufreader.read_until(b'\n', &mut line) {
-            Ok(0) => {
+        // Read on every command so CONFIG SET applies to clients that are already connected.
+        let (idle, read, auth_required) = {
+            let config = state.config.read().unwrap();
+            let seconds = |s: u64| Some(s).filter(|s| *s > 0).map(Duration::from_secs);
+            (seconds(config.idle_timeout), seconds(config.read_timeout), !config.users.is_empty())
+        };
+        match smirk_stream::read_line(&mut bufreader, &mut line, idle, read) {
+            Ok(LineRead::Closed) => {
+                break;
+            }
+            Ok(LineRead::Idle) => {
+                log::info!("Disconnecting {} after {} idle seconds", peer, idle.unwrap_or_default().as_secs());
                 break;
             }
-            Ok(_) => {
-                let cmd = Command::from_vec(line);
+            Ok(LineRead::TimedOut) => {
+                log::info!("Disconnecting {}, it didn't finish sending a command within {} seconds", peer, read.unwrap_or_default().as_secs());
+                responses.write_all("-ERR timed out reading the command\n".as_bytes()).unwrap();
+                if let Err(e) = responses.write_to(&mut writer) {
+                    log::warn!("Error writing to {}: {}", peer, e);
+                }
+                break;
+            }
+            Ok(LineRead::Line) => {
+                // Everything a line sent with CHECKSUM ON gets back is framed, CHECKSUM OFF's reply too.
+                let framed = session.checksum;
+                let mut reply_start = responses.len();
+                let (line, frame_error) = match framed {
+                    true => match smirk_checksum::unframe(line) {
+                        Ok(line) => (line, None),
+                        // An empty line runs nothing, leaving just the error as the reply.
+                        Err(e) => (Vec::new(), Some(e))
+                    },
+                    false => (line, None)
+                };
+                let text = String::from_utf8_lossy(&line).trim_end().to_string();
+                let parsing = Instant::now();
+                // Disabled and renamed commands are sorted out before anything parses the arguments, so
+                // no client reaches them by their old names whatever else it's allowed to run.
+                let cmd = Command::split_line(line)
+                    .and_then(|arguments| {
+                        let config = state.config.read().unwrap();
+                        smirk_commands::resolve(arguments, &config.disabled_commands, &config.renamed_commands)
+                    })
+                    .and_then(Command::from_arguments);
+                let parse = parsing.elapsed();
+                // CLIENT LIST, the slowlog and traces all keep the line, so AUTH's password is left out.
+                let text = match &cmd {
+                    Ok(Command::Auth(name, _)) => format!("AUTH {} ***", name),
+                    _ if text.split_whitespace().next().is_some_and(|word| word.eq_ignore_ascii_case("AUTH")) => String::from("AUTH ***"),
+                    _ => text
+                };
+                let executing = Instant::now();
+                let mut lock_wait = Duration::ZERO;
+                let mut quit = false;
 
-                if let Ok(cmd) = cmd {
-                    let mut sclone = stream.try_clone().unwrap();
-                    process_command(&mut sclone, &cmd, &mut smirk_map);
+                if let Ok(mut cmd) = cmd {
+                    // Keys are moved into the namespace before anything else looks at them, so
+                    // cluster routing, WATCH and MULTI all see the real key.
+                    cmd.namespace(&session.namespace);
+                    let namespace = session.namespace.clone();
+                    let names = reply_names(&mut cmd, &mut session);
+                    log::debug!("{} ran {:?}", peer, cmd);
+                    state.clients.lock().unwrap().touch(session.client_id, &text);
+                    quit = matches!(cmd, Command::Quit);
+                    if let Command::Status = cmd {
+                        responses.write_all(state.startup.describe().as_bytes()).unwrap();
+                    } else if !state.startup.is_ready() && !matches!(cmd, Command::Quit | Command::Help(_)) {
+                        responses.write_all("LOADING smirk is loading the dataset in memory.\n".as_bytes()).unwrap();
+                    } else if auth_required
+                        && session.user.is_none()
+                        && !matches!(cmd, Command::Auth(..) | Command::Quit | Command::Help(_) | Command::Ping(_))
+                    {
+                        responses.write_all("NOAUTH Authentication required.\n".as_bytes()).unwrap();
+                    } else if !session.namespace.is_empty() && server_wide(&cmd) {
+                        let name = text.split_whitespace().next().unwrap_or_default().to_uppercase();
+                        responses.write_all(format!("-ERR '{}' can't be used inside a namespace\n", name).as_bytes()).unwrap();
+                    } else if debug_command(&cmd) && !state.config.read().unwrap().enable_debug {
+                        responses.write_all("-ERR DEBUG is off, start the server with --enable-debug to use it\n".as_bytes()).unwrap();
+                    } else if matches!(cmd, Command::ModuleLoad(_)) && !state.config.read().unwrap().enable_module_load {
+                        responses.write_all("-ERR MODULE LOAD is off, start the server with --enable-module-load or load modules with --load-module\n".as_bytes()).unwrap();
+                    } else if let Some(redirect) = cluster_redirect(&state.cluster, &cmd) {
+                        responses.write_all(redirect.as_bytes()).unwrap();
+                    } else if let (Some(queued), false) = (&mut session.transaction, cmd.controls_transaction()) {
+                        queued.push(cmd);
+                        responses.write_all("QUEUED\n".as_bytes()).unwrap();
+                    } else if let Command::Get(_, key, _) | Command::GetAny(key, _) = &cmd {
+                        // Only the value's Arc is cloned under the lock. Rendering it, which can take a
+                        // while for a large value, happens once the lock is released.
+                        let started = Instant::now();
+                        let value = {
+                            let mut smirk_map = threadsafe_server_data.lock().unwrap();
+                            smirk_backing::through_store(state, &mut smirk_map, &[key], |smirk_map| {
+                                smirk_map.touch(std::slice::from_ref(key));
+                                smirk_map.get_shared(key)
+                            })
+                        };
+                        // Big binary and String values are written from the map's own copy. Framed
+                        // replies need every byte in `responses` for the checksum, so they're copied.
+                        let verbatim = match &value {
+                            Ok(shared) if !framed && writes_verbatim(&cmd, shared) => responses.push_verbatim(shared),
+                            _ => false
+                        };
+                        if !verbatim {
+                            write_get(&mut responses, &cmd, &value, &session.float_format);
+                        }
+                        record_access(state, &[key], false);
+                        record_if_slow(state, &peer, &text, started.elapsed());
+                    } else if let (Command::GetChunked(key), false) = (&cmd, framed) {
+                        // Hold on to the value's Arc so the lock isn't held while a large value trickles out to the client.
+                        let value = smirk_backing::through_store(state, &mut threadsafe_server_data.lock().unwrap(), &[key], |smirk_map| {
+                            smirk_map.get_shared(key)
+                        });
+                        record_access(state, &[key], false);
+                        match value.as_ref().map(|value| shared_bytes(key, value)) {
+                            Ok(Ok(value)) => {
+                                let chunk_size = state.config.read().unwrap().chunk_size;
+                                let written = responses
+                                    .write_to(&mut writer)
+                                    .and_then(|_| write_chunked(&mut writer, value, chunk_size));
+                                if let Err(e) = written {
+                                    log::error!("Error writing to {}: {}", peer, e);
+                                    break;
+                                }
+                            }
+                            Ok(Err(e)) => responses.write_all(e.to_string().as_bytes()).unwrap(),
+                            Err(e) => responses.write_all(e.to_string().as_bytes()).unwrap()
+                        }
+                    } else if let (Command::Keys(pattern, order, offset, limit), false) = (&cmd, framed) {
+                        // Matches go straight out to the client instead of piling up in `responses`,
+                        // so a huge keyspace doesn't need its whole reply in memory at once. The map
+                        // lock is only held while each batch is found, so a client that stops reading
+                        // holds up nobody else. Framed replies need the whole reply for the checksum,
+                        // so they take the usual path.
+                        let started = Instant::now();
+                        let mut buffered = BufWriter::new(&mut writer);
+                        let written = responses.write_to(&mut buffered).map_err(SmirkError::from).and_then(|_| {
+                            write_keys(&mut buffered, pattern, *order, *offset, *limit, &session.namespace, |after| {
+                                let smirk_map = threadsafe_server_data.lock().unwrap();
+                                matching_keys_after(&smirk_map, pattern, &session.namespace, after, KEYS_BATCH)
+                            })
+                        });
+                        let flushed = buffered.flush().map_err(SmirkError::from);
+                        match written.and(flushed) {
+                            Ok(()) => {}
+                            Err(SmirkError::Io(e)) => {
+                                log::error!("Error writing to {}: {}", peer, e);
+                                break;
+                            }
+                            Err(e) => responses.write_all(format!("-ERR {}\n", e).as_bytes()).unwrap()
+                        }
+                        record_if_slow(state, &peer, &text, started.elapsed());
+                    } else if let Command::Migrate(host, port, key, destroy) = &cmd {
+                        let started = Instant::now();
+                        migrate(&mut responses, threadsafe_server_data, host, *port, key, *destroy, state);
+                        record_if_slow(state, &peer, &text, started.elapsed());
+                    } else if let Command::XRead(count, Some(block), streams) = &cmd {
+                        // Flush earlier pipelined replies first so they don't wait on this one.
+                        if let Err(e) = responses.write_to(&mut writer) {
+                            log::error!("Error writing to {}: {}", peer, e);
+                            break;
+                        }
+                        reply_start = 0;
+                        xread_blocking(&mut responses, threadsafe_server_data, *count, *block, streams, &session.namespace, state);
+                    } else if let Command::XReadGroup(_, _, _, Some(_), _) = &cmd {
+                        if let Err(e) = responses.write_to(&mut writer) {
+                            log::error!("Error writing to {}: {}", peer, e);
+                            break;
+                        }
+                        reply_start = 0;
+                        xreadgroup_blocking(&mut responses, threadsafe_server_data, &cmd, &session.namespace, state);
+                    } else if let Command::WaitExpire(key, timeout) = &cmd {
+                        if let Err(e) = responses.write_to(&mut writer) {
+                            log::error!("Error writing to {}: {}", peer, e);
+                            break;
+                        }
+                        reply_start = 0;
+                        wait_expire(&mut responses, threadsafe_server_data, key, *timeout, &session.namespace, state);
+                    } else if let Command::BlockingPop(end, keys, timeout) = &cmd {
+                        if let Err(e) = responses.write_to(&mut writer) {
+                            log::error!("Error writing to {}: {}", peer, e);
+                            break;
+                        }
+                        reply_start = 0;
+                        pop_blocking(&mut responses, threadsafe_server_data, *end, keys, *timeout, &session.namespace, state);
+                    } else {
+                        let waiting = Instant::now();
+                        let mut smirk_map = threadsafe_server_data.lock().unwrap();
+                        lock_wait = waiting.elapsed();
+                        let started = Instant::now();
+                        let removals = smirk_map.removals;
+                        if let Err(e) = run_command(&mut responses, &cmd, &mut smirk_map, &mut session, state) {
+                            responses.write_all(format!("-ERR {}\n", e).as_bytes()).unwrap();
+                        }
+                        if smirk_map.removals != removals {
+                            state.blocking.wake_removed(|key| !smirk_map.exists(key));
+                        }
+                        let elapsed = started.elapsed();
+                        drop(smirk_map);
+                        record_if_slow(state, &peer, &text, elapsed);
+                    }
+                    // Replies name keys as they're stored, so the namespace is taken back off here
+                    // rather than by every command that can mention one.
+                    for name in &names {
+                        if let Some(shown) = name.strip_prefix(namespace.as_str()) {
+                            responses.replace_since(reply_start, format!("\"{}\"", name).as_bytes(), format!("\"{}\"", shown).as_bytes());
+                        }
+                    }
+                    if session.trace {
+                        let execute = executing.elapsed().saturating_sub(lock_wait);
+                        traced.push(state.traces.lock().unwrap().record(&peer, &text, parse, lock_wait, execute));
+                    }
                 } else if let Err(cmd_err) = cmd {
-                    println!("{:?}", cmd_err);
+                    log::debug!("{} sent a command that didn't parse: {:?}", peer, cmd_err);
+                    // Blank lines are ignored like a shell would, anything else gets told why it failed.
+                    if !matches!(cmd_err, CommandError::NoInput) {
+                        responses.write_all(format!("-ERR {}\n", cmd_err).as_bytes()).unwrap();
+                    }
+                }
+                if let Some(e) = frame_error {
+                    log::debug!("{} sent a line that failed its checksum: {}", peer, e);
+                    responses.write_all(format!("-ERR {}\n", e).as_bytes()).unwrap();
+                }
+                if framed && responses.len() > reply_start {
+                    let trailer = smirk_checksum::trailer(&responses[reply_start..]);
+                    responses.write_all(trailer.as_bytes()).unwrap();
+                }
+
+                if quit || bufreader.buffer().is_empty() {
+                    let writing = Instant::now();
+                    if let Err(e) = responses.write_to(&mut writer) {
+                        log::error!("Error writing to {}: {}", peer, e);
+                        break;
+                    }
+                    if !traced.is_empty() {
+                        let mut traces = state.traces.lock().unwrap();
+                        for trace in traces.written(&traced, writing.elapsed()) {
+                            log::info!("Trace {}", trace);
+                        }
+                        traced.clear();
+                    }
+                }
+                if quit {
+                    if let Err(e) = stream.shutdown(std::net::Shutdown::Both) {
+                        log::warn!("Hmm. It seems like we're having problems shutting down the stream to {}. {}", peer, e);
+                    }
+                    break;
                 }
             }
             Err(e) => {
-                eprintln!("Error reading from socket: {}", e);
+                log::error!("Error reading from {}: {}", peer, e);
                 break;
             }
         }
     }
+    state.clients.lock().unwrap().unregister(session.client_id);
+    log::info!("Client disconnected: {}", peer);
 }
-
@@ -0,0 +1,67 @@
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+/// A block of addresses written in CIDR notation, like `10.0.0.0/8` or `fd00::/8`. A bare address
+/// is a block of one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix: u8
+}
+
+impl Cidr {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        // IPv4 clients of a dual stack listener show up as ::ffff:a.b.c.d.
+        match (self.network, ip.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false
+        }
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid address block \"{}\", expected an address with an optional /<prefix length>", s);
+        let (address, prefix) = match s.split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix.parse::<u8>().map_err(|_| invalid())?)),
+            None => (s, None)
+        };
+        let network = address.parse::<IpAddr>().map_err(|_| invalid())?.to_canonical();
+        let bits = if network.is_ipv4() { 32 } else { 128 };
+        match prefix.unwrap_or(bits) {
+            prefix if prefix <= bits => Ok(Cidr { network, prefix }),
+            _ => Err(invalid())
+        }
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix)
+    }
+}
+
+/// Parses a list of address blocks separated by spaces or commas, as CONFIG SET takes them.
+pub fn parse_cidrs(value: &str) -> Result<Vec<Cidr>, String> {
+    value.split([' ', ',']).filter(|s| !s.is_empty()).map(str::parse).collect()
+}
+
+pub fn format_cidrs(cidrs: &[Cidr]) -> String {
+    cidrs.iter().map(|cidr| cidr.to_string()).collect::<Vec<String>>().join(" ")
+}
+
+/// Whether a client at `ip` may connect. The deny list wins over the allow list, and an empty
+/// allow list lets in everyone who isn't denied.
+pub fn allowed(ip: IpAddr, allow: &[Cidr], deny: &[Cidr]) -> bool {
+    !deny.iter().any(|cidr| cidr.contains(ip)) && (allow.is_empty() || allow.iter().any(|cidr| cidr.contains(ip)))
+}
@@ -0,0 +1,56 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// A user clients can AUTH as, written as `<name>:<password>[:<namespace>]`.
+///
+/// A user with a namespace only sees keys starting with it, and clients never see the prefix
+/// itself. The namespace is everything after the second colon, so `tenant:` is a valid one.
+#[derive(Clone, PartialEq)]
+pub struct SmirkUser {
+    pub name: String,
+    password: String,
+    pub namespace: String
+}
+
+/// Leaves the password out, so a user can be logged.
+impl fmt::Debug for SmirkUser {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SmirkUser")
+            .field("name", &self.name)
+            .field("password", &"***")
+            .field("namespace", &self.namespace)
+            .finish()
+    }
+}
+
+impl FromStr for SmirkUser {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(3, ':');
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(name), Some(password), namespace) if !name.is_empty() => Ok(SmirkUser {
+                name: name.to_string(),
+                password: password.to_string(),
+                namespace: namespace.unwrap_or_default().to_string()
+            }),
+            _ => Err(format!("Invalid user \"{}\", expected <name>:<password>[:<namespace>]", s))
+        }
+    }
+}
+
+/// Finds the user `name` if `password` is theirs. The password is compared in constant time, so
+/// how long a wrong guess takes says nothing about how close it was.
+pub fn authenticate<'a>(users: &'a [SmirkUser], name: &str, password: &str) -> Option<&'a SmirkUser> {
+    users
+        .iter()
+        .find(|user| user.name == name)
+        .filter(|user| constant_time_eq(user.password.as_bytes(), password.as_bytes()))
+}
+
+/// Whether two byte strings are equal, looking at every byte of the longer one whatever they hold.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    let differences = (0..a.len().max(b.len()))
+        .fold(a.len() ^ b.len(), |differences, i| differences | (a.get(i).unwrap_or(&0) ^ b.get(i).unwrap_or(&0)) as usize);
+    differences == 0
+}
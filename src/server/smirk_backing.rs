@@ -0,0 +1,78 @@
+use std::ops::DerefMut;
+
+use smirk::core::backing_store::BackingStore;
+use smirk::core::record::RecordLike;
+use smirk::core::smirk_map::SmirkMap;
+use smirk::core::snapshot::{record_from_json, record_to_json};
+
+use crate::smirk_state::SmirkState;
+
+/// Runs `f` with the backing store, if there is one, in front of and behind the map: any of
+/// `keys` the map is missing, or only has expired, are loaded from the store first, and the
+/// ones `f` changes or deletes are handed back to it afterwards.
+///
+/// The store is only ever called with the map locked, so it sees writes in the order they happen.
+pub fn through_store<M: DerefMut<Target = SmirkMap>, T>(
+    state: &SmirkState,
+    smirk_map: &mut M,
+    keys: &[&String],
+    f: impl FnOnce(&mut M) -> T
+) -> T {
+    let Some(store) = &state.backing_store else {
+        return f(smirk_map);
+    };
+    let before = read_through(store.as_ref(), smirk_map, keys);
+    let result = f(smirk_map);
+    write_through(store.as_ref(), smirk_map, before);
+    result
+}
+
+/// Loads the keys the map is missing from the store, returning every key's version after that.
+fn read_through(store: &dyn BackingStore, smirk_map: &mut SmirkMap, keys: &[&String]) -> Vec<(String, Option<u64>)> {
+    keys.iter()
+        .map(|key| {
+            let live = smirk_map.get_record(key).is_ok_and(|record| !record.is_expired());
+            if !live {
+                let loaded = store.load(key).and_then(|record| match record {
+                    Some(record) => record_from_json(smirk_map, key, &record).map(|_| ()),
+                    None => Ok(())
+                });
+                if let Err(e) = loaded {
+                    log::warn!("Couldn't load key \"{}\" from the backing store: {}", key, e);
+                }
+            }
+            (key.to_string(), smirk_map.version(key))
+        })
+        .collect()
+}
+
+/// Hands the store every key whose version has moved on from `before`, or its deletion.
+fn write_through(store: &dyn BackingStore, smirk_map: &SmirkMap, before: Vec<(String, Option<u64>)>) {
+    for (key, version) in before {
+        if smirk_map.version(&key) != version {
+            write_key(store, smirk_map, &key);
+        }
+    }
+}
+
+/// Hands the store keys a command changed without naming them, like DELPATTERN's matches.
+pub fn write_keys(state: &SmirkState, smirk_map: &SmirkMap, keys: &[String]) {
+    if let Some(store) = &state.backing_store {
+        for key in keys {
+            write_key(store.as_ref(), smirk_map, key);
+        }
+    }
+}
+
+/// Stores the record at key, or removes it from the store if the map no longer has it.
+fn write_key(store: &dyn BackingStore, smirk_map: &SmirkMap, key: &String) {
+    let written = match smirk_map.version(key) {
+        Some(_) => record_to_json(smirk_map, key)
+            .map_err(|e| e.to_string().trim_end().to_string())
+            .and_then(|record| store.store(key, &record)),
+        None => store.remove(key)
+    };
+    if let Err(e) = written {
+        log::warn!("Couldn't write key \"{}\" through to the backing store: {}", key, e);
+    }
+}
@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+/// Wakes up clients blocked in commands like XREAD BLOCK when the data they wait on may have changed.
+///
+/// Blocked clients wait here instead of holding the SmirkMap lock. The version counter makes sure a
+/// change that lands between a client's last look at the map and it starting to wait isn't missed.
+#[derive(Debug, Default)]
+pub struct SmirkBlocking {
+    version: Mutex<u64>,
+    changed: Condvar,
+    /// Clients in WAITEXPIRE, by the key they're waiting to see go.
+    key_waiters: Mutex<HashMap<String, Vec<Arc<KeyWaiter>>>>,
+    /// Clients in BLPOP or BRPOP, by the keys they're waiting to see pushed to.
+    push_waiters: Mutex<HashMap<String, Vec<Arc<KeyWaiter>>>>
+}
+
+/// One client waiting on a key: for it to expire or be deleted, or for a push to it.
+#[derive(Debug, Default)]
+pub struct KeyWaiter {
+    happened: Mutex<bool>,
+    woken: Condvar
+}
+
+impl KeyWaiter {
+    /// Waits until what it's waiting for happens or `timeout` passes, returning whether it happened.
+    ///
+    /// `None` waits forever.
+    pub fn wait(&self, timeout: Option<Duration>) -> bool {
+        let happened = self.happened.lock().unwrap();
+        match timeout {
+            Some(timeout) => *self.woken.wait_timeout_while(happened, timeout, |happened| !*happened).unwrap().0,
+            None => *self.woken.wait_while(happened, |happened| !*happened).unwrap()
+        }
+    }
+
+    fn wake(&self) {
+        *self.happened.lock().unwrap() = true;
+        self.woken.notify_all();
+    }
+}
+
+impl SmirkBlocking {
+    pub fn version(&self) -> u64 {
+        *self.version.lock().unwrap()
+    }
+
+    /// Wakes every blocked client so it can check the map again.
+    pub fn notify(&self) {
+        *self.version.lock().unwrap() += 1;
+        self.changed.notify_all();
+    }
+
+    /// Waits until something changes after `since` was read, or `timeout` passes.
+    ///
+    /// `None` waits forever.
+    pub fn wait(&self, since: u64, timeout: Option<Duration>) {
+        let version = self.version.lock().unwrap();
+        match timeout {
+            Some(timeout) => drop(self.changed.wait_timeout_while(version, timeout, |v| *v == since).unwrap()),
+            None => drop(self.changed.wait_while(version, |v| *v == since).unwrap())
+        }
+    }
+
+    /// Adds a waiter for key to go.
+    ///
+    /// Call this while holding the SmirkMap lock, after checking the key is still there, so the
+    /// key can't go before the waiter is listed.
+    pub fn wait_for_key(&self, key: &str) -> Arc<KeyWaiter> {
+        let waiter = Arc::new(KeyWaiter::default());
+        self.key_waiters.lock().unwrap().entry(key.to_string()).or_default().push(waiter.clone());
+        waiter
+    }
+
+    /// Takes a waiter that gave up off the list for key.
+    pub fn forget(&self, key: &str, waiter: &Arc<KeyWaiter>) {
+        let mut key_waiters = self.key_waiters.lock().unwrap();
+        if let Some(waiters) = key_waiters.get_mut(key) {
+            waiters.retain(|w| !Arc::ptr_eq(w, waiter));
+            if waiters.is_empty() {
+                key_waiters.remove(key);
+            }
+        }
+    }
+
+    /// Wakes the waiters of every key `gone` says has gone. Called after keys are deleted or
+    /// expire, with the SmirkMap lock still held.
+    pub fn wake_removed(&self, gone: impl Fn(&String) -> bool) {
+        self.key_waiters.lock().unwrap().retain(|key, waiters| {
+            if !gone(key) {
+                return true;
+            }
+            for waiter in waiters {
+                waiter.wake();
+            }
+            false
+        });
+    }
+
+    /// Adds one waiter for a push to any of keys.
+    ///
+    /// Call this while holding the SmirkMap lock, after finding nothing to pop from the keys, so
+    /// a push can't land before the waiter is listed.
+    pub fn wait_for_push(&self, keys: &[String]) -> Arc<KeyWaiter> {
+        let waiter = Arc::new(KeyWaiter::default());
+        let mut push_waiters = self.push_waiters.lock().unwrap();
+        for key in keys {
+            push_waiters.entry(key.clone()).or_default().push(waiter.clone());
+        }
+        waiter
+    }
+
+    /// Takes a waiter off the lists for keys, once it's been woken or has given up.
+    pub fn forget_push(&self, keys: &[String], waiter: &Arc<KeyWaiter>) {
+        let mut push_waiters = self.push_waiters.lock().unwrap();
+        for key in keys {
+            if let Some(waiters) = push_waiters.get_mut(key) {
+                waiters.retain(|w| !Arc::ptr_eq(w, waiter));
+                if waiters.is_empty() {
+                    push_waiters.remove(key);
+                }
+            }
+        }
+    }
+
+    /// Wakes every client waiting for a push to key. Called after a push, with the SmirkMap lock
+    /// still held. They all look again, and the ones that find the list empty wait again.
+    pub fn wake_pushed(&self, key: &str) {
+        if let Some(waiters) = self.push_waiters.lock().unwrap().remove(key) {
+            for waiter in waiters {
+                waiter.wake();
+            }
+        }
+    }
+}
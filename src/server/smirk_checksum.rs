@@ -0,0 +1,64 @@
+use std::fmt;
+
+/// Why a line sent with CHECKSUM ON was turned away without running.
+#[derive(Debug, PartialEq)]
+pub enum FrameError {
+    Missing,
+    Mismatch { expected: u32, actual: u32 }
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameError::Missing => write!(f, "checksum missing, expected the command followed by *<crc32 in hex>"),
+            FrameError::Mismatch { expected, actual } => {
+                write!(f, "checksum mismatch, the command hashes to {:08x} not {:08x}, resend it", actual, expected)
+            }
+        }
+    }
+}
+
+/// CRC-32 as zlib and Ethernet compute it.
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+/// Checks a `<command> *<crc32>` line and returns the command with the checksum taken off.
+///
+/// The checksum is eight hex digits covering the command's bytes, without the space before it or
+/// the line ending. Blank lines are passed on as they are, since they're ignored anyway.
+pub fn unframe(line: Vec<u8>) -> Result<Vec<u8>, FrameError> {
+    let mut line = line;
+    while line.last().is_some_and(|b| *b == b'\n' || *b == b'\r') {
+        line.pop();
+    }
+    if line.iter().all(u8::is_ascii_whitespace) {
+        return Ok(line);
+    }
+    let split = line.iter().rposition(|b| *b == b' ').ok_or(FrameError::Missing)?;
+    let expected = match &line[split + 1..] {
+        [b'*', hex @ ..] if hex.len() == 8 => std::str::from_utf8(hex)
+            .ok()
+            .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+            .ok_or(FrameError::Missing)?,
+        _ => return Err(FrameError::Missing)
+    };
+    line.truncate(split);
+    let actual = crc32(&line);
+    if actual != expected {
+        return Err(FrameError::Mismatch { expected, actual });
+    }
+    Ok(line)
+}
+
+/// The line that follows a reply with CHECKSUM ON, so the client can tell it arrived intact.
+pub fn trailer(reply: &[u8]) -> String {
+    format!("*{:08x}\n", crc32(reply))
+}
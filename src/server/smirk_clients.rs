@@ -0,0 +1,100 @@
+use std::collections::BTreeMap;
+use std::net::Shutdown;
+use std::time::Instant;
+
+use crate::smirk_stream::SmirkStream;
+
+pub struct ClientInfo {
+    pub id: u64,
+    pub address: String,
+    pub connected_at: Instant,
+    pub last_active: Instant,
+    pub last_command: String,
+    /// A handle to the client's socket, used to disconnect it from another thread.
+    stream: SmirkStream
+}
+
+impl ClientInfo {
+    /// Describes the client as `key=value` pairs, one client per line.
+    pub fn describe(&self) -> String {
+        format!(
+            "id={} addr={} age={} idle={} cmd={}\n",
+            self.id,
+            self.address,
+            self.connected_at.elapsed().as_secs(),
+            self.last_active.elapsed().as_secs(),
+            self.last_command
+        )
+    }
+}
+
+/// Every client currently connected to the server.
+#[derive(Default)]
+pub struct SmirkClients {
+    clients: BTreeMap<u64, ClientInfo>,
+    next_id: u64,
+    /// Connections turned away because max-clients was reached.
+    pub rejected: u64,
+    /// Connections refused by allow-ip or deny-ip, or for not coming from a trusted-proxy.
+    pub denied: u64
+}
+
+impl SmirkClients {
+    /// Registers a new connection and returns its client id.
+    pub fn register(&mut self, stream: SmirkStream, address: &str) -> u64 {
+        self.next_id += 1;
+        let now = Instant::now();
+        self.clients.insert(self.next_id, ClientInfo {
+            id: self.next_id,
+            address: address.to_string(),
+            connected_at: now,
+            last_active: now,
+            last_command: String::new(),
+            stream
+        });
+        self.next_id
+    }
+
+    /// How many clients are connected.
+    pub fn count(&self) -> usize {
+        self.clients.len()
+    }
+
+    /// How many connections have been accepted since the server started, ids being handed out in order.
+    pub fn total_accepted(&self) -> u64 {
+        self.next_id
+    }
+
+    pub fn unregister(&mut self, id: u64) {
+        self.clients.remove(&id);
+    }
+
+    /// Notes the command a client just ran.
+    pub fn touch(&mut self, id: u64, command: &str) {
+        if let Some(client) = self.clients.get_mut(&id) {
+            client.last_active = Instant::now();
+            client.last_command = command.split(' ').next().unwrap_or_default().to_uppercase();
+        }
+    }
+
+    pub fn list(&self) -> impl Iterator<Item = &ClientInfo> {
+        self.clients.values()
+    }
+
+    /// Disconnects every client whose id or address matches `target`, returning how many were killed.
+    ///
+    /// Shutting the socket down makes the client's handler thread see the connection close and exit.
+    pub fn kill(&mut self, target: &str) -> usize {
+        let mut killed = 0;
+        for client in self.clients.values() {
+            if client.id.to_string() == target || client.address == target {
+                if let Err(e) = client.stream.shutdown(Shutdown::Both) {
+                    log::warn!("Couldn't kill client {}: {}", client.id, e);
+                } else {
+                    killed += 1;
+                }
+            }
+        }
+        killed
+    }
+}
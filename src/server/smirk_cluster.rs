@@ -0,0 +1,159 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// The number of hash slots the keyspace is divided into.
+pub const CLUSTER_SLOTS: u16 = 16384;
+
+/// An inclusive range of hash slots, written as `start-end` (or a single `slot`).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SlotRange {
+    pub start: u16,
+    pub end: u16
+}
+
+impl SlotRange {
+    pub fn contains(&self, slot: u16) -> bool {
+        slot >= self.start && slot <= self.end
+    }
+}
+
+impl FromStr for SlotRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, end) = match s.split_once('-') {
+            Some((start, end)) => (start, end),
+            None => (s, s)
+        };
+        let start: u16 = start.trim().parse().map_err(|_| format!("Invalid slot range \"{}\"", s))?;
+        let end: u16 = end.trim().parse().map_err(|_| format!("Invalid slot range \"{}\"", s))?;
+        if start > end || end >= CLUSTER_SLOTS {
+            return Err(format!("Invalid slot range \"{}\"", s));
+        }
+        Ok(SlotRange { start, end })
+    }
+}
+
+impl fmt::Display for SlotRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.start == self.end {
+            write!(f, "{}", self.start)
+        } else {
+            write!(f, "{}-{}", self.start, self.end)
+        }
+    }
+}
+
+/// Parses a comma separated list of slot ranges, e.g. `0-5460,10923`.
+pub fn parse_slot_ranges(s: &str) -> Result<Vec<SlotRange>, String> {
+    s.split(',').map(|range| range.parse::<SlotRange>()).collect()
+}
+
+/// Another node in a static cluster topology, written as `<ranges>@<host>:<port>`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClusterNode {
+    pub address: String,
+    pub slots: Vec<SlotRange>
+}
+
+impl FromStr for ClusterNode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (slots, address) = s
+            .split_once('@')
+            .ok_or(format!("Invalid cluster node \"{}\", expected <slots>@<host>:<port>", s))?;
+        Ok(ClusterNode {
+            address: address.to_string(),
+            slots: parse_slot_ranges(slots)?
+        })
+    }
+}
+
+/// Formats slot ranges the way `parse_slot_ranges` reads them.
+pub fn format_slot_ranges(ranges: &[SlotRange]) -> String {
+    ranges.iter().map(|r| r.to_string()).collect::<Vec<String>>().join(",")
+}
+
+impl fmt::Display for ClusterNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}@{}", format_slot_ranges(&self.slots), self.address)
+    }
+}
+
+/// Where a key lives in the cluster.
+pub enum SlotOwner<'a> {
+    Local,
+    Remote(&'a ClusterNode),
+    Unassigned
+}
+
+#[derive(Debug, Default)]
+pub struct SmirkCluster {
+    pub address: String,
+    pub slots: Vec<SlotRange>,
+    pub nodes: Vec<ClusterNode>
+}
+
+impl SmirkCluster {
+    /// A cluster is only enabled when this node has been assigned slots.
+    pub fn is_enabled(&self) -> bool {
+        !self.slots.is_empty()
+    }
+
+    pub fn owner(&self, slot: u16) -> SlotOwner<'_> {
+        if self.slots.iter().any(|r| r.contains(slot)) {
+            return SlotOwner::Local;
+        }
+        match self.nodes.iter().find(|n| n.slots.iter().any(|r| r.contains(slot))) {
+            Some(node) => SlotOwner::Remote(node),
+            None => SlotOwner::Unassigned
+        }
+    }
+
+    /// Describes the slot layout, one `<start>-<end> <address>` line per range.
+    pub fn describe_slots(&self) -> String {
+        let mut lines: Vec<String> = self.slots
+            .iter()
+            .map(|r| format!("{}-{} {} (self)\n", r.start, r.end, self.address))
+            .collect();
+        for node in &self.nodes {
+            for r in &node.slots {
+                lines.push(format!("{}-{} {}\n", r.start, r.end, node.address));
+            }
+        }
+        lines.concat()
+    }
+}
+
+/// Computes the hash slot for a key, CRC16 (XMODEM) modulo the slot count.
+///
+/// If the key contains a non-empty `{...}` hash tag only the tag is hashed,
+/// so related keys such as `{user:1}:name` and `{user:1}:email` land together.
+pub fn key_slot(key: &str) -> u16 {
+    let bytes = key.as_bytes();
+    let mut hashed = bytes;
+    if let Some(open) = bytes.iter().position(|&b| b == b'{') {
+        if let Some(close) = bytes[open + 1..].iter().position(|&b| b == b'}') {
+            if close > 0 {
+                hashed = &bytes[open + 1..open + 1 + close];
+            }
+        }
+    }
+    crc16(hashed) % CLUSTER_SLOTS
+}
+
+fn crc16(bytes: &[u8]) -> u16 {
+    let mut crc: u16 = 0;
+    for &byte in bytes {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            if crc & 0x8000 != 0 {
+                crc = (crc << 1) ^ 0x1021;
+            } else {
+                crc <<= 1;
+            }
+        }
+    }
+    crc
+}
@@ -0,0 +1,78 @@
+use std::fmt;
+use std::str::FromStr;
+
+use smirk::core::command_error::CommandError;
+use smirk::core::command_spec;
+
+/// A command clients have to call by another name, written as `<command>:<name>`.
+///
+/// The command's own name stops working, so a deployment can keep IMPORT for its operators
+/// under a name semi-trusted clients won't guess.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommandRename {
+    pub command: String,
+    pub name: String
+}
+
+impl FromStr for CommandRename {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some((command, name)) if !name.is_empty() && !name.contains(char::is_whitespace) => {
+                let command = known_command(command)?;
+                let name = name.to_uppercase();
+                if command_spec::find(&name).is_some() {
+                    return Err(format!("Can't rename {} to {}, there's already a command called that", command, name));
+                }
+                Ok(CommandRename { command, name })
+            }
+            _ => Err(format!("Invalid rename \"{}\", expected <command>:<new name>", s))
+        }
+    }
+}
+
+impl fmt::Display for CommandRename {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.command, self.name)
+    }
+}
+
+/// Returns the uppercase name of a command the server has, for `--disable-command`.
+pub fn known_command(name: &str) -> Result<String, String> {
+    let name = name.to_uppercase();
+    match command_spec::find(&name) {
+        Some(_) => Ok(name),
+        None => Err(format!("There's no command called \"{}\"", name))
+    }
+}
+
+/// Puts the real command name back into a line's arguments if it used a renamed one, and refuses
+/// lines for disabled commands, or renamed ones under their old name, as if there were no such
+/// command. The arguments are checked once `Command::split_line` has unquoted them, so quoting a
+/// name doesn't get past this.
+///
+/// DRYRUN runs the command after it, so that command's name is checked too.
+pub fn resolve(arguments: Vec<Vec<u8>>, disabled: &[String], renames: &[CommandRename]) -> Result<Vec<Vec<u8>>, CommandError> {
+    if disabled.is_empty() && renames.is_empty() {
+        return Ok(arguments);
+    }
+    let mut arguments = arguments;
+    for argument in arguments.iter_mut() {
+        let name = String::from_utf8_lossy(argument).to_uppercase();
+        if disabled.contains(&name) || renames.iter().any(|rename| rename.command == name) {
+            return Err(CommandError::UnknownCommand(name));
+        }
+        let command = match renames.iter().find(|rename| rename.name == name) {
+            Some(rename) => {
+                *argument = rename.command.clone().into_bytes();
+                rename.command.clone()
+            }
+            None => name
+        };
+        if command != "DRYRUN" {
+            break;
+        }
+    }
+    Ok(arguments)
+}
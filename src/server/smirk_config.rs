@@ -1,22 +1,215 @@
 use std::env;
 
+use log::LevelFilter;
+
 use smirk::core::smirk_search_mode::SmirkSearchMode;
+use smirk::core::snapshot_keys::SnapshotKeys;
+
+use crate::smirk_access::{Cidr, format_cidrs, parse_cidrs};
+use crate::smirk_auth::SmirkUser;
+use crate::smirk_commands::{CommandRename, known_command};
+use crate::smirk_cluster::{ClusterNode, SlotRange, format_slot_ranges, parse_slot_ranges};
+use crate::smirk_logger::parse_level;
+use crate::smirk_saver::{SavePoint, format_save_points, parse_save_points};
+
+/// Every parameter CONFIG GET knows about, named after its command line flag.
+const PARAMETERS: [&str; 42] = [
+    "port",
+    "unixsocket",
+    "http-port",
+    "number-of-dbs",
+    "max-threads",
+    "default-key-search-type",
+    "cluster-slots",
+    "cluster-node",
+    "migrations",
+    "seed",
+    "import",
+    "repair",
+    "log-level",
+    "log-file",
+    "slowlog-log-slower-than",
+    "slowlog-max-len",
+    "track-key-stats",
+    "default-ttl",
+    "ttl-jitter",
+    "max-ttl",
+    "script-time-limit",
+    "history-depth",
+    "tcp-keepalive",
+    "idle-timeout",
+    "read-timeout",
+    "max-clients",
+    "allow-ip",
+    "deny-ip",
+    "proxy-protocol",
+    "trusted-proxy",
+    "user",
+    "chunk-size",
+    "lazy-free-threshold",
+    "backing-dir",
+    "save",
+    "save-file",
+    "snapshot-key-file",
+    "enable-debug",
+    "enable-module-load",
+    "disable-command",
+    "rename-command",
+    "load-module"
+];
+
+fn parse_search_mode(value: &str) -> Option<SmirkSearchMode> {
+    match value.to_uppercase().as_str() {
+        "GLOB" => Some(SmirkSearchMode::Glob),
+        "REGEX" => Some(SmirkSearchMode::Regex),
+        "TRIE" => Some(SmirkSearchMode::Trie),
+        _ => None
+    }
+}
+
+/// Parses a default or maximum TTL in seconds, where `0` turns it off.
+fn parse_default_ttl(value: &str) -> Option<Option<u64>> {
+    value.parse::<u64>().ok().map(|ttl| Some(ttl).filter(|ttl| *ttl > 0))
+}
 
 #[derive(Debug)]
 pub struct SmirkConfig {
     pub port: u16,
+    /// A unix socket path to listen on as well as the TCP port.
+    pub unixsocket: Option<String>,
+    /// Port for the HTTP API. The API is off unless this is set.
+    pub http_port: Option<u16>,
     pub number_of_dbs: u8,
     pub max_threads: usize,
-    pub default_key_search_method: SmirkSearchMode
+    pub default_key_search_method: SmirkSearchMode,
+    pub cluster_slots: Vec<SlotRange>,
+    pub cluster_nodes: Vec<ClusterNode>,
+    pub migrations: Option<String>,
+    /// A file of `<type> <key> <value> [EX <seconds>]` lines, stored at startup where the keys
+    /// don't exist yet.
+    pub seed: Option<String>,
+    /// A JSON or CBOR snapshot, as written by EXPORT, loaded before the server accepts commands.
+    pub import: Option<String>,
+    /// Load snapshots that fail their checksum anyway, rather than refusing them.
+    pub repair: bool,
+    pub log_level: LevelFilter,
+    pub log_file: Option<String>,
+    /// Commands running longer than this many microseconds are recorded in the slow log.
+    pub slowlog_log_slower_than: u64,
+    pub slowlog_max_len: usize,
+    /// Whether reads and writes are counted per key for HOTKEYS, at some memory for every key.
+    pub track_key_stats: bool,
+    /// TTL in seconds for keys written without one. `0` on the command line means no default.
+    pub default_ttl: Option<u64>,
+    /// Most seconds added at random to every TTL, so keys written together expire spread out.
+    pub ttl_jitter: u64,
+    /// Longest TTL in seconds any key is given. `0` on the command line means no limit.
+    pub max_ttl: Option<u64>,
+    /// Milliseconds an EVAL script may run before it is stopped.
+    pub script_time_limit: u64,
+    /// How many old values HISTORY keeps for every key. `0` leaves history off unless KEEPHISTORY
+    /// turns it on for a key.
+    pub history_depth: usize,
+    /// Seconds a TCP connection sits idle before the OS starts probing whether the client is still
+    /// there. `0` turns keepalive off.
+    pub tcp_keepalive: u64,
+    /// Seconds a client may go without sending a command before it's disconnected. `0` means never.
+    pub idle_timeout: u64,
+    /// Seconds a client has to finish sending a command once it has started. `0` means no limit.
+    pub read_timeout: u64,
+    /// Connections beyond this many are told so and closed. `0` means no limit.
+    pub max_clients: usize,
+    /// Address blocks TCP clients may connect from. Empty lets in every address not denied.
+    pub allow_ip: Vec<Cidr>,
+    /// Address blocks TCP clients are refused from, whether or not they're also allowed.
+    pub deny_ip: Vec<Cidr>,
+    /// Whether every connection starts with a PROXY protocol header from a load balancer, giving
+    /// the address of the client behind it. Connections without one are closed.
+    pub proxy_protocol: bool,
+    /// Address blocks of the load balancers whose PROXY protocol headers are believed. With
+    /// proxy-protocol on, TCP connections from anywhere else are closed before a header is read,
+    /// so a client can't claim someone else's address.
+    pub trusted_proxies: Vec<Cidr>,
+    /// Users clients AUTH as. With none, clients don't need to authenticate and share one keyspace.
+    pub users: Vec<SmirkUser>,
+    /// Most bytes of a GETCHUNKED value written to the socket at once.
+    pub chunk_size: usize,
+    /// Values at least this long, in bytes or members, are dropped on a background thread when
+    /// they're overwritten rather than while the map is locked. `0` drops every value in place.
+    pub lazy_free_threshold: usize,
+    /// A directory of records to read through to on a miss and write through to on every change,
+    /// for running as a cache in front of it.
+    pub backing_dir: Option<String>,
+    /// When to save the dataset to `save_file` in the background. Empty means never.
+    pub save_points: Vec<SavePoint>,
+    /// Where SAVE and save points write the dataset, as JSON unless it ends in `.cbor`.
+    pub save_file: String,
+    /// A file of keys SAVE, save points and EXPORT encrypt snapshots with, and IMPORT and startup
+    /// decrypt them with. Snapshots are written in the clear without one.
+    pub snapshot_key_file: Option<String>,
+    /// The keys read from `snapshot_key_file` at startup.
+    pub snapshot_keys: SnapshotKeys,
+    /// Whether the DEBUG commands are there, for client test suites. Off unless asked for, since
+    /// they can stall the server or expire anyone's keys.
+    pub enable_debug: bool,
+    /// Whether clients may MODULE LOAD a library, which runs its code inside the server. Off unless
+    /// asked for, so only the modules named with --load-module at startup are loaded.
+    pub enable_module_load: bool,
+    /// Commands clients get told don't exist. Fixed at startup so a client can't CONFIG SET its
+    /// way back to them.
+    pub disabled_commands: Vec<String>,
+    /// Commands clients have to call by another name, also fixed at startup.
+    pub renamed_commands: Vec<CommandRename>,
+    /// Module libraries loaded at startup, before the dataset is read.
+    pub load_module: Vec<String>
 }
 
 impl Default for SmirkConfig {
     fn default() -> Self {
         Self {
             port: 53173,
+            unixsocket: None,
+            http_port: None,
             number_of_dbs: 1,
             max_threads: num_cpus::get(),
-            default_key_search_method: SmirkSearchMode::Glob
+            default_key_search_method: SmirkSearchMode::Glob,
+            cluster_slots: Vec::new(),
+            cluster_nodes: Vec::new(),
+            migrations: None,
+            seed: None,
+            import: None,
+            repair: false,
+            log_level: LevelFilter::Info,
+            log_file: None,
+            slowlog_log_slower_than: 10000,
+            slowlog_max_len: 128,
+            track_key_stats: false,
+            default_ttl: None,
+            ttl_jitter: 0,
+            max_ttl: None,
+            script_time_limit: 5000,
+            history_depth: 0,
+            tcp_keepalive: 300,
+            idle_timeout: 0,
+            read_timeout: 0,
+            max_clients: 10000,
+            allow_ip: Vec::new(),
+            deny_ip: Vec::new(),
+            proxy_protocol: false,
+            trusted_proxies: Vec::new(),
+            users: Vec::new(),
+            chunk_size: 65536,
+            lazy_free_threshold: 65536,
+            backing_dir: None,
+            save_points: Vec::new(),
+            save_file: String::from("smirk-dump.json"),
+            snapshot_key_file: None,
+            snapshot_keys: SnapshotKeys::default(),
+            enable_debug: false,
+            enable_module_load: false,
+            disabled_commands: Vec::new(),
+            renamed_commands: Vec::new(),
+            load_module: Vec::new()
         }
     }
 }
@@ -31,6 +224,12 @@ impl SmirkConfig {
                 if args[i] == "--port" && i + 1 < args.len() {
                     config.port = args[i+1].parse().unwrap_or(config.port);
                 }
+                else if args[i] == "--unixsocket" && i + 1 < args.len() {
+                    config.unixsocket = Some(args[i+1].clone());
+                }
+                else if args[i] == "--http-port" && i + 1 < args.len() {
+                    config.http_port = args[i+1].parse().ok().or(config.http_port);
+                }
                 else if args[i] == "--number-of-dbs" && i + 1 < args.len() {
                     config.number_of_dbs = args[i+1].parse().unwrap_or(config.number_of_dbs);
                 }
@@ -38,13 +237,331 @@ impl SmirkConfig {
                     config.max_threads = args[i+1].parse().unwrap_or(config.max_threads);
                 }
                 else if args[i] == "--default-key-search-type" && i + 1 < args.len() {
-                    config.default_key_search_method = match args[i+1].to_uppercase().as_str() {
-                        "REGEX" => SmirkSearchMode::Regex,
-                        _ => SmirkSearchMode::Glob
+                    config.default_key_search_method = parse_search_mode(&args[i+1]).unwrap_or(SmirkSearchMode::Glob);
+                }
+                else if args[i] == "--cluster-slots" && i + 1 < args.len() {
+                    match parse_slot_ranges(&args[i+1]) {
+                        Ok(slots) => config.cluster_slots = slots,
+                        Err(e) => eprintln!("Ignoring --cluster-slots: {}", e)
+                    }
+                }
+                else if args[i] == "--cluster-node" && i + 1 < args.len() {
+                    match args[i+1].parse::<ClusterNode>() {
+                        Ok(node) => config.cluster_nodes.push(node),
+                        Err(e) => eprintln!("Ignoring --cluster-node: {}", e)
+                    }
+                }
+                else if args[i] == "--migrations" && i + 1 < args.len() {
+                    config.migrations = Some(args[i+1].clone());
+                }
+                else if args[i] == "--seed" && i + 1 < args.len() {
+                    config.seed = Some(args[i+1].clone());
+                }
+                else if args[i] == "--import" && i + 1 < args.len() {
+                    config.import = Some(args[i+1].clone());
+                }
+                else if args[i] == "--repair" {
+                    config.repair = true;
+                }
+                else if args[i] == "--log-level" && i + 1 < args.len() {
+                    config.log_level = parse_level(&args[i+1]).unwrap_or(config.log_level);
+                }
+                else if args[i] == "--log-file" && i + 1 < args.len() {
+                    config.log_file = Some(args[i+1].clone());
+                }
+                else if args[i] == "--slowlog-log-slower-than" && i + 1 < args.len() {
+                    config.slowlog_log_slower_than = args[i+1].parse().unwrap_or(config.slowlog_log_slower_than);
+                }
+                else if args[i] == "--slowlog-max-len" && i + 1 < args.len() {
+                    config.slowlog_max_len = args[i+1].parse().unwrap_or(config.slowlog_max_len);
+                }
+                else if args[i] == "--track-key-stats" {
+                    config.track_key_stats = true;
+                }
+                else if args[i] == "--default-ttl" && i + 1 < args.len() {
+                    config.default_ttl = parse_default_ttl(&args[i+1]).unwrap_or(config.default_ttl);
+                }
+                else if args[i] == "--ttl-jitter" && i + 1 < args.len() {
+                    config.ttl_jitter = args[i+1].parse().unwrap_or(config.ttl_jitter);
+                }
+                else if args[i] == "--max-ttl" && i + 1 < args.len() {
+                    config.max_ttl = parse_default_ttl(&args[i+1]).unwrap_or(config.max_ttl);
+                }
+                else if args[i] == "--script-time-limit" && i + 1 < args.len() {
+                    config.script_time_limit = args[i+1].parse().unwrap_or(config.script_time_limit);
+                }
+                else if args[i] == "--history-depth" && i + 1 < args.len() {
+                    config.history_depth = args[i+1].parse().unwrap_or(config.history_depth);
+                }
+                else if args[i] == "--tcp-keepalive" && i + 1 < args.len() {
+                    config.tcp_keepalive = args[i+1].parse().unwrap_or(config.tcp_keepalive);
+                }
+                else if args[i] == "--idle-timeout" && i + 1 < args.len() {
+                    config.idle_timeout = args[i+1].parse().unwrap_or(config.idle_timeout);
+                }
+                else if args[i] == "--read-timeout" && i + 1 < args.len() {
+                    config.read_timeout = args[i+1].parse().unwrap_or(config.read_timeout);
+                }
+                else if args[i] == "--max-clients" && i + 1 < args.len() {
+                    config.max_clients = args[i+1].parse().unwrap_or(config.max_clients);
+                }
+                else if args[i] == "--allow-ip" && i + 1 < args.len() {
+                    match args[i+1].parse::<Cidr>() {
+                        Ok(cidr) => config.allow_ip.push(cidr),
+                        Err(e) => eprintln!("Ignoring --allow-ip: {}", e)
+                    }
+                }
+                else if args[i] == "--deny-ip" && i + 1 < args.len() {
+                    match args[i+1].parse::<Cidr>() {
+                        Ok(cidr) => config.deny_ip.push(cidr),
+                        Err(e) => eprintln!("Ignoring --deny-ip: {}", e)
+                    }
+                }
+                else if args[i] == "--proxy-protocol" {
+                    config.proxy_protocol = true;
+                }
+                else if args[i] == "--trusted-proxy" && i + 1 < args.len() {
+                    match args[i+1].parse::<Cidr>() {
+                        Ok(cidr) => config.trusted_proxies.push(cidr),
+                        Err(e) => eprintln!("Ignoring --trusted-proxy: {}", e)
+                    }
+                }
+                else if args[i] == "--chunk-size" && i + 1 < args.len() {
+                    config.chunk_size = args[i+1].parse().ok().filter(|size| *size > 0).unwrap_or(config.chunk_size);
+                }
+                else if args[i] == "--lazy-free-threshold" && i + 1 < args.len() {
+                    config.lazy_free_threshold = args[i+1].parse().unwrap_or(config.lazy_free_threshold);
+                }
+                else if args[i] == "--backing-dir" && i + 1 < args.len() {
+                    config.backing_dir = Some(args[i+1].clone());
+                }
+                else if args[i] == "--save" && i + 1 < args.len() {
+                    match args[i+1].parse::<SavePoint>() {
+                        Ok(save_point) => config.save_points.push(save_point),
+                        Err(e) => eprintln!("Ignoring --save: {}", e)
+                    }
+                }
+                else if args[i] == "--save-file" && i + 1 < args.len() {
+                    config.save_file = args[i+1].clone();
+                }
+                else if args[i] == "--enable-debug" {
+                    config.enable_debug = true;
+                }
+                else if args[i] == "--enable-module-load" {
+                    config.enable_module_load = true;
+                }
+                else if args[i] == "--disable-command" && i + 1 < args.len() {
+                    match known_command(&args[i+1]) {
+                        Ok(command) => config.disabled_commands.push(command),
+                        Err(e) => eprintln!("Ignoring --disable-command: {}", e)
+                    }
+                }
+                else if args[i] == "--rename-command" && i + 1 < args.len() {
+                    match args[i+1].parse::<CommandRename>() {
+                        Ok(rename) => config.renamed_commands.push(rename),
+                        Err(e) => eprintln!("Ignoring --rename-command: {}", e)
+                    }
+                }
+                else if args[i] == "--snapshot-key-file" && i + 1 < args.len() {
+                    config.snapshot_key_file = Some(args[i+1].clone());
+                }
+                else if args[i] == "--load-module" && i + 1 < args.len() {
+                    config.load_module.push(args[i+1].clone());
+                }
+                else if args[i] == "--user" && i + 1 < args.len() {
+                    match args[i+1].parse::<SmirkUser>() {
+                        Ok(user) => config.users.push(user),
+                        Err(e) => eprintln!("Ignoring --user: {}", e)
                     }
                 }
             }
         }
         config
     }
+
+    /// Returns the current value of a parameter, formatted like its command line flag.
+    pub fn get(&self, param: &str) -> Option<String> {
+        match param {
+            "port" => Some(self.port.to_string()),
+            "unixsocket" => Some(self.unixsocket.clone().unwrap_or_default()),
+            "http-port" => Some(self.http_port.map(|port| port.to_string()).unwrap_or_default()),
+            "number-of-dbs" => Some(self.number_of_dbs.to_string()),
+            "max-threads" => Some(self.max_threads.to_string()),
+            "default-key-search-type" => Some(format!("{:?}", self.default_key_search_method).to_lowercase()),
+            "cluster-slots" => Some(format_slot_ranges(&self.cluster_slots)),
+            "cluster-node" => Some(self.cluster_nodes.iter().map(|n| n.to_string()).collect::<Vec<String>>().join(" ")),
+            "migrations" => Some(self.migrations.clone().unwrap_or_default()),
+            "seed" => Some(self.seed.clone().unwrap_or_default()),
+            "import" => Some(self.import.clone().unwrap_or_default()),
+            "repair" => Some(String::from(if self.repair { "yes" } else { "no" })),
+            "log-level" => Some(self.log_level.to_string().to_lowercase()),
+            "log-file" => Some(self.log_file.clone().unwrap_or_default()),
+            "slowlog-log-slower-than" => Some(self.slowlog_log_slower_than.to_string()),
+            "slowlog-max-len" => Some(self.slowlog_max_len.to_string()),
+            "track-key-stats" => Some(String::from(if self.track_key_stats { "yes" } else { "no" })),
+            "default-ttl" => Some(self.default_ttl.unwrap_or(0).to_string()),
+            "ttl-jitter" => Some(self.ttl_jitter.to_string()),
+            "max-ttl" => Some(self.max_ttl.unwrap_or(0).to_string()),
+            "script-time-limit" => Some(self.script_time_limit.to_string()),
+            "history-depth" => Some(self.history_depth.to_string()),
+            "tcp-keepalive" => Some(self.tcp_keepalive.to_string()),
+            "idle-timeout" => Some(self.idle_timeout.to_string()),
+            "read-timeout" => Some(self.read_timeout.to_string()),
+            "max-clients" => Some(self.max_clients.to_string()),
+            "allow-ip" => Some(format_cidrs(&self.allow_ip)),
+            "deny-ip" => Some(format_cidrs(&self.deny_ip)),
+            "proxy-protocol" => Some(String::from(if self.proxy_protocol { "yes" } else { "no" })),
+            "trusted-proxy" => Some(format_cidrs(&self.trusted_proxies)),
+            // Passwords stay out of CONFIG GET.
+            "user" => Some(self.users.iter().map(|u| u.name.clone()).collect::<Vec<String>>().join(" ")),
+            "chunk-size" => Some(self.chunk_size.to_string()),
+            "lazy-free-threshold" => Some(self.lazy_free_threshold.to_string()),
+            "backing-dir" => Some(self.backing_dir.clone().unwrap_or_default()),
+            "save" => Some(format_save_points(&self.save_points)),
+            "save-file" => Some(self.save_file.clone()),
+            "snapshot-key-file" => Some(self.snapshot_key_file.clone().unwrap_or_default()),
+            "enable-debug" => Some(String::from(if self.enable_debug { "yes" } else { "no" })),
+            "enable-module-load" => Some(String::from(if self.enable_module_load { "yes" } else { "no" })),
+            "disable-command" => Some(self.disabled_commands.join(" ")),
+            "rename-command" => Some(self.renamed_commands.iter().map(|r| r.to_string()).collect::<Vec<String>>().join(" ")),
+            "load-module" => Some(self.load_module.join(" ")),
+            _ => None
+        }
+    }
+
+    /// Returns every parameter whose name matches a glob `pattern`, with its value.
+    pub fn matching(&self, pattern: &str) -> Vec<(&'static str, String)> {
+        let pattern = match glob::Pattern::new(pattern) {
+            Ok(pattern) => pattern,
+            Err(_) => return Vec::new()
+        };
+        PARAMETERS
+            .iter()
+            .filter(|param| pattern.matches(param))
+            .filter_map(|param| self.get(param).map(|value| (*param, value)))
+            .collect()
+    }
+
+    /// Changes a parameter at runtime. Only parameters that don't need a restart can be set.
+    pub fn set(&mut self, param: &str, value: &str) -> Result<(), String> {
+        match param {
+            "default-key-search-type" => {
+                self.default_key_search_method = parse_search_mode(value)
+                    .ok_or(format!("Invalid search type \"{}\", expected glob, regex or trie", value))?;
+                Ok(())
+            }
+            "log-level" => {
+                self.log_level = parse_level(value)
+                    .ok_or(format!("Invalid log level \"{}\", expected off, error, warn, info, debug or trace", value))?;
+                log::set_max_level(self.log_level);
+                Ok(())
+            }
+            "slowlog-log-slower-than" => {
+                self.slowlog_log_slower_than = value.parse()
+                    .map_err(|_| format!("Invalid number of microseconds \"{}\"", value))?;
+                Ok(())
+            }
+            "slowlog-max-len" => {
+                self.slowlog_max_len = value.parse()
+                    .map_err(|_| format!("Invalid slow log length \"{}\"", value))?;
+                Ok(())
+            }
+            "track-key-stats" => {
+                self.track_key_stats = match value.to_lowercase().as_str() {
+                    "yes" => true,
+                    "no" => false,
+                    _ => return Err(format!("Invalid key statistics setting \"{}\", expected yes or no", value))
+                };
+                Ok(())
+            }
+            "default-ttl" => {
+                self.default_ttl = parse_default_ttl(value)
+                    .ok_or(format!("Invalid number of seconds \"{}\"", value))?;
+                Ok(())
+            }
+            "ttl-jitter" => {
+                self.ttl_jitter = value.parse()
+                    .map_err(|_| format!("Invalid number of seconds \"{}\"", value))?;
+                Ok(())
+            }
+            "max-ttl" => {
+                self.max_ttl = parse_default_ttl(value)
+                    .ok_or(format!("Invalid number of seconds \"{}\"", value))?;
+                Ok(())
+            }
+            "script-time-limit" => {
+                self.script_time_limit = value.parse()
+                    .map_err(|_| format!("Invalid number of milliseconds \"{}\"", value))?;
+                Ok(())
+            }
+            "history-depth" => {
+                self.history_depth = value.parse()
+                    .map_err(|_| format!("Invalid history depth \"{}\"", value))?;
+                Ok(())
+            }
+            "tcp-keepalive" => {
+                // Connections that are already open keep the setting they were accepted with.
+                self.tcp_keepalive = value.parse()
+                    .map_err(|_| format!("Invalid number of seconds \"{}\"", value))?;
+                Ok(())
+            }
+            "idle-timeout" => {
+                self.idle_timeout = value.parse()
+                    .map_err(|_| format!("Invalid number of seconds \"{}\"", value))?;
+                Ok(())
+            }
+            "read-timeout" => {
+                self.read_timeout = value.parse()
+                    .map_err(|_| format!("Invalid number of seconds \"{}\"", value))?;
+                Ok(())
+            }
+            "max-clients" => {
+                // Lowering the limit doesn't disconnect anyone, it only turns away new connections.
+                self.max_clients = value.parse()
+                    .map_err(|_| format!("Invalid number of clients \"{}\"", value))?;
+                Ok(())
+            }
+            "allow-ip" => {
+                // Like max-clients, only new connections are checked against the change.
+                self.allow_ip = parse_cidrs(value)?;
+                Ok(())
+            }
+            "deny-ip" => {
+                self.deny_ip = parse_cidrs(value)?;
+                Ok(())
+            }
+            "trusted-proxy" => {
+                self.trusted_proxies = parse_cidrs(value)?;
+                Ok(())
+            }
+            "lazy-free-threshold" => {
+                self.lazy_free_threshold = value.parse()
+                    .map_err(|_| format!("Invalid lazy free threshold \"{}\", expected a length, or 0 to turn it off", value))?;
+                Ok(())
+            }
+            "chunk-size" => {
+                self.chunk_size = value.parse().ok().filter(|size| *size > 0)
+                    .ok_or(format!("Invalid chunk size \"{}\", expected a number of bytes above 0", value))?;
+                Ok(())
+            }
+            "repair" => {
+                self.repair = match value.to_lowercase().as_str() {
+                    "yes" => true,
+                    "no" => false,
+                    _ => return Err(format!("Invalid repair setting \"{}\", expected yes or no", value))
+                };
+                Ok(())
+            }
+            "save" => {
+                self.save_points = parse_save_points(value)?;
+                Ok(())
+            }
+            "save-file" => {
+                self.save_file = value.to_string();
+                Ok(())
+            }
+            p if PARAMETERS.contains(&p) => Err(format!("Config parameter \"{}\" can't be changed at runtime", p)),
+            p => Err(format!("Unknown config parameter \"{}\"", p))
+        }
+    }
 }
@@ -0,0 +1,51 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// A snapshot of the keys matched by a KEYS query, kept server-side under a name.
+pub struct KeyCursor {
+    pub keys: Vec<String>,
+    expires_at: Instant
+}
+
+impl KeyCursor {
+    pub fn is_expired(&self) -> bool {
+        Instant::now() >= self.expires_at
+    }
+
+    /// Returns the keys on a zero-based page of `size` keys.
+    pub fn page(&self, page: usize, size: usize) -> &[String] {
+        let start = page.saturating_mul(size).min(self.keys.len());
+        let end = start.saturating_add(size).min(self.keys.len());
+        &self.keys[start..end]
+    }
+}
+
+#[derive(Default)]
+pub struct SmirkCursors {
+    cursors: HashMap<String, KeyCursor>
+}
+
+impl SmirkCursors {
+    /// Stores `keys` under `name` for `ttl` seconds, replacing any cursor of the same name.
+    pub fn create(&mut self, name: &str, keys: Vec<String>, ttl: u64) {
+        self.remove_expired();
+        self.cursors.insert(name.to_string(), KeyCursor {
+            keys,
+            expires_at: Instant::now() + Duration::from_secs(ttl)
+        });
+    }
+
+    pub fn get(&mut self, name: &str) -> Option<&KeyCursor> {
+        self.remove_expired();
+        self.cursors.get(name)
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<KeyCursor> {
+        self.remove_expired();
+        self.cursors.remove(name)
+    }
+
+    fn remove_expired(&mut self) {
+        self.cursors.retain(|_, cursor| !cursor.is_expired());
+    }
+}
@@ -0,0 +1,23 @@
+use std::sync::Mutex;
+use std::thread::sleep;
+use std::time::Duration;
+
+use smirk::core::smirk_map::SmirkMap;
+
+use crate::smirk_state::SmirkState;
+
+/// How often the sweeper looks for expired keys.
+const SWEEP_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Deletes expired keys every SWEEP_INTERVAL, forever, waking anyone in WAITEXPIRE on them.
+pub fn run(threadsafe_server_data: &Mutex<SmirkMap>, state: &SmirkState) {
+    loop {
+        sleep(SWEEP_INTERVAL);
+        let mut smirk_map = threadsafe_server_data.lock().unwrap();
+        let removed = smirk_map.remove_expired();
+        if removed > 0 {
+            log::debug!("Expired {} keys.", removed);
+            state.blocking.wake_removed(|key| !smirk_map.exists(key));
+        }
+    }
+}
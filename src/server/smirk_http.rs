@@ -0,0 +1,274 @@
+use std::io::{Cursor, Read};
+use std::sync::{Arc, Mutex};
+
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use smirk::core::smirk_map::{MAX_RANGE_BYTES, SmirkMap};
+use smirk::core::smirk_messages::SmirkMessages;
+use smirk::core::smirk_search_mode::{KeyPattern, SmirkSearchMode};
+
+use crate::smirk_access;
+use crate::smirk_auth;
+use crate::smirk_backing::through_store;
+use crate::smirk_state::SmirkState;
+
+/// Header naming the type a PUT value is parsed into, and the type GET found.
+const TYPE_HEADER: &str = "X-Smirk-Type";
+/// Header giving a PUT value a TTL in seconds.
+const TTL_HEADER: &str = "X-Smirk-TTL";
+/// The largest PUT body accepted, the same cap SETRANGE puts on a record.
+const MAX_BODY_BYTES: usize = MAX_RANGE_BYTES;
+
+type HttpResponse = Response<Cursor<Vec<u8>>>;
+
+fn reply(status: u16, body: &str) -> HttpResponse {
+    Response::from_string(body).with_status_code(status)
+}
+
+/// Decodes `%xx` escapes, so keys with spaces or slashes can be put in a URL.
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| s.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(if bytes[i] == b'+' { b' ' } else { bytes[i] });
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+/// Decodes standard base64, padded or not. `None` if it isn't base64.
+fn base64_decode(s: &str) -> Option<Vec<u8>> {
+    let mut decoded = Vec::with_capacity(s.len() * 3 / 4);
+    let mut bits = 0u32;
+    let mut count = 0;
+    for c in s.trim_end_matches('=').bytes() {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None
+        };
+        bits = (bits << 6) | value as u32;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            decoded.push((bits >> count) as u8);
+        }
+    }
+    Some(decoded)
+}
+
+fn header<'a>(request: &'a Request, name: &'static str) -> Option<&'a str> {
+    request.headers().iter().find(|h| h.field.equiv(name)).map(|h| h.value.as_str())
+}
+
+/// Serves the HTTP API until the process exits. Every request takes the map lock just like a
+/// command from a TCP client does.
+///
+/// Requests are held to what a TCP client would be. Addresses allow-ip and deny-ip keep out are
+/// refused, and each route is refused while the command it stands in for is disabled or renamed.
+/// Once users are configured, requests need Basic credentials for one of them and only see that
+/// user's namespace. In a cluster, a key served by another node gets a 421 carrying the same
+/// MOVED line a TCP client would see, and reads and writes are counted in INFO and HOTKEYS.
+///
+/// * `GET /keys?pattern=<pattern>` lists matching keys, one per line, using the search mode.
+/// * `GET /keys/<key>` returns the value as GET prints it, with its type in `X-Smirk-Type`.
+/// * `PUT /keys/<key>` stores the body, parsed into `X-Smirk-Type` (String if absent), with an
+///   optional `X-Smirk-TTL`. Bodies over 512 MiB are refused with a 413.
+/// * `DELETE /keys/<key>` deletes the key.
+pub fn serve(port: u16, threadsafe_server_data: Arc<Mutex<SmirkMap>>, state: Arc<SmirkState>) {
+    let server = match Server::http(format!("127.0.0.1:{}", port)) {
+        Ok(server) => server,
+        Err(e) => {
+            log::error!("Failed to bind the HTTP API to port {}: {}", port, e);
+            return;
+        }
+    };
+    log::info!("HTTP API listening on port {}", port);
+    for mut request in server.incoming_requests() {
+        let response = handle_request(&mut request, &threadsafe_server_data, &state);
+        if let Err(e) = request.respond(response) {
+            log::warn!("Error answering an HTTP request: {}", e);
+        }
+    }
+}
+
+fn handle_request(request: &mut Request, threadsafe_server_data: &Mutex<SmirkMap>, state: &SmirkState) -> HttpResponse {
+    if !state.startup.is_ready() {
+        return reply(503, "smirk is loading the dataset in memory.\n");
+    }
+    let namespace = match admit(request, state) {
+        Ok(namespace) => namespace,
+        Err(response) => return response
+    };
+    let url = request.url().to_string();
+    let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+    let key = match path.strip_prefix("/keys") {
+        Some("") | Some("/") => None,
+        Some(rest) if rest.starts_with('/') => Some(percent_decode(&rest[1..])),
+        _ => return reply(404, "Not found.\n")
+    };
+    let command = match (request.method(), &key) {
+        (Method::Get, None) => "KEYS",
+        (Method::Get, Some(_)) => "GET",
+        (Method::Put, Some(_)) => "SET",
+        (Method::Delete, Some(_)) => "DEL",
+        _ => return reply(405, "Method not allowed.\n")
+    };
+    {
+        let config = state.config.read().unwrap();
+        let renamed = config.renamed_commands.iter().any(|rename| rename.command == command);
+        if renamed || config.disabled_commands.iter().any(|disabled| disabled == command) {
+            return reply(403, &format!("{} is disabled.\n", command));
+        }
+    }
+    let key = key.map(|key| format!("{}{}", namespace, key));
+    if let Some(redirect) = key.as_ref().and_then(|key| crate::slot_redirect(&state.cluster, &[key])) {
+        let status = if redirect.starts_with("MOVED") { 421 } else { 503 };
+        return reply(status, &redirect);
+    }
+    // Messages name the key as it's stored, so the namespace is taken back off before they're sent.
+    let shown = |message: &SmirkMessages| match &key {
+        Some(key) => message.to_string().replace(&format!("\"{}\"", key), &format!("\"{}\"", &key[namespace.len()..])),
+        None => message.to_string()
+    };
+
+    match (request.method(), key.clone()) {
+        (Method::Get, None) => {
+            let pattern = query
+                .split('&')
+                .find_map(|pair| pair.strip_prefix("pattern="))
+                .map(percent_decode);
+            list_keys(&threadsafe_server_data.lock().unwrap(), pattern, &namespace)
+        }
+        (Method::Get, Some(key)) => {
+            through_store(state, &mut threadsafe_server_data.lock().unwrap(), &[&key], |smirk_map| {
+                crate::record_access(state, &[&key], false);
+                let value = match smirk_map.get_as_string(&key) {
+                    Ok(value) => value,
+                    Err(SmirkMessages::NullValue(_)) => String::from("(null)"),
+                    Err(e @ SmirkMessages::KeyNotFound(_)) => return reply(404, &shown(&e)),
+                    Err(e) => return reply(500, &shown(&e))
+                };
+                let type_name = smirk_map.get_record(&key).map(|record| record.desired_type_name.clone()).unwrap_or_default();
+                smirk_map.touch(std::slice::from_ref(&key));
+                let response = reply(200, &format!("{}\n", value));
+                match Header::from_bytes(TYPE_HEADER, type_name) {
+                    Ok(header) => response.with_header(header),
+                    Err(_) => response
+                }
+            })
+        }
+        (Method::Put, Some(key)) => {
+            let type_name = header(request, TYPE_HEADER).unwrap_or("String").to_string();
+            let ttl = match header(request, TTL_HEADER).map(|ttl| ttl.parse::<u64>()) {
+                None => None,
+                Some(Ok(ttl)) => Some(ttl),
+                Some(Err(_)) => return reply(400, &format!("Invalid {} header.\n", TTL_HEADER))
+            };
+            let too_large = || reply(413, &format!("The request body is over {} bytes.\n", MAX_BODY_BYTES));
+            if request.body_length().is_some_and(|length| length > MAX_BODY_BYTES) {
+                return too_large();
+            }
+            let mut value = Vec::new();
+            if let Err(e) = request.as_reader().take(MAX_BODY_BYTES as u64 + 1).read_to_end(&mut value) {
+                return reply(400, &format!("Couldn't read the request body: {}.\n", e));
+            }
+            if value.len() > MAX_BODY_BYTES {
+                return too_large();
+            }
+
+            let stored = through_store(state, &mut threadsafe_server_data.lock().unwrap(), &[&key], |smirk_map| {
+                let existed = smirk_map.exists(&key);
+                smirk_map.set_typed(&key, value, &type_name).map(|message| {
+                    if ttl.is_some() {
+                        smirk_map.set_ttl(&key, &ttl);
+                    }
+                    (existed, message)
+                })
+            });
+            crate::record_access(state, &[&key], stored.is_ok());
+            match stored {
+                Ok((existed, message)) => {
+                    state.blocking.notify();
+                    reply(if existed { 200 } else { 201 }, &shown(&message))
+                }
+                Err(e) => reply(400, &shown(&e))
+            }
+        }
+        (Method::Delete, Some(key)) => {
+            let deleted = through_store(state, &mut threadsafe_server_data.lock().unwrap(), &[&key], |smirk_map| {
+                let deleted = smirk_map.del(&key);
+                state.blocking.wake_removed(|key| !smirk_map.exists(key));
+                deleted
+            });
+            crate::record_access(state, &[&key], deleted > 0);
+            match deleted {
+                0 => reply(404, &shown(&SmirkMessages::KeyNotFound(key.clone()))),
+                _ => reply(200, "OK\n")
+            }
+        }
+        _ => reply(405, "Method not allowed.\n")
+    }
+}
+
+/// Checks the request's address and credentials, returning the namespace its keys are in.
+fn admit(request: &Request, state: &SmirkState) -> Result<String, HttpResponse> {
+    let config = state.config.read().unwrap();
+    let ip = request.remote_addr().map(|address| address.ip());
+    if !ip.is_none_or(|ip| smirk_access::allowed(ip, &config.allow_ip, &config.deny_ip)) {
+        return Err(reply(403, "Your address isn't allowed.\n"));
+    }
+    if config.users.is_empty() {
+        return Ok(String::new());
+    }
+    let credentials = header(request, "Authorization")
+        .and_then(|value| value.strip_prefix("Basic "))
+        .and_then(|encoded| base64_decode(encoded.trim()))
+        .map(|decoded| String::from_utf8_lossy(&decoded).to_string());
+    let user = credentials
+        .as_deref()
+        .and_then(|credentials| credentials.split_once(':'))
+        .and_then(|(name, password)| smirk_auth::authenticate(&config.users, name, password));
+    match user {
+        Some(user) => Ok(user.namespace.clone()),
+        None => {
+            let response = reply(401, "Authentication required.\n");
+            Err(match Header::from_bytes("WWW-Authenticate", "Basic realm=\"smirk\"") {
+                Ok(header) => response.with_header(header),
+                Err(_) => response
+            })
+        }
+    }
+}
+
+/// Lists the keys in `namespace` matching `pattern`, or every key without one.
+fn list_keys(smirk_map: &SmirkMap, pattern: Option<String>, namespace: &str) -> HttpResponse {
+    let pattern = match (pattern, smirk_map.search_mode) {
+        (Some(pattern), _) => pattern,
+        (None, SmirkSearchMode::Glob) => String::from("*"),
+        (None, SmirkSearchMode::Regex) => String::from(".*"),
+        (None, SmirkSearchMode::Trie) => String::new()
+    };
+    let mut keys = match crate::matching_keys(smirk_map, &KeyPattern::from(pattern.as_str()), namespace) {
+        Ok(keys) => keys,
+        Err(e) => return reply(400, &format!("{}\n", e))
+    };
+    keys.sort();
+    reply(200, &keys.iter().map(|key| format!("{}\n", &key[namespace.len()..])).collect::<String>())
+}
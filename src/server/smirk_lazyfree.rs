@@ -0,0 +1,27 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::Receiver;
+
+use smirk::core::record::SharedValue;
+
+use crate::smirk_state::SmirkState;
+
+/// How many of the values the map sent off have been dropped.
+#[derive(Default)]
+pub struct SmirkLazyFree {
+    freed: AtomicU64
+}
+
+impl SmirkLazyFree {
+    pub fn freed(&self) -> u64 {
+        self.freed.load(Ordering::Relaxed)
+    }
+}
+
+/// Drops the big overwritten values the map sends, so the map's lock isn't held while they're
+/// freed. Runs until the map goes away.
+pub fn run(values: Receiver<SharedValue>, state: &SmirkState) {
+    for value in values {
+        drop(value);
+        state.lazy_free.freed.fetch_add(1, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,67 @@
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use log::{LevelFilter, Log, Metadata, Record};
+
+/// Writes log lines to stderr, or to a file when one is configured.
+struct SmirkLogger {
+    file: Option<Mutex<File>>
+}
+
+impl Log for SmirkLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let line = format!(
+            "{}.{:03} {:<5} {}\n",
+            now.as_secs(),
+            now.subsec_millis(),
+            record.level(),
+            record.args()
+        );
+        match &self.file {
+            Some(file) => {
+                let _ = file.lock().unwrap().write_all(line.as_bytes());
+            }
+            None => {
+                let _ = std::io::stderr().write_all(line.as_bytes());
+            }
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(file) = &self.file {
+            let _ = file.lock().unwrap().flush();
+        }
+    }
+}
+
+/// Parses a log level name such as `info` or `DEBUG`.
+pub fn parse_level(level: &str) -> Option<LevelFilter> {
+    level.parse::<LevelFilter>().ok()
+}
+
+/// Installs the global logger. Logs go to stderr unless `log_file` is given.
+pub fn init(level: LevelFilter, log_file: &Option<String>) -> Result<(), String> {
+    let file = match log_file {
+        Some(path) => Some(Mutex::new(
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(|e| format!("Couldn't open log file \"{}\": {}", path, e))?
+        )),
+        None => None
+    };
+    log::set_boxed_logger(Box::new(SmirkLogger { file })).map_err(|e| e.to_string())?;
+    log::set_max_level(level);
+    Ok(())
+}
@@ -0,0 +1,101 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+
+use smirk::core::smirk_map::SmirkMap;
+
+/// A one-time change to the keyspace, applied at startup.
+#[derive(Debug, PartialEq)]
+pub enum Migration {
+    /// Moves every key starting with the first prefix under the second one.
+    RenamePrefix(String, String),
+    /// Converts every key under a prefix whose User-Type is the first type into the second type.
+    Retype(String, String, String)
+}
+
+impl Migration {
+    /// Applies the migration, returning how many keys it changed and the errors for keys it couldn't.
+    pub fn apply(&self, smirk_map: &mut SmirkMap) -> (usize, Vec<String>) {
+        let mut changed = 0;
+        let mut errors = Vec::new();
+        match self {
+            Migration::RenamePrefix(from, to) => {
+                let keys: Vec<String> = smirk_map.map.keys().filter(|k| k.starts_with(from.as_str())).cloned().collect();
+                for key in keys {
+                    let renamed = format!("{}{}", to, &key[from.len()..]);
+                    match smirk_map.rename(&key, &renamed) {
+                        Ok(()) => changed += 1,
+                        Err(e) => errors.push(e.to_string())
+                    }
+                }
+            }
+            Migration::Retype(prefix, from_type, to_type) => {
+                let keys: Vec<String> = smirk_map.map
+                    .iter()
+                    .filter(|(k, record)| k.starts_with(prefix.as_str()) && &record.desired_type_name == from_type)
+                    .map(|(k, _)| k.clone())
+                    .collect();
+                for key in keys {
+                    match smirk_map.cast(&key, to_type) {
+                        Ok(_) => changed += 1,
+                        Err(e) => errors.push(e.to_string())
+                    }
+                }
+            }
+        }
+        (changed, errors)
+    }
+}
+
+/// Parses one line of a migrations file.
+///
+/// Lines look like `<id> RENAMEPREFIX <from> <to>` or `<id> RETYPE <prefix> <from_type> <to_type>`.
+fn parse_line(line: &str) -> Result<(String, Migration), String> {
+    let tokens: Vec<&str> = line.split_whitespace().collect();
+    match tokens.as_slice() {
+        [id, op, from, to] if op.eq_ignore_ascii_case("RENAMEPREFIX") => {
+            Ok((id.to_string(), Migration::RenamePrefix(from.to_string(), to.to_string())))
+        }
+        [id, op, prefix, from_type, to_type] if op.eq_ignore_ascii_case("RETYPE") => {
+            Ok((id.to_string(), Migration::Retype(prefix.to_string(), from_type.to_string(), to_type.to_string())))
+        }
+        _ => Err(format!("Invalid migration \"{}\"", line))
+    }
+}
+
+/// Runs every migration in `path` that hasn't been applied yet.
+///
+/// Applied migration ids are appended to `<path>.applied` so they never run twice.
+/// A migration that fails for any key is left unrecorded so it runs again on the next start.
+pub fn run_migrations(path: &str, smirk_map: &mut SmirkMap) -> Result<(), String> {
+    let contents = fs::read_to_string(path).map_err(|e| format!("Couldn't read migrations file \"{}\": {}", path, e))?;
+    let applied_path = format!("{}.applied", path);
+    let applied = fs::read_to_string(&applied_path).unwrap_or_default();
+    let applied: Vec<&str> = applied.lines().map(|l| l.trim()).collect();
+
+    for line in contents.lines().map(|l| l.trim()) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (id, migration) = parse_line(line)?;
+        if applied.contains(&id.as_str()) {
+            continue;
+        }
+
+        let (changed, errors) = migration.apply(smirk_map);
+        if errors.is_empty() {
+            let mut applied_file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&applied_path)
+                .map_err(|e| format!("Couldn't record migration \"{}\": {}", id, e))?;
+            writeln!(applied_file, "{}", id).map_err(|e| format!("Couldn't record migration \"{}\": {}", id, e))?;
+            log::info!("Applied migration \"{}\" to {} keys.", id, changed);
+        } else {
+            log::error!("Migration \"{}\" changed {} keys but failed for {}; it will run again next start.", id, changed, errors.len());
+            for error in errors {
+                log::error!("  {}", error.trim_end());
+            }
+        }
+    }
+    Ok(())
+}
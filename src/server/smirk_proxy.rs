@@ -0,0 +1,83 @@
+use std::io::{self, ErrorKind, Read};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+/// What every PROXY protocol v2 header starts with.
+const V2_SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+/// The longest a v1 header can be, CRLF included.
+const V1_MAX_LENGTH: usize = 107;
+
+fn malformed(reason: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, format!("bad PROXY protocol header: {}", reason))
+}
+
+/// Reads the PROXY protocol header a load balancer sends before anything from the client, and
+/// returns the address of the client it's passing on.
+///
+/// Both the text v1 header and the binary v2 one are understood. `None` means the balancer
+/// connected on its own behalf, to health check the server, or couldn't tell who the client was.
+/// Only the header is read, whatever the client sent after it is left on the socket.
+pub fn read_header(mut stream: impl Read) -> io::Result<Option<SocketAddr>> {
+    // The shortest v1 header, "PROXY UNKNOWN\r\n", is longer than the v2 signature.
+    let mut start = [0; 12];
+    stream.read_exact(&mut start)?;
+    if start == V2_SIGNATURE {
+        read_v2(stream)
+    } else if start.starts_with(b"PROXY ") {
+        let mut header = start.to_vec();
+        while !header.ends_with(b"\r\n") {
+            if header.len() == V1_MAX_LENGTH {
+                return Err(malformed("v1 header is too long"));
+            }
+            let mut byte = [0];
+            stream.read_exact(&mut byte)?;
+            header.push(byte[0]);
+        }
+        parse_v1(&header[..header.len() - 2])
+    } else {
+        Err(malformed("missing"))
+    }
+}
+
+/// Parses `PROXY TCP4|TCP6|UNKNOWN <source> <destination> <source port> <destination port>`.
+fn parse_v1(header: &[u8]) -> io::Result<Option<SocketAddr>> {
+    let header = std::str::from_utf8(header).map_err(|_| malformed("v1 header isn't text"))?;
+    let fields: Vec<&str> = header.split(' ').collect();
+    match fields.as_slice() {
+        ["PROXY", "UNKNOWN", ..] => Ok(None),
+        ["PROXY", "TCP4" | "TCP6", source, _, port, _] => {
+            let ip = source.parse::<IpAddr>().map_err(|_| malformed("invalid source address"))?;
+            let port = port.parse::<u16>().map_err(|_| malformed("invalid source port"))?;
+            Ok(Some(SocketAddr::new(ip, port)))
+        }
+        _ => Err(malformed("unrecognised v1 header"))
+    }
+}
+
+/// Reads the rest of a v2 header: version and command, address family, length, then the addresses.
+fn read_v2(mut stream: impl Read) -> io::Result<Option<SocketAddr>> {
+    let mut fixed = [0; 4];
+    stream.read_exact(&mut fixed)?;
+    let [version_command, family, length @ ..] = fixed;
+    if version_command >> 4 != 2 {
+        return Err(malformed("unsupported version"));
+    }
+    let mut addresses = vec![0; u16::from_be_bytes(length) as usize];
+    stream.read_exact(&mut addresses)?;
+    // LOCAL connections are the balancer's own, and unix socket addresses don't mean anything here.
+    if version_command & 0x0f == 0 {
+        return Ok(None);
+    }
+    let port = |at: usize| u16::from_be_bytes([addresses[at], addresses[at + 1]]);
+    match family >> 4 {
+        1 if addresses.len() >= 12 => {
+            let ip: [u8; 4] = addresses[..4].try_into().unwrap();
+            Ok(Some(SocketAddr::new(Ipv4Addr::from(ip).into(), port(8))))
+        }
+        2 if addresses.len() >= 36 => {
+            let ip: [u8; 16] = addresses[..16].try_into().unwrap();
+            Ok(Some(SocketAddr::new(Ipv6Addr::from(ip).into(), port(32))))
+        }
+        1 | 2 => Err(malformed("addresses are cut short")),
+        _ => Ok(None)
+    }
+}
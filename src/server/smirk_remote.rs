@@ -0,0 +1,53 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use smirk::core::tokenizer::quote;
+
+/// How long MIGRATE waits to connect to the other server, and then for its reply.
+const REMOTE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Sends one command to another smirk server and returns the first line of its reply.
+fn send_command(host: &str, port: u16, command: &str) -> Result<String, String> {
+    let address = (host, port)
+        .to_socket_addrs()
+        .map_err(|e| format!("can't resolve {}:{}: {}", host, port, e))?
+        .next()
+        .ok_or(format!("can't resolve {}:{}", host, port))?;
+    let stream = TcpStream::connect_timeout(&address, REMOTE_TIMEOUT)
+        .map_err(|e| format!("can't connect to {}:{}: {}", host, port, e))?;
+    stream.set_read_timeout(Some(REMOTE_TIMEOUT)).map_err(|e| e.to_string())?;
+    (&stream)
+        .write_all(format!("{}\n", command).as_bytes())
+        .map_err(|e| format!("can't write to {}:{}: {}", host, port, e))?;
+
+    let mut reply = String::new();
+    BufReader::new(&stream)
+        .read_line(&mut reply)
+        .map_err(|e| format!("no reply from {}:{}: {}", host, port, e))?;
+    Ok(reply.trim_end().to_string())
+}
+
+/// Whether host and port reach the server listening on `own_port`, which only listens on the
+/// loopback address.
+pub fn is_local(host: &str, port: u16, own_port: u16) -> bool {
+    port == own_port
+        && (host, port)
+            .to_socket_addrs()
+            .is_ok_and(|mut addresses| addresses.any(|address| address.ip().is_loopback() || address.ip().is_unspecified()))
+}
+
+/// Recreates a record on another server from its DUMP blob, through RESTORE.
+///
+/// The target refuses keys it already has unless `replace` is set.
+pub fn restore_on(host: &str, port: u16, key: &str, ttl: Option<u64>, blob: &str, replace: bool) -> Result<(), String> {
+    let mut command = format!("RESTORE {} {} {}", quote(key), ttl.unwrap_or(0), blob);
+    if replace {
+        command.push_str(" REPLACE");
+    }
+    match send_command(host, port, &command)? {
+        reply if reply == "OK" => Ok(()),
+        reply if reply.is_empty() => Err(format!("{}:{} rejected the RESTORE", host, port)),
+        reply => Err(format!("{}:{} replied: {}", host, port, reply.trim_end_matches('.')))
+    }
+}
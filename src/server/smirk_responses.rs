@@ -0,0 +1,104 @@
+use std::io::{self, ErrorKind, IoSlice, Write};
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
+
+use smirk::core::record::SharedValue;
+
+/// Values shorter than this are cheaper to copy into the buffer than to write from their own slice.
+const VERBATIM_MIN: usize = 4096;
+
+/// A connection's replies waiting to be written.
+///
+/// Replies are written into the buffer it derefs to. Large binary and String values can instead
+/// be queued as they're stored in the map, and go out in the same vectored write as the replies
+/// around them without ever being copied.
+#[derive(Default)]
+pub struct Responses {
+    buffer: Vec<u8>,
+    /// Queued values, each with the offset in `buffer` it's written at.
+    values: Vec<(usize, SharedValue)>
+}
+
+impl Responses {
+    /// Queues a binary or String value as a reply line, returning false if it's another type
+    /// or small enough to just copy.
+    pub fn push_verbatim(&mut self, value: &SharedValue) -> bool {
+        match verbatim(value) {
+            Some(bytes) if bytes.len() >= VERBATIM_MIN => {
+                self.values.push((self.buffer.len(), Arc::clone(value)));
+                self.buffer.push(b'\n');
+                true
+            }
+            _ => false
+        }
+    }
+
+    /// Replaces every `from` in the replies written since `start` with `to`. Replies with a queued
+    /// value are left alone, since the value is the client's own data.
+    pub fn replace_since(&mut self, start: usize, from: &[u8], to: &[u8]) {
+        if from.is_empty() || self.values.iter().any(|(at, _)| *at >= start) {
+            return;
+        }
+        let mut replaced = Vec::with_capacity(self.buffer.len() - start);
+        let mut rest = &self.buffer[start..];
+        while let Some(at) = rest.windows(from.len()).position(|window| window == from) {
+            replaced.extend_from_slice(&rest[..at]);
+            replaced.extend_from_slice(to);
+            rest = &rest[at + from.len()..];
+        }
+        replaced.extend_from_slice(rest);
+        self.buffer.truncate(start);
+        self.buffer.extend_from_slice(&replaced);
+    }
+
+    /// Writes every waiting reply and empties the buffer, even if the write fails.
+    pub fn write_to(&mut self, writer: &mut impl Write) -> io::Result<()> {
+        let mut slices = Vec::with_capacity(self.values.len() * 2 + 1);
+        let mut start = 0;
+        for (at, value) in &self.values {
+            slices.push(IoSlice::new(&self.buffer[start..*at]));
+            slices.push(IoSlice::new(verbatim(value).unwrap_or_default()));
+            start = *at;
+        }
+        slices.push(IoSlice::new(&self.buffer[start..]));
+        slices.retain(|slice| !slice.is_empty());
+        let written = write_all_vectored(writer, &mut slices);
+        self.buffer.clear();
+        self.values.clear();
+        written
+    }
+}
+
+impl Deref for Responses {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Vec<u8> {
+        &self.buffer
+    }
+}
+
+impl DerefMut for Responses {
+    fn deref_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.buffer
+    }
+}
+
+fn verbatim(value: &SharedValue) -> Option<&[u8]> {
+    value
+        .downcast_ref::<Vec<u8>>()
+        .map(Vec::as_slice)
+        .or_else(|| value.downcast_ref::<String>().map(String::as_bytes))
+}
+
+/// `Write::write_all_vectored` isn't stable yet.
+fn write_all_vectored(writer: &mut impl Write, mut slices: &mut [IoSlice<'_>]) -> io::Result<()> {
+    while !slices.is_empty() {
+        match writer.write_vectored(slices) {
+            Ok(0) => return Err(io::Error::new(ErrorKind::WriteZero, "failed to write whole buffer")),
+            Ok(written) => IoSlice::advance_slices(&mut slices, written),
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e)
+        }
+    }
+    Ok(())
+}
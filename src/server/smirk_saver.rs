@@ -0,0 +1,124 @@
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use smirk::core::record::{Record, SharedValue};
+use smirk::core::smirk_map::SmirkMap;
+use smirk::core::snapshot::{self, SnapshotFormat};
+
+use crate::smirk_state::SmirkState;
+
+/// Save the dataset once `seconds` have passed since the last save, if at least `changes` writes
+/// happened in that time. Written `<seconds> <changes>`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SavePoint {
+    pub seconds: u64,
+    pub changes: u64
+}
+
+impl FromStr for SavePoint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_whitespace().map(str::parse::<u64>).collect::<Vec<_>>()[..] {
+            [Ok(seconds), Ok(changes)] => Ok(SavePoint { seconds, changes }),
+            _ => Err(format!("Invalid save point \"{}\", expected <seconds> <changes>", s))
+        }
+    }
+}
+
+/// Parses any number of save points run together, as CONFIG SET save takes them, e.g.
+/// `900 1 60 10000`. An empty string means none.
+pub fn parse_save_points(s: &str) -> Result<Vec<SavePoint>, String> {
+    let numbers: Vec<&str> = s.split_whitespace().collect();
+    if !numbers.len().is_multiple_of(2) {
+        return Err(format!("Invalid save points \"{}\", expected pairs of <seconds> <changes>", s));
+    }
+    numbers.chunks(2).map(|pair| pair.join(" ").parse()).collect()
+}
+
+pub fn format_save_points(save_points: &[SavePoint]) -> String {
+    save_points.iter().map(|point| format!("{} {}", point.seconds, point.changes)).collect::<Vec<String>>().join(" ")
+}
+
+/// When the dataset was last saved, and how many changes the map had made by then.
+pub struct SmirkSaver {
+    last: Mutex<(Instant, u64)>
+}
+
+impl Default for SmirkSaver {
+    fn default() -> Self {
+        Self { last: Mutex::new((Instant::now(), 0)) }
+    }
+}
+
+impl SmirkSaver {
+    /// Records a save of the map as it was after `changes` changes.
+    pub fn saved(&self, changes: u64) {
+        let mut last = self.last.lock().unwrap();
+        // Saves can finish out of order, and an older capture doesn't make newer changes safe.
+        *last = (Instant::now(), changes.max(last.1));
+    }
+
+    /// How many of the map's `changes` haven't been saved yet.
+    pub fn unsaved(&self, changes: u64) -> u64 {
+        changes.saturating_sub(self.last.lock().unwrap().1)
+    }
+
+    /// Whether any save point has been reached, now that the map has made `changes` changes.
+    fn due(&self, save_points: &[SavePoint], changes: u64) -> bool {
+        let (at, saved) = *self.last.lock().unwrap();
+        let elapsed = at.elapsed().as_secs();
+        let unsaved = changes.saturating_sub(saved);
+        save_points.iter().any(|point| elapsed >= point.seconds && unsaved >= point.changes && unsaved > 0)
+    }
+}
+
+/// Writes records taken with `snapshot::capture` to the save file, returning how many there were.
+/// A save that fails partway leaves the last good one in place.
+pub fn write(state: &SmirkState, records: Vec<(String, Record<SharedValue>)>, changes: u64) -> Result<usize, String> {
+    let (path, keys) = {
+        let config = state.config.read().unwrap();
+        (config.save_file.clone(), config.snapshot_keys.clone())
+    };
+    let count = records.len();
+    snapshot::write_snapshot(&path, SnapshotFormat::from_path(&path), snapshot::export_records(records), &keys)?;
+    state.saver.saved(changes);
+    Ok(count)
+}
+
+/// Saves the dataset without blocking writers for the whole save: the map is only locked while
+/// its records are copied out, and copying them just shares their values.
+pub fn save(threadsafe_server_data: &Mutex<SmirkMap>, state: &SmirkState) -> Result<usize, String> {
+    let (records, changes) = {
+        let smirk_map = threadsafe_server_data.lock().unwrap();
+        (snapshot::capture(&smirk_map), smirk_map.changes)
+    };
+    write(state, records, changes)
+}
+
+/// Checks the save points every second, forever, saving whenever one is reached.
+pub fn run(threadsafe_server_data: &Mutex<SmirkMap>, state: &SmirkState) {
+    loop {
+        sleep(Duration::from_secs(1));
+        let save_points = state.config.read().unwrap().save_points.clone();
+        if save_points.is_empty() {
+            continue;
+        }
+        let changes = threadsafe_server_data.lock().unwrap().changes;
+        if !state.saver.due(&save_points, changes) {
+            continue;
+        }
+        let started = Instant::now();
+        match save(threadsafe_server_data, state) {
+            Ok(count) => log::info!("Saved {} keys in {:.2}s.", count, started.elapsed().as_secs_f64()),
+            Err(e) => {
+                // Counted as a save for timing, so a failing disk is retried at the next save
+                // point rather than every second.
+                log::error!("Couldn't save the dataset: {}", e);
+                state.saver.saved(0);
+            }
+        }
+    }
+}
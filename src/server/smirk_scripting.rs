@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Scope, AST};
+
+use smirk::core::smirk_map::SmirkMap;
+use smirk::core::smirk_messages::SmirkMessages;
+
+/// How many script operations run between checks of the time limit.
+const TIME_CHECK_INTERVAL: u64 = 1024;
+
+/// The longest string a script may build, in bytes.
+const MAX_STRING_SIZE: usize = 16 * 1024 * 1024;
+/// The most items an array or object map in a script may hold.
+const MAX_COLLECTION_SIZE: usize = 1024 * 1024;
+/// How deep script functions may call each other, so recursion fails before the thread's stack does.
+const MAX_CALL_LEVELS: usize = 64;
+
+/// The SHA1 a script is cached under, as 40 hex digits.
+pub fn script_sha(source: &str) -> String {
+    sha1_smol::Sha1::from(source).digest().to_string()
+}
+
+/// An engine without `eval` or `print` output. Rhai has no file or network access to begin with.
+/// Strings, arrays, maps and call depth are capped so a script can't take the server's memory or
+/// stack before the time limit stops it.
+fn sandboxed_engine() -> Engine {
+    let mut engine = Engine::new();
+    engine.disable_symbol("eval");
+    engine.set_max_string_size(MAX_STRING_SIZE);
+    engine.set_max_array_size(MAX_COLLECTION_SIZE);
+    engine.set_max_map_size(MAX_COLLECTION_SIZE);
+    engine.set_max_call_levels(MAX_CALL_LEVELS);
+    engine.on_print(|_| {});
+    engine.on_debug(|_, _, _| {});
+    engine
+}
+
+/// Renders a script value for the reply, `()` as `(null)` like a null GET.
+fn render(value: &Dynamic) -> String {
+    if value.is_unit() {
+        return String::from("(null)");
+    }
+    value.to_string()
+}
+
+/// Compiled scripts from SCRIPT LOAD and EVAL, cached by the SHA1 of their source for EVALSHA.
+#[derive(Default)]
+pub struct SmirkScripts {
+    scripts: HashMap<String, AST>
+}
+
+impl SmirkScripts {
+    /// Compiles and caches a script, returning its SHA1.
+    pub fn load(&mut self, source: &str) -> Result<String, String> {
+        let sha = script_sha(source);
+        if !self.scripts.contains_key(&sha) {
+            let ast = sandboxed_engine().compile(source).map_err(|e| format!("Script error: {}", e))?;
+            self.scripts.insert(sha.clone(), ast);
+        }
+        Ok(sha)
+    }
+
+    pub fn get(&self, sha: &str) -> Option<AST> {
+        self.scripts.get(&sha.to_lowercase()).cloned()
+    }
+
+    pub fn exists(&self, sha: &str) -> bool {
+        self.scripts.contains_key(&sha.to_lowercase())
+    }
+
+    pub fn flush(&mut self) {
+        self.scripts.clear();
+    }
+}
+
+/// The map, moved into a shared cell while a script runs, since Rhai functions must be 'static.
+///
+/// It's put back when this is dropped, so a script that panics doesn't take the dataset with it.
+struct LentMap<'a> {
+    home: &'a mut SmirkMap,
+    shared: Arc<Mutex<SmirkMap>>
+}
+
+impl<'a> LentMap<'a> {
+    fn new(home: &'a mut SmirkMap) -> LentMap<'a> {
+        let empty = SmirkMap::new(home.search_mode);
+        let shared = Arc::new(Mutex::new(std::mem::replace(home, empty)));
+        LentMap { home, shared }
+    }
+}
+
+impl Drop for LentMap<'_> {
+    fn drop(&mut self) {
+        // A panic inside one of the script's functions poisons the lock, but the map is still whole.
+        let mut shared = self.shared.lock().unwrap_or_else(PoisonError::into_inner);
+        std::mem::swap(self.home, &mut shared);
+    }
+}
+
+/// Runs a compiled script against the map, with `KEYS` and `ARGV` arrays in scope.
+///
+/// Scripts see `get(key)`, `set(type, key, value)`, `del(key)` and `exists(key)`. The caller holds
+/// the map's lock for the whole run, so nothing else observes the script half done. A script
+/// still running after `time_limit` is stopped, keeping whatever it already wrote.
+///
+/// Scripts run in the caller's `namespace`: the keys they name, and those in `KEYS`, are
+/// relative to it, so a script can't reach outside it either.
+///
+/// # Returns
+///
+/// * The script's result as reply lines, one per element for arrays.
+pub fn eval_script(
+    smirk_map: &mut SmirkMap,
+    ast: &AST,
+    keys: &[String],
+    args: &[String],
+    namespace: &str,
+    time_limit: Duration
+) -> Result<Vec<String>, String> {
+    let lent = LentMap::new(smirk_map);
+    let shared = &lent.shared;
+    let mut engine = sandboxed_engine();
+
+    let map = shared.clone();
+    let ns = namespace.to_string();
+    engine.register_fn("get", move |key: &str| -> Result<Dynamic, Box<EvalAltResult>> {
+        match map.lock().unwrap().get_as_string(&format!("{}{}", ns, key)) {
+            Ok(value) => Ok(value.into()),
+            Err(SmirkMessages::KeyNotFound(_)) | Err(SmirkMessages::NullValue(_)) => Ok(Dynamic::UNIT),
+            Err(e) => Err(e.to_string().trim_end().into())
+        }
+    });
+    let map = shared.clone();
+    let ns = namespace.to_string();
+    engine.register_fn("set", move |type_name: &str, key: &str, value: &str| -> Result<(), Box<EvalAltResult>> {
+        map.lock()
+            .unwrap()
+            .set_typed(&format!("{}{}", ns, key), value.as_bytes().to_vec(), &type_name.to_string())
+            .map(|_| ())
+            .map_err(|e| e.to_string().trim_end().into())
+    });
+    let map = shared.clone();
+    let ns = namespace.to_string();
+    engine.register_fn("del", move |key: &str| map.lock().unwrap().del(&format!("{}{}", ns, key)) as i64);
+    let map = shared.clone();
+    let ns = namespace.to_string();
+    engine.register_fn("exists", move |key: &str| map.lock().unwrap().exists(&format!("{}{}", ns, key)));
+
+    let deadline = Instant::now() + time_limit;
+    engine.on_progress(move |operations| {
+        if operations.is_multiple_of(TIME_CHECK_INTERVAL) && Instant::now() > deadline {
+            return Some(Dynamic::UNIT);
+        }
+        None
+    });
+
+    let mut scope = Scope::new();
+    let keys = keys.iter().map(|key| key.strip_prefix(namespace).unwrap_or(key).to_string());
+    scope.push("KEYS", keys.map(Dynamic::from).collect::<Array>());
+    scope.push("ARGV", args.iter().cloned().map(Dynamic::from).collect::<Array>());
+    // A panic is turned into a script error, so the client's thread doesn't die holding the
+    // server's map lock.
+    let result = panic::catch_unwind(AssertUnwindSafe(|| engine.eval_ast_with_scope::<Dynamic>(&mut scope, ast)));
+
+    drop(engine);
+    drop(lent);
+
+    match result.map_err(|_| String::from("Script error: the script panicked"))? {
+        Ok(value) if value.is_array() => Ok(value.cast::<Array>().iter().map(render).collect()),
+        Ok(value) => Ok(vec![render(&value)]),
+        Err(e) if matches!(*e, EvalAltResult::ErrorTerminated(..)) => {
+            Err(format!("Script exceeded the time limit of {} ms", time_limit.as_millis()))
+        }
+        Err(e) => Err(format!("Script error: {}", e))
+    }
+}
@@ -0,0 +1,68 @@
+use std::fs;
+
+use smirk::core::smirk_map::SmirkMap;
+use smirk::core::tokenizer::tokenize;
+
+/// One key from a seed file.
+#[derive(Debug, PartialEq)]
+struct Seed {
+    type_name: String,
+    key: String,
+    value: Vec<u8>,
+    ttl: Option<u64>
+}
+
+/// Parses one line of a seed file.
+///
+/// Lines look like `<type> <key> <value> [EX <seconds>]`, split and quoted the same way commands
+/// are, so values with spaces in them go in quotes.
+fn parse_line(line: &str) -> Result<Seed, String> {
+    let invalid = || format!("Invalid seed \"{}\", expected <type> <key> <value> [EX <seconds>]", line);
+    let tokens = tokenize(line.as_bytes()).map_err(|_| invalid())?;
+    let text = |token: &Vec<u8>| String::from_utf8(token.clone()).map_err(|_| invalid());
+    match tokens.as_slice() {
+        [type_name, key, value] => Ok(Seed { type_name: text(type_name)?, key: text(key)?, value: value.clone(), ttl: None }),
+        [type_name, key, value, ex, ttl] if ex.eq_ignore_ascii_case(b"EX") => {
+            let ttl = text(ttl)?.parse::<u64>().map_err(|_| invalid())?;
+            Ok(Seed { type_name: text(type_name)?, key: text(key)?, value: value.clone(), ttl: Some(ttl) })
+        }
+        _ => Err(invalid())
+    }
+}
+
+/// Stores every key in the seed file at `path` that the map doesn't already have, so keys
+/// imported or changed since aren't put back. Blank lines and lines starting with `#` are skipped.
+///
+/// A file that doesn't parse loads nothing. Values that don't parse as their type are skipped.
+///
+/// # Returns
+///
+/// * How many keys were stored, and the errors for the ones that couldn't be.
+pub fn load_seed(path: &str, smirk_map: &mut SmirkMap) -> Result<(usize, Vec<String>), String> {
+    let contents = fs::read_to_string(path).map_err(|e| format!("Couldn't read seed file \"{}\": {}", path, e))?;
+    let seeds = contents
+        .lines()
+        .map(|l| l.trim())
+        .enumerate()
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(n, line)| parse_line(line).map_err(|e| format!("{} on line {} of \"{}\"", e, n + 1, path)))
+        .collect::<Result<Vec<Seed>, String>>()?;
+
+    let mut stored = 0;
+    let mut errors = Vec::new();
+    for seed in seeds {
+        if smirk_map.exists(&seed.key) {
+            continue;
+        }
+        match smirk_map.set_typed(&seed.key, seed.value, &seed.type_name) {
+            Ok(_) => {
+                if seed.ttl.is_some() {
+                    smirk_map.set_ttl(&seed.key, &seed.ttl);
+                }
+                stored += 1;
+            }
+            Err(e) => errors.push(e.to_string())
+        }
+    }
+    Ok((stored, errors))
+}
@@ -0,0 +1,23 @@
+use std::collections::HashMap;
+
+use smirk::core::command::Command;
+use smirk::core::float_format::FloatFormat;
+
+/// Per-connection state that lives for as long as a client stays connected.
+#[derive(Debug, Default)]
+pub struct SmirkSession {
+    pub client_id: u64,
+    pub float_format: FloatFormat,
+    /// Commands queued since MULTI, or `None` outside a transaction.
+    pub transaction: Option<Vec<Command>>,
+    /// WATCHed keys and the record version each had when watched (`None` if it was missing).
+    pub watched: HashMap<String, Option<u64>>,
+    /// The user the client AUTHed as.
+    pub user: Option<String>,
+    /// Prefix added to every key the client names, empty outside a namespace.
+    pub namespace: String,
+    /// Whether TRACE ON is recording where the time goes for each of the client's commands.
+    pub trace: bool,
+    /// Whether CHECKSUM ON has the client's lines checked and its replies framed with a CRC32.
+    pub checksum: bool
+}
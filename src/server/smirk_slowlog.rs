@@ -0,0 +1,54 @@
+use std::collections::VecDeque;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The longest command text kept in a slow log entry.
+const MAX_COMMAND_LENGTH: usize = 128;
+
+pub struct SlowLogEntry {
+    pub id: u64,
+    pub timestamp: u64,
+    pub duration: Duration,
+    pub client: String,
+    pub command: String
+}
+
+/// A bounded ring buffer of the most recent commands that ran longer than the threshold.
+#[derive(Default)]
+pub struct SmirkSlowLog {
+    entries: VecDeque<SlowLogEntry>,
+    next_id: u64
+}
+
+impl SmirkSlowLog {
+    /// Records a command, dropping the oldest entry once `max_len` entries are held.
+    pub fn record(&mut self, client: &str, command: &str, duration: Duration, max_len: usize) {
+        if max_len == 0 {
+            return;
+        }
+        while self.entries.len() >= max_len {
+            self.entries.pop_back();
+        }
+        let command: String = command.chars().take(MAX_COMMAND_LENGTH).collect();
+        self.entries.push_front(SlowLogEntry {
+            id: self.next_id,
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs(),
+            duration,
+            client: client.to_string(),
+            command
+        });
+        self.next_id += 1;
+    }
+
+    /// Returns up to `count` entries, newest first.
+    pub fn get(&self, count: usize) -> impl Iterator<Item = &SlowLogEntry> {
+        self.entries.iter().take(count)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn reset(&mut self) {
+        self.entries.clear();
+    }
+}
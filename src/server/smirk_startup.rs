@@ -0,0 +1,101 @@
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// Tracks the server's progress through the work it does before serving commands.
+///
+/// The listener is bound before this work starts, so clients can ask for STATUS
+/// while everything else is still being answered with a LOADING reply.
+pub struct SmirkStartup {
+    ready: AtomicBool,
+    phase: Mutex<Phase>,
+    started: Instant,
+    /// Keys of the snapshot being imported parsed so far.
+    imported: AtomicUsize
+}
+
+/// What startup is currently doing, and when it's importing a snapshot, when that started and
+/// how many keys the snapshot has.
+struct Phase {
+    name: String,
+    import: Option<(Instant, usize)>
+}
+
+impl Default for SmirkStartup {
+    fn default() -> Self {
+        Self {
+            ready: AtomicBool::new(false),
+            phase: Mutex::new(Phase { name: String::from("starting"), import: None }),
+            started: Instant::now(),
+            imported: AtomicUsize::new(0)
+        }
+    }
+}
+
+impl SmirkStartup {
+    pub fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::Acquire)
+    }
+
+    pub fn set_ready(&self) {
+        self.ready.store(true, Ordering::Release);
+        log::info!("Ready to accept commands after {:.2}s.", self.started.elapsed().as_secs_f64());
+    }
+
+    /// How long ago the server process started.
+    pub fn uptime(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// Describes what startup is currently doing, e.g. `running migrations`.
+    pub fn set_phase(&self, phase: &str) {
+        log::info!("Startup: {}", phase);
+        *self.phase.lock().unwrap() = Phase { name: phase.to_string(), import: None };
+    }
+
+    /// Moves on to importing a snapshot of `total` keys, counting them in `imported` as they're
+    /// parsed.
+    pub fn start_import(&self, total: usize) {
+        log::info!("Startup: importing snapshot of {} keys", total);
+        self.imported.store(0, Ordering::Relaxed);
+        *self.phase.lock().unwrap() = Phase { name: String::from("importing snapshot"), import: Some((Instant::now(), total)) };
+    }
+
+    /// The counter the import adds to as it parses keys.
+    pub fn imported(&self) -> &AtomicUsize {
+        &self.imported
+    }
+
+    /// How far the import has got, e.g. `keys=1500/6000 eta=3.0s`, or `None` if nothing is
+    /// being imported.
+    pub fn import_progress(&self) -> Option<String> {
+        let import = self.phase.lock().unwrap().import;
+        self.progress(import)
+    }
+
+    /// Describes startup progress, e.g. `loading phase="running migrations" elapsed=2.0s`, with
+    /// `import_progress` after it while a snapshot is imported.
+    pub fn describe(&self) -> String {
+        let elapsed = self.started.elapsed().as_secs_f64();
+        if self.is_ready() {
+            return format!("ready uptime={:.1}s\n", elapsed);
+        }
+        let phase = self.phase.lock().unwrap();
+        let description = format!("loading phase=\"{}\" elapsed={:.1}s", phase.name, elapsed);
+        match self.progress(phase.import) {
+            Some(progress) => format!("{} {}\n", description, progress),
+            None => format!("{}\n", description)
+        }
+    }
+
+    /// The ETA is left out until some keys are in to estimate it from.
+    fn progress(&self, import: Option<(Instant, usize)>) -> Option<String> {
+        let (since, total) = import?;
+        let loaded = self.imported.load(Ordering::Relaxed).min(total);
+        if loaded == 0 {
+            return Some(format!("keys=0/{}", total));
+        }
+        let eta = since.elapsed().as_secs_f64() * (total - loaded) as f64 / loaded as f64;
+        Some(format!("keys={}/{} eta={:.1}s", loaded, total, eta))
+    }
+}
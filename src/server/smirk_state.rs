@@ -0,0 +1,34 @@
+use std::sync::{Mutex, RwLock};
+
+use smirk::core::backing_store::BackingStore;
+
+use crate::smirk_blocking::SmirkBlocking;
+use crate::smirk_clients::SmirkClients;
+use crate::smirk_cluster::SmirkCluster;
+use crate::smirk_config::SmirkConfig;
+use crate::smirk_cursors::SmirkCursors;
+use crate::smirk_lazyfree::SmirkLazyFree;
+use crate::smirk_saver::SmirkSaver;
+use crate::smirk_scripting::SmirkScripts;
+use crate::smirk_slowlog::SmirkSlowLog;
+use crate::smirk_startup::SmirkStartup;
+use crate::smirk_stats::SmirkStats;
+use crate::smirk_trace::SmirkTraces;
+
+/// Server-wide state shared by every connection, alongside the SmirkMap itself.
+pub struct SmirkState {
+    pub config: RwLock<SmirkConfig>,
+    pub cluster: SmirkCluster,
+    pub cursors: Mutex<SmirkCursors>,
+    pub slowlog: Mutex<SmirkSlowLog>,
+    pub stats: Mutex<SmirkStats>,
+    pub traces: Mutex<SmirkTraces>,
+    pub clients: Mutex<SmirkClients>,
+    pub startup: SmirkStartup,
+    pub blocking: SmirkBlocking,
+    pub scripts: Mutex<SmirkScripts>,
+    pub saver: SmirkSaver,
+    pub lazy_free: SmirkLazyFree,
+    /// Where keys are read through from and written through to, if smirk is caching something.
+    pub backing_store: Option<Box<dyn BackingStore>>
+}
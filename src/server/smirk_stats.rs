@@ -0,0 +1,59 @@
+use std::collections::HashMap;
+
+/// How often one key has been read and written.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct KeyStats {
+    pub reads: u64,
+    pub writes: u64
+}
+
+impl KeyStats {
+    pub fn accesses(&self) -> u64 {
+        self.reads + self.writes
+    }
+}
+
+/// Counts the reads and writes commands make to keys, for HOTKEYS and INFO.
+///
+/// The totals are always kept. Counts per key cost memory for every key touched, so they're only
+/// kept while `--track-key-stats` is on.
+#[derive(Default)]
+pub struct SmirkStats {
+    pub totals: KeyStats,
+    keys: HashMap<String, KeyStats>
+}
+
+impl SmirkStats {
+    /// Counts a command's keys as read, or written if the command changed anything.
+    pub fn record(&mut self, keys: &[&String], write: bool, per_key: bool) {
+        let count = |stats: &mut KeyStats| match write {
+            true => stats.writes += 1,
+            false => stats.reads += 1
+        };
+        for key in keys {
+            count(&mut self.totals);
+            if per_key {
+                count(self.keys.entry(key.to_string()).or_default());
+            }
+        }
+    }
+
+    /// The `n` most accessed keys under `prefix`, most accessed first. Keys that no longer
+    /// `exist` are forgotten rather than reported.
+    pub fn hottest(&mut self, n: usize, prefix: &str, exists: impl Fn(&String) -> bool) -> Vec<(String, KeyStats)> {
+        self.keys.retain(|key, _| exists(key));
+        let mut hottest: Vec<(String, KeyStats)> = self.keys
+            .iter()
+            .filter(|(key, _)| key.starts_with(prefix))
+            .map(|(key, stats)| (key.clone(), *stats))
+            .collect();
+        hottest.sort_by(|(a, a_stats), (b, b_stats)| b_stats.accesses().cmp(&a_stats.accesses()).then_with(|| a.cmp(b)));
+        hottest.truncate(n);
+        hottest
+    }
+
+    /// Forgets the counts per key, once tracking them is turned off.
+    pub fn clear_keys(&mut self) {
+        self.keys = HashMap::new();
+    }
+}
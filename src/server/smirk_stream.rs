@@ -0,0 +1,170 @@
+use std::io::{self, BufRead, BufReader, ErrorKind, IoSlice, Read, Write};
+use std::net::{IpAddr, Shutdown, TcpStream};
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+use std::time::{Duration, Instant};
+
+use socket2::{SockRef, TcpKeepalive};
+
+/// A client connection, over TCP or a unix socket. Both are served by the same command loop.
+#[derive(Debug)]
+pub enum SmirkStream {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream)
+}
+
+impl SmirkStream {
+    /// The client's address for logs and CLIENT LIST. Unix clients have no address of their own,
+    /// so they're named after the socket they connected to.
+    pub fn peer(&self) -> String {
+        match self {
+            SmirkStream::Tcp(stream) => stream.peer_addr().map(|a| a.to_string()).unwrap_or_default(),
+            #[cfg(unix)]
+            SmirkStream::Unix(stream) => {
+                let path = stream.local_addr().ok().and_then(|a| a.as_pathname().map(|p| p.display().to_string()));
+                format!("unix:{}", path.unwrap_or_default())
+            }
+        }
+    }
+
+    /// The client's IP address, or `None` for unix clients, which never have one.
+    pub fn ip(&self) -> Option<IpAddr> {
+        match self {
+            SmirkStream::Tcp(stream) => stream.peer_addr().ok().map(|a| a.ip()),
+            #[cfg(unix)]
+            SmirkStream::Unix(_) => None
+        }
+    }
+
+    pub fn try_clone(&self) -> io::Result<SmirkStream> {
+        match self {
+            SmirkStream::Tcp(stream) => stream.try_clone().map(SmirkStream::Tcp),
+            #[cfg(unix)]
+            SmirkStream::Unix(stream) => stream.try_clone().map(SmirkStream::Unix)
+        }
+    }
+
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        match self {
+            SmirkStream::Tcp(stream) => stream.set_read_timeout(timeout),
+            #[cfg(unix)]
+            SmirkStream::Unix(stream) => stream.set_read_timeout(timeout)
+        }
+    }
+
+    /// Turns on TCP keepalive, probing after `idle` without traffic, or turns it off for `None`.
+    /// Unix sockets can't lose their peer silently, so they're left alone.
+    pub fn set_keepalive(&self, idle: Option<Duration>) -> io::Result<()> {
+        match self {
+            SmirkStream::Tcp(stream) => {
+                let socket = SockRef::from(stream);
+                match idle {
+                    Some(idle) => socket.set_tcp_keepalive(&TcpKeepalive::new().with_time(idle)),
+                    None => socket.set_keepalive(false)
+                }
+            }
+            #[cfg(unix)]
+            SmirkStream::Unix(_) => Ok(())
+        }
+    }
+
+    pub fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        match self {
+            SmirkStream::Tcp(stream) => stream.shutdown(how),
+            #[cfg(unix)]
+            SmirkStream::Unix(stream) => stream.shutdown(how)
+        }
+    }
+}
+
+/// How reading a command line ended.
+pub enum LineRead {
+    /// A whole line, or whatever was left when the client closed its side.
+    Line,
+    Closed,
+    /// Nothing arrived within the idle timeout.
+    Idle,
+    /// The line was started but not finished within the read timeout.
+    TimedOut
+}
+
+/// Reads the next command line into `line`, newline included.
+///
+/// `idle` bounds the wait for a command to start and `read` the time from its first byte to its
+/// newline, so a client trickling in a byte at a time can't hold the connection open forever.
+pub fn read_line(reader: &mut BufReader<&SmirkStream>, line: &mut Vec<u8>, idle: Option<Duration>, read: Option<Duration>) -> io::Result<LineRead> {
+    let mut deadline: Option<Instant> = None;
+    loop {
+        if reader.buffer().is_empty() {
+            let timeout = match deadline {
+                None if line.is_empty() => idle,
+                None => None,
+                Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                    Some(left) if !left.is_zero() => Some(left),
+                    _ => return Ok(LineRead::TimedOut)
+                }
+            };
+            reader.get_ref().set_read_timeout(timeout)?;
+        }
+        let available = match reader.fill_buf() {
+            Ok(available) => available,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                return Ok(if line.is_empty() { LineRead::Idle } else { LineRead::TimedOut });
+            }
+            Err(e) => return Err(e)
+        };
+        if available.is_empty() {
+            return Ok(if line.is_empty() { LineRead::Closed } else { LineRead::Line });
+        }
+        let (taken, done) = match available.iter().position(|b| *b == b'\n') {
+            Some(newline) => (newline + 1, true),
+            None => (available.len(), false)
+        };
+        line.extend_from_slice(&available[..taken]);
+        reader.consume(taken);
+        if done {
+            return Ok(LineRead::Line);
+        }
+        if deadline.is_none() {
+            deadline = read.map(|read| Instant::now() + read);
+        }
+    }
+}
+
+impl Read for &SmirkStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            SmirkStream::Tcp(stream) => (&*stream).read(buf),
+            #[cfg(unix)]
+            SmirkStream::Unix(stream) => (&*stream).read(buf)
+        }
+    }
+}
+
+impl Write for &SmirkStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            SmirkStream::Tcp(stream) => (&*stream).write(buf),
+            #[cfg(unix)]
+            SmirkStream::Unix(stream) => (&*stream).write(buf)
+        }
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        match self {
+            SmirkStream::Tcp(stream) => (&*stream).write_vectored(bufs),
+            #[cfg(unix)]
+            SmirkStream::Unix(stream) => (&*stream).write_vectored(bufs)
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            SmirkStream::Tcp(stream) => (&*stream).flush(),
+            #[cfg(unix)]
+            SmirkStream::Unix(stream) => (&*stream).flush()
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::Duration;
+
+/// How many traces are kept before the oldest are dropped.
+const MAX_TRACES: usize = 1024;
+/// The longest command text kept in a trace.
+const MAX_COMMAND_LENGTH: usize = 128;
+
+/// Where the time went for one command from a connection with TRACE ON.
+pub struct Trace {
+    pub id: u64,
+    pub client: String,
+    pub command: String,
+    /// Turning the line into a command.
+    pub parse: Duration,
+    /// Waiting for the map's lock.
+    pub lock_wait: Duration,
+    /// Running the command once it had the lock.
+    pub execute: Duration,
+    /// Writing the replies it went out with to the socket. Pipelined commands share one write,
+    /// and it's unknown until the batch is flushed.
+    pub write: Option<Duration>
+}
+
+/// `<id> <client> parse=<us> lock_wait=<us> execute=<us> write=<us> <command>`, with `write=-`
+/// until the reply has been written.
+impl fmt::Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let write = match self.write {
+            Some(write) => write.as_micros().to_string(),
+            None => String::from("-")
+        };
+        write!(
+            f,
+            "{} {} parse={} lock_wait={} execute={} write={} {}",
+            self.id,
+            self.client,
+            self.parse.as_micros(),
+            self.lock_wait.as_micros(),
+            self.execute.as_micros(),
+            write,
+            self.command
+        )
+    }
+}
+
+/// The most recent traces from every connection with TRACE ON, for TRACE GET and TRACE LIST.
+#[derive(Default)]
+pub struct SmirkTraces {
+    traces: VecDeque<Trace>,
+    next_id: u64
+}
+
+impl SmirkTraces {
+    /// Keeps a trace, dropping the oldest once there are too many, and returns the ID it's given.
+    pub fn record(&mut self, client: &str, command: &str, parse: Duration, lock_wait: Duration, execute: Duration) -> u64 {
+        if self.traces.len() >= MAX_TRACES {
+            self.traces.pop_back();
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        self.traces.push_front(Trace {
+            id,
+            client: client.to_string(),
+            command: command.chars().take(MAX_COMMAND_LENGTH).collect(),
+            parse,
+            lock_wait,
+            execute,
+            write: None
+        });
+        id
+    }
+
+    /// Fills in how long writing the replies to the traces `ids` took, returning the traces.
+    pub fn written(&mut self, ids: &[u64], write: Duration) -> Vec<&Trace> {
+        let mut written = Vec::new();
+        for trace in self.traces.iter_mut().filter(|trace| ids.contains(&trace.id)) {
+            trace.write = Some(write);
+            written.push(&*trace);
+        }
+        written
+    }
+
+    pub fn get(&self, id: u64) -> Option<&Trace> {
+        self.traces.iter().find(|trace| trace.id == id)
+    }
+
+    /// Returns up to `count` traces, newest first.
+    pub fn recent(&self, count: usize) -> impl Iterator<Item = &Trace> {
+        self.traces.iter().take(count)
+    }
+}
@@ -0,0 +1,98 @@
+mod common;
+
+use common::{session, start_server};
+use smirk::core::command::Command;
+
+#[test]
+fn skipmissing_is_a_trailing_flag_on_add() {
+    let parse = |line: &str| Command::from_vec(line.as_bytes().to_vec());
+    assert!(matches!(parse("ADD i32 a b skipmissing"), Ok(Command::Add(_, keys, true)) if keys == ["a", "b"]));
+    assert!(matches!(parse("ADD i32 a b"), Ok(Command::Add(_, _, false))));
+    assert!(parse("ADD i32 SKIPMISSING").is_err());
+    assert!(matches!(parse("SUB i32 a SKIPMISSING"), Ok(Command::Sub(_, keys)) if keys == ["a", "SKIPMISSING"]));
+}
+
+#[test]
+fn arithmetic_errors_name_the_key() {
+    let server = start_server();
+    let replies = session(&server, "SET i32 a 5\nSET string s hi\nSETNULL i32 n\nADD i32 a b\nADD i32 a s\nADD i32 a n\nMUL f64 a\n");
+    assert_eq!(&replies[3..], [
+        "Key \"b\" not found.",
+        "Couldn't downcast the value stored in key \"s\" to type \"i32\".",
+        "Key \"n\" holds a null, which can't be used in arithmetic.",
+        "Couldn't downcast the value stored in key \"a\" to type \"f64\".",
+        "Bye."
+    ]);
+}
+
+#[test]
+fn skipmissing_counts_missing_keys_as_zero() {
+    let server = start_server();
+    let replies = session(&server, "SET i32 a 5\nSET f64 x 1.5\nSET string s hi\nADD i32 a b SKIPMISSING\nADD f64 x y z SKIPMISSING\nADDSTORE i32 d a b SKIPMISSING\nGET d\nADD i32 a s SKIPMISSING\n");
+    assert_eq!(&replies[3..], ["5", "1.5", "5", "5", "Couldn't downcast the value stored in key \"s\" to type \"i32\".", "Bye."]);
+}
+
+#[test]
+fn agg_combines_keys_or_the_matching_ones_of_the_type() {
+    let server = start_server();
+    session(&server, "SET i32 load:a 5\nSET i32 load:b 10\nSET i32 load:c 6\nSET string load:note hi\nSET f64 t:1 1.5\nSET f64 t:2 2.5\nSET i8 big:1 100\nSET i8 big:2 100\n");
+    let replies = session(&server, concat!(
+        "AGG sum i32 load:a load:b\n",
+        "AGG min i32 MATCH load:*\n",
+        "AGG max i32 MATCH load:*\n",
+        "AGG avg i32 MATCH load:*\n",
+        "AGG count i32 MATCH load:*\n",
+        "AGG mean f64 t:1 t:2\n",
+        "AGG max i32 MATCH nothing:*\n",
+        "AGG sum i32 MATCH nothing:*\n",
+        "AGG sum i32 load:a load:note\n",
+        "AGG sum i8 big:1 big:2\n",
+        "AGG avg BigDecimal MATCH nothing:*\n",
+        "AGG median i32 load:a\n"
+    ));
+    assert_eq!(replies, [
+        "15",
+        "5",
+        "10",
+        "7",
+        "3",
+        "2",
+        "No values to aggregate.",
+        "0",
+        "Couldn't downcast the value stored in key \"load:note\" to type \"i32\".",
+        "Cannot add these. It's an overflow.",
+        "No values to aggregate.",
+        "-ERR invalid argument 'median' for 'AGG'",
+        "Bye."
+    ]);
+}
+
+#[test]
+fn division_fails_on_zero_and_overflow_rather_than_panicking() {
+    let server = start_server();
+    let replies = session(&server, concat!(
+        "SET i32 a 7\nSET i32 b 2\nSET i32 z 0\nSET i32 min -2147483648\nSET i32 neg -1\n",
+        "SET f64 x 1\nSET f64 y 0\nSET BigDecimal d 1\nSET BigDecimal e 0\n",
+        "DIV i32 a b\n",
+        "DIV i32 a z\n",
+        "DIV i32 min neg\n",
+        "DIV f64 x y\n",
+        "DIV BigDecimal d e\n",
+        "DIVSTORE i32 q a b\n",
+        "GET i32 q\n",
+        "SUB i32 a b\n",
+        "MUL i32 a b\n"
+    ));
+    assert_eq!(replies[9..], [
+        "3",
+        "Cannot divide by key \"z\". It's zero.",
+        "Cannot divide these. It's an overflow.",
+        "inf",
+        "Cannot divide by key \"e\". It's zero.",
+        "3",
+        "3",
+        "5",
+        "14",
+        "Bye."
+    ]);
+}
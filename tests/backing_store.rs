@@ -0,0 +1,54 @@
+mod common;
+
+use common::{scratch_dir, session, start_server_with};
+use serde_json::json;
+use smirk::core::backing_store::{BackingStore, DirectoryStore};
+
+#[test]
+fn directory_store_keeps_one_record_per_key() {
+    let dir = scratch_dir("store");
+    let store = DirectoryStore::new(dir.to_str().unwrap()).unwrap();
+    let record = json!({"type": "i32", "user_type": "i32", "ttl": null, "value": "7"});
+    let long_key = "k/../".repeat(100);
+
+    assert_eq!(store.load("a").unwrap(), None);
+    store.store("a", &record).unwrap();
+    store.store(&long_key, &record).unwrap();
+    assert_eq!(store.load("a").unwrap(), Some(record.clone()));
+    assert_eq!(store.load(&long_key).unwrap(), Some(record));
+    store.remove("a").unwrap();
+    store.remove("a").unwrap();
+    assert_eq!(store.load("a").unwrap(), None);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn writes_go_through_to_the_directory_and_misses_read_from_it() {
+    let dir = scratch_dir("server");
+    let flags = ["--backing-dir", dir.to_str().unwrap()];
+
+    let server = start_server_with(&flags);
+    session(&server, "SET i32 a 5\nZADD board 1 ann\nSET String gone x\nDEL gone\nMULTI\nSET String queued yes\nEXEC\n");
+    drop(server);
+
+    // A fresh server starts empty, so everything it knows comes from the directory.
+    let server = start_server_with(&flags);
+    let replies = session(&server, "KEYS *\nGET a\nZRANGE board 0 -1\nGET gone\nGET queued\nKEYS * SORT\n");
+    assert_eq!(replies, vec![
+        "No matches for key query \"*\" were found.",
+        "5",
+        "ann",
+        "Key \"gone\" not found.",
+        "yes",
+        "a",
+        "board",
+        "queued",
+        "Bye."
+    ]);
+
+    session(&server, "DEL a\n");
+    drop(server);
+    let server = start_server_with(&flags);
+    assert_eq!(session(&server, "GET a\n"), vec!["Key \"a\" not found.", "Bye."]);
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,58 @@
+mod common;
+
+use common::{session, start_server};
+use smirk::core::bitfield::{self, BitFieldOp, BitFieldType, Overflow};
+
+fn ty(s: &str) -> BitFieldType {
+    s.parse().unwrap()
+}
+
+#[test]
+fn fields_pack_most_significant_bit_first() {
+    let mut bytes = Vec::new();
+    let replies = bitfield::apply(&mut bytes, &[
+        BitFieldOp::Set(ty("u4"), 0, 0xA),
+        BitFieldOp::Set(ty("i4"), 4, -1),
+        BitFieldOp::Get(ty("u8"), 0),
+        BitFieldOp::Get(ty("i64"), 0)
+    ]);
+    assert_eq!(bytes, [0xAF]);
+    assert_eq!(replies, [Some(0), Some(0), Some(0xAF), Some(0xAF << 56)]);
+    assert_eq!(ty("u8").offset("#3"), Ok(24));
+    assert!("u64".parse::<BitFieldType>().is_err());
+    assert!("i0".parse::<BitFieldType>().is_err());
+}
+
+#[test]
+fn overflow_wraps_saturates_or_fails() {
+    let mut bytes = vec![0xFE];
+    let replies = bitfield::apply(&mut bytes, &[
+        BitFieldOp::IncrBy(ty("u8"), 0, 3),
+        BitFieldOp::Overflow(Overflow::Sat),
+        BitFieldOp::IncrBy(ty("i8"), 0, -200),
+        BitFieldOp::Overflow(Overflow::Fail),
+        BitFieldOp::Set(ty("u4"), 0, 16),
+        BitFieldOp::IncrBy(ty("i8"), 0, -1)
+    ]);
+    assert_eq!(replies, [Some(1), Some(-128), None, None]);
+    assert_eq!(bytes, [0x80]);
+}
+
+#[test]
+fn bitfield_command() {
+    let server = start_server();
+    let replies = session(&server, "BITFIELD k GET u8 0\nEXISTS k\nBITFIELD k SET u8 #1 200 INCRBY u8 #1 100 OVERFLOW FAIL INCRBY u8 #1 1000 GET u16 0\nSET string s A\nBITFIELD s SET u1 6 1\nGET s\nBITFIELD k OVERFLOW SAT\n");
+    assert_eq!(replies, [
+        "0",
+        "false",
+        "0",
+        "44",
+        "(null)",
+        "44",
+        "Set key \"s\" successfully. Stored-Type: Vec<u8>, User-Type: string",
+        "0",
+        "C",
+        "-ERR wrong arguments for 'BITFIELD'",
+        "Bye."
+    ]);
+}
@@ -0,0 +1,61 @@
+mod common;
+
+use common::{session, start_server};
+use smirk::core::bloom_filter::BloomFilter;
+
+#[test]
+fn false_positives_stay_near_the_error_rate_it_was_sized_for() {
+    let mut filter = BloomFilter::new(1000, 0.01).unwrap();
+    for i in 0..1000 {
+        filter.add(format!("member:{}", i).as_bytes());
+    }
+    assert!((0..1000).all(|i| filter.contains(format!("member:{}", i).as_bytes())));
+    let false_positives = (0..10000).filter(|i| filter.contains(format!("stranger:{}", i).as_bytes())).count();
+    assert!(false_positives < 200, "{} false positives", false_positives);
+
+    assert!(BloomFilter::new(0, 0.01).is_err());
+    assert!(BloomFilter::new(10, 1.0).is_err());
+    assert!(BloomFilter::new(u64::MAX, 0.01).is_err());
+    assert!(BloomFilter::new(1000, f64::MIN_POSITIVE).is_ok());
+}
+
+#[test]
+fn bloom_filters_are_reserved_added_to_and_checked() {
+    let server = start_server();
+    let replies = session(&server, concat!(
+        "BF.RESERVE seen 0.001 500\n",
+        "BF.RESERVE seen 0.001 500\n",
+        "BF.ADD seen alice\n",
+        "BF.ADD seen alice\n",
+        "BF.EXISTS seen alice\n",
+        "BF.EXISTS seen bob\n",
+        "BF.EXISTS nothing alice\n",
+        "BF.ADD auto x\n",
+        "TYPE auto\n",
+        "SET i32 number 1\n",
+        "BF.ADD number x\n",
+        "BF.RESERVE other 1.5 10\n",
+        "BF.RESERVE other 0.1 0\n",
+        "BF.RESERVE huge 0.000001 18446744073709551615\n",
+        "EXISTS huge\n"
+    ));
+    assert_eq!(&replies[..9], [
+        "OK",
+        "Can't reserve a Bloom filter at key \"seen\": the key already exists.",
+        "1",
+        "0",
+        "1",
+        "0",
+        "0",
+        "1",
+        "Stored-Type: smirk::core::bloom_filter::BloomFilter, User-Type: BloomFilter"
+    ]);
+    assert!(replies[10].starts_with("Couldn't downcast"), "{:?}", replies);
+    assert_eq!(&replies[11..], [
+        "-ERR invalid argument '1.5' for 'BF.RESERVE'",
+        "-ERR invalid argument '0' for 'BF.RESERVE'",
+        "Can't reserve a Bloom filter at key \"huge\": it would need more than the 4294967296 bits a filter may have.",
+        "false",
+        "Bye."
+    ]);
+}
@@ -0,0 +1,39 @@
+mod common;
+
+use common::{session, start_server};
+
+#[test]
+fn cast_converts_values_it_can_and_leaves_the_rest() {
+    let server = start_server();
+    let replies = session(&server, concat!(
+        "SET String s 42\n",
+        "CAST s i64\n",
+        "GET i64 s\n",
+        "SET i64 big 300\n",
+        "CAST big i8\n",
+        "GET i64 big\n",
+        "CAST big BigInt\n",
+        "TYPE big\n",
+        "SET String word hello\n",
+        "CAST word i32\n",
+        "SET f64 f 1.5\n",
+        "CAST f i32\n",
+        "CAST missing i32\n"
+    ));
+    assert_eq!(replies, [
+        "Set key \"s\" successfully. Stored-Type: alloc::string::String, User-Type: String",
+        "Set key \"s\" successfully. Stored-Type: i64, User-Type: i64",
+        "42",
+        "Set key \"big\" successfully. Stored-Type: i64, User-Type: i64",
+        "Casting key \"big\" from \"i64\" to \"i8\" would lose information.",
+        "300",
+        "Set key \"big\" successfully. Stored-Type: num_bigint::bigint::BigInt, User-Type: BigInt",
+        "Stored-Type: num_bigint::bigint::BigInt, User-Type: BigInt",
+        "Set key \"word\" successfully. Stored-Type: alloc::string::String, User-Type: String",
+        "Casting key \"word\" from \"alloc::string::String\" to \"i32\" would lose information.",
+        "Set key \"f\" successfully. Stored-Type: f64, User-Type: f64",
+        "Casting key \"f\" from \"f64\" to \"i32\" would lose information.",
+        "Key \"missing\" not found.",
+        "Bye."
+    ]);
+}
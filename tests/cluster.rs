@@ -0,0 +1,30 @@
+mod common;
+
+use common::{session, start_server_with};
+
+#[test]
+fn keys_in_slots_owned_elsewhere_are_moved() {
+    let server = start_server_with(&["--cluster-slots", "0-8191", "--cluster-node", "8192-16383@127.0.0.1:7001"]);
+    let replies = session(&server, concat!(
+        "CLUSTER KEYSLOT foo\n",
+        "CLUSTER KEYSLOT bar\n",
+        "CLUSTER KEYSLOT {user}:a\n",
+        "CLUSTER KEYSLOT {user}:b\n",
+        "CLUSTER SLOTS\n",
+        "SET i32 foo 1\n",
+        "SET i32 bar 1\n",
+        "GET i32 foo\n"
+    ));
+    assert_eq!(replies, [
+        "12182",
+        "5061",
+        "5474",
+        "5474",
+        &format!("0-8191 127.0.0.1:{} (self)", server.port),
+        "8192-16383 127.0.0.1:7001",
+        "MOVED 12182 127.0.0.1:7001",
+        "Set key \"bar\" successfully. Stored-Type: i32, User-Type: i32",
+        "MOVED 12182 127.0.0.1:7001",
+        "Bye."
+    ]);
+}
@@ -0,0 +1,307 @@
+mod common;
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::Shutdown;
+
+use common::{connect, start_server};
+use smirk::core::command::Command;
+use smirk::core::command_error::CommandError;
+use smirk::core::command_spec::{self, COMMANDS};
+use smirk::core::smirk_search_mode::{KeyOrder, SmirkSearchMode};
+use smirk::core::tokenizer::{quote, tokenize};
+
+fn parse(line: &str) -> Result<Command, CommandError> {
+    Command::from_vec(line.as_bytes().to_vec())
+}
+
+fn tokens(line: &str) -> Vec<String> {
+    tokenize(line.as_bytes()).unwrap().iter().map(|t| String::from_utf8_lossy(t).to_string()).collect()
+}
+
+#[test]
+fn crlf_and_lf_line_endings_parse_the_same() {
+    for line in ["GET i32 counter\n", "GET i32 counter\r\n", "GET i32 counter"] {
+        match parse(line) {
+            Ok(Command::Get(t, k, None)) => assert_eq!((t.as_str(), k.as_str()), ("i32", "counter"), "{:?}", line),
+            other => panic!("{:?} parsed as {:?}", line, other)
+        }
+    }
+}
+
+#[test]
+fn runs_of_spaces_and_tabs_separate_arguments() {
+    assert_eq!(tokens("  SET \t i32   a\t\t5  "), vec!["SET", "i32", "a", "5"]);
+    match parse("set  String\tgreeting   hello    world\r\n") {
+        Ok(Command::Set(t, k, v, None)) => {
+            assert_eq!((t.as_str(), k.as_str()), ("String", "greeting"));
+            assert_eq!(v, b"hello world");
+        }
+        other => panic!("parsed as {:?}", other)
+    }
+}
+
+#[test]
+fn command_names_and_keywords_are_case_insensitive() {
+    assert!(matches!(parse("mode glob"), Ok(Command::Mode(SmirkSearchMode::Glob))));
+    assert!(matches!(parse("Mode Trie\r\n"), Ok(Command::Mode(SmirkSearchMode::Trie))));
+    assert!(matches!(parse("set i32 a 1 ex 10"), Ok(Command::Set(_, _, _, Some(10)))));
+    assert!(matches!(parse("zRange z 0 -1 withscores"), Ok(Command::ZRange(_, 0, -1, true))));
+}
+
+#[test]
+fn blank_lines_are_no_input() {
+    for line in ["", "\n", "\r\n", "  \t \r\n"] {
+        assert!(matches!(parse(line), Err(CommandError::NoInput)), "{:?}", line);
+    }
+}
+
+#[test]
+fn quoted_arguments_keep_whitespace_and_decode_escapes() {
+    assert_eq!(tokens(r#"SET String "my key" "  two  spaces""#), vec!["SET", "String", "my key", "  two  spaces"]);
+    assert_eq!(tokens(r#""a\tb\n\"c\"\\\x41""#), vec!["a\tb\n\"c\"\\A"]);
+    assert_eq!(tokens(r"'it\'s \n raw'"), vec![r"it's \n raw"]);
+    assert_eq!(tokens(r#"'' """#), vec!["", ""]);
+    assert_eq!(tokens(r#"{"a":1}"#), vec![r#"{"a":1}"#]);
+}
+
+#[test]
+fn malformed_quoting_reports_the_offset() {
+    assert!(matches!(tokenize(br#"GET "open"#), Err(CommandError::UnterminatedQuote(4))));
+    assert!(matches!(tokenize(br#"GET "bad \q""#), Err(CommandError::InvalidEscape(9))));
+    assert!(matches!(tokenize(br#"GET "\xZZ""#), Err(CommandError::InvalidEscape(5))));
+    assert!(matches!(tokenize(br#"GET "a"b"#), Err(CommandError::UnexpectedCharacter(7))));
+}
+
+#[test]
+fn quote_round_trips_through_tokenize() {
+    for argument in ["plain", "two words", "", "\"quoted\"", "'single'", "tab\there", "new\nline", "back\\slash"] {
+        assert_eq!(tokens(&quote(argument)), vec![argument]);
+    }
+}
+
+#[test]
+fn telnet_style_client() {
+    let server = start_server();
+    let mut stream = connect(&server);
+
+    // Telnet sends \r\n and people type however many spaces they like.
+    stream.write_all(b"set  i32   a\t7\r\nMODE GLOB\r\nget i32 a\r\nquit\r\n").unwrap();
+
+    let replies: Vec<String> = BufReader::new(stream).lines().map(|l| l.unwrap()).collect();
+    assert_eq!(replies, vec![
+        "Set key \"a\" successfully. Stored-Type: i32, User-Type: i32",
+        "OK",
+        "7",
+        "Bye."
+    ]);
+}
+
+#[test]
+fn netcat_style_client() {
+    let server = start_server();
+    let mut stream = connect(&server);
+
+    // `printf ... | nc` may leave the last line without a newline before closing its side.
+    stream.write_all(b"SET String greeting \"hello  world\"\nGET greeting").unwrap();
+    stream.shutdown(Shutdown::Write).unwrap();
+
+    let mut replies = String::new();
+    stream.read_to_string(&mut replies).unwrap();
+    assert_eq!(replies, "Set key \"greeting\" successfully. Stored-Type: alloc::string::String, User-Type: String\nhello  world\n");
+}
+
+#[test]
+fn parse_errors_name_the_problem() {
+    assert_eq!(parse("FROB a").unwrap_err().to_string(), "unknown command 'FROB'");
+    assert_eq!(parse("SET i32 a").unwrap_err().to_string(), "wrong arguments for 'SET'");
+    assert_eq!(parse("ZADD z one a").unwrap_err().to_string(), "invalid argument 'one' for 'ZADD'");
+    assert_eq!(parse("ZADD z 1 a NaN b").unwrap_err().to_string(), "invalid argument 'NaN' for 'ZADD'");
+    assert_eq!(parse("ZADD z inf a").unwrap_err().to_string(), "invalid argument 'inf' for 'ZADD'");
+    assert_eq!(parse("ZINCRBY z -inf a").unwrap_err().to_string(), "invalid argument '-inf' for 'ZINCRBY'");
+    assert_eq!(parse("TTL a soon").unwrap_err().to_string(), "invalid TTL 'soon', expected a number of seconds");
+    assert_eq!(parse("MODE fuzzy").unwrap_err().to_string(), "invalid search mode 'fuzzy', expected GLOB, REGEX or TRIE");
+}
+
+#[test]
+fn unparseable_commands_get_an_err_reply() {
+    let server = start_server();
+    let mut stream = connect(&server);
+
+    stream.write_all(b"FROB\n\nGET \"open\nSET i32 a\nQUIT\n").unwrap();
+
+    let replies: Vec<String> = BufReader::new(stream).lines().map(|l| l.unwrap()).collect();
+    assert_eq!(replies, vec![
+        "-ERR unknown command 'FROB'",
+        "-ERR unterminated quote starting at offset 4",
+        "-ERR wrong arguments for 'SET'",
+        "Bye."
+    ]);
+}
+
+#[test]
+fn every_registered_command_parses() {
+    assert!(COMMANDS.windows(2).all(|pair| pair[0].name < pair[1].name), "COMMANDS must stay sorted for find");
+    for spec in COMMANDS.iter() {
+        assert!(std::ptr::eq(command_spec::find(spec.name).unwrap(), spec));
+        // Too few or too many arguments are caught before the command's own parsing.
+        if spec.min_args > 0 {
+            let line = format!("{}{}", spec.name, " a".repeat(spec.min_args - 1));
+            assert!(matches!(parse(&line), Err(CommandError::ArgumentMismatch(_))), "{:?}", line);
+        }
+        if let Some(max) = spec.max_args {
+            let line = format!("{}{}", spec.name, " a".repeat(max + 1));
+            assert!(matches!(parse(&line), Err(CommandError::ArgumentMismatch(_))), "{:?}", line);
+        }
+    }
+}
+
+#[test]
+fn help_lists_commands_and_describes_one() {
+    let server = start_server();
+    let mut stream = connect(&server);
+
+    stream.write_all(b"HELP set\nHELP FROB\nHELP\nQUIT\n").unwrap();
+
+    let replies: Vec<String> = BufReader::new(stream).lines().map(|l| l.unwrap()).collect();
+    assert_eq!(replies[..3], [
+        "SET <type> <key> <value> [EX <seconds>]".to_string(),
+        "Stores a value as the type.".to_string(),
+        "Unknown command \"FROB\".".to_string()
+    ]);
+    assert_eq!(replies.len(), 3 + COMMANDS.len() + 1);
+    assert!(replies[3].starts_with("ADD "));
+    assert_eq!(replies.last().unwrap(), "Bye.");
+}
+
+#[test]
+fn ping_and_echo() {
+    let server = start_server();
+    let mut stream = connect(&server);
+
+    stream.write_all(b"PING\nping \"are you there\"\nECHO hello\nEXISTS hello\nQUIT\n").unwrap();
+
+    let replies: Vec<String> = BufReader::new(stream).lines().map(|l| l.unwrap()).collect();
+    assert_eq!(replies, vec!["PONG", "are you there", "hello", "false", "Bye."]);
+}
+
+#[test]
+fn invalid_patterns_reply_with_an_error() {
+    let server = start_server();
+    let mut stream = connect(&server);
+    stream.write_all(b"KEYS [\nMODE REGEX\nKEYS (\nMULTI\nKEYS [\nPING\nEXEC\nPING\nQUIT\n").unwrap();
+    let replies: Vec<String> = BufReader::new(stream).lines().map(|l| l.unwrap()).collect();
+    assert_eq!(replies, [
+        "-ERR invalid pattern '[': Pattern syntax error near position 0: invalid range pattern",
+        "OK",
+        "-ERR invalid pattern '(': unclosed group",
+        "OK",
+        "QUEUED",
+        "QUEUED",
+        "-ERR invalid pattern '[': unclosed character class",
+        "PONG",
+        "PONG",
+        "Bye."
+    ]);
+}
+
+#[test]
+fn keys_patterns_can_name_their_matcher() {
+    let pattern = |line: &str| match parse(line) {
+        Ok(Command::Keys(pattern, ..)) | Ok(Command::KeysCursor(pattern, ..)) => pattern,
+        other => panic!("{:?}", other)
+    };
+    assert_eq!(pattern("KEYS re:^user:[0-9]+$").mode, Some(SmirkSearchMode::Regex));
+    assert_eq!(pattern("KEYS re:^user:[0-9]+$").pattern, "^user:[0-9]+$");
+    assert_eq!(pattern("KEYS pre:user: CURSOR c 10").mode, Some(SmirkSearchMode::Trie));
+    assert_eq!(pattern("KEYS glob:glob:*").pattern, "glob:*");
+    assert_eq!(pattern("KEYS user:*").mode, None);
+
+    let server = start_server();
+    let mut stream = connect(&server);
+    stream.write_all(b"SET i32 user:1 1\nSET i32 user:22 1\nKEYS re:^user:[0-9]{2}$\nKEYS pre:user:2\nMODE REGEX\nKEYS glob:user:?\nKEYS pre:nobody\nQUIT\n").unwrap();
+    let replies: Vec<String> = BufReader::new(stream).lines().map(|l| l.unwrap()).collect();
+    assert_eq!(&replies[2..], ["user:22", "user:22", "OK", "user:1", "No matches for key query \"pre:nobody\" were found.", "Bye."]);
+}
+
+#[test]
+fn keys_can_page_through_matches() {
+    assert!(matches!(parse("KEYS * OFFSET 2 LIMIT 3"), Ok(Command::Keys(_, _, 2, Some(3)))));
+    assert!(matches!(parse("KEYS * LIMIT 3"), Ok(Command::Keys(_, _, 0, Some(3)))));
+    assert!(matches!(parse("KEYS * LIMIT"), Err(CommandError::ArgumentMismatch(_))));
+    assert!(matches!(parse("KEYS * LIMIT -1"), Err(CommandError::InvalidArgument(_, _))));
+    assert!(matches!(parse("KEYS * TOP 1"), Err(CommandError::ArgumentMismatch(_))));
+
+    let server = start_server();
+    let mut stream = connect(&server);
+    stream.write_all(b"MODE TRIE\nSET i32 a 1\nSET i32 b 1\nSET i32 c 1\nKEYS '' OFFSET 1 LIMIT 1\nKEYS '' LIMIT 0\nKEYS '' OFFSET 5\nQUIT\n").unwrap();
+    let replies: Vec<String> = BufReader::new(stream).lines().map(|l| l.unwrap()).collect();
+    // Neither the map nor the trie promise an order, so only the page size is checked.
+    assert!(["a", "b", "c"].contains(&replies[4].as_str()));
+    assert_eq!(&replies[5..], [
+        "Showing 1 of 3 matching keys.",
+        "Showing 0 of 3 matching keys.",
+        "Showing 0 of 3 matching keys.",
+        "Bye."
+    ]);
+}
+
+#[test]
+fn trie_mode_handles_empty_emoji_and_cjk_keys() {
+    assert!(matches!(Command::from_vec(b"SET i32 a\xff 1".to_vec()), Err(CommandError::InvalidKey(key)) if key == "a\u{FFFD}"));
+    assert!(matches!(Command::from_vec(b"DEL ok \xfe".to_vec()), Err(CommandError::InvalidKey(_))));
+    // A U+FFFD sent as UTF-8 is a real key, and binary values can still hold any bytes.
+    assert!(parse("SET i32 a\u{FFFD} 1").is_ok());
+    assert!(Command::from_vec(b"SET binary b \xff\xfe".to_vec()).is_ok());
+
+    let server = start_server();
+    let mut stream = connect(&server);
+    stream.write_all("MODE TRIE\nSET i32 '' 0\nSET i32 🦀 1\nSET i32 🦀rust 2\nSET i32 東京 3\nSET i32 東大 4\nKEYS 🦀\nKEYS 東\nDEL ''\nKEYS ''\nQUIT\n".as_bytes()).unwrap();
+    let replies: Vec<String> = BufReader::new(stream).lines().map(|l| l.unwrap()).collect();
+    assert_eq!(&replies[6..], ["🦀", "🦀rust", "東京", "東大", "1", "東京", "東大", "🦀", "🦀rust", "Bye."]);
+}
+
+#[test]
+fn keys_can_be_sorted_alphabetically_or_by_length() {
+    assert!(matches!(parse("KEYS * SORT"), Ok(Command::Keys(_, KeyOrder::Alpha, 0, None))));
+    assert!(matches!(parse("KEYS * sort length LIMIT 2"), Ok(Command::Keys(_, KeyOrder::Length, 0, Some(2)))));
+    assert!(matches!(parse("KEYS *"), Ok(Command::Keys(_, KeyOrder::Unordered, 0, None))));
+    assert!(matches!(parse("KEYS * SORT ALPHA CURSOR c 10"), Ok(Command::KeysCursor(_, KeyOrder::Alpha, _, 10))));
+    assert!(matches!(parse("KEYS * SORT BACKWARDS"), Err(CommandError::ArgumentMismatch(_))));
+    assert!(matches!(parse("KEYS * LIMIT 2 SORT"), Err(CommandError::ArgumentMismatch(_))));
+
+    let server = start_server();
+    let mut stream = connect(&server);
+    stream.write_all(b"SET i32 bb 1\nSET i32 a 1\nSET i32 ccc 1\nSET i32 ab 1\nKEYS * SORT\nKEYS * SORT LENGTH OFFSET 1 LIMIT 2\nKEYS pre: SORT\nQUIT\n").unwrap();
+    let replies: Vec<String> = BufReader::new(stream).lines().map(|l| l.unwrap()).collect();
+    assert_eq!(&replies[4..], [
+        "a", "ab", "bb", "ccc",
+        "ab", "bb", "Showing 2 of 4 matching keys.",
+        "a", "ab", "bb", "ccc",
+        "Bye."
+    ]);
+}
+
+#[test]
+fn no_line_makes_the_parser_panic() {
+    // Every word of every syntax line, so arguments get past each command's first checks.
+    let mut words: Vec<&str> = COMMANDS
+        .iter()
+        .flat_map(|spec| spec.syntax.split(|c: char| c.is_whitespace() || "<>[]|.".contains(c)))
+        .filter(|word| !word.is_empty())
+        .collect();
+    words.extend(["0", "-1", "1.5", "NaN", "99999999999999999999999", "1-1", "*", "\"", "'", "\\", "{", "é"]);
+    for spec in COMMANDS.iter() {
+        let most = spec.max_args.unwrap_or(spec.min_args + 6);
+        for count in 0..=most + 1 {
+            for start in 0..words.len() {
+                let args: Vec<&str> = (0..count).map(|i| words[(start + i * 7) % words.len()]).collect();
+                if let Ok(mut command) = parse(&format!("{} {}", spec.name, args.join(" "))) {
+                    command.namespace("ns:");
+                }
+            }
+        }
+    }
+    for line in ["", "\n", "\r\n", "\r", " ", "\"", "'", "\"\\", "\\x", "SET \"a", "\u{0}", "GET i32 \"\\xff\""] {
+        let _ = parse(line);
+    }
+}
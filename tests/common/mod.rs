@@ -0,0 +1,99 @@
+// Each test file compiles its own copy of this module and uses only some of it.
+#![allow(dead_code)]
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::thread::sleep;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub struct Server {
+    child: Child,
+    pub port: u16
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+impl Server {
+    /// The most memory the server has had resident so far, in kB. Only Linux reports it.
+    pub fn peak_memory(&self) -> Option<u64> {
+        let status = std::fs::read_to_string(format!("/proc/{}/status", self.child.id())).ok()?;
+        let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+        line.split_whitespace().nth(1)?.parse().ok()
+    }
+
+    /// Starts `peak_memory` over from what's resident now. Returns false where that isn't supported.
+    pub fn reset_peak_memory(&self) -> bool {
+        std::fs::write(format!("/proc/{}/clear_refs", self.child.id()), "5").is_ok()
+    }
+}
+
+pub fn start_server() -> Server {
+    start_server_with(&[])
+}
+
+/// Starts a server with extra command line flags. Snapshots and seed files are loaded after the
+/// server starts listening, so with either of those it's handed over once it says it's ready.
+pub fn start_server_with(flags: &[&str]) -> Server {
+    let server = spawn_server_with(flags);
+    if flags.iter().any(|flag| matches!(*flag, "--import" | "--seed")) {
+        wait_until_ready(&server);
+    }
+    server
+}
+
+/// Starts a server with extra command line flags without waiting for it to finish loading.
+pub fn spawn_server_with(flags: &[&str]) -> Server {
+    let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let child = Command::new(env!("CARGO_BIN_EXE_smirk-server"))
+        .args(["--port", &port.to_string()])
+        .args(flags)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    Server { child, port }
+}
+
+pub fn connect(server: &Server) -> TcpStream {
+    for _ in 0..100 {
+        if let Ok(stream) = TcpStream::connect(("127.0.0.1", server.port)) {
+            return stream;
+        }
+        sleep(Duration::from_millis(20));
+    }
+    panic!("smirk-server never started listening on port {}", server.port);
+}
+
+/// Sends `commands` on a new connection, then QUIT, and returns every line the server replies with.
+pub fn session(server: &Server, commands: &str) -> Vec<String> {
+    let mut stream = connect(server);
+    stream.write_all(format!("{}QUIT\n", commands).as_bytes()).unwrap();
+    BufReader::new(stream).lines().map(|l| l.unwrap()).collect()
+}
+
+fn wait_until_ready(server: &Server) {
+    for _ in 0..1000 {
+        let mut stream = connect(server);
+        stream.write_all(b"STATUS\nQUIT\n").unwrap();
+        let mut status = String::new();
+        BufReader::new(stream).read_line(&mut status).unwrap();
+        if !status.starts_with("loading") {
+            return;
+        }
+        sleep(Duration::from_millis(20));
+    }
+    panic!("smirk-server on port {} never finished loading", server.port);
+}
+
+/// A path under the temp directory that no other test, or test run, is using.
+pub fn scratch_dir(name: &str) -> PathBuf {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos();
+    std::env::temp_dir().join(format!("smirk-{}-{}-{}", name, std::process::id(), nanos))
+}
@@ -0,0 +1,167 @@
+mod common;
+
+use std::io::{Read, Write};
+use std::time::{Duration, Instant};
+
+use common::{connect, scratch_dir, start_server, start_server_with};
+
+#[test]
+fn idle_clients_are_disconnected() {
+    let server = start_server_with(&["--idle-timeout", "1"]);
+    let mut stream = connect(&server);
+    stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+
+    stream.write_all(b"PING\n").unwrap();
+    let mut pong = [0; 5];
+    stream.read_exact(&mut pong).unwrap();
+    assert_eq!(&pong, b"PONG\n");
+
+    let waited = Instant::now();
+    let mut rest = Vec::new();
+    stream.read_to_end(&mut rest).unwrap();
+    assert!(rest.is_empty());
+    assert!(waited.elapsed() >= Duration::from_millis(900), "closed after {:?}", waited.elapsed());
+}
+
+#[test]
+fn unfinished_commands_time_out() {
+    let server = start_server_with(&["--read-timeout", "1"]);
+    let mut stream = connect(&server);
+    stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+
+    // Trickling bytes in doesn't restart the clock.
+    for byte in b"PIN" {
+        stream.write_all(&[*byte]).unwrap();
+        std::thread::sleep(Duration::from_millis(400));
+    }
+
+    let mut reply = String::new();
+    stream.read_to_string(&mut reply).unwrap();
+    assert_eq!(reply, "-ERR timed out reading the command\n");
+}
+
+#[test]
+fn timeouts_are_off_by_default() {
+    let server = start_server();
+    let mut stream = connect(&server);
+
+    stream.write_all(b"CONFIG GET *timeout\n").unwrap();
+    std::thread::sleep(Duration::from_millis(1500));
+    stream.write_all(b"PING\nQUIT\n").unwrap();
+
+    let mut replies = String::new();
+    stream.read_to_string(&mut replies).unwrap();
+    assert!(replies.ends_with("PONG\nBye.\n"), "{:?}", replies);
+}
+
+#[test]
+fn connections_beyond_max_clients_are_rejected() {
+    let server = start_server_with(&["--max-clients", "1"]);
+    let mut first = connect(&server);
+    first.write_all(b"PING\n").unwrap();
+    let mut pong = [0; 5];
+    first.read_exact(&mut pong).unwrap();
+
+    let mut second = connect(&server);
+    let mut rejection = String::new();
+    second.read_to_string(&mut rejection).unwrap();
+    assert_eq!(rejection, "-ERR max clients reached\n");
+
+    first.write_all(b"INFO\nQUIT\n").unwrap();
+    let mut info = String::new();
+    first.read_to_string(&mut info).unwrap();
+    assert!(info.contains("connected_clients:1\nmax_clients:1\ntotal_connections:1\nrejected_connections:1\n"), "{:?}", info);
+}
+
+#[test]
+fn connections_from_denied_addresses_are_closed() {
+    let server = start_server_with(&["--allow-ip", "127.0.0.0/8", "--allow-ip", "::1"]);
+    let mut first = connect(&server);
+    first.write_all(b"CONFIG SET deny-ip 127.0.0.1,10.0.0.0/33\nCONFIG SET deny-ip 127.0.0.1\n").unwrap();
+    let mut replies = String::new();
+    while !replies.ends_with("OK\n") {
+        let mut buf = [0; 128];
+        let read = first.read(&mut buf).unwrap();
+        assert!(read > 0, "{:?}", replies);
+        replies.push_str(&String::from_utf8_lossy(&buf[..read]));
+    }
+    assert!(replies.starts_with("Invalid address block \"10.0.0.0/33\""), "{:?}", replies);
+
+    let mut second = connect(&server);
+    let mut refusal = String::new();
+    second.read_to_string(&mut refusal).unwrap();
+    assert_eq!(refusal, "");
+
+    first.write_all(b"CONFIG GET *-ip\nINFO\nQUIT\n").unwrap();
+    let mut info = String::new();
+    first.read_to_string(&mut info).unwrap();
+    assert!(info.contains("allow-ip 127.0.0.0/8 ::1/128\ndeny-ip 127.0.0.1/32\n"), "{:?}", info);
+    assert!(info.contains("rejected_connections:0\ndenied_connections:1\n"), "{:?}", info);
+}
+
+#[test]
+fn proxy_protocol_headers_give_the_real_client_address() {
+    let server = start_server_with(&["--proxy-protocol", "--trusted-proxy", "127.0.0.1", "--deny-ip", "203.0.113.7"]);
+    let mut proxied = connect(&server);
+    proxied.write_all(b"PROXY TCP4 198.51.100.1 10.0.0.1 4000 53173\r\nCLIENT LIST\nQUIT\n").unwrap();
+    let mut list = String::new();
+    proxied.read_to_string(&mut list).unwrap();
+    assert!(list.contains("addr=198.51.100.1:4000"), "{:?}", list);
+
+    let mut header = b"\r\n\r\n\0\r\nQUIT\n\x21\x11\x00\x0c".to_vec();
+    header.extend_from_slice(&[203, 0, 113, 7, 10, 0, 0, 1, 0x0f, 0xa0, 0xcf, 0x35]);
+    let mut denied = connect(&server);
+    denied.write_all(&header).unwrap();
+    let mut reply = String::new();
+    denied.read_to_string(&mut reply).unwrap();
+    assert_eq!(reply, "");
+
+    let mut unproxied = connect(&server);
+    // Exactly as much as the server reads looking for a header, so nothing's left unread to reset the connection.
+    unproxied.write_all(b"PING\nPING\nP\n").unwrap();
+    let mut reply = String::new();
+    unproxied.read_to_string(&mut reply).unwrap();
+    assert_eq!(reply, "");
+}
+
+#[test]
+fn proxy_protocol_headers_are_only_taken_from_trusted_proxies() {
+    let server = start_server_with(&["--proxy-protocol", "--trusted-proxy", "127.0.0.1"]);
+    let mut proxied = connect(&server);
+    proxied.write_all(b"PROXY TCP4 198.51.100.1 10.0.0.1 4000 53173\r\nCONFIG SET trusted-proxy 10.0.0.0/8\nCONFIG GET trusted-proxy\nQUIT\n").unwrap();
+    let mut replies = String::new();
+    proxied.read_to_string(&mut replies).unwrap();
+    assert!(replies.contains("trusted-proxy 10.0.0.0/8\n"), "{:?}", replies);
+
+    let mut untrusted = connect(&server);
+    // It's closed before the header is read, so writing may already fail.
+    untrusted.write_all(b"PROXY TCP4 198.51.100.1 10.0.0.1 4000 53173\r\nPING\n").ok();
+    let mut reply = String::new();
+    untrusted.read_to_string(&mut reply).ok();
+    assert_eq!(reply, "");
+}
+
+#[cfg(unix)]
+#[test]
+fn clients_can_connect_over_a_unix_socket() {
+    use std::os::unix::net::UnixStream;
+
+    let dir = scratch_dir("unixsocket");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("smirk.sock");
+    let server = start_server_with(&["--unixsocket", path.to_str().unwrap()]);
+    let mut tcp = connect(&server);
+    tcp.write_all(b"SET i32 shared 1\nQUIT\n").unwrap();
+    tcp.read_to_end(&mut Vec::new()).unwrap();
+
+    let mut unix = (0..100)
+        .find_map(|_| UnixStream::connect(&path).map_err(|_| std::thread::sleep(Duration::from_millis(20))).ok())
+        .expect("smirk-server never listened on the unix socket");
+    unix.write_all(b"GET i32 shared\nCLIENT LIST\nQUIT\n").unwrap();
+    let mut replies = String::new();
+    unix.read_to_string(&mut replies).unwrap();
+    assert!(replies.starts_with("1\n"), "{:?}", replies);
+    assert!(replies.ends_with("Bye.\n"), "{:?}", replies);
+    drop(server);
+    std::fs::remove_dir_all(dir).ok();
+}
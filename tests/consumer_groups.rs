@@ -0,0 +1,113 @@
+mod common;
+
+use std::io::{BufRead, BufReader, Write};
+use std::thread::{self, sleep};
+use std::time::Duration;
+
+use common::{connect, session, start_server, Server};
+
+/// XPENDING lines without the idle time, which depends on how fast the test runs.
+fn pending(server: &Server, key: &str, group: &str) -> Vec<String> {
+    session(server, &format!("XPENDING {} {}\n", key, group))
+        .iter()
+        .map(|line| {
+            let parts: Vec<&str> = line.split(' ').collect();
+            match parts[..] {
+                [id, consumer, _, deliveries] => format!("{} {} {}", id, consumer, deliveries),
+                _ => line.clone()
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn each_entry_goes_to_one_consumer_until_acknowledged() {
+    let server = start_server();
+    let replies = session(&server, concat!(
+        "XADD jobs 1-0 task a\n",
+        "XADD jobs 2-0 task b\n",
+        "XADD jobs 3-0 task c\n",
+        "XGROUP CREATE jobs workers 0\n",
+        "XGROUP CREATE jobs workers 0\n",
+        "XREADGROUP GROUP workers alice COUNT 2 STREAMS jobs >\n",
+        "XREADGROUP GROUP workers bob STREAMS jobs >\n",
+        "XREADGROUP GROUP workers bob STREAMS jobs >\n",
+        "XACK jobs workers 1-0 3-0 9-0\n",
+        "XREADGROUP GROUP workers alice STREAMS jobs 0\n",
+        "XREADGROUP GROUP nobody alice STREAMS jobs >\n"
+    ));
+    assert_eq!(&replies[3..], [
+        "OK",
+        "Stream \"jobs\" has a consumer group \"workers\" already.",
+        "jobs 1-0 task a",
+        "jobs 2-0 task b",
+        "jobs 3-0 task c",
+        "No new entries.",
+        "2",
+        "jobs 2-0 task b",
+        "Stream \"jobs\" has no consumer group \"nobody\".",
+        "Bye."
+    ]);
+    assert_eq!(pending(&server, "jobs", "workers"), vec!["2-0 alice 1", "Bye."]);
+
+    // A group created at $ only sees what's added after it.
+    let replies = session(&server, "XGROUP CREATE jobs late $\nXADD jobs 4-0 task d\nXREADGROUP GROUP late carol STREAMS jobs >\nXGROUP DESTROY jobs late\nXGROUP DESTROY jobs late\n");
+    assert_eq!(replies, vec!["OK", "4-0", "jobs 4-0 task d", "1", "0", "Bye."]);
+    assert_eq!(session(&server, "XGROUP CREATE fresh g $\nXGROUP CREATE fresh g $ MKSTREAM\n"), vec![
+        "Key \"fresh\" not found.",
+        "OK",
+        "Bye."
+    ]);
+}
+
+#[test]
+fn xclaim_takes_over_entries_a_consumer_left_idle() {
+    let server = start_server();
+    session(&server, "XADD jobs 1-0 task a\nXGROUP CREATE jobs workers 0\nXREADGROUP GROUP workers alice STREAMS jobs >\n");
+    assert_eq!(session(&server, "XCLAIM jobs workers bob 60000 1-0\n"), vec!["No entries claimed.", "Bye."]);
+    sleep(Duration::from_millis(50));
+    assert_eq!(session(&server, "XCLAIM jobs workers bob 10 1-0 2-0\n"), vec!["1-0 task a", "Bye."]);
+    assert_eq!(pending(&server, "jobs", "workers"), vec!["1-0 bob 2", "Bye."]);
+
+    // Groups and what's pending in them are kept by DUMP and RESTORE, and so by snapshots.
+    let blob = session(&server, "DUMP jobs\n").remove(0);
+    assert_eq!(session(&server, &format!("RESTORE copy 0 {}\n", blob)), vec!["OK", "Bye."]);
+    assert_eq!(pending(&server, "copy", "workers"), vec!["1-0 bob 2", "Bye."]);
+    assert_eq!(session(&server, "XACK copy workers 1-0\nXPENDING copy workers\n"), vec!["1", "No pending entries.", "Bye."]);
+}
+
+#[test]
+fn blocked_consumers_get_entries_added_after_they_started_waiting() {
+    let server = start_server();
+    session(&server, "XGROUP CREATE jobs workers $ MKSTREAM\n");
+    let consumer = {
+        let mut stream = connect(&server);
+        thread::spawn(move || {
+            stream.write_all(b"XREADGROUP GROUP workers alice BLOCK 5000 STREAMS jobs >\nQUIT\n").unwrap();
+            BufReader::new(stream).lines().map(|l| l.unwrap()).collect::<Vec<String>>()
+        })
+    };
+    sleep(Duration::from_millis(200));
+    session(&server, "XADD jobs 1-0 task a\n");
+    assert_eq!(consumer.join().unwrap(), vec!["jobs 1-0 task a", "Bye."]);
+    assert_eq!(
+        session(&server, "XREADGROUP GROUP workers bob BLOCK 100 STREAMS jobs >\n"),
+        vec!["No new entries.", "Bye."]
+    );
+}
+
+#[test]
+fn generated_ids_move_to_the_next_millisecond_and_fail_when_none_are_left() {
+    let server = start_server();
+    let replies = session(&server, concat!(
+        "XADD s 99999999999999-18446744073709551615 f v\n",
+        "XADD s * f v\n",
+        "XADD full 18446744073709551615-18446744073709551615 f v\n",
+        "XADD full * f v\n"
+    ));
+    assert_eq!(replies[1], "100000000000000-0");
+    assert_eq!(replies[3..], [
+        "Can't add to stream \"full\": No IDs are left after the last ID 18446744073709551615-18446744073709551615.",
+        "Bye."
+    ]);
+}
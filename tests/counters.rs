@@ -0,0 +1,58 @@
+mod common;
+
+use common::{session, start_server};
+
+#[test]
+fn counters_follow_their_overflow_policy() {
+    let server = start_server();
+    let replies = session(&server, concat!(
+        "COUNTER wraps u8 WRAP 250\n",
+        "INCRBY wraps 10\n",
+        "DECRBY wraps 5\n",
+        "COUNTER sats i8 SAT\n",
+        "DECRBY sats 1000\n",
+        "INCR sats\n",
+        "COUNTER fails i4 FAIL 7\n",
+        "INCR fails\n",
+        "GET fails\n",
+        "TYPE fails\n"
+    ));
+    assert_eq!(replies, vec![
+        "OK",
+        "4",
+        "255",
+        "OK",
+        "-128",
+        "-127",
+        "OK",
+        "Counter \"fails\" would overflow i4, so it wasn't changed.",
+        "7",
+        "Stored-Type: smirk::core::counter::Counter, User-Type: Counter",
+        "Bye."
+    ]);
+}
+
+#[test]
+fn incr_and_decr_make_a_default_counter_and_leave_other_types_alone() {
+    let server = start_server();
+    let replies = session(&server, concat!(
+        "INCR hits\n",
+        "INCR hits\n",
+        "DECR hits\n",
+        "COUNTER big i64 FAIL 9223372036854775807\n",
+        "INCR big\n",
+        "SET i32 plain 5\n",
+        "INCR plain\n",
+        "COUNTER small u8 WRAP 300\n",
+        "COUNTER small u8 BOUNCE\n",
+        "DECRBY hits -9223372036854775808\n"
+    ));
+    assert_eq!(&replies[..5], ["1", "2", "1", "OK", "Counter \"big\" would overflow i64, so it wasn't changed."]);
+    assert!(replies[6].starts_with("Couldn't downcast"), "{:?}", replies);
+    assert_eq!(&replies[7..], [
+        "Can't set counter \"small\": 300 doesn't fit in u8.",
+        "-ERR invalid argument 'BOUNCE' for 'COUNTER'",
+        "-ERR invalid argument '-9223372036854775808' for 'DECRBY'",
+        "Bye."
+    ]);
+}
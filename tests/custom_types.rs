@@ -0,0 +1,81 @@
+use std::any::type_name;
+use std::sync::Arc;
+
+use smirk::core::codec::{self, Arithmetic, ValueType};
+use smirk::core::float_format::FloatFormat;
+use smirk::core::record::SharedValue;
+use smirk::core::smirk_map::{SmirkMap, render};
+use smirk::core::smirk_messages::SmirkMessages;
+use smirk::core::smirk_search_mode::SmirkSearchMode;
+use smirk::core::snapshot;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Point {
+    x: i64,
+    y: i64
+}
+
+/// Points written as `x,y`, which ADD sums component by component.
+struct PointType;
+
+impl ValueType for PointType {
+    fn rust_type(&self) -> &'static str {
+        type_name::<Point>()
+    }
+
+    fn parse(&self, value: &[u8]) -> Option<SharedValue> {
+        let (x, y) = std::str::from_utf8(value).ok()?.split_once(',')?;
+        Some(Arc::new(Point { x: x.trim().parse().ok()?, y: y.trim().parse().ok()? }))
+    }
+
+    fn serialize(&self, value: &SharedValue, _: &FloatFormat) -> Option<Vec<u8>> {
+        value.downcast_ref::<Point>().map(|point| format!("{},{}", point.x, point.y).into_bytes())
+    }
+
+    fn arithmetic(&self, smirk_map: &SmirkMap, arithmetic: Arithmetic, keys: Vec<String>) -> Option<Result<SharedValue, SmirkMessages>> {
+        if !matches!(arithmetic, Arithmetic::Add(_)) {
+            return None;
+        }
+        let mut total = Point { x: 0, y: 0 };
+        for key in &keys {
+            match smirk_map.get::<Point>(key) {
+                Ok(point) => {
+                    total.x += point.x;
+                    total.y += point.y;
+                }
+                Err(e) => return Some(Err(e))
+            }
+        }
+        Some(Ok(Arc::new(total)))
+    }
+}
+
+fn ok<T>(result: Result<T, SmirkMessages>) -> T {
+    result.unwrap_or_else(|e| panic!("{}", e))
+}
+
+#[test]
+fn registered_types_work_like_built_in_ones() {
+    codec::register("Point", PointType).unwrap();
+    assert!(codec::register("Point", PointType).is_err());
+    assert!(codec::register("i32", PointType).is_err());
+
+    let mut map = SmirkMap::new(SmirkSearchMode::Glob);
+    let (a, b) = (String::from("a"), String::from("b"));
+    ok(map.set_typed(&a, b"1,2".to_vec(), &String::from("Point")));
+    ok(map.set_typed(&b, b"10, 20".to_vec(), &String::from("Point")));
+    assert!(map.set_typed(&b, b"nowhere".to_vec(), &String::from("Point")).is_err());
+    assert_eq!(ok(map.get::<Point>(&b)), &Point { x: 10, y: 20 });
+    assert_eq!(ok(render(&a, &ok(map.get_shared(&a)))), "1,2");
+
+    let point_type = codec::find("Point").unwrap();
+    let total = ok(point_type.arithmetic(&map, Arithmetic::Add(false), vec![a.clone(), b.clone()]).unwrap());
+    assert_eq!(point_type.serialize(&total, &FloatFormat::Default).unwrap(), b"11,22");
+    assert!(point_type.arithmetic(&map, Arithmetic::Mul, vec![a.clone(), b.clone()]).is_none());
+
+    // Snapshots keep registered values as they serialize and parse them back.
+    let mut restored = SmirkMap::new(SmirkSearchMode::Glob);
+    snapshot::import(&mut restored, &snapshot::export(&map)).unwrap();
+    assert_eq!(ok(restored.get::<Point>(&a)), &Point { x: 1, y: 2 });
+    assert_eq!(ok(restored.get_record(&b)).desired_type_name, "Point");
+}
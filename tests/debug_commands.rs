@@ -0,0 +1,52 @@
+mod common;
+
+use std::time::Instant;
+
+use common::{session, start_server, start_server_with};
+
+#[test]
+fn debug_is_off_unless_enabled() {
+    let server = start_server();
+    assert_eq!(session(&server, "DEBUG SLEEP 1\nMULTI\nDEBUG OBJECT a\nDISCARD\nCONFIG SET enable-debug yes\nCONFIG GET enable-debug\n"), vec![
+        "-ERR DEBUG is off, start the server with --enable-debug to use it",
+        "OK",
+        "-ERR DEBUG is off, start the server with --enable-debug to use it",
+        "OK",
+        "Config parameter \"enable-debug\" can't be changed at runtime.",
+        "enable-debug no",
+        "Bye."
+    ]);
+}
+
+#[test]
+fn debug_object_shows_the_record_and_set_expire_now_expires_it() {
+    let server = start_server_with(&["--enable-debug"]);
+    let replies = session(&server, "SET i32 a 5 EX 100\nSET i32 a 6\nDEBUG OBJECT a\nDEBUG SET-EXPIRE-NOW a\nGET a\nDEBUG SET-EXPIRE-NOW a\nDEBUG OBJECT a\n");
+    let fields: Vec<&str> = replies[2..13].iter().map(|line| line.split(' ').next().unwrap()).collect();
+    assert_eq!(fields, vec![
+        "stored-type", "user-type", "version", "ttl", "ttl-start", "expired", "created", "modified", "last-access", "shared", "serialized-size"
+    ]);
+    assert_eq!(&replies[2..4], ["stored-type i32", "user-type i32"]);
+    assert_eq!(replies[7], "expired no");
+    assert_eq!(&replies[13..], [
+        "OK",
+        "Key \"a\" not found.",
+        "Key \"a\" not found.",
+        "Key \"a\" not found.",
+        "Bye."
+    ]);
+}
+
+#[test]
+fn debug_sleep_holds_up_the_server_and_is_logged_as_slow() {
+    let server = start_server_with(&["--enable-debug"]);
+    let started = Instant::now();
+    assert_eq!(session(&server, "DEBUG SLEEP 0.3\nDEBUG SLEEP -1\n"), vec![
+        "OK",
+        "-ERR invalid argument '-1' for 'DEBUG'",
+        "Bye."
+    ]);
+    assert!(started.elapsed().as_millis() >= 300);
+    let slowlog = session(&server, "SLOWLOG GET\n");
+    assert!(slowlog[0].ends_with("DEBUG SLEEP 0.3"), "{:?}", slowlog);
+}
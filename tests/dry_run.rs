@@ -0,0 +1,124 @@
+mod common;
+
+use common::{session, start_server};
+
+#[test]
+fn dry_runs_report_what_counters_and_probabilistic_types_would_do() {
+    let server = start_server();
+    session(&server, "INCRBY hits 5\nCOUNTER small u8 FAIL 250\nPFADD visitors alice\nBF.ADD seen alice\nSET i32 a 1\nSET i32 b 2\n");
+    let replies = session(&server, concat!(
+        "DRYRUN INCRBY hits 3\n",
+        "DRYRUN INCR fresh\n",
+        "DRYRUN INCRBY small 10\n",
+        "DRYRUN INCR a\n",
+        "DRYRUN PFADD visitors alice\n",
+        "DRYRUN PFADD visitors bob\n",
+        "DRYRUN PFADD new bob\n",
+        "DRYRUN PFMERGE visitors new missing\n",
+        "DRYRUN PFMERGE union visitors\n",
+        "DRYRUN BF.RESERVE seen 0.01 100\n",
+        "DRYRUN BF.RESERVE other 0.01 100\n",
+        "DRYRUN BF.ADD seen alice\n",
+        "DRYRUN BF.ADD seen bob\n",
+        "DRYRUN BF.ADD other bob\n",
+        "DRYRUN ADD i32 a b\n",
+        "DRYRUN ADD i32 a missing\n",
+        "GET hits\n",
+        "EXISTS fresh\n",
+        "EXISTS new\n",
+        "EXISTS union\n",
+        "EXISTS other\n"
+    ));
+    assert_eq!(replies, vec![
+        "Would change counter \"hits\" to 8.",
+        "Would create counter \"fresh\" at 1.",
+        "Would fail: Counter \"small\" would overflow u8, so it wasn't changed.",
+        "Would fail: Couldn't downcast the value stored in key \"a\" to type \"smirk::core::counter::Counter\".",
+        "Would do nothing: key \"visitors\" already has those elements.",
+        "Would add 1 elements to key \"visitors\", changing its estimate.",
+        "Would create key \"new\" with 1 elements.",
+        "Would merge 0 keys into key \"visitors\".",
+        "Would create key \"union\" from 1 keys.",
+        "Would fail: Can't reserve a Bloom filter at key \"seen\": the key already exists.",
+        "Would reserve a Bloom filter of 959 bits at key \"other\".",
+        "Would do nothing: key \"seen\" may already have \"alice\".",
+        "Would add \"bob\" to key \"seen\".",
+        "Would reserve a Bloom filter at key \"other\" and add \"bob\".",
+        "Would only read 2 keys, nothing would change.",
+        "Would fail: Key \"missing\" not found.",
+        "5",
+        "false",
+        "false",
+        "false",
+        "false",
+        "Bye."
+    ]);
+}
+
+#[test]
+fn dry_runs_report_what_streams_series_tags_and_limits_would_do() {
+    let server = start_server();
+    session(&server, concat!(
+        "XADD jobs 1-0 task a\n",
+        "XADD jobs 2-0 task b\n",
+        "XGROUP CREATE jobs workers 0\n",
+        "XREADGROUP GROUP workers alice STREAMS jobs >\n",
+        "TS.CREATE temps RETENTION 1000\n",
+        "TS.ADD temps 5000 1.5\n",
+        "SET i32 a 1\n",
+        "TAG ADD a red\n",
+        "RATELIMIT api 1 60\n"
+    ));
+    let replies = session(&server, concat!(
+        "DRYRUN XGROUP CREATE jobs workers $\n",
+        "DRYRUN XGROUP CREATE jobs readers $\n",
+        "DRYRUN XGROUP CREATE fresh readers $ MKSTREAM\n",
+        "DRYRUN XGROUP DESTROY jobs workers\n",
+        "DRYRUN XGROUP DESTROY jobs readers\n",
+        "DRYRUN XACK jobs workers 1-0 3-0\n",
+        "DRYRUN XACK jobs readers 1-0\n",
+        "DRYRUN XCLAIM jobs workers bob 0 1-0 2-0 3-0\n",
+        "DRYRUN XCLAIM jobs workers bob 600000 1-0\n",
+        "DRYRUN TS.CREATE temps\n",
+        "DRYRUN TS.CREATE other RETENTION 60\n",
+        "DRYRUN TS.ADD temps 1000 2\n",
+        "DRYRUN TS.ADD temps 4500 2\n",
+        "DRYRUN TS.ADD other 1 2\n",
+        "DRYRUN TAG ADD a red blue\n",
+        "DRYRUN TAG ADD missing red\n",
+        "DRYRUN TAG DEL a red blue\n",
+        "DRYRUN RATELIMIT api 1 60\n",
+        "DRYRUN RATELIMIT other 3 60\n",
+        "DRYRUN KEEPHISTORY a 5\n",
+        "DRYRUN GET a\n",
+        "XPENDING jobs workers\n",
+        "TAG LIST a\n",
+        "RATELIMIT other 3 60\n"
+    ));
+    assert_eq!(replies[..21], [
+        "Would fail: Stream \"jobs\" has a consumer group \"workers\" already.",
+        "Would create consumer group \"readers\" on stream \"jobs\".",
+        "Would create stream \"fresh\" with consumer group \"readers\".",
+        "Would destroy consumer group \"workers\" and its 2 pending entries.",
+        "Would do nothing: stream \"jobs\" has no consumer group \"readers\".",
+        "Would acknowledge 1 of 2 entries.",
+        "Would fail: Stream \"jobs\" has no consumer group \"readers\".",
+        "Would hand 2 of 3 entries to consumer \"bob\".",
+        "Would hand 0 of 1 entries to consumer \"bob\".",
+        "Would fail: Can't use time series \"temps\": the key already exists.",
+        "Would create time series \"other\" keeping 60 ms of samples.",
+        "Would fail: Can't use time series \"temps\": timestamp 1000 is older than the retention period keeps.",
+        "Would add a sample to time series \"temps\".",
+        "Would create time series \"other\" with one sample.",
+        "Would add 1 new tags to key \"a\".",
+        "Would fail: Key \"missing\" not found.",
+        "Would remove 1 of 2 tags from key \"a\".",
+        "Would deny the request to \"api\".",
+        "Would allow the request to \"other\", leaving 2 more.",
+        "Would keep 5 old values of key \"a\" instead of 0.",
+        "This command has no dry-run support, so nothing was run."
+    ]);
+    // Nothing was changed by any of them.
+    assert!(replies[21].starts_with("1-0 alice"), "{:?}", replies);
+    assert_eq!(replies[23..], ["red", "allowed 2", "Bye."]);
+}
@@ -0,0 +1,389 @@
+mod common;
+
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Command, Stdio};
+use std::thread::{self, sleep};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use common::{connect, scratch_dir, session, start_server, start_server_with};
+
+#[test]
+fn every_type_reads_back_what_was_set() {
+    let server = start_server();
+    let cases = [
+        ("i8", "-128", "-128"),
+        ("i16", "-32768", "-32768"),
+        ("i32", "2147483647", "2147483647"),
+        ("i64", "-9223372036854775808", "-9223372036854775808"),
+        ("i128", "170141183460469231731687303715884105727", "170141183460469231731687303715884105727"),
+        ("isize", "-7", "-7"),
+        ("u8", "255", "255"),
+        ("u16", "65535", "65535"),
+        ("u32", "4294967295", "4294967295"),
+        ("u64", "18446744073709551615", "18446744073709551615"),
+        ("u128", "340282366920938463463374607431768211455", "340282366920938463463374607431768211455"),
+        ("usize", "7", "7"),
+        ("f32", "1.5", "1.5"),
+        ("f64", "-0.25", "-0.25"),
+        ("bool", "false", "false"),
+        ("char", "z", "z"),
+        ("String", "\"two words\"", "two words"),
+        ("BigInt", "-1000000000000000000000000000000", "-1000000000000000000000000000000"),
+        ("BigDecimal", "3.14159265358979323846264338327950288", "3.14159265358979323846264338327950288"),
+        ("Json", "{\"a\":[1,true]}", "{\"a\":[1,true]}"),
+        ("Vector", "[1,2.5]", "1,2.5"),
+        ("Blob", "bytes", "bytes")
+    ];
+    let commands: String = cases.iter().map(|(t, value, _)| format!("SET {} {} {}\nGET {}\n", t, t, value, t)).collect();
+    let replies = session(&server, &commands);
+    for (i, (t, _, expected)) in cases.iter().enumerate() {
+        assert!(replies[i * 2].starts_with(&format!("Set key \"{}\" successfully.", t)), "{:?}", replies[i * 2]);
+        assert!(replies[i * 2].ends_with(&format!("User-Type: {}", t)), "{:?}", replies[i * 2]);
+        assert_eq!(replies[i * 2 + 1], *expected, "{}", t);
+    }
+
+    // Values that don't fit their type are refused rather than stored.
+    let replies = session(&server, "SET u8 small 256\nSET bool flag maybe\nEXISTS small\n");
+    assert!(replies[0].contains("256"), "{:?}", replies);
+    assert!(replies[1].contains("maybe"), "{:?}", replies);
+    assert_eq!(replies[2], "false");
+}
+
+#[test]
+fn ttls_count_down_and_can_be_changed() {
+    let server = start_server();
+    let replies = session(&server, "SET i32 short 1 EX 1\nSET i32 long 2\nTTL long 100\nTTL long\nTTL never\n");
+    assert_eq!(&replies[2..], ["OK", "100", "Key \"never\" does not exist.", "Bye."]);
+    sleep(Duration::from_millis(1100));
+    assert_eq!(session(&server, "TTL short\nDELTTL long\nTTL long\n"), vec![
+        "Key \"short\" does not exist.",
+        "OK",
+        "Key \"long\" does not expire.",
+        "Bye."
+    ]);
+}
+
+#[test]
+fn keys_match_in_every_mode() {
+    let server = start_server();
+    let setup = "SET i32 user:1 1\nSET i32 user:2 2\nSET i32 user:10 10\nSET i32 team:1 1\n";
+    session(&server, setup);
+    let replies = session(&server, "MODE GLOB\nKEYS user:? SORT\nMODE REGEX\nKEYS ^user:1[0-9]*$ SORT\nMODE TRIE\nKEYS user: SORT\nKEYS nobody\n");
+    assert_eq!(replies, vec![
+        "OK",
+        "user:1",
+        "user:2",
+        "OK",
+        "user:1",
+        "user:10",
+        "OK",
+        "user:1",
+        "user:10",
+        "user:2",
+        "No matches for key query \"nobody\" were found.",
+        "Bye."
+    ]);
+    // A prefix on the pattern picks the matcher whatever the mode is.
+    assert_eq!(session(&server, "KEYS glob:team:* SORT\nKEYS re:^team SORT\nKEYS pre:team SORT\n"), vec![
+        "team:1", "team:1", "team:1", "Bye."
+    ]);
+}
+
+#[test]
+fn scanvalues_finds_numbers_of_a_type_in_a_range() {
+    let server = start_server();
+    let setup = "SET i64 price:a 150\nSET i64 price:b 99\nSET i64 price:c 200\nSET i64 price:d 120\nSET i32 other 150\nSET f64 ratio 0.5\nSET f64 nan NaN\nSET String text 150\n";
+    session(&server, setup);
+    let replies = session(&server, concat!(
+        "SCANVALUES i64 100 200\n",
+        "SCANVALUES i64 100 200 LIMIT 2\n",
+        "SCANVALUES f64 -inf +inf\n",
+        "SCANVALUES u8 0 10\n",
+        "SCANVALUES i64 100 200 CURSOR prices 60\n",
+        "CURSOR PAGE prices 1 2\n",
+        "SCANVALUES String 0 1\n",
+        "SCANVALUES i64 low 1\n"
+    ));
+    assert_eq!(replies, vec![
+        "price:a",
+        "price:c",
+        "price:d",
+        "price:a",
+        "price:c",
+        "ratio",
+        "No u8 values in range.",
+        "Cursor \"prices\" holds 3 keys for 60 seconds.",
+        "price:d",
+        "-ERR invalid argument 'String' for 'SCANVALUES'",
+        "-ERR invalid argument 'low' for 'SCANVALUES'",
+        "Bye."
+    ]);
+}
+
+#[test]
+fn hotkeys_lists_the_most_accessed_keys_when_tracking_is_on() {
+    let server = start_server_with(&["--track-key-stats"]);
+    let replies = session(&server, "SET i32 a 1\nSET i32 b 2\nGET a\nGET a\nGET b\nGET gone\nHOTKEYS\nHOTKEYS 1\nHOTKEYS many\n");
+    assert_eq!(&replies[6..], [
+        "a 2 1",
+        "b 1 1",
+        "a 2 1",
+        "-ERR invalid argument 'many' for 'HOTKEYS'",
+        "Bye."
+    ]);
+    let info = session(&server, "INFO\n");
+    assert!(info.contains(&String::from("db0:keys=2,reads=4,writes=2,read_write_ratio=2.00")), "{:?}", info);
+
+    assert_eq!(session(&server, "CONFIG SET track-key-stats no\nHOTKEYS\n"), vec![
+        "OK",
+        "Key statistics are off. Turn them on with CONFIG SET track-key-stats yes.",
+        "Bye."
+    ]);
+}
+
+#[test]
+fn trace_breaks_down_where_each_command_spent_its_time() {
+    let server = start_server();
+    let replies = session(&server, "TRACE ON\nSET i32 a 1\nGET a\nTRACE LIST 2\nTRACE GET 1\nTRACE GET 99\nTRACE GET first\n");
+    assert_eq!(&replies[..3], ["OK", "Set key \"a\" successfully. Stored-Type: i32, User-Type: i32", "1"]);
+    for (reply, (id, command)) in replies[3..6].iter().zip([(2, "GET a"), (1, "SET i32 a 1"), (1, "SET i32 a 1")]) {
+        assert!(reply.starts_with(&format!("{} ", id)), "{}", reply);
+        assert!(reply.ends_with(command), "{}", reply);
+        assert!(reply.contains(" parse=") && reply.contains(" lock_wait=") && reply.contains(" execute="), "{}", reply);
+    }
+    assert_eq!(&replies[6..], [
+        "Trace 99 not found.",
+        "-ERR invalid argument 'first' for 'TRACE'",
+        "Bye."
+    ]);
+
+    let replies = session(&server, "TRACE OFF\nGET a\nTRACE LIST 1\n");
+    assert!(replies[2].ends_with(" QUIT"), "{}", replies[2]);
+}
+
+#[test]
+fn disabled_and_renamed_commands_are_unknown_by_their_old_names() {
+    let server = start_server_with(&["--disable-command", "del", "--rename-command", "config:admin-config"]);
+    assert_eq!(session(&server, "SET i32 a 1\nDEL a\nDRYRUN del a\nCONFIG GET disable-command\nadmin-config GET disable-command\nADMIN-CONFIG GET rename-command\nGET a\n")[1..], [
+        "-ERR unknown command 'DEL'",
+        "-ERR unknown command 'DEL'",
+        "-ERR unknown command 'CONFIG'",
+        "disable-command DEL",
+        "rename-command CONFIG:ADMIN-CONFIG",
+        "1",
+        "Bye."
+    ]);
+    // Quotes come off before the names are checked.
+    assert_eq!(session(&server, "\"DEL\" a\nDRYRUN 'del' a\n\"config\" GET port\n\"admin-config\" GET disable-command\nGET a\n"), [
+        "-ERR unknown command 'DEL'",
+        "-ERR unknown command 'DEL'",
+        "-ERR unknown command 'CONFIG'",
+        "disable-command DEL",
+        "1",
+        "Bye."
+    ]);
+}
+
+#[test]
+fn keys_remember_when_they_were_created_and_last_written() {
+    let server = start_server();
+    session(&server, "SET i32 a 1\nSET i32 b 2\n");
+    sleep(Duration::from_millis(1100));
+    let since = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    let replies = session(&server, &format!(
+        "SET i32 a 3\nKEYS * MODIFIEDSINCE {since}\nKEYS * SORT MODIFIEDSINCE {since} CURSOR recent 10\nKEYS * MODIFIEDSINCE 4102444800\nSTAT a\nSTAT missing\n"
+    ));
+    assert_eq!(replies[1..4], [
+        "a",
+        "Cursor \"recent\" holds 1 keys for 10 seconds.",
+        "No matches for key query \"*\" were found."
+    ]);
+    let stat: Vec<(&str, u64)> = replies[4..7]
+        .iter()
+        .map(|line| line.split_once(' ').map(|(field, secs)| (field, secs.parse().unwrap())).unwrap())
+        .collect();
+    assert_eq!(stat.iter().map(|(field, _)| *field).collect::<Vec<&str>>(), ["created", "modified", "last-access"]);
+    // Overwriting the key kept when it was created.
+    assert!(stat[0].1 < since && stat[1].1 >= since, "{:?}", stat);
+    assert_eq!(replies[7..], ["Key \"missing\" not found.", "Bye."]);
+}
+
+#[test]
+fn overwritten_big_values_are_freed_in_the_background() {
+    let server = start_server_with(&["--lazy-free-threshold", "8"]);
+    session(&server, "SET String big 0123456789\nSET String big x\nSET String small abc\nSET String small def\n");
+    let info = session(&server, "INFO\n");
+    assert!(info.contains(&String::from("lazyfree_deferred:1")), "{:?}", info);
+    assert!(info.iter().any(|line| line == "lazyfree_pending:0" || line == "lazyfree_pending:1"), "{:?}", info);
+
+    let replies = session(&server, "CONFIG SET lazy-free-threshold 0\nCONFIG GET lazy-free-threshold\nSET String big 0123456789\nSET String big x\nINFO\n");
+    assert_eq!(replies[..2], ["OK", "lazy-free-threshold 0"]);
+    assert!(replies.contains(&String::from("lazyfree_deferred:1")), "{:?}", replies);
+}
+
+#[test]
+fn get_writes_big_values_in_order_with_the_replies_around_them() {
+    let server = start_server();
+    let big = "x".repeat(100_000);
+    let replies = session(&server, &format!(
+        "SET String big {}\nSET Blob blob {}\nGET i32 missing\nGET big\nGET i32 big\nGET blob\nGET Blob big\nGET String blob\nEXISTS big\n",
+        big, big
+    ));
+    assert_eq!(replies[2], "Key \"missing\" not found.");
+    assert_eq!(replies[3], big);
+    assert!(replies[4].starts_with("Couldn't downcast"), "{}", replies[4]);
+    assert_eq!(replies[5], big);
+    assert!(replies[6].starts_with("Couldn't downcast"), "{}", replies[6]);
+    assert!(replies[7].starts_with("Couldn't downcast"), "{}", replies[7]);
+    assert_eq!(replies[8..], ["true", "Bye."]);
+
+    // Numbers skip Display, so check the cases its output is easy to get wrong for.
+    let floats = [("3", "3"), ("-0", "-0"), ("1e20", "100000000000000000000"), ("1e-7", "0.0000001"), ("0.1", "0.1"), ("inf", "inf"), ("NaN", "NaN")];
+    let commands: String = floats.iter().map(|(value, _)| format!("SET f64 n {}\nGET f64 n\nGET n\n", value)).collect();
+    let replies = session(&server, &commands);
+    for (i, (value, expected)) in floats.iter().enumerate() {
+        assert_eq!(replies[i * 3 + 1..i * 3 + 3], [*expected, *expected], "{}", value);
+    }
+    let replies = session(&server, "SET f32 f 0.1\nGET f\nSET i64 i -42\nGET i\nGET u64 i\nFORMAT FLOAT FIXED 2\nGET f32 f\nGET f\n");
+    assert_eq!(replies[1], "0.1");
+    assert_eq!(replies[3], "-42");
+    assert!(replies[4].starts_with("Couldn't downcast"), "{}", replies[4]);
+    assert_eq!(replies[6..], ["0.10", "0.10", "Bye."]);
+}
+
+#[test]
+fn del_removes_keys_and_counts_the_ones_that_existed() {
+    let server = start_server();
+    let replies = session(&server, "SET i32 a 1\nSET i32 b 2\nDEL a b c\nDEL a\nGET a\nEXISTS b\n");
+    assert_eq!(&replies[2..], ["2", "0", "Key \"a\" not found.", "false", "Bye."]);
+}
+
+#[test]
+fn delpattern_deletes_every_matching_key() {
+    let server = start_server();
+    let replies = session(&server, "SET i32 user:1 1\nSET i32 user:2 2\nSET i32 order:1 3\nDRYRUN DELPATTERN user:*\nDELPATTERN user:*\nKEYS pre:user\nDELPATTERN pre:user\nDELPATTERN re:^ord\nDELPATTERN re:(\nEXISTS order:1\n");
+    assert_eq!(replies[3..9], [
+        "Would delete 2 keys matching \"user:*\".",
+        "2",
+        "No matches for key query \"pre:user\" were found.",
+        "0",
+        "1",
+        "-ERR invalid pattern '(': unclosed group"
+    ]);
+    assert_eq!(replies[9..], ["false", "Bye."]);
+}
+
+#[test]
+fn concurrent_clients_all_get_their_writes_in() {
+    let server = start_server();
+    let clients: Vec<_> = (0..8)
+        .map(|client| {
+            let mut stream = connect(&server);
+            thread::spawn(move || {
+                let commands: String = (0..100).map(|i| format!("SET i32 c{}:{} {}\n", client, i, i)).collect();
+                stream.write_all(format!("{}QUIT\n", commands).as_bytes()).unwrap();
+                BufReader::new(stream).lines().map(|l| l.unwrap()).collect::<Vec<String>>()
+            })
+        })
+        .collect();
+    for client in clients {
+        let replies = client.join().unwrap();
+        assert_eq!(replies.len(), 101);
+        assert!(replies[..100].iter().all(|reply| reply.starts_with("Set key")), "{:?}", replies);
+    }
+    let keys = session(&server, "KEYS c*\n");
+    assert_eq!(keys.len(), 8 * 100 + 1);
+    assert_eq!(session(&server, "GET c7:99\n"), vec!["99", "Bye."]);
+}
+
+#[test]
+fn both_client_binaries_talk_to_the_server_the_same_way() {
+    let server = start_server();
+    connect(&server);
+    let port = server.port.to_string();
+
+    let client = Command::new(env!("CARGO_BIN_EXE_smirk-client"))
+        .args(["-p", &port, "SET", "String", "greeting", "hello there"])
+        .output()
+        .unwrap();
+    assert!(client.status.success());
+    assert_eq!(String::from_utf8_lossy(&client.stdout), "Set key \"greeting\" successfully. Stored-Type: alloc::string::String, User-Type: String\n");
+
+    // Without a terminal the CLI reads commands from stdin, one per line.
+    let home = scratch_dir("cli-home");
+    std::fs::create_dir_all(&home).unwrap();
+    let mut cli = Command::new(env!("CARGO_BIN_EXE_smirk-cli"))
+        .args(["-p", &port])
+        .env("HOME", &home)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    cli.stdin.take().unwrap().write_all(b"GET greeting\nquit\n").unwrap();
+    let output = cli.wait_with_output().unwrap();
+    let lines: Vec<String> = String::from_utf8_lossy(&output.stdout).lines().map(String::from).collect();
+    assert!(lines.iter().any(|line| line.ends_with("hello there")), "{:?}", lines);
+
+    // Failures exit non-zero with the reply on stderr, so scripts can tell them from values.
+    let failures = [
+        (vec!["GET", "nothing"], "Key \"nothing\" not found.\n"),
+        (vec!["GET", "i32", "greeting"], "Couldn't downcast the value stored in key \"greeting\" to type \"i32\".\n"),
+        (vec!["SET", "i32", "number", "twelve"], "Setting key \"number\" failed. Could not parse \"twelve\" into \"i32\".\n"),
+        (vec!["NOSUCHCOMMAND"], "-ERR unknown command 'NOSUCHCOMMAND'\n")
+    ];
+    for (args, reply) in failures {
+        let failed = Command::new(env!("CARGO_BIN_EXE_smirk-client"))
+            .args(["-p", &port])
+            .args(&args)
+            .output()
+            .unwrap();
+        assert_eq!(failed.status.code(), Some(1), "{:?}", args);
+        assert_eq!(String::from_utf8_lossy(&failed.stdout), "", "{:?}", args);
+        assert_eq!(String::from_utf8_lossy(&failed.stderr), reply, "{:?}", args);
+    }
+    std::fs::remove_dir_all(home).unwrap();
+}
+
+#[test]
+fn getdel_and_getex_read_and_change_the_key_in_one_go() {
+    let server = start_server();
+    let replies = session(&server, concat!(
+        "SET String job queued\n",
+        "GETDEL i32 job\n",
+        "GETDEL job\n",
+        "GETDEL job\n",
+        "SET i32 session 7 EX 100\n",
+        "GETEX session PERSIST\n",
+        "TTL session\n",
+        "GETEX i32 session EX 30\n",
+        "TTL session\n",
+        "GETEX session\n",
+        "TTL session\n",
+        "GETEX nothing EX 5\n",
+        "EXISTS nothing\n"
+    ));
+    assert!(replies[1].starts_with("Couldn't downcast"), "{:?}", replies);
+    assert_eq!(&replies[2..4], ["queued", "Key \"job\" not found."]);
+    assert_eq!(&replies[5..], [
+        "7",
+        "Key \"session\" does not expire.",
+        "7",
+        "30",
+        "7",
+        "30",
+        "Key \"nothing\" not found.",
+        "false",
+        "Bye."
+    ]);
+}
+
+#[test]
+fn wait_is_refused_without_replicas() {
+    let server = start_server();
+    assert_eq!(session(&server, "WAIT 1 100\nWAIT many 100\n"), [
+        "-ERR WAIT isn't supported, there are no replicas",
+        "-ERR invalid argument 'many' for 'WAIT'",
+        "Bye."
+    ]);
+}
@@ -0,0 +1,116 @@
+mod common;
+
+use std::io::{BufRead, BufReader, Write};
+use std::thread::{self, sleep};
+use std::time::{Duration, Instant};
+
+use common::{connect, session, start_server, start_server_with};
+
+#[test]
+fn expired_keys_are_swept_away() {
+    let server = start_server();
+    session(&server, "SET i32 brief 1 EX 1\nSET i32 lasting 2\n");
+    sleep(Duration::from_millis(1300));
+    assert_eq!(session(&server, "EXISTS brief\nKEYS * SORT\n"), vec!["false", "lasting", "Bye."]);
+}
+
+#[test]
+fn waitexpire_returns_once_the_key_is_deleted_or_expires() {
+    let server = start_server();
+    session(&server, "SET i32 job 1\nSET i32 lease 1 EX 1\n");
+
+    let waiter = {
+        let mut stream = connect(&server);
+        thread::spawn(move || {
+            let started = Instant::now();
+            stream.write_all(b"WAITEXPIRE job 0\nQUIT\n").unwrap();
+            let replies: Vec<String> = BufReader::new(stream).lines().map(|l| l.unwrap()).collect();
+            (replies, started.elapsed())
+        })
+    };
+    sleep(Duration::from_millis(300));
+    session(&server, "DEL job\n");
+    let (replies, waited) = waiter.join().unwrap();
+    assert_eq!(replies, vec!["Key \"job\" is gone.", "Bye."]);
+    assert!(waited >= Duration::from_millis(300), "{:?}", waited);
+
+    let started = Instant::now();
+    assert_eq!(session(&server, "WAITEXPIRE lease 5000\n"), vec!["Key \"lease\" is gone.", "Bye."]);
+    assert!(started.elapsed() < Duration::from_secs(3), "{:?}", started.elapsed());
+
+    // Keys that were never there are gone already.
+    assert_eq!(session(&server, "WAITEXPIRE nothing 0\n"), vec!["Key \"nothing\" is gone.", "Bye."]);
+}
+
+#[test]
+fn waitexpire_gives_up_at_its_timeout() {
+    let server = start_server();
+    let replies = session(&server, "SET i32 stays 1\nWAITEXPIRE stays 200\nGET stays\nWAITEXPIRE stays -1\n");
+    assert_eq!(&replies[1..], [
+        "Key \"stays\" is still there.",
+        "1",
+        "-ERR invalid argument '-1' for 'WAITEXPIRE'",
+        "Bye."
+    ]);
+}
+
+#[test]
+fn ttls_are_capped_and_jittered_by_the_ttl_policy() {
+    let server = start_server_with(&["--max-ttl", "60"]);
+    let replies = session(&server, "SET i32 capped 1 EX 100\nSET i32 short 1 EX 30\nTTL short 90\nTTL short 60\n");
+    assert_eq!(replies, [
+        "Set key \"capped\" successfully. Stored-Type: i32, User-Type: i32, TTL: 60 seconds",
+        "Set key \"short\" successfully. Stored-Type: i32, User-Type: i32",
+        "OK, TTL: 60 seconds",
+        "OK",
+        "Bye."
+    ]);
+    let ttl: u64 = session(&server, "TTL capped\n")[0].parse().unwrap();
+    assert!(ttl <= 60, "{}", ttl);
+
+    let replies = session(&server, "CONFIG SET max-ttl 0\nCONFIG SET ttl-jitter 10\nSET i32 spread 1\nTTL spread 100\nCONFIG GET *ttl*\n");
+    assert_eq!(&replies[..3], ["OK", "OK", "Set key \"spread\" successfully. Stored-Type: i32, User-Type: i32"]);
+    let given = match replies[3].as_str() {
+        "OK" => 100,
+        reply => reply.trim_start_matches("OK, TTL: ").trim_end_matches(" seconds").parse::<u64>().unwrap()
+    };
+    assert!((100..=110).contains(&given), "{:?}", replies);
+    assert_eq!(&replies[4..], ["default-ttl 0", "ttl-jitter 10", "max-ttl 0", "Bye."]);
+}
+
+#[test]
+fn expirepattern_sets_and_clears_ttls_of_matching_keys() {
+    let server = start_server();
+    let replies = session(&server, "SET i32 cache:a 1\nSET i32 cache:b 2\nSET i32 other 3\nDRYRUN EXPIREPATTERN cache:* 100\nEXPIREPATTERN cache:* 100\nTTL other\nEXPIREPATTERN re:^cache PERSIST\nTTL cache:a\nEXPIREPATTERN cache:* 0\nEXPIREPATTERN cache:* PERSIST\nEXPIREPATTERN cache:* soon\n");
+    assert_eq!(replies[3..], [
+        "Would expire 2 keys matching \"cache:*\" in 100 seconds.",
+        "2",
+        "Key \"other\" does not expire.",
+        "2",
+        "Key \"cache:a\" does not expire.",
+        "2",
+        // Keys that have already expired stay expired, even before they're swept away.
+        "0",
+        "-ERR invalid TTL 'soon', expected a number of seconds",
+        "Bye."
+    ]);
+}
+
+#[test]
+fn expirepattern_counts_the_ttl_from_now() {
+    let server = start_server();
+    session(&server, "SET i32 user:1 1\n");
+    sleep(Duration::from_secs(3));
+    let replies = session(&server, "EXPIREPATTERN user:* 10\nTTL user:1\n");
+    assert_eq!(replies[0], "1");
+    let ttl: u64 = replies[1].parse().unwrap();
+    assert!((9..=10).contains(&ttl), "{}", ttl);
+}
+
+#[test]
+fn setting_the_ttl_of_a_missing_key_changes_nothing() {
+    let server = start_server();
+    assert_eq!(session(&server, "TTL missing 10\nEXISTS missing\n"), ["Key \"missing\" not found.", "false", "Bye."]);
+    let info = session(&server, "INFO\n");
+    assert!(info.contains(&String::from("changes_since_last_save:0")), "{:?}", info);
+}
@@ -0,0 +1,99 @@
+mod common;
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread::sleep;
+use std::time::Duration;
+
+use common::{connect, start_server_with, Server};
+
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+}
+
+/// Sends one request and returns the status code and body.
+fn request(port: u16, method: &str, path: &str, headers: &str, body: &str) -> (u16, String) {
+    let mut stream = (0..100)
+        .find_map(|_| TcpStream::connect(("127.0.0.1", port)).map_err(|_| sleep(Duration::from_millis(20))).ok())
+        .expect("the HTTP API never started listening");
+    stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    write!(stream, "{} {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Length: {}\r\n{}\r\n{}", method, path, body.len(), headers, body).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let status = response[9..12].parse().unwrap();
+    let body = response.split_once("\r\n\r\n").map(|(_, body)| body.to_string()).unwrap_or_default();
+    (status, body)
+}
+
+fn start(flags: &[&str]) -> (Server, u16) {
+    loop {
+        let http_port = free_port();
+        let mut flags = flags.to_vec();
+        let port = http_port.to_string();
+        flags.extend(["--http-port", &port]);
+        let server = start_server_with(&flags);
+        // Both ports come from the OS one after the other, so they can be the same one.
+        if server.port != http_port {
+            drop(connect(&server));
+            return (server, http_port);
+        }
+    }
+}
+
+#[test]
+fn users_need_credentials_and_only_see_their_namespace() {
+    // alice:secret and bob:hunter2 in base64.
+    let (alice, bob) = ("Authorization: Basic YWxpY2U6c2VjcmV0\r\n", "Authorization: Basic Ym9iOmh1bnRlcjI=\r\n");
+    let (_server, port) = start(&["--user", "alice:secret:a:", "--user", "bob:hunter2:b:"]);
+    assert_eq!(request(port, "GET", "/keys", "", "").0, 401);
+    assert_eq!(request(port, "PUT", "/keys/k", "Authorization: Basic YWxpY2U6d3Jvbmc=\r\n", "v").0, 401);
+
+    assert_eq!(request(port, "PUT", "/keys/k", alice, "alice's").0, 201);
+    assert_eq!(request(port, "PUT", "/keys/k", bob, "bob's").0, 201);
+    assert_eq!(request(port, "GET", "/keys/k", alice, ""), (200, String::from("alice's\n")));
+    assert_eq!(request(port, "GET", "/keys", bob, ""), (200, String::from("k\n")));
+    assert_eq!(request(port, "DELETE", "/keys/k", bob, "").0, 200);
+    assert_eq!(request(port, "DELETE", "/keys/k", bob, ""), (404, String::from("Key \"k\" not found.\n")));
+    assert_eq!(request(port, "GET", "/keys/k", alice, "").0, 200);
+    assert_eq!(request(port, "GET", "/keys/k", bob, ""), (404, String::from("Key \"k\" not found.\n")));
+    assert_eq!(request(port, "PUT", "/keys/n", "Authorization: Basic Ym9iOmh1bnRlcjI=\r\nX-Smirk-Type: i32\r\n", "many").1, "Setting key \"n\" failed. Could not parse \"many\" into \"i32\".\n");
+}
+
+#[test]
+fn denied_addresses_and_disabled_commands_are_refused() {
+    let (_server, port) = start(&["--deny-ip", "127.0.0.0/8"]);
+    assert_eq!(request(port, "GET", "/keys", "", "").0, 403);
+
+    let (_server, port) = start(&["--disable-command", "DEL", "--rename-command", "SET:WRITE"]);
+    assert_eq!(request(port, "GET", "/keys/k", "", "").0, 404);
+    assert_eq!(request(port, "PUT", "/keys/k", "", "v"), (403, String::from("SET is disabled.\n")));
+    assert_eq!(request(port, "DELETE", "/keys/k", "", ""), (403, String::from("DEL is disabled.\n")));
+}
+
+#[test]
+fn bodies_over_the_limit_are_refused() {
+    let (server, port) = start(&[]);
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    write!(stream, "PUT /keys/big HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Length: 1073741824\r\n\r\nstart").unwrap();
+    let mut response = String::new();
+    let _ = stream.read_to_string(&mut response);
+    assert!(response.starts_with("HTTP/1.1 413"), "{}", response);
+    assert_eq!(common::session(&server, "EXISTS big\n"), vec!["false", "Bye."]);
+}
+
+#[test]
+fn requests_are_routed_by_slot_and_counted() {
+    let (server, port) = start(&["--cluster-slots", "0-8191", "--cluster-node", "8192-16383@127.0.0.1:7001"]);
+    // foo hashes to slot 12182 and bar to slot 5061.
+    assert_eq!(request(port, "GET", "/keys/foo", "", ""), (421, String::from("MOVED 12182 127.0.0.1:7001\n")));
+    assert_eq!(request(port, "PUT", "/keys/foo", "", "v"), (421, String::from("MOVED 12182 127.0.0.1:7001\n")));
+    assert_eq!(request(port, "PUT", "/keys/bar", "", "v").0, 201);
+    assert_eq!(request(port, "GET", "/keys/bar", "", "").0, 200);
+    assert_eq!(request(port, "DELETE", "/keys/bar", "", "").0, 200);
+    let info = common::session(&server, "INFO\n").concat();
+    assert!(info.contains("reads=1,writes=2"), "{}", info);
+
+    let (_server, port) = start(&["--cluster-slots", "0-8191"]);
+    assert_eq!(request(port, "GET", "/keys/foo", "", ""), (503, String::from("CLUSTERDOWN Hash slot 12182 is not served by any node.\n")));
+}
@@ -0,0 +1,43 @@
+mod common;
+
+use common::{session, start_server};
+
+#[test]
+fn indexes_follow_set_del_and_cast() {
+    let server = start_server();
+    let replies = session(&server, concat!(
+        "SET String a hi\n",
+        "SET i64 b 5\n",
+        "INDEX CREATE bytype ON type_name\n",
+        "INDEX CREATE byuser ON desired_type_name\n",
+        "INDEX QUERY bytype i64\n",
+        "SET i64 a 7\n",
+        "INDEX QUERY bytype i64\n",
+        "DEL b\n",
+        "INDEX QUERY bytype i64\n",
+        "CAST a String\n",
+        "INDEX QUERY bytype i64\n",
+        "INDEX QUERY byuser String\n",
+        "INDEX LIST\n",
+        "INDEX DROP bytype\n",
+        "INDEX QUERY bytype i64\n"
+    ));
+    assert_eq!(replies[2..], [
+        "Indexed 2 keys.",
+        "Indexed 2 keys.",
+        "b",
+        "Set key \"a\" successfully. Stored-Type: i64, User-Type: i64",
+        "a",
+        "b",
+        "1",
+        "a",
+        "Set key \"a\" successfully. Stored-Type: alloc::string::String, User-Type: String",
+        "No keys with type_name \"i64\" were found.",
+        "a",
+        "bytype on=type_name",
+        "byuser on=desired_type_name",
+        "1",
+        "Index \"bytype\" not found.",
+        "Bye."
+    ]);
+}
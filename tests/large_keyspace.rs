@@ -0,0 +1,81 @@
+mod common;
+
+use std::io::{BufRead, BufReader, BufWriter, Lines, Write};
+use std::net::TcpStream;
+use std::thread;
+use std::time::Duration;
+
+use common::{connect, start_server, Server};
+
+const KEYS: usize = 1_000_000;
+
+/// Sets `count` keys named by `name` over one connection, returning it and its unread replies.
+fn load(server: &Server, count: usize, name: fn(usize) -> String) -> (TcpStream, Lines<BufReader<TcpStream>>) {
+    let stream = connect(server);
+    // Replies are read while the SETs are still going out, or both ends could stall on full buffers.
+    let mut writer = BufWriter::new(stream.try_clone().unwrap());
+    let sender = thread::spawn(move || {
+        for i in 0..count {
+            writeln!(writer, "SET i32 {} {}", name(i), i).unwrap();
+        }
+        writer.flush().unwrap();
+    });
+    let mut replies = BufReader::new(stream.try_clone().unwrap()).lines();
+    for _ in 0..count {
+        replies.next().unwrap().unwrap();
+    }
+    sender.join().unwrap();
+    (stream, replies)
+}
+
+#[test]
+fn keys_streams_a_million_matches_without_buffering_the_reply() {
+    let server = start_server();
+    let (stream, replies) = load(&server, KEYS, |i| format!("key:{}", i));
+
+    // Loading grows the map's table several times over, and those peaks would hide the one KEYS causes.
+    if !server.reset_peak_memory() {
+        // Only Linux can reset the peak. Anywhere else this test can't measure anything.
+        if cfg!(target_os = "linux") {
+            panic!("couldn't reset the server's peak memory through /proc/<pid>/clear_refs");
+        }
+        eprintln!("skipping keys_streams_a_million_matches_without_buffering_the_reply, the peak memory can't be reset here");
+        return;
+    }
+    let before = server.peak_memory().unwrap();
+    (&stream).write_all(b"KEYS key:*\nQUIT\n").unwrap();
+    let mut listed = 0;
+    let mut reply_bytes = 0;
+    for line in replies.map(|l| l.unwrap()) {
+        if line == "Bye." {
+            break;
+        }
+        assert!(line.starts_with("key:"), "{}", line);
+        listed += 1;
+        reply_bytes += line.len() + 1;
+    }
+    assert_eq!(listed, KEYS);
+
+    // The reply is about 11MB. Building it up before writing would raise the peak by at least that much.
+    // The kernel only folds resident memory into the peak lazily and reports whichever is higher, so
+    // `before` is whatever was resident at that instant. Pages the allocator hands back afterwards
+    // lower that without raising the peak, so the later reading can be the smaller one, which just
+    // means KEYS didn't grow anything.
+    let grown = server.peak_memory().unwrap().saturating_sub(before) * 1024;
+    assert!(grown < reply_bytes as u64 / 4, "peak memory grew by {} bytes for a {} byte reply", grown, reply_bytes);
+}
+
+#[test]
+fn a_client_that_stops_reading_keys_holds_up_nobody_else() {
+    let server = start_server();
+    // About 20MB of keys, far more than the socket buffers between the server and a stalled reader.
+    let (stalled, _replies) = load(&server, 200_000, |i| format!("{:0>96}", i));
+    (&stalled).write_all(b"KEYS *\n").unwrap();
+    thread::sleep(Duration::from_millis(200));
+
+    let other = connect(&server);
+    other.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    (&other).write_all(format!("GET {:0>96}\nQUIT\n", 7).as_bytes()).unwrap();
+    let replies: Vec<String> = BufReader::new(other).lines().map(|l| l.unwrap()).collect();
+    assert_eq!(replies, ["7", "Bye."]);
+}
@@ -0,0 +1,80 @@
+mod common;
+
+use std::io::{BufRead, BufReader, Write};
+use std::thread::{self, sleep};
+use std::time::{Duration, Instant};
+
+use common::{connect, session, start_server};
+
+#[test]
+fn lists_are_pushed_and_popped_at_either_end() {
+    let server = start_server();
+    let replies = session(&server, "RPUSH jobs b c\nLPUSH jobs a z\nLRANGE jobs 0 -1\nLLEN jobs\nLPOP jobs\nRPOP jobs\nLRANGE jobs -1 5\nLRANGE jobs 3 4\nLLEN missing\nRPOP missing\n");
+    assert_eq!(replies, vec![
+        "2",
+        "4",
+        "z",
+        "a",
+        "b",
+        "c",
+        "4",
+        "z",
+        "c",
+        "b",
+        "No elements in range.",
+        "0",
+        "Key \"missing\" not found.",
+        "Bye."
+    ]);
+
+    // Popping the last element deletes the list.
+    let replies = session(&server, "LPOP jobs\nLPOP jobs\nEXISTS jobs\nSET i32 n 1\nLPUSH n x\n");
+    assert_eq!(replies, vec![
+        "a",
+        "b",
+        "false",
+        "Set key \"n\" successfully. Stored-Type: i32, User-Type: i32",
+        "Couldn't downcast the value stored in key \"n\" to type \"smirk::core::list::List\".",
+        "Bye."
+    ]);
+}
+
+#[test]
+fn blpop_waits_for_a_push() {
+    let server = start_server();
+
+    let consumer = {
+        let mut stream = connect(&server);
+        thread::spawn(move || {
+            let started = Instant::now();
+            stream.write_all(b"BLPOP empty jobs 0\nQUIT\n").unwrap();
+            let replies: Vec<String> = BufReader::new(stream).lines().map(|l| l.unwrap()).collect();
+            (replies, started.elapsed())
+        })
+    };
+    sleep(Duration::from_millis(300));
+    assert_eq!(session(&server, "RPUSH jobs first second\n"), vec!["2", "Bye."]);
+    let (replies, waited) = consumer.join().unwrap();
+    assert_eq!(replies, vec!["jobs first", "Bye."]);
+    assert!(waited >= Duration::from_millis(300), "{:?}", waited);
+
+    // Anything already there is popped without waiting.
+    assert_eq!(session(&server, "BRPOP jobs 0\nLLEN jobs\n"), vec!["jobs second", "0", "Bye."]);
+}
+
+#[test]
+fn blpop_gives_up_at_its_timeout() {
+    let server = start_server();
+    let started = Instant::now();
+    let replies = session(&server, "BLPOP nothing 200\nMULTI\nBRPOP nothing 0\nEXEC\nBLPOP nothing soon\n");
+    assert_eq!(replies, vec![
+        "No elements to pop.",
+        "OK",
+        "QUEUED",
+        // Inside MULTI there's no waiting.
+        "No elements to pop.",
+        "-ERR invalid argument 'soon' for 'BLPOP'",
+        "Bye."
+    ]);
+    assert!(started.elapsed() >= Duration::from_millis(200), "{:?}", started.elapsed());
+}
@@ -0,0 +1,70 @@
+mod common;
+
+use std::io::{BufRead, BufReader, Write};
+use std::thread::{self, sleep};
+use std::time::Duration;
+
+use common::{connect, session, start_server};
+
+#[test]
+fn locks_only_move_between_holders_with_the_right_token() {
+    let server = start_server();
+    let replies = session(&server, concat!(
+        "SETLOCK job alice 10\n",
+        "SETLOCK job bob 10\n",
+        "RELEASELOCK job bob\n",
+        "EXTENDLOCK job bob 20\n",
+        "EXTENDLOCK job alice 20\n",
+        "TTL job\n",
+        "DRYRUN RELEASELOCK job alice\n",
+        "RELEASELOCK job alice\n",
+        "RELEASELOCK job alice\n",
+        "SETLOCK job bob 10\n",
+        "GET job\n",
+        "SETLOCK job carol 0\n"
+    ));
+    assert_eq!(replies, vec![
+        "OK",
+        "Lock \"job\" is already held.",
+        "Lock \"job\" isn't held with that token.",
+        "Lock \"job\" isn't held with that token.",
+        "OK",
+        "20",
+        "Would free lock \"job\".",
+        "OK",
+        "Lock \"job\" isn't held with that token.",
+        "OK",
+        "bob",
+        "-ERR invalid argument '0' for 'SETLOCK'",
+        "Bye."
+    ]);
+}
+
+#[test]
+fn locks_free_themselves_when_their_ttl_runs_out() {
+    let server = start_server();
+    assert_eq!(session(&server, "SETLOCK job alice 1\n"), vec!["OK", "Bye."]);
+    sleep(Duration::from_millis(1100));
+    // Whether or not the sweeper got to it yet, the old holder has lost it.
+    assert_eq!(session(&server, "EXTENDLOCK job alice 10\nSETLOCK job bob 10\n"), vec![
+        "Lock \"job\" isn't held with that token.",
+        "OK",
+        "Bye."
+    ]);
+}
+
+#[test]
+fn only_one_of_many_racing_clients_gets_the_lock() {
+    let server = start_server();
+    let clients: Vec<_> = (0..8)
+        .map(|client| {
+            let mut stream = connect(&server);
+            thread::spawn(move || {
+                stream.write_all(format!("SETLOCK race client{} 10\nQUIT\n", client).as_bytes()).unwrap();
+                BufReader::new(stream).lines().next().unwrap().unwrap()
+            })
+        })
+        .collect();
+    let winners = clients.into_iter().map(|client| client.join().unwrap()).filter(|reply| reply == "OK").count();
+    assert_eq!(winners, 1);
+}
@@ -0,0 +1,122 @@
+mod common;
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use common::{connect, session, start_server};
+
+#[test]
+fn dump_and_restore_copy_a_record_with_its_type() {
+    let server = start_server();
+    session(&server, "SET u8 small 200\n");
+    let blob = session(&server, "DUMP small\n").remove(0);
+    assert_eq!(session(&server, &format!("RESTORE copy 0 {}\nTYPE copy\nGET copy\n", blob)), vec![
+        "OK",
+        "Stored-Type: u8, User-Type: u8",
+        "200",
+        "Bye."
+    ]);
+    assert_eq!(session(&server, &format!("RESTORE copy 0 {}\nRESTORE copy 0 {} REPLACE\n", blob, blob)), vec![
+        "Key \"copy\" already exists. Use REPLACE to overwrite it.",
+        "OK",
+        "Bye."
+    ]);
+    assert_eq!(session(&server, "RESTORE broken 0 abc\nDUMP missing\n"), vec![
+        "Invalid DUMP payload.",
+        "Key \"missing\" not found.",
+        "Bye."
+    ]);
+}
+
+#[test]
+fn migrate_copies_or_moves_a_key_to_another_server() {
+    let source = start_server();
+    let target = start_server();
+    session(&source, "SET i32 copied 1\nSET String moved two\n");
+
+    let migrate = |key: &str, destroy: &str| format!("MIGRATE 127.0.0.1 {} {}{}\n", target.port, key, destroy);
+    assert_eq!(session(&source, &(migrate("copied", "") + &migrate("moved", " DESTROY"))), vec!["OK", "OK", "Bye."]);
+    assert_eq!(session(&source, "GET copied\nGET moved\n"), vec!["1", "Key \"moved\" not found.", "Bye."]);
+    assert_eq!(session(&target, "GET copied\nGET moved\n"), vec!["1", "two", "Bye."]);
+
+    // The target keeps what it already has.
+    let replies = session(&source, &migrate("copied", " DESTROY"));
+    assert!(replies[0].starts_with("Couldn't migrate key \"copied\""), "{:?}", replies);
+    assert_eq!(session(&source, "GET copied\n"), vec!["1", "Bye."]);
+}
+
+#[test]
+fn migrate_refuses_this_server_and_transactions() {
+    let server = start_server();
+    session(&server, "SET i32 a 1\n");
+    for host in ["127.0.0.1", "localhost"] {
+        assert_eq!(session(&server, &format!("MIGRATE {} {} a DESTROY\n", host, server.port)), vec![
+            format!("Couldn't migrate key \"a\" to {}:{}: that's this server.", host, server.port),
+            String::from("Bye.")
+        ]);
+    }
+    assert_eq!(session(&server, &format!("MULTI\nMIGRATE 127.0.0.1 {} a\nEXEC\nGET a\n", server.port + 1)), vec![
+        "OK",
+        "QUEUED",
+        "MIGRATE inside MULTI is not allowed.",
+        "1",
+        "Bye."
+    ]);
+}
+
+#[test]
+fn a_slow_target_holds_up_nobody_else() {
+    let server = start_server();
+    session(&server, "SET i32 a 1\n");
+    // Accepts the RESTORE and never answers it.
+    let silent = TcpListener::bind("127.0.0.1:0").unwrap();
+    let silent_port = silent.local_addr().unwrap().port();
+    let accepted = thread::spawn(move || silent.accept().map(|(stream, _)| stream));
+
+    let mut migrating = connect(&server);
+    migrating.write_all(format!("MIGRATE 127.0.0.1 {} a DESTROY\n", silent_port).as_bytes()).unwrap();
+    let _held = accepted.join().unwrap().unwrap();
+
+    let started = Instant::now();
+    assert_eq!(session(&server, "GET a\n"), vec!["1", "Bye."]);
+    assert!(started.elapsed() < Duration::from_secs(2), "GET waited {:?} on the migration", started.elapsed());
+
+    let mut reply = String::new();
+    BufReader::new(migrating).read_line(&mut reply).unwrap();
+    assert!(reply.starts_with("Couldn't migrate key \"a\""), "{:?}", reply);
+    assert_eq!(session(&server, "GET a\n"), vec!["1", "Bye."]);
+}
+
+#[test]
+fn a_key_written_during_a_move_is_kept() {
+    let server = start_server();
+    session(&server, "SET i32 a 1\n");
+    // Answers the RESTORE only once the key has been written to.
+    let target = TcpListener::bind("127.0.0.1:0").unwrap();
+    let target_port = target.local_addr().unwrap().port();
+    let (received, restored) = std::sync::mpsc::channel::<()>();
+    let (written, wait) = std::sync::mpsc::channel::<()>();
+    let answered = thread::spawn(move || {
+        let (stream, _) = target.accept().unwrap();
+        let mut restore = String::new();
+        BufReader::new(&stream).read_line(&mut restore).unwrap();
+        received.send(()).unwrap();
+        wait.recv().unwrap();
+        (&stream).write_all(b"OK\n").unwrap();
+        restore
+    });
+
+    let mut migrating = connect(&server);
+    migrating.write_all(format!("MIGRATE 127.0.0.1 {} a DESTROY\n", target_port).as_bytes()).unwrap();
+    restored.recv().unwrap();
+    session(&server, "SET i32 a 2\n");
+    written.send(()).unwrap();
+    assert!(answered.join().unwrap().starts_with("RESTORE a 0 "));
+
+    let mut reply = String::new();
+    BufReader::new(migrating).read_line(&mut reply).unwrap();
+    assert_eq!(reply, format!("Copied key \"a\" to 127.0.0.1:{}, but kept it here because it changed meanwhile.\n", target_port));
+    assert_eq!(session(&server, "GET a\n"), vec!["2", "Bye."]);
+}
@@ -0,0 +1,87 @@
+mod common;
+
+use std::env::consts::{DLL_PREFIX, DLL_SUFFIX};
+use std::path::{Path, PathBuf};
+
+use smirk::core::command::Command;
+use smirk::core::command_spec::CommandSpec;
+use smirk::core::module::{self, ModuleCommand, Registrar, all_keys};
+use smirk::core::smirk_map::SmirkMap;
+
+use common::{start_server_with, session};
+
+/// The example module, which `cargo test` builds along with the other examples.
+fn word_count_module() -> PathBuf {
+    let path = Path::new(env!("CARGO_BIN_EXE_smirk-server"))
+        .parent()
+        .unwrap()
+        .join("examples")
+        .join(format!("{}word_count_module{}", DLL_PREFIX, DLL_SUFFIX));
+    assert!(path.exists(), "{} is missing, build it with cargo build --example word_count_module", path.display());
+    path
+}
+
+#[test]
+fn loaded_modules_add_commands() {
+    let path = word_count_module();
+    let server = start_server_with(&["--load-module", path.to_str().unwrap(), "--enable-module-load"]);
+    let replies = session(&server, "SET String doc \"the quick brown fox\"\nWORDCOUNT doc\nwordcount missing\nWORDCOUNT\nHELP WORDCOUNT\nMODULE LIST\nMODULE LOAD /nowhere/libnothing.so\n");
+    assert_eq!(replies[1..7], [
+        "4",
+        "Key \"missing\" not found.",
+        "-ERR wrong arguments for 'WORDCOUNT'",
+        "WORDCOUNT <key>",
+        "Counts the words in the String at the key.",
+        &format!("name=word_count_module path={} commands=WORDCOUNT types=", path.display())
+    ]);
+    // The rest of the reason is whatever the platform's loader says.
+    assert!(replies[7].starts_with("Couldn't load module /nowhere/libnothing.so: "), "{}", replies[7]);
+    assert_eq!(replies[8], "Bye.");
+}
+
+#[test]
+fn module_libraries_have_to_match_the_version_compiler_and_target() {
+    let abi = module::ABI.to_str().unwrap();
+    assert!(abi.starts_with(&format!("smirk {} built by rustc ", env!("CARGO_PKG_VERSION"))), "{}", abi);
+    assert!(abi.contains(&format!(" for {}", std::env::consts::ARCH)), "{}", abi);
+}
+
+#[test]
+fn module_load_is_refused_unless_enabled() {
+    let path = word_count_module();
+    let server = start_server_with(&[]);
+    let replies = session(&server, &format!("MODULE LOAD {}\nWORDCOUNT doc\nMODULE LIST\n", path.display()));
+    assert_eq!(replies, [
+        "-ERR MODULE LOAD is off, start the server with --enable-module-load or load modules with --load-module",
+        "-ERR unknown command 'WORDCOUNT'",
+        "No modules are loaded.",
+        "Bye."
+    ]);
+}
+
+fn keys_in_order(_: &mut SmirkMap, arguments: &[Vec<u8>]) -> Result<Vec<u8>, String> {
+    Ok(arguments.join(&b' '))
+}
+
+#[test]
+fn installed_modules_parse_like_built_in_commands() {
+    let command = |name: &'static str| ModuleCommand {
+        spec: CommandSpec { name, min_args: 1, max_args: None, syntax: "", summary: "" },
+        keys: all_keys,
+        handler: keys_in_order
+    };
+    let mut clashing = Registrar::new("clashing");
+    clashing.command(command("GET"));
+    assert!(module::install(clashing).is_err());
+    assert!(module::find_command("GET").is_none());
+
+    let mut registrar = Registrar::new("ordered");
+    registrar.command(command("KEYSINORDER"));
+    module::install(registrar).unwrap();
+    assert!(module::install(Registrar::new("ordered")).is_err());
+
+    let mut parsed = Command::from_vec(b"keysinorder a b\n".to_vec()).unwrap();
+    parsed.namespace("tenant:");
+    assert_eq!(parsed.keys(), ["tenant:a", "tenant:b"]);
+    assert!(Command::from_vec(b"KEYSINORDER\n".to_vec()).is_err());
+}
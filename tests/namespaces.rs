@@ -0,0 +1,99 @@
+mod common;
+
+use common::{session, start_server_with, Server};
+
+fn start() -> Server {
+    start_server_with(&["--user", "alice:secret:a:", "--user", "bob:hunter2:b:", "--user", "admin:root"])
+}
+
+#[test]
+fn clients_must_authenticate_once_users_exist() {
+    let server = start();
+    let replies = session(&server, "GET k\nPING\nAUTH alice wrong\nGET k\nAUTH alice secret\nEXISTS k\n");
+    assert_eq!(replies, vec![
+        "NOAUTH Authentication required.",
+        "PONG",
+        "-ERR invalid user name or password",
+        "NOAUTH Authentication required.",
+        "OK",
+        "false",
+        "Bye."
+    ]);
+}
+
+#[test]
+fn tenants_only_see_their_own_keys() {
+    let server = start();
+    session(&server, "AUTH alice secret\nSET i32 counter 1\nTAG ADD counter hot\nKEYS *\n");
+    session(&server, "AUTH bob hunter2\nSET i32 counter 2\nSET i32 other 3\n");
+
+    let alice = session(&server, "AUTH alice secret\nGET i32 counter\nKEYS *\nTAG KEYS hot\nEXISTS other\n");
+    assert_eq!(alice, vec!["OK", "1", "counter", "counter", "false", "Bye."]);
+
+    let bob = session(&server, "AUTH bob hunter2\nGET i32 counter\nDEL BYTAG hot\nTAG KEYS hot\n");
+    assert_eq!(bob, vec!["OK", "2", "0", "No keys tagged \"hot\" were found.", "Bye."]);
+
+    // A user without a namespace sees the real keys.
+    let mut admin = session(&server, "AUTH admin root\nKEYS *\n");
+    admin[1..4].sort();
+    assert_eq!(admin, vec!["OK", "a:counter", "b:counter", "b:other", "Bye."]);
+}
+
+#[test]
+fn scripts_stay_inside_the_namespace() {
+    let server = start();
+    session(&server, "AUTH bob hunter2\nSET String secret bob's\n");
+    let alice = session(&server, "AUTH alice secret\nEVAL 1 mine 'set(\"String\", KEYS[0], \"x\"); [KEYS[0], get(\"secret\")]'\nGET mine\n");
+    assert_eq!(alice, vec!["OK", "mine", "(null)", "x", "Bye."]);
+}
+
+#[test]
+fn server_wide_commands_are_refused_inside_a_namespace() {
+    let server = start();
+    let replies = session(&server, "AUTH alice secret\nMODE REGEX\nDRYRUN CONFIG SET max-clients 1\nCLIENT LIST\nINFO\nCONFIG GET port\n");
+    assert_eq!(replies, vec![
+        "OK",
+        "-ERR 'MODE' can't be used inside a namespace",
+        "-ERR 'DRYRUN' can't be used inside a namespace",
+        "-ERR 'CLIENT' can't be used inside a namespace",
+        "-ERR 'INFO' can't be used inside a namespace",
+        "-ERR 'CONFIG' can't be used inside a namespace",
+        "Bye."
+    ]);
+}
+
+#[test]
+fn passwords_stay_out_of_the_slowlog() {
+    let server = start_server_with(&["--user", "alice:secret:a:", "--user", "admin:root", "--slowlog-log-slower-than", "0"]);
+    session(&server, "AUTH alice secret\nAUTH alice secret extra\n");
+    let slowlog = session(&server, "AUTH admin root\nSLOWLOG GET 10\n");
+    assert!(slowlog.iter().any(|entry| entry.ends_with(" AUTH alice ***")), "{:?}", slowlog);
+    assert!(slowlog.iter().all(|entry| !entry.contains("secret") && !entry.contains("root")), "{:?}", slowlog);
+}
+
+#[test]
+fn replies_name_keys_without_the_namespace() {
+    let server = start();
+    let replies = session(&server, "AUTH alice secret\nGET missing\nSET i32 k many\nTTL missing\nHISTORY missing\nMULTI\nDUMP missing\nEXEC\nCURSOR PAGE c 1 10\n");
+    assert_eq!(replies, vec![
+        "OK",
+        "Key \"missing\" not found.",
+        "Setting key \"k\" failed. Could not parse \"many\" into \"i32\".",
+        "Key \"missing\" does not exist.",
+        "No history for key \"missing\".",
+        "OK",
+        "QUEUED",
+        "Key \"missing\" not found.",
+        "Cursor \"c\" does not exist or has expired.",
+        "Bye."
+    ]);
+}
+
+#[test]
+fn vsearch_ranks_only_the_namespaces_own_vectors() {
+    let server = start_server_with(&["--user", "alice:secret:t:a:", "--user", "bob:hunter2:t:b:", "--user", "admin:root"]);
+    session(&server, "AUTH admin root\nVINDEX CREATE docs PREFIX t: DIM 2\n");
+    session(&server, "AUTH bob hunter2\nSET Vector x [1,0]\nSET Vector y [0.9,0.1]\n");
+    let alice = session(&server, "AUTH alice secret\nSET Vector far [0,1]\nVSEARCH docs 1 [1,0]\n");
+    assert_eq!(alice[2..], ["far 1", "Bye."]);
+}
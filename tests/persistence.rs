@@ -0,0 +1,381 @@
+mod common;
+
+use std::io::{BufRead, BufReader, Write};
+use std::thread::sleep;
+use std::time::Duration;
+
+use common::{connect, scratch_dir, session, spawn_server_with, start_server_with};
+
+#[test]
+fn save_points_write_the_dataset_in_the_background() {
+    let dir = scratch_dir("save-points");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("dump.json");
+    let path = path.to_str().unwrap();
+
+    let server = start_server_with(&["--save", "1 2", "--save-file", path]);
+    // One change isn't enough for the save point.
+    session(&server, "SET i32 a 1\n");
+    sleep(Duration::from_millis(1500));
+    assert!(!std::path::Path::new(path).exists());
+
+    session(&server, "SET String b two\n");
+    let mut waited = 0;
+    while session(&server, "INFO\n").iter().any(|line| line != "changes_since_last_save:0" && line.starts_with("changes_since")) {
+        assert!(waited < 50, "the save point was never reached");
+        sleep(Duration::from_millis(100));
+        waited += 1;
+    }
+    drop(server);
+
+    let server = start_server_with(&["--import", path]);
+    assert_eq!(session(&server, "GET a\nGET b\n"), vec!["1", "two", "Bye."]);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn save_writes_the_dataset_now() {
+    let dir = scratch_dir("save");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("dump.cbor");
+    let path = path.to_str().unwrap();
+
+    let server = start_server_with(&["--save-file", path]);
+    let replies = session(&server, "SET i32 a 1\nSAVE\nCONFIG SET save '60 1 300'\nCONFIG SET save '60 1 300 10'\nCONFIG GET save\n");
+    assert_eq!(replies, vec![
+        "Set key \"a\" successfully. Stored-Type: i32, User-Type: i32",
+        format!("Saved 1 keys to \"{}\".", path).as_str(),
+        "Invalid save points \"60 1 300\", expected pairs of <seconds> <changes>.",
+        "OK",
+        "save 60 1 300 10",
+        "Bye."
+    ]);
+    drop(server);
+
+    let server = start_server_with(&["--import", path]);
+    assert_eq!(session(&server, "GET a\n"), vec!["1", "Bye."]);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn corrupt_snapshots_are_refused_unless_repairing() {
+    let dir = scratch_dir("checksum");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("dump.json");
+    let path = path.to_str().unwrap();
+
+    let server = start_server_with(&["--save-file", path]);
+    session(&server, "SET String a apple\nSET i32 b 2\nSAVE\n");
+    drop(server);
+    let leftovers: Vec<_> = std::fs::read_dir(&dir).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+    assert_eq!(leftovers, vec!["dump.json"]);
+
+    // Still valid JSON, but not what was saved.
+    let saved = std::fs::read_to_string(path).unwrap();
+    std::fs::write(path, saved.replace("apple", "apricot")).unwrap();
+
+    let server = start_server_with(&["--import", path]);
+    let replies = session(&server, &format!("GET a\nIMPORT {}\nCONFIG SET repair yes\nIMPORT {}\nGET a\n", path, path));
+    assert_eq!(replies, vec![
+        "Key \"a\" not found.",
+        format!("Couldn't import from \"{}\": Snapshot doesn't match its checksum, so it's corrupt. Use --repair to load what's left of it.", path).as_str(),
+        "OK",
+        "Imported 2 keys.",
+        "apricot",
+        "Bye."
+    ]);
+    drop(server);
+
+    let server = start_server_with(&["--import", path, "--repair"]);
+    assert_eq!(session(&server, "GET a\nCONFIG GET repair\n"), vec!["apricot", "repair yes", "Bye."]);
+    drop(server);
+
+    std::fs::write(path, &saved[..saved.len() / 2]).unwrap();
+    let server = start_server_with(&["--import", path, "--repair"]);
+    let replies = session(&server, &format!("IMPORT {}\n", path));
+    assert!(replies[0].starts_with("Couldn't import from"), "{:?}", replies);
+    assert!(replies[0].contains("corrupt or truncated"), "{:?}", replies);
+
+    // Snapshots from before checksums have nothing to check.
+    std::fs::write(path, r#"{"version": 1, "keys": {"old": {"type": "i32", "user_type": "i32", "ttl": null, "value": "3"}}}"#).unwrap();
+    assert_eq!(session(&server, &format!("CONFIG SET repair no\nIMPORT {}\nGET old\n", path)), vec!["OK", "Imported 1 keys.", "3", "Bye."]);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn verify_lists_what_changed_since_the_snapshot() {
+    let dir = scratch_dir("verify");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("dump.json");
+    let path = path.to_str().unwrap();
+    let later = dir.join("later.cbor");
+    let later = later.to_str().unwrap();
+
+    let server = start_server_with(&["--save-file", path]);
+    let replies = session(&server, &format!(
+        "SET i32 kept 1\nSET i32 changed 2\nSET i32 removed 3\nSAVE\nVERIFY\nSET i32 changed 20\nDEL removed\nSET i32 added 4\nVERIFY\nEXPORT {}\nVERIFY {}\nVERIFY {}/missing.json\n",
+        later, later, dir.to_str().unwrap()
+    ));
+    assert_eq!(&replies[4..], [
+        format!("0 added, 0 changed, 0 removed since \"{}\".", path).as_str(),
+        "Set key \"changed\" successfully. Stored-Type: i32, User-Type: i32",
+        "1",
+        "Set key \"added\" successfully. Stored-Type: i32, User-Type: i32",
+        "+ added",
+        "~ changed",
+        "- removed",
+        format!("1 added, 1 changed, 1 removed since \"{}\".", path).as_str(),
+        format!("Exported 3 keys to \"{}\".", later).as_str(),
+        format!("0 added, 0 changed, 0 removed since \"{}\".", later).as_str(),
+        format!("Couldn't verify against \"{}/missing.json\": No such file or directory (os error 2).", dir.to_str().unwrap()).as_str(),
+        "Bye."
+    ]);
+    drop(server);
+
+    // The CLI compares snapshots offline, exiting 1 when they differ.
+    let verify = |paths: &[&str]| std::process::Command::new(env!("CARGO_BIN_EXE_smirk-cli")).arg("--verify").args(paths).output().unwrap();
+    let output = verify(&[path, later]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), format!(
+        "Read 3 keys from \"{}\".\nRead 3 keys from \"{}\".\n+ added\n~ changed\n- removed\n1 added, 1 changed, 1 removed from \"{}\" to \"{}\".\n",
+        path, later, path, later
+    ));
+    assert_eq!(verify(&[later]).status.code(), Some(0));
+
+    let saved = std::fs::read_to_string(path).unwrap();
+    std::fs::write(path, saved.replace("\"value\": \"1\"", "\"value\": \"9\"")).unwrap();
+    let output = verify(&[path]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("doesn't match its checksum"), "{:?}", output);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn snapshots_round_trip_every_type() {
+    let dir = scratch_dir("types");
+    std::fs::create_dir_all(&dir).unwrap();
+    let writes: &[&[u8]] = &[
+        b"SET i8 i8 -8", b"SET i16 i16 -16", b"SET i32 i32 -32", b"SET i64 i64 -64",
+        b"SET i128 i128 -170141183460469231731687303715884105728", b"SET isize isize -1",
+        b"SET u8 u8 255", b"SET u16 u16 16", b"SET u32 u32 32", b"SET u64 u64 18446744073709551615",
+        b"SET u128 u128 340282366920938463463374607431768211455", b"SET usize usize 1",
+        b"SET f32 f32 0.1", b"SET f64 f64 0.1", b"SET f64 nan NaN", b"SET f64 inf -inf",
+        b"SET bool bool true", b"SET char char \xc3\xa9", b"SET String string hello",
+        b"SET BigInt bigint -123456789012345678901234567890123",
+        b"SET BigDecimal bigdecimal 1.000000000000000000000000001",
+        b"SET Vector vector [1.5,2,3]", b"SET Json json {\"a\":[1,2.5,null]}",
+        b"SET Blob binary \xff\x00\x01", b"SET Blob text plain", b"SETNULL u64 null",
+        b"ZADD zset 1e300 top -1e300 bottom 1.5 middle", b"XADD stream 1-1 field value",
+        b"GEOADD geo 13.361389 38.115556 palermo", b"PFADD hll a b c",
+        b"BF.ADD bloom a", b"COUNTER counter u8 WRAP 200",
+        b"TS.CREATE series RETENTION 1000", b"TS.ADD series 5 1.5", b"TS.ADD series 10 -inf"
+    ];
+    let keys = [
+        "i8", "i16", "i32", "i64", "i128", "isize", "u8", "u16", "u32", "u64", "u128", "usize", "f32", "f64",
+        "nan", "inf", "bool", "char", "string", "bigint", "bigdecimal", "vector", "json", "binary", "text",
+        "null", "zset", "stream", "geo", "hll", "bloom", "counter", "series"
+    ];
+    // DUMP shows each record's stored type, user type and value exactly, binary included.
+    let describe = keys.iter().map(|key| format!("TYPE {}\nDUMP {}\n", key, key)).collect::<String>()
+        + "GET u128\nGET bigdecimal\nGET inf\nZRANGE zset 0 -1 WITHSCORES\nXRANGE stream - +\nPFCOUNT hll\nBF.EXISTS bloom a\nGET counter\nTS.RANGE series - +\n";
+
+    let server = start_server_with(&[]);
+    let mut stream = connect(&server);
+    for write in writes {
+        stream.write_all(write).unwrap();
+        stream.write_all(b"\n").unwrap();
+    }
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    for _ in writes {
+        reader.read_until(b'\n', &mut Vec::new()).unwrap();
+    }
+    let before = session(&server, &describe);
+    assert!(before.iter().all(|line| !line.contains("not found")), "{:?}", before);
+
+    for file in ["dump.json", "dump.cbor"] {
+        let path = dir.join(file);
+        let path = path.to_str().unwrap();
+        assert_eq!(session(&server, &format!("EXPORT {}\n", path))[0], format!("Exported {} keys to \"{}\".", keys.len(), path));
+        let restored = start_server_with(&["--import", path]);
+        assert_eq!(session(&restored, &describe), before, "{}", file);
+    }
+    assert!(before.contains(&String::from("Stored-Type: Vec<u8>, User-Type: Blob")));
+    assert!(before.contains(&String::from("340282366920938463463374607431768211455")));
+    assert!(before.contains(&String::from("middle 1.5")));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn scalars_come_back_as_their_stored_type_whatever_their_user_type() {
+    let dir = scratch_dir("user-types");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("dump.json");
+    let path = path.to_str().unwrap();
+    std::fs::write(path, r#"{"version": 1, "keys": {
+        "visits": {"type": "u128", "user_type": "Counter", "ttl": null, "value": "340282366920938463463374607431768211455"},
+        "odd": {"type": "smirk::core::unknown::Type", "user_type": "Odd", "ttl": null, "value": "1"}
+    }}"#).unwrap();
+
+    let server = start_server_with(&[]);
+    assert_eq!(session(&server, &format!("IMPORT {}\nTYPE visits\nGET visits\n", path)), vec![
+        "Imported 1 keys. Key \"odd\" has a type this server doesn't know, smirk::core::unknown::Type.",
+        "Stored-Type: u128, User-Type: Counter",
+        "340282366920938463463374607431768211455",
+        "Bye."
+    ]);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn ttls_count_down_while_saved() {
+    let dir = scratch_dir("ttl");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("dump.json");
+    let path = path.to_str().unwrap();
+
+    let server = start_server_with(&["--save-file", path]);
+    session(&server, "SET i32 soon 1 EX 2\nSET i32 later 2 EX 300\nSET i32 never 3\nSAVE\n");
+    drop(server);
+    sleep(Duration::from_millis(2100));
+
+    let server = start_server_with(&["--import", path]);
+    let replies = session(&server, "GET soon\nTTL later\nTTL never\n");
+    assert_eq!(replies[0], "Key \"soon\" not found.");
+    let later: u64 = replies[1].parse().unwrap();
+    assert!((295..=298).contains(&later), "{:?}", replies);
+    assert_eq!(replies[2], "Key \"never\" does not expire.");
+
+    // Past due keys aren't counted, and snapshots from before absolute expiry still count from now.
+    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_millis() as u64;
+    std::fs::write(path, format!(r#"{{"version": 2, "keys": {{
+        "gone": {{"type": "i32", "user_type": "i32", "expires_at": {}, "value": "1"}},
+        "kept": {{"type": "i32", "user_type": "i32", "expires_at": {}, "value": "2"}},
+        "old": {{"type": "i32", "user_type": "i32", "ttl": 100, "value": "3"}}
+    }}}}"#, now - 1000, now + 50_000)).unwrap();
+    assert_eq!(session(&server, &format!("IMPORT {}\nEXISTS gone\nTTL kept\nTTL old\n", path)), vec![
+        "Imported 2 keys.", "false", "50", "100", "Bye."
+    ]);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn seed_files_fill_in_missing_keys_at_startup() {
+    let dir = scratch_dir("seed");
+    std::fs::create_dir_all(&dir).unwrap();
+    let seed = dir.join("seed.txt");
+    let snapshot = dir.join("dump.json");
+    std::fs::write(&seed, "# Known configuration\n\
+        u16 config:port 8080\n\
+        String config:motd \"hello there\"\n\
+        Json config:flags '{\"beta\": true}'\n\
+        i32 session:demo 1 EX 300\n\
+        \n\
+        u8 config:broken 300\n").unwrap();
+    std::fs::write(&snapshot, r#"{"version": 2, "keys": {"config:port": {"type": "u16", "user_type": "u16", "expires_at": null, "value": "9090"}}}"#).unwrap();
+
+    let server = start_server_with(&["--seed", seed.to_str().unwrap(), "--import", snapshot.to_str().unwrap()]);
+    let replies = session(&server, "GET config:port\nGET config:motd\nTYPE config:flags\nTTL session:demo\nEXISTS config:broken\n");
+    assert_eq!(replies, vec![
+        "9090",
+        "hello there",
+        "Stored-Type: serde_json::value::Value, User-Type: Json",
+        "300",
+        "false",
+        "Bye."
+    ]);
+    drop(server);
+
+    // A line that doesn't parse loads nothing at all.
+    std::fs::write(&seed, "u16 config:port 8080\nString config:motd\n").unwrap();
+    let server = start_server_with(&["--seed", seed.to_str().unwrap()]);
+    assert_eq!(session(&server, "EXISTS config:port\n"), vec!["false", "Bye."]);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn snapshots_are_encrypted_with_the_first_key_and_read_with_any() {
+    let dir = scratch_dir("encrypted");
+    std::fs::create_dir_all(&dir).unwrap();
+    let file = |name: &str| dir.join(name).to_str().unwrap().to_string();
+    let (old_key, new_key) = ("11".repeat(32), "22".repeat(32));
+    std::fs::write(file("old.keys"), format!("{}\n", old_key)).unwrap();
+    std::fs::write(file("rotated.keys"), format!("# new key first\n{}\n\n{}\n", new_key, old_key)).unwrap();
+    std::fs::write(file("new.keys"), format!("{}\n", new_key)).unwrap();
+
+    let server = start_server_with(&["--snapshot-key-file", &file("old.keys")]);
+    let replies = session(&server, &format!("SET String secret hunter2\nEXPORT {}\nCONFIG GET snapshot-key-file\n", file("dump.json")));
+    assert_eq!(replies[1..], [format!("Exported 1 keys to \"{}\".", file("dump.json")), format!("snapshot-key-file {}", file("old.keys")), String::from("Bye.")]);
+    let written = std::fs::read(file("dump.json")).unwrap();
+    assert!(written.starts_with(b"SMIRKAES1"));
+    assert!(!written.windows(7).any(|window| window == b"hunter2"));
+    drop(server);
+
+    // After rotating, old snapshots still load and new ones are written with the new key.
+    let server = start_server_with(&["--snapshot-key-file", &file("rotated.keys"), "--import", &file("dump.json")]);
+    assert_eq!(session(&server, &format!("GET secret\nEXPORT {}\n", file("rotated.json")))[0], "hunter2");
+    drop(server);
+    let server = start_server_with(&["--snapshot-key-file", &file("new.keys")]);
+    let replies = session(&server, &format!("IMPORT {}\nIMPORT {}\nGET secret\n", file("dump.json"), file("rotated.json")));
+    assert_eq!(replies, [
+        format!("Couldn't import from \"{}\": Snapshot was encrypted with a key that isn't in the key file.", file("dump.json")),
+        String::from("Imported 1 keys."),
+        String::from("hunter2"),
+        String::from("Bye.")
+    ]);
+    drop(server);
+
+    // Without keys an encrypted snapshot can't be read, but plain ones still load with keys.
+    let server = start_server_with(&[]);
+    let replies = session(&server, &format!("IMPORT {}\nSET i32 plain 1\nEXPORT {}\n", file("rotated.json"), file("plain.json")));
+    assert_eq!(replies[0], format!("Couldn't import from \"{}\": Snapshot is encrypted, start with --snapshot-key-file to read it.", file("rotated.json")));
+    drop(server);
+    let server = start_server_with(&["--snapshot-key-file", &file("new.keys"), "--import", &file("plain.json")]);
+    assert_eq!(session(&server, "GET plain\n"), ["1", "Bye."]);
+    drop(server);
+
+    // A snapshot that's been tampered with doesn't decrypt, even when repairing.
+    let mut tampered = std::fs::read(file("rotated.json")).unwrap();
+    *tampered.last_mut().unwrap() ^= 1;
+    std::fs::write(file("rotated.json"), tampered).unwrap();
+    let server = start_server_with(&["--snapshot-key-file", &file("new.keys"), "--repair"]);
+    assert_eq!(session(&server, &format!("IMPORT {}\n", file("rotated.json")))[0], format!("Couldn't import from \"{}\": Snapshot doesn't decrypt, so it's corrupt or was tampered with.", file("rotated.json")));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn status_reports_keys_loaded_while_a_snapshot_is_imported() {
+    const KEYS: usize = 300_000;
+    let dir = scratch_dir("import-progress");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("dump.json");
+    let mut snapshot = String::from("{\"version\":2,\"keys\":{");
+    for i in 0..KEYS {
+        if i > 0 {
+            snapshot.push(',');
+        }
+        snapshot.push_str(&format!("\"key:{}\":{{\"type\":\"i32\",\"user_type\":\"i32\",\"value\":\"{}\"}}", i, i));
+    }
+    snapshot.push_str("}}");
+    std::fs::write(&path, snapshot).unwrap();
+
+    let server = spawn_server_with(&["--import", path.to_str().unwrap()]);
+    let mut statuses = Vec::new();
+    loop {
+        let status = session(&server, "STATUS\n").remove(0);
+        if status.starts_with("ready") {
+            break;
+        }
+        assert!(statuses.len() < 6000, "the import never finished");
+        statuses.push(status);
+        sleep(Duration::from_millis(5));
+    }
+    assert!(!statuses.is_empty(), "STATUS was never answered while loading");
+    let total = format!("/{}", KEYS);
+    for status in statuses.iter().filter(|status| status.contains("phase=\"importing snapshot\"")) {
+        let progress = status.split(" keys=").nth(1).unwrap_or_else(|| panic!("no progress in {:?}", status));
+        let loaded: usize = progress.split('/').next().unwrap().parse().unwrap();
+        assert!(loaded <= KEYS && progress.contains(&total), "{:?}", status);
+    }
+    assert_eq!(session(&server, "GET key:0\nGET key:299999\n"), vec!["0", "299999", "Bye."]);
+    std::fs::remove_dir_all(dir).unwrap();
+}
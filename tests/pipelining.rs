@@ -0,0 +1,87 @@
+mod common;
+
+use std::io::{BufRead, BufReader, Write};
+
+use common::{connect, start_server};
+
+#[test]
+fn pipelined_commands_reply_in_order() {
+    let server = start_server();
+    let mut stream = connect(&server);
+
+    let mut pipeline = String::new();
+    for i in 0..100 {
+        if i % 2 == 0 {
+            pipeline.push_str(&format!("SET i64 key{} {}\n", i, i * 10));
+        } else {
+            pipeline.push_str(&format!("GET i64 key{}\n", i - 1));
+        }
+    }
+    stream.write_all(pipeline.as_bytes()).unwrap();
+
+    let mut reader = BufReader::new(stream);
+    for i in 0..100 {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        if i % 2 == 0 {
+            assert_eq!(line, format!("Set key \"key{}\" successfully. Stored-Type: i64, User-Type: i64\n", i));
+        } else {
+            assert_eq!(line, format!("{}\n", (i - 1) * 10));
+        }
+    }
+}
+
+#[test]
+fn every_reply_is_newline_terminated() {
+    let server = start_server();
+    let mut stream = connect(&server);
+
+    stream.write_all(b"SET i32 a 1\nSET i32 b 2\nADD i32 a b\nDEL a b missing\nEXISTS a\nQUIT\n").unwrap();
+
+    let replies: Vec<String> = BufReader::new(stream).lines().map(|l| l.unwrap()).collect();
+    assert_eq!(replies, vec![
+        "Set key \"a\" successfully. Stored-Type: i32, User-Type: i32",
+        "Set key \"b\" successfully. Stored-Type: i32, User-Type: i32",
+        "3",
+        "2",
+        "false",
+        "Bye."
+    ]);
+}
+
+#[test]
+fn checksummed_lines_are_checked_and_replies_framed() {
+    let server = start_server();
+    let mut stream = connect(&server);
+
+    // *1340d049 is the CRC32 of "PING", *b7ade92b of "CHECKSUM OFF".
+    stream.write_all(b"CHECKSUM ON\nPING *1340d049\nPING *1340d04a\nPING\nCHECKSUM OFF *b7ade92b\nPING\nQUIT\n").unwrap();
+
+    let replies: Vec<String> = BufReader::new(stream).lines().map(|l| l.unwrap()).collect();
+    assert_eq!(replies.len(), 11, "{:?}", replies);
+    assert_eq!(&replies[..3], ["OK", "PONG", "*18afe0ab"]);
+    assert_eq!(replies[3], "-ERR checksum mismatch, the command hashes to 1340d049 not 1340d04a, resend it");
+    assert_eq!(replies[5], "-ERR checksum missing, expected the command followed by *<crc32 in hex>");
+    for trailer in [&replies[4], &replies[6]] {
+        assert!(trailer.starts_with('*') && trailer.len() == 9, "{}", trailer);
+    }
+    assert_eq!(&replies[7..], ["OK", "*77df6c3f", "PONG", "Bye."]);
+}
+
+#[test]
+fn clients_do_not_block_each_other() {
+    let server = start_server();
+    let mut first = connect(&server);
+    let mut second = connect(&server);
+
+    first.write_all(b"SET String greeting hello\n").unwrap();
+    let mut first_reader = BufReader::new(first.try_clone().unwrap());
+    let mut line = String::new();
+    first_reader.read_line(&mut line).unwrap();
+
+    second.write_all(b"GET String greeting\n").unwrap();
+    let mut second_reader = BufReader::new(second);
+    line.clear();
+    second_reader.read_line(&mut line).unwrap();
+    assert_eq!(line, "hello\n");
+}
@@ -0,0 +1,161 @@
+use std::collections::BTreeSet;
+
+use proptest::prelude::*;
+use smirk::core::radix_tree::RadixTree;
+use smirk::core::smirk_map::SmirkMap;
+use smirk::core::smirk_search_mode::SmirkSearchMode;
+
+fn tree(keys: &[&str]) -> RadixTree {
+    let mut tree = RadixTree::new();
+    for key in keys {
+        tree.insert(key);
+    }
+    tree
+}
+
+#[test]
+fn prefixes_list_and_count_their_keys_in_order() {
+    let tree = tree(&["user:2", "user:10", "user:1", "session:a", "use", "héllo", "hé", "hëllo"]);
+    assert_eq!(tree.len(), 8);
+    assert_eq!(tree.keys_with_prefix("user:").collect::<Vec<_>>(), ["user:1", "user:10", "user:2"]);
+    // A prefix can stop partway along an edge, or partway through a char.
+    assert_eq!(tree.keys_with_prefix("us").collect::<Vec<_>>(), ["use", "user:1", "user:10", "user:2"]);
+    assert_eq!(tree.keys_with_prefix("h").collect::<Vec<_>>(), ["hé", "héllo", "hëllo"]);
+    assert_eq!(tree.count_prefix("user:1"), 2);
+    assert_eq!(tree.count_prefix("hé"), 2);
+    assert_eq!(tree.count_prefix(""), 8);
+    assert_eq!(tree.count_prefix("users"), 0);
+    assert_eq!(tree.keys_with_prefix("x").count(), 0);
+    assert!(tree.contains("use") && !tree.contains("us") && !tree.contains("user:"));
+}
+
+#[test]
+fn adding_a_key_twice_and_removing_it_once_leaves_nothing_behind() {
+    let mut tree = tree(&["a", "ab"]);
+    assert!(!tree.insert("ab"));
+    assert!(tree.remove("ab"));
+    assert!(!tree.remove("ab"));
+    assert!(!tree.remove("zz"));
+    assert_eq!(tree.count_prefix("a"), 1);
+    assert_eq!(tree.keys().collect::<Vec<_>>(), ["a"]);
+
+    assert!(tree.remove("a"));
+    assert!(tree.is_empty());
+    assert!(tree.insert(""));
+    assert_eq!(tree.keys().collect::<Vec<_>>(), [""]);
+}
+
+#[test]
+fn matches_a_sorted_set_through_random_inserts_and_removes() {
+    let mut tree = RadixTree::new();
+    let mut model = BTreeSet::new();
+    let mut seed: u64 = 42;
+    for _ in 0..20_000 {
+        seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        // Short keys over a small alphabet, so they share prefixes and split and merge edges a lot.
+        let key: String = (0..(seed >> 60) % 6).map(|i| ["a", "b", "ü"][((seed >> (i * 3 + 20)) % 3) as usize]).collect();
+        if (seed >> 40).is_multiple_of(3) {
+            assert_eq!(tree.remove(&key), model.remove(&key), "{}", key);
+        } else {
+            assert_eq!(tree.insert(&key), model.insert(key.clone()), "{}", key);
+        }
+    }
+    assert_eq!(tree.keys().collect::<Vec<_>>(), model.iter().cloned().collect::<Vec<_>>());
+    for prefix in ["", "a", "ab", "ü", "bü", "aaa"] {
+        let expected: Vec<String> = model.iter().filter(|key| key.starts_with(prefix)).cloned().collect();
+        assert_eq!(tree.keys_with_prefix(prefix).collect::<Vec<_>>(), expected, "{}", prefix);
+        assert_eq!(tree.count_prefix(prefix), expected.len(), "{}", prefix);
+    }
+}
+
+#[test]
+fn emoji_and_cjk_keys_split_between_bytes_and_come_back_whole() {
+    // 東京 and 東大 share their first char, 🦀 and 🦁 share their first three bytes.
+    let mut tree = tree(&["東京", "東大", "東", "🦀", "🦀🦀", "🦁", "京都"]);
+    assert_eq!(tree.keys_with_prefix("東").collect::<Vec<_>>(), ["東", "東京", "東大"]);
+    assert_eq!(tree.keys_with_prefix("🦀").collect::<Vec<_>>(), ["🦀", "🦀🦀"]);
+    assert_eq!(tree.count_prefix("京"), 1);
+    assert!(!tree.contains("東京都"));
+
+    assert!(tree.remove("🦀"));
+    assert!(tree.remove("東"));
+    assert_eq!(tree.keys().collect::<Vec<_>>(), ["京都", "東京", "東大", "🦀🦀", "🦁"]);
+}
+
+#[derive(Debug, Clone)]
+enum Op {
+    Insert(String),
+    Remove(String),
+    Rename(String, String)
+}
+
+/// Short keys over a small alphabet, multi-byte chars included, so they share prefixes and edges
+/// split and merge a lot.
+fn key() -> impl Strategy<Value = String> {
+    "[abü🦀]{0,5}"
+}
+
+fn op() -> impl Strategy<Value = Op> {
+    prop_oneof![
+        3 => key().prop_map(Op::Insert),
+        2 => key().prop_map(Op::Remove),
+        1 => (key(), key()).prop_map(|(from, to)| Op::Rename(from, to))
+    ]
+}
+
+fn with_prefix(model: &BTreeSet<String>, prefix: &str) -> Vec<String> {
+    model.iter().filter(|key| key.starts_with(prefix)).cloned().collect()
+}
+
+proptest! {
+    #[test]
+    fn prefix_listings_are_exactly_the_live_keys(ops in prop::collection::vec(op(), 0..200), prefix in key()) {
+        let mut tree = RadixTree::new();
+        let mut model = BTreeSet::new();
+        for op in ops {
+            match op {
+                Op::Insert(key) => prop_assert_eq!(tree.insert(&key), model.insert(key)),
+                Op::Remove(key) => prop_assert_eq!(tree.remove(&key), model.remove(&key)),
+                Op::Rename(..) => {}
+            }
+        }
+        prop_assert_eq!(tree.len(), model.len());
+        prop_assert_eq!(tree.keys_with_prefix(&prefix).collect::<Vec<_>>(), with_prefix(&model, &prefix));
+        prop_assert_eq!(tree.count_prefix(&prefix), with_prefix(&model, &prefix).len());
+    }
+
+    #[test]
+    fn listings_pick_up_after_any_key(keys in prop::collection::vec(key(), 0..100), prefix in "[aü]{0,2}", after in key()) {
+        let tree = tree(&keys.iter().map(String::as_str).collect::<Vec<_>>());
+        let model: BTreeSet<&String> = keys.iter().collect();
+        let expected: Vec<String> = model.iter().filter(|key| key.starts_with(&prefix) && key.as_str() > after.as_str()).map(|key| key.to_string()).collect();
+        prop_assert_eq!(tree.keys_with_prefix_after(&prefix, &after).collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn the_map_keeps_its_tree_in_step_with_its_records(ops in prop::collection::vec(op(), 0..100), prefix in key()) {
+        let mut smirk_map = SmirkMap::new(SmirkSearchMode::Trie);
+        let mut model = BTreeSet::new();
+        for op in ops {
+            match op {
+                Op::Insert(key) => {
+                    smirk_map.binary_set(&key, key.clone().into_bytes(), "Blob").unwrap();
+                    model.insert(key);
+                }
+                Op::Remove(key) => {
+                    smirk_map.del(&key);
+                    model.remove(&key);
+                }
+                Op::Rename(from, to) => {
+                    if smirk_map.rename(&from, &to).is_ok() {
+                        model.remove(&from);
+                        model.insert(to);
+                    }
+                }
+            }
+        }
+        let keys: BTreeSet<String> = smirk_map.map.keys().cloned().collect();
+        prop_assert_eq!(&keys, &model);
+        prop_assert_eq!(smirk_map.trie.keys_with_prefix(&prefix).collect::<Vec<_>>(), with_prefix(&model, &prefix));
+    }
+}
@@ -0,0 +1,69 @@
+mod common;
+
+use std::io::{BufRead, BufReader, Read, Write};
+
+use common::{connect, session, start_server};
+
+#[test]
+fn getrange_clamps_and_counts_from_the_end() {
+    let server = start_server();
+    let replies = session(&server, "SET String s \"Hello World\"\nGETRANGE s 0 4\nGETRANGE s -5 -1\nGETRANGE s 3 100\nGETRANGE s 5 2\nGETRANGE missing 0 -1\n");
+    assert_eq!(&replies[1..], ["Hello", "World", "lo World", "", "", "Bye."]);
+}
+
+#[test]
+fn setrange_patches_and_pads() {
+    let server = start_server();
+    let replies = session(&server, "SET String s \"Hello World\"\nSETRANGE s 6 Smirk\nGET s\nSETRANGE b 2 ab\nGETRANGE b 2 3\nSETRANGE e 0 \"\"\nEXISTS e\nSET String u héllo\nSETRANGE u 1 x\nGET u\n");
+    assert_eq!(&replies[1..], [
+        "11",
+        "Hello Smirk",
+        "4",
+        "ab",
+        "0",
+        "false",
+        "Set key \"u\" successfully. Stored-Type: alloc::string::String, User-Type: String",
+        "Can't set a range of key \"u\": the result isn't valid UTF-8.",
+        "héllo",
+        "Bye."
+    ]);
+}
+
+#[test]
+fn setrange_refuses_offsets_past_the_size_limit() {
+    let server = start_server();
+    let replies = session(&server, "SETRANGE huge 18446744073709551615 x
+SETRANGE huge 536870912 x
+DRYRUN SETRANGE huge 18446744073709551615 x
+EXISTS huge
+PING
+");
+    assert_eq!(replies, [
+        "Can't set a range of key \"huge\": it would be longer than 536870912 bytes.",
+        "Can't set a range of key \"huge\": it would be longer than 536870912 bytes.",
+        "Would fail: Can't set a range of key \"huge\": it would be longer than 536870912 bytes.",
+        "false",
+        "PONG",
+        "Bye."
+    ]);
+}
+
+#[test]
+fn getchunked_sends_a_length_then_the_bytes() {
+    let server = start_server();
+    let mut stream = connect(&server);
+    stream.write_all(b"CONFIG SET chunk-size 1000\nSETRANGE big 199999 x\nPING\nGETCHUNKED big\nGETCHUNKED missing\nQUIT\n").unwrap();
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    for expected in ["OK\n", "200000\n", "PONG\n", "$200000\n"] {
+        line.clear();
+        reader.read_line(&mut line).unwrap();
+        assert_eq!(line, expected);
+    }
+    let mut value = vec![0; 200001];
+    reader.read_exact(&mut value).unwrap();
+    assert!(value[..199999].iter().all(|b| *b == 0));
+    assert_eq!(&value[199999..], b"x\n");
+    let rest: Vec<String> = reader.lines().map(|l| l.unwrap()).collect();
+    assert_eq!(rest, ["Key \"missing\" not found.", "Bye."]);
+}
@@ -0,0 +1,35 @@
+mod common;
+
+use std::io::{BufRead, BufReader, Write};
+use std::thread;
+
+use common::{connect, session, start_server};
+
+#[test]
+fn requests_past_the_limit_are_denied() {
+    let server = start_server();
+    let replies = session(&server, &"RATELIMIT api:alice 3 60\n".repeat(5));
+    assert_eq!(replies, vec!["allowed 2", "allowed 1", "allowed 0", "denied 0", "denied 0", "Bye."]);
+    // Each key has a limit of its own.
+    assert_eq!(session(&server, "RATELIMIT api:bob 3 60\n")[0], "allowed 2");
+
+    let replies = session(&server, "SET i32 taken 1\nRATELIMIT taken 3 60\nRATELIMIT x 3 0\nRATELIMIT x many 60\n");
+    assert!(replies[1].starts_with("Couldn't downcast"), "{:?}", replies);
+    assert_eq!(&replies[2..], ["-ERR invalid argument '0' for 'RATELIMIT'", "-ERR invalid argument 'many' for 'RATELIMIT'", "Bye."]);
+}
+
+#[test]
+fn racing_clients_never_get_more_than_the_limit_between_them() {
+    let server = start_server();
+    let clients: Vec<_> = (0..8)
+        .map(|_| {
+            let mut stream = connect(&server);
+            thread::spawn(move || {
+                stream.write_all(format!("{}QUIT\n", "RATELIMIT shared 25 60\n".repeat(10)).as_bytes()).unwrap();
+                BufReader::new(stream).lines().map(|l| l.unwrap()).filter(|reply| reply.starts_with("allowed")).count()
+            })
+        })
+        .collect();
+    let allowed: usize = clients.into_iter().map(|client| client.join().unwrap()).sum();
+    assert_eq!(allowed, 25);
+}
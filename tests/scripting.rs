@@ -0,0 +1,21 @@
+mod common;
+
+use common::{session, start_server};
+
+#[test]
+fn scripts_cant_take_unbounded_memory_or_stack() {
+    let server = start_server();
+    let replies = session(&server, concat!(
+        "EVAL 0 'let s = \"x\"; loop { s += s; }'\n",
+        "EVAL 0 'let a = [1]; loop { a += a; }'\n",
+        "EVAL 0 'fn deeper(n) { deeper(n + 1) } deeper(0)'\n",
+        "EVAL 0 '[1, 2]'\n"
+    ));
+    assert_eq!(replies[..5], [
+        "Script error: Length of string too large (line 1, position 23).",
+        "Script error: Size of array/BLOB too large (line 1, position 23).",
+        "Script error: Stack overflow (line 1, position 32).",
+        "1",
+        "2"
+    ]);
+}
@@ -0,0 +1,53 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use smirk::core::smirk_map::SmirkMap;
+use smirk::core::smirk_messages::SmirkMessages;
+use smirk::core::smirk_search_mode::SmirkSearchMode;
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Profile {
+    name: String,
+    age: u32,
+    tags: Vec<String>,
+    scores: BTreeMap<String, f64>,
+    manager: Option<Box<Profile>>
+}
+
+fn ok<T>(result: Result<T, SmirkMessages>) -> T {
+    result.unwrap_or_else(|e| panic!("{}", e))
+}
+
+#[test]
+fn serde_values_round_trip_through_the_map() {
+    let mut map = SmirkMap::new(SmirkSearchMode::Glob);
+    let key = String::from("profile:1");
+    let profile = Profile {
+        name: String::from("Ada"),
+        age: 36,
+        tags: vec![String::from("admin")],
+        scores: BTreeMap::from([(String::from("chess"), 1820.5)]),
+        manager: Some(Box::new(Profile {
+            name: String::from("Grace"),
+            age: 45,
+            tags: Vec::new(),
+            scores: BTreeMap::new(),
+            manager: None
+        }))
+    };
+    ok(map.set_serde(&key, &profile));
+    assert_eq!(ok(map.get_serde::<Profile>(&key)), profile);
+    assert_eq!(ok(map.get_record(&key)).desired_type_name, "Cbor");
+}
+
+#[test]
+fn serde_values_of_the_wrong_shape_or_type_are_errors() {
+    let mut map = SmirkMap::new(SmirkSearchMode::Glob);
+    let key = String::from("pair");
+    ok(map.set_serde(&key, &(1u8, "one")));
+    assert!(matches!(map.get_serde::<Profile>(&key), Err(SmirkMessages::SerdeError(..))));
+
+    ok(map.set_typed(&key, b"7".to_vec(), &String::from("i32")));
+    assert!(matches!(map.get_serde::<(u8, String)>(&key), Err(SmirkMessages::TypeMismatch(..))));
+    assert!(matches!(map.get_serde::<u8>(&String::from("missing")), Err(SmirkMessages::KeyNotFound(_))));
+}
@@ -0,0 +1,60 @@
+use smirk::core::codec::{self, ValueType, CODECS};
+use smirk::core::float_format::FloatFormat;
+use smirk::core::smirk_map::{SmirkMap, downcast, render};
+use smirk::core::smirk_messages::SmirkMessages;
+use smirk::core::smirk_search_mode::SmirkSearchMode;
+use smirk::core::sorted_set::SortedSet;
+
+fn ok<T>(result: Result<T, SmirkMessages>) -> T {
+    result.unwrap_or_else(|e| panic!("{}", e))
+}
+
+#[test]
+fn writes_leave_values_held_outside_the_map_alone() {
+    let mut map = SmirkMap::new(SmirkSearchMode::Glob);
+    let key = String::from("scores");
+    ok(map.zadd(&key, &[(1.0, String::from("a"))]));
+
+    let held = ok(map.get_shared(&key));
+    ok(map.zadd(&key, &[(2.0, String::from("b"))]));
+    assert_eq!(ok(downcast::<SortedSet>(&key, &held)).len(), 1);
+    assert_eq!(ok(map.get::<SortedSet>(&key)).len(), 2);
+
+    // Once nothing else holds it, the value is changed in place rather than copied.
+    drop(held);
+    let before = ok(map.get::<SortedSet>(&key)) as *const SortedSet;
+    ok(map.zadd(&key, &[(3.0, String::from("c"))]));
+    assert_eq!(ok(map.get::<SortedSet>(&key)) as *const SortedSet, before);
+}
+
+#[test]
+fn shared_values_render_like_get() {
+    let mut map = SmirkMap::new(SmirkSearchMode::Glob);
+    let key = String::from("n");
+    ok(map.set_typed(&key, b"42".to_vec(), &String::from("i64")));
+    let held = ok(map.get_shared(&key));
+    map.del(&key);
+    assert_eq!(ok(render(&key, &held)), "42");
+    assert!(downcast::<i32>(&key, &held).is_err());
+}
+
+#[test]
+fn every_codec_round_trips_through_set_typed() {
+    let mut map = SmirkMap::new(SmirkSearchMode::Glob);
+    for codec in &CODECS {
+        let key = format!("{}-key", codec.name);
+        let value = match codec.name {
+            "Json" => "{\"a\":1}",
+            "Vector" => "[1,2]",
+            "bool" => "true",
+            "char" | "String" => "x",
+            _ => "7"
+        };
+        ok(map.set_typed(&key, value.as_bytes().to_vec(), &String::from(codec.name)));
+        assert_eq!(ok(map.get_record(&key)).type_name, (codec.rust_type)(), "{}", codec.name);
+        let held = ok(map.get_shared(&key));
+        let rendered = ok(codec.render(&key, &held, &FloatFormat::Default));
+        assert!(String::from_utf8_lossy(&rendered).contains(value.trim_matches(['[', ']'])), "{}", codec.name);
+    }
+    assert!(codec::find("Cbor").is_none());
+}
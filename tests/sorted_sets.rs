@@ -0,0 +1,27 @@
+mod common;
+
+use common::{session, start_server};
+use smirk::core::sorted_set::SortedSet;
+
+#[test]
+fn scores_stay_finite() {
+    let mut set = SortedSet::new();
+    assert_eq!(set.incr("a", f64::MAX), Ok(f64::MAX));
+    assert!(set.incr("a", f64::MAX).is_err());
+    assert_eq!(set.score("a"), Some(f64::MAX));
+
+    let server = start_server();
+    let replies = session(&server, concat!(
+        "ZADD z 1e308 a\n",
+        "ZINCRBY z 1e308 a\n",
+        "ZSCORE z a\n",
+        "ZADD z NaN b\n"
+    ));
+    let score = format!("1{}", "0".repeat(308));
+    assert_eq!(replies[..4], [
+        "1",
+        "Can't change sorted set \"z\": the score of \"a\" would become inf, which isn't a finite number.",
+        &score,
+        "-ERR invalid argument 'NaN' for 'ZADD'"
+    ]);
+}
@@ -0,0 +1,71 @@
+mod common;
+
+use common::{session, start_server};
+use smirk::core::timeseries::{Aggregation, TimeSeries};
+
+#[test]
+fn retention_prunes_old_samples_and_refuses_ones_that_old() {
+    let mut series = TimeSeries::new(Some(100));
+    for timestamp in [0, 50, 100, 150] {
+        series.add(timestamp, timestamp as f64).unwrap();
+    }
+    assert_eq!(series.range(0, u64::MAX), [(50, 50.0), (100, 100.0), (150, 150.0)]);
+    assert!(series.add(49, 1.0).is_err());
+    series.add(50, 2.0).unwrap();
+    assert_eq!(series.range(50, 50), [(50, 2.0)]);
+    assert!(series.range(100, 50).is_empty());
+}
+
+#[test]
+fn buckets_start_at_multiples_of_their_width_and_skip_empty_ones() {
+    let mut series = TimeSeries::new(None);
+    for (timestamp, value) in [(5, 1.0), (9, 3.0), (10, 10.0), (35, 4.0)] {
+        series.add(timestamp, value).unwrap();
+    }
+    assert_eq!(series.aggregate(0, u64::MAX, Aggregation::Avg, 10), [(0, 2.0), (10, 10.0), (30, 4.0)]);
+    assert_eq!(series.aggregate(0, u64::MAX, Aggregation::Max, 20), [(0, 10.0), (20, 4.0)]);
+    assert_eq!(series.aggregate(6, u64::MAX, Aggregation::Count, 100), [(0, 3.0)]);
+}
+
+#[test]
+fn time_series_are_added_to_and_queried_raw_or_aggregated() {
+    let server = start_server();
+    let replies = session(&server, concat!(
+        "TS.CREATE cpu RETENTION 1000\n",
+        "TS.CREATE cpu\n",
+        "TS.ADD cpu 1000 0.5\n",
+        "TS.ADD cpu 1500 1.5\n",
+        "TS.ADD cpu 2100 4\n",
+        "TS.ADD cpu 500 1\n",
+        "TS.RANGE cpu - +\n",
+        "TS.RANGE cpu 1200 2000\n",
+        "TS.RANGE cpu - + AGG avg 1000\n",
+        "TS.RANGE cpu - + AGG min 1000\n",
+        "TS.RANGE cpu 0 999\n",
+        "TS.RANGE missing - +\n",
+        "TS.RANGE cpu - + AGG median 1000\n",
+        "TS.RANGE cpu - + AGG avg 0\n",
+        "TS.ADD cpu soon 1\n"
+    ));
+    assert_eq!(replies, [
+        "OK",
+        "Can't use time series \"cpu\": the key already exists.",
+        "1000",
+        "1500",
+        "2100",
+        "Can't use time series \"cpu\": timestamp 500 is older than the retention period keeps.",
+        "1500 1.5",
+        "2100 4",
+        "1500 1.5",
+        "1000 1.5",
+        "2000 4",
+        "1000 1.5",
+        "2000 4",
+        "No samples in range.",
+        "Key \"missing\" not found.",
+        "-ERR invalid argument 'median' for 'TS.RANGE'",
+        "-ERR invalid argument '0' for 'TS.RANGE'",
+        "-ERR invalid argument 'soon' for 'TS.ADD'",
+        "Bye."
+    ]);
+}
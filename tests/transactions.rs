@@ -0,0 +1,57 @@
+mod common;
+
+use std::io::{BufRead, BufReader, Write};
+
+use common::{connect, session, start_server};
+
+#[test]
+fn exec_aborts_once_a_watched_key_changes() {
+    let server = start_server();
+    session(&server, "SET i32 k 1\nSET i32 other 1\n");
+
+    let mut watcher = connect(&server);
+    watcher.write_all(b"WATCH k\nMULTI\nSET i32 k 2\nSET i32 other 2\n").unwrap();
+    let mut reader = BufReader::new(watcher.try_clone().unwrap());
+    let mut queued = Vec::new();
+    for _ in 0..4 {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        queued.push(line);
+    }
+    assert_eq!(queued, ["OK\n", "OK\n", "QUEUED\n", "QUEUED\n"]);
+
+    session(&server, "SET i32 k 10\n");
+    watcher.write_all(b"EXEC\nGET i32 k\nGET i32 other\nWATCH k\nMULTI\nSET i32 k 3\nEXEC\nQUIT\n").unwrap();
+    let replies: Vec<String> = reader.lines().map(|l| l.unwrap()).collect();
+    assert_eq!(replies, [
+        "Transaction aborted: a watched key changed.",
+        "10",
+        "1",
+        "OK",
+        "OK",
+        "QUEUED",
+        "Set key \"k\" successfully. Stored-Type: i32, User-Type: i32",
+        "Bye."
+    ]);
+}
+
+#[test]
+fn failed_writes_to_a_watched_key_dont_abort_exec() {
+    let server = start_server();
+    session(&server, "SET String doc plain\nCOUNTER c i8 FAIL 127\n");
+
+    let mut watcher = connect(&server);
+    watcher.write_all(b"WATCH doc c\nMULTI\nSET i32 other 1\n").unwrap();
+    let mut reader = BufReader::new(watcher.try_clone().unwrap());
+    for expected in ["OK\n", "OK\n", "QUEUED\n"] {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        assert_eq!(line, expected);
+    }
+
+    let failed = session(&server, "JSON.SET doc $.a 1\nINCR c\nXADD doc * f v\n");
+    assert!(failed[..3].iter().all(|reply| !reply.starts_with("OK") && !reply.starts_with("Set key")), "{:?}", failed);
+    watcher.write_all(b"EXEC\nQUIT\n").unwrap();
+    let replies: Vec<String> = reader.lines().map(|l| l.unwrap()).collect();
+    assert_eq!(replies, ["Set key \"other\" successfully. Stored-Type: i32, User-Type: i32", "Bye."]);
+}
@@ -0,0 +1,34 @@
+mod common;
+
+use common::{session, start_server};
+
+#[test]
+fn vsearch_finds_the_nearest_vectors_under_the_prefix() {
+    let server = start_server();
+    let replies = session(&server, concat!(
+        "VINDEX CREATE docs PREFIX doc: DIM 2\n",
+        "VINDEX CREATE near PREFIX doc: DIM 2 METRIC euclidean\n",
+        "SET Vector doc:a [1,0]\n",
+        "SET Vector doc:b [0,1]\n",
+        "SET Vector doc:c [0.9,0.1]\n",
+        "SET Vector other [1,0]\n",
+        "VSEARCH docs 2 [1,0]\n",
+        "VSEARCH near 3 [0,1]\n",
+        "VSEARCH docs 1 [1,2,3]\n",
+        "VSEARCH nope 1 [1,0]\n",
+        "VINDEX LIST\n"
+    ));
+    assert_eq!(replies[..2], ["OK", "OK"]);
+    assert_eq!(replies[6..], [
+        "doc:a 0",
+        "doc:c 0.006116271",
+        "doc:b 0",
+        "doc:c 1.2727922",
+        "doc:a 1.4142135",
+        "Expected a vector with 2 dimensions, got 3.",
+        "Index \"nope\" not found.",
+        "docs prefix=doc: dim=2 metric=cosine",
+        "near prefix=doc: dim=2 metric=euclidean",
+        "Bye."
+    ]);
+}
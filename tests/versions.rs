@@ -0,0 +1,73 @@
+mod common;
+
+use common::{session, start_server, start_server_with, Server};
+
+#[test]
+fn setcas_only_writes_over_the_record_it_expects() {
+    let server = start_server();
+    let replies = session(&server, concat!(
+        "SET i32 k 1\n",
+        "SETCAS i32 k VERSION 1 2\n",
+        "SETCAS i32 k VERSION 1 3\n",
+        "SETCAS i32 k VALUE 2 4\n",
+        "SETCAS i32 k VALUE 2 5\n",
+        "SETCAS i32 fresh VERSION 0 9\n",
+        "SETCAS i32 fresh VERSION 0 10\n",
+        "GET i32 k\n",
+        "GET i32 fresh\n",
+        "SETCAS i32 k VERSION x 1\n"
+    ));
+    assert_eq!(replies[1..], [
+        "2",
+        "Key \"k\" has changed. Nothing was set.",
+        "3",
+        "Key \"k\" has changed. Nothing was set.",
+        "4",
+        "Key \"fresh\" has changed. Nothing was set.",
+        "4",
+        "9",
+        "-ERR invalid argument 'x' for 'SETCAS'",
+        "Bye."
+    ]);
+}
+
+/// HISTORY lines without the time each value was replaced.
+fn history(server: &Server, commands: &str) -> Vec<String> {
+    session(server, commands)
+        .iter()
+        .map(|line| match line.split(' ').collect::<Vec<&str>>()[..] {
+            [version, _, ty, value] => format!("{} {} {}", version, ty, value),
+            _ => line.clone()
+        })
+        .collect()
+}
+
+#[test]
+fn history_keeps_replaced_values_to_restore() {
+    let server = start_server_with(&["--history-depth", "2"]);
+    let replies = history(&server, concat!(
+        "SET i32 k 1\n",
+        "SET i32 k 2\n",
+        "SET i32 k 3\n",
+        "HISTORY k\n",
+        "HISTORY k 1\n",
+        "RESTOREVERSION k 2\n",
+        "GET i32 k\n",
+        "RESTOREVERSION k 99\n",
+        "HISTORY never\n"
+    ));
+    assert_eq!(replies[3..], [
+        "2 i32 2",
+        "1 i32 1",
+        "2 i32 2",
+        "Set key \"k\" successfully. Stored-Type: i32, User-Type: i32",
+        "2",
+        "Version 99 of key \"k\" is not in its history.",
+        "No history for key \"never\".",
+        "Bye."
+    ]);
+
+    let server = start_server();
+    let replies = history(&server, "SET i32 k 1\nSET i32 k 2\nHISTORY k\nKEEPHISTORY k 3\nSET i32 k 3\nHISTORY k\n");
+    assert_eq!(replies[2..], ["No history for key \"k\".", "OK", "Set key \"k\" successfully. Stored-Type: i32, User-Type: i32", "2 i32 2", "Bye."]);
+}